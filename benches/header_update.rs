@@ -0,0 +1,51 @@
+//! How long committing takes as the number of lists each transaction changes grows. Every
+//! transaction pushes one entry to each of the lists so the cost of updating their heads in the
+//! header grows with the number of lists. Run with `cargo bench --bench header_update`.
+use llsdb::{Durability, InitOptions, LinkedList, LlsDb};
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+const N_TXS: u32 = 200;
+
+fn main() {
+    println!("{:>8} {:>14} {:>14}", "lists", "per tx", "per list");
+    for n_lists in [1, 10, 100, 250, 500, 1000] {
+        let per_tx = bench(n_lists);
+        println!(
+            "{:>8} {:>14?} {:>14?}",
+            n_lists,
+            per_tx,
+            per_tx / n_lists as u32
+        );
+    }
+}
+
+fn bench(n_lists: usize) -> Duration {
+    let options = InitOptions {
+        // enough room for the lists that don't fit in the first page
+        n_extra_header_pages: 4,
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(Cursor::new(vec![]), options).unwrap();
+    // leave syncing out of it since it would swamp everything else
+    db.set_durability(Durability::NoSync);
+    let lists = db
+        .execute(|tx| {
+            (0..n_lists)
+                .map(|i| tx.take_list(&format!("tenant-{}", i)))
+                .collect::<Result<Vec<LinkedList<u32>>, _>>()
+        })
+        .unwrap();
+
+    let start = Instant::now();
+    for i in 0..N_TXS {
+        db.execute(|tx| {
+            for list in &lists {
+                list.api(&tx).push(&i)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+    }
+    start.elapsed() / N_TXS
+}
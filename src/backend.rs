@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::io;
 use std::{
     borrow::BorrowMut,
@@ -10,6 +10,21 @@ pub trait Backend: Read + Write + Seek {
     fn init_max_size(&self) -> u64;
     fn init_page_size(&self) -> u16;
     fn sync_data(&self) -> Result<()>;
+    /// Called by [`LlsDb::discard_free_space`](crate::LlsDb::discard_free_space) with `len` bytes
+    /// at `position` that are free space. The backend can give back the storage behind them
+    /// (e.g. by deleting a file that's entirely within them or punching a hole in one) since the
+    /// database never reads free space. Anything read from there later can be anything. By
+    /// default nothing is done.
+    fn discard(&mut self, _position: u64, _len: u64) -> Result<()> {
+        Ok(())
+    }
+    /// Called by [`LlsDb::close`](crate::LlsDb::close) once the database is done with the backend.
+    /// Flushes and syncs anything still buffered and should release anything the backend holds
+    /// like a lock on a file.
+    fn close(&mut self) -> Result<()> {
+        self.flush()?;
+        self.sync_data()
+    }
 }
 
 /// this is for tests
@@ -36,6 +51,68 @@ where
     }
 }
 
+/// A [`Backend`] over a database image in memory that can only be read. Use it to load an image
+/// embedded with `include_bytes!` or received over the network from a `&[u8]` or `Arc<[u8]>`
+/// without copying it into a `Vec<u8>`. Query it with
+/// [`LlsDb::execute_read_only`](crate::LlsDb::execute_read_only) since
+/// [`LlsDb::execute`](crate::LlsDb::execute) always writes. Every write fails.
+#[derive(Debug, Clone)]
+pub struct ReadOnlySlice<B> {
+    cursor: io::Cursor<B>,
+}
+
+impl<B: AsRef<[u8]>> ReadOnlySlice<B> {
+    pub fn new(image: B) -> Self {
+        Self {
+            cursor: io::Cursor::new(image),
+        }
+    }
+
+    pub fn into_inner(self) -> B {
+        self.cursor.into_inner()
+    }
+}
+
+impl<B: AsRef<[u8]>> Read for ReadOnlySlice<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.cursor.read(buf)
+    }
+}
+
+impl<B: AsRef<[u8]>> Seek for ReadOnlySlice<B> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.cursor.seek(pos)
+    }
+}
+
+impl<B> Write for ReadOnlySlice<B> {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("the database is read-only"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B: AsRef<[u8]>> Backend for ReadOnlySlice<B> {
+    fn truncate(&mut self, _size: u64) -> Result<()> {
+        Err(anyhow!("the database is read-only"))
+    }
+
+    fn init_max_size(&self) -> u64 {
+        self.cursor.get_ref().as_ref().len() as u64
+    }
+
+    fn init_page_size(&self) -> u16 {
+        4096
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
 impl Backend for std::fs::File {
     fn truncate(&mut self, size: u64) -> Result<()> {
         self.set_len(size)?;
@@ -53,4 +130,85 @@ impl Backend for std::fs::File {
     fn sync_data(&self) -> Result<()> {
         Ok(std::fs::File::sync_data(self)?)
     }
+
+    /// With the `hole-punching` feature on Linux this punches a hole over the region so the
+    /// filesystem can give back the blocks behind it even though the file can't be truncated
+    /// because there is live data after it. The file stays the same length and the hole reads as
+    /// zeros. Nothing is done on filesystems that can't punch holes.
+    #[cfg(all(feature = "hole-punching", target_os = "linux"))]
+    fn discard(&mut self, position: u64, len: u64) -> Result<()> {
+        use rustix::{
+            fs::{fallocate, FallocateFlags},
+            io::Errno,
+        };
+        match fallocate(
+            &*self,
+            FallocateFlags::PUNCH_HOLE | FallocateFlags::KEEP_SIZE,
+            position,
+            len,
+        ) {
+            Ok(()) | Err(Errno::OPNOTSUPP) | Err(Errno::NOSYS) => Ok(()),
+            Err(e) => Err(io::Error::from(e).into()),
+        }
+    }
+
+    /// Also releases the lock taken by [`LlsDb::open`](crate::LlsDb::open)
+    fn close(&mut self) -> Result<()> {
+        self.flush()?;
+        std::fs::File::sync_data(self)?;
+        self.unlock()?;
+        Ok(())
+    }
+}
+
+impl<B: Backend + ?Sized> Backend for Box<B> {
+    fn truncate(&mut self, size: u64) -> Result<()> {
+        (**self).truncate(size)
+    }
+
+    fn init_max_size(&self) -> u64 {
+        (**self).init_max_size()
+    }
+
+    fn init_page_size(&self) -> u16 {
+        (**self).init_page_size()
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        (**self).sync_data()
+    }
+
+    fn discard(&mut self, position: u64, len: u64) -> Result<()> {
+        (**self).discard(position, len)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        (**self).close()
+    }
+}
+
+impl<B: Backend + ?Sized> Backend for &mut B {
+    fn truncate(&mut self, size: u64) -> Result<()> {
+        (**self).truncate(size)
+    }
+
+    fn init_max_size(&self) -> u64 {
+        (**self).init_max_size()
+    }
+
+    fn init_page_size(&self) -> u16 {
+        (**self).init_page_size()
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        (**self).sync_data()
+    }
+
+    fn discard(&mut self, position: u64, len: u64) -> Result<()> {
+        (**self).discard(position, len)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        (**self).close()
+    }
 }
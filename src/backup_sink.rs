@@ -0,0 +1,87 @@
+use crate::{Backend, BackupHeader, LlsDb};
+use anyhow::Result;
+use std::io::{self, Write};
+
+/// Somewhere to stream a backup to a chunk at a time (see [`LlsDb::backup_to`]). Handy when the
+/// backup is going over the network to something that takes uploads of a limited size.
+pub trait BackupSink {
+    /// The most bytes handed to [`put_chunk`](Self::put_chunk) at once. Every chunk but the last
+    /// is exactly this long.
+    fn chunk_size(&self) -> usize;
+    /// Store the chunk of the backup at `index`. Chunks are put in order starting from zero.
+    fn put_chunk(&mut self, index: u64, chunk: &[u8]) -> Result<()>;
+    /// Called once every chunk has been put. The backup isn't complete until this succeeds.
+    fn finish(&mut self, header: &BackupHeader, n_chunks: u64) -> Result<()>;
+}
+
+impl<S: BackupSink + ?Sized> BackupSink for &mut S {
+    fn chunk_size(&self) -> usize {
+        (**self).chunk_size()
+    }
+
+    fn put_chunk(&mut self, index: u64, chunk: &[u8]) -> Result<()> {
+        (**self).put_chunk(index, chunk)
+    }
+
+    fn finish(&mut self, header: &BackupHeader, n_chunks: u64) -> Result<()> {
+        (**self).finish(header, n_chunks)
+    }
+}
+
+impl<F: Backend> LlsDb<F> {
+    /// Like [`backup_since`](Self::backup_since) (or [`backup`](Self::backup) if `since` is
+    /// `None`) but streams the backup to `sink` in chunks rather than to a writer. Only one chunk
+    /// is held in memory at a time. The chunks joined together are the same as what
+    /// [`backup_since`](Self::backup_since) writes so they can be read back with
+    /// [`apply_backup`](Self::apply_backup).
+    pub fn backup_to(
+        &mut self,
+        since: Option<u64>,
+        mut sink: impl BackupSink,
+    ) -> Result<BackupHeader> {
+        let mut chunks = Chunks {
+            buf: Vec::with_capacity(sink.chunk_size()),
+            sink: &mut sink,
+            n_chunks: 0,
+        };
+        let header = self.write_backup(since, &mut chunks)?;
+        chunks.put()?;
+        let n_chunks = chunks.n_chunks;
+        sink.finish(&header, n_chunks)?;
+        Ok(header)
+    }
+}
+
+/// Cuts what's written to it into chunks for a [`BackupSink`]
+struct Chunks<'a, S> {
+    sink: &'a mut S,
+    buf: Vec<u8>,
+    n_chunks: u64,
+}
+
+impl<S: BackupSink> Chunks<'_, S> {
+    fn put(&mut self) -> Result<()> {
+        if !self.buf.is_empty() {
+            self.sink.put_chunk(self.n_chunks, &self.buf)?;
+            self.n_chunks += 1;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<S: BackupSink> Write for Chunks<'_, S> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let chunk_size = self.sink.chunk_size().max(1);
+        let n = bytes.len().min(chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&bytes[..n]);
+        if self.buf.len() == chunk_size {
+            self.put().map_err(io::Error::other)?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    index::{ChangeSetLog, Merge},
+    Backend, IndexHandle, LlsDb,
+};
+use anyhow::Result;
+use bdk_wallet::{ChangeSet, WalletPersister};
+use bincode::{
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    serde::Compat,
+    Decode, Encode,
+};
+
+/// A bdk wallet [`ChangeSet`] as it's stored in a [`ChangeSetLog`].
+///
+/// bdk's changesets only implement serde's traits so they are encoded through bincode's serde
+/// support. Merging is bdk's own [`Merge`](bdk_chain::Merge).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WalletChangeSet(pub ChangeSet);
+
+impl Encode for WalletChangeSet {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> core::result::Result<(), EncodeError> {
+        Compat(&self.0).encode(encoder)
+    }
+}
+
+impl Decode for WalletChangeSet {
+    fn decode<D: Decoder>(decoder: &mut D) -> core::result::Result<Self, DecodeError> {
+        Ok(Self(Compat::decode(decoder)?.0))
+    }
+}
+
+impl Merge for WalletChangeSet {
+    fn merge(&mut self, other: Self) {
+        bdk_chain::Merge::merge(&mut self.0, other.0)
+    }
+
+    fn is_empty(&self) -> bool {
+        bdk_chain::Merge::is_empty(&self.0)
+    }
+}
+
+/// Persists a bdk wallet to a list in a [`LlsDb`] by implementing bdk's [`WalletPersister`].
+///
+/// Each call to `persist` appends the wallet's changeset to a [`ChangeSetLog`] in its own
+/// transaction and `initialize` aggregates them. Pass it to bdk's `create_wallet` and
+/// `load_wallet` like any other persister. Use [`compact`](Self::compact) now and then so loading
+/// doesn't have to merge every changeset the wallet has ever written.
+pub struct WalletStore<'db, F: Backend> {
+    db: &'db mut LlsDb<F>,
+    log: IndexHandle<ChangeSetLog<WalletChangeSet>>,
+}
+
+impl<'db, F: Backend> WalletStore<'db, F> {
+    /// Persist the wallet to the list called `list_name`
+    pub fn new(db: &'db mut LlsDb<F>, list_name: &str) -> Result<Self> {
+        let log = db.execute(|tx| {
+            let list = tx.take_list(list_name)?;
+            Ok(tx.store_index(ChangeSetLog::new(list)))
+        })?;
+        Ok(Self { db, log })
+    }
+
+    /// Replace the changesets with their aggregate (see [`ChangeSetLogApi::compact`])
+    ///
+    /// [`ChangeSetLogApi::compact`]: crate::index::ChangeSetLogApi::compact
+    pub fn compact(&mut self) -> Result<()> {
+        let log = self.log;
+        self.db.execute(|tx| {
+            tx.take_index(log).compact()?;
+            Ok(())
+        })
+    }
+
+    pub fn db(&mut self) -> &mut LlsDb<F> {
+        self.db
+    }
+}
+
+impl<F: Backend> WalletPersister for WalletStore<'_, F> {
+    type Error = anyhow::Error;
+
+    fn initialize(persister: &mut Self) -> Result<ChangeSet> {
+        let log = persister.log;
+        persister
+            .db
+            .execute(|tx| Ok(tx.take_index(log).aggregate()?.0))
+    }
+
+    fn persist(persister: &mut Self, changeset: &ChangeSet) -> Result<()> {
+        let log = persister.log;
+        persister.db.execute(|tx| {
+            tx.take_index(log)
+                .append(&WalletChangeSet(changeset.clone()))?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+/// Where the database gets the current time from.
+///
+/// Anything that depends on time (timestamps, expiry, metrics) asks the installed clock rather than
+/// calling [`SystemTime::now`] directly so tests and simulations can control it. Install with
+/// [`LlsDb::set_clock`](crate::LlsDb::set_clock). The default is [`SystemClock`].
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+    /// Wait for `duration` to pass (see [`ThrottledBackend`](crate::ThrottledBackend))
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// The system's wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when it's told to.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Moves the clock forward rather than waiting
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
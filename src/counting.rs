@@ -0,0 +1,123 @@
+use crate::Backend;
+use anyhow::Result;
+use std::{
+    cell::Cell,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+/// A [`Backend`] that counts the IO done to the backend it wraps. Get the counts with
+/// [`LlsDb::backend`](crate::LlsDb::backend) and [`CountingBackend::stats`] to see how an access
+/// pattern hits storage.
+#[derive(Debug, Default)]
+pub struct CountingBackend<B> {
+    inner: B,
+    stats: Cell<IoStats>,
+}
+
+/// What a [`CountingBackend`] has counted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoStats {
+    pub seeks: u64,
+    /// Calls to `read`. A read of an entry can take more than one.
+    pub reads: u64,
+    pub bytes_read: u64,
+    /// Calls to `write`
+    pub writes: u64,
+    pub bytes_written: u64,
+    pub truncates: u64,
+    pub syncs: u64,
+}
+
+impl<B> CountingBackend<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            stats: Default::default(),
+        }
+    }
+
+    /// What has been counted since the backend was created or the counts were last reset
+    pub fn stats(&self) -> IoStats {
+        self.stats.get()
+    }
+
+    /// Return the counts and start again from zero
+    pub fn reset_stats(&self) -> IoStats {
+        self.stats.take()
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn count(&self, f: impl FnOnce(&mut IoStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+}
+
+impl<B: Read> Read for CountingBackend<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count(|stats| {
+            stats.reads += 1;
+            stats.bytes_read += n as u64;
+        });
+        Ok(n)
+    }
+}
+
+impl<B: Write> Write for CountingBackend<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count(|stats| {
+            stats.writes += 1;
+            stats.bytes_written += n as u64;
+        });
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<B: Seek> Seek for CountingBackend<B> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.count(|stats| stats.seeks += 1);
+        self.inner.seek(pos)
+    }
+}
+
+impl<B: Backend> Backend for CountingBackend<B> {
+    fn truncate(&mut self, size: u64) -> Result<()> {
+        self.count(|stats| stats.truncates += 1);
+        self.inner.truncate(size)
+    }
+
+    fn init_max_size(&self) -> u64 {
+        self.inner.init_max_size()
+    }
+
+    fn init_page_size(&self) -> u16 {
+        self.inner.init_page_size()
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.count(|stats| stats.syncs += 1);
+        self.inner.sync_data()
+    }
+
+    fn discard(&mut self, position: u64, len: u64) -> Result<()> {
+        self.inner.discard(position, len)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{EntryHandle, LinkedList, LinkedListApi, TxIo, BINCODE_CONFIG};
+use anyhow::{anyhow, Result};
+
+/// A value that can be pushed to a [`DynList`]. Its `TAG` is stored with it so it can be told
+/// apart from the other kinds of values in the list.
+///
+/// Tags are forever. Once a type has been written with a tag don't reuse the tag for something
+/// else. Adding a new type with a new tag is fine since older readers skip entries with tags they
+/// don't know.
+pub trait DynValue: bincode::Encode + bincode::Decode {
+    const TAG: u32;
+}
+
+/// An entry of a [`DynList`]: a tag saying what kind of value it is and the bytes of the value.
+/// The bytes are length prefixed on disk so an entry can be read without knowing its type.
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct DynEntry {
+    pub tag: u32,
+    pub bytes: Vec<u8>,
+}
+
+impl DynEntry {
+    pub fn new<T: DynValue>(value: &T) -> Result<Self> {
+        Ok(Self {
+            tag: T::TAG,
+            bytes: bincode::encode_to_vec(value, BINCODE_CONFIG)?,
+        })
+    }
+
+    /// Decode the value if the entry holds a `T` or return `None` if it holds something else
+    pub fn decode<T: DynValue>(&self) -> Result<Option<T>> {
+        if self.tag != T::TAG {
+            return Ok(None);
+        }
+        let (value, len) = bincode::decode_from_slice(&self.bytes, BINCODE_CONFIG)?;
+        if len != self.bytes.len() {
+            return Err(anyhow!(
+                "entry with tag {} has {} trailing bytes",
+                self.tag,
+                self.bytes.len() - len
+            ));
+        }
+        Ok(Some(value))
+    }
+}
+
+/// A list whose entries can be values of different types (see [`DynValue`]).
+///
+/// Useful for logs of events where new kinds of events appear over time. Each entry says what it
+/// is so readers can pick out the ones they understand and skip the rest.
+#[derive(Debug, Clone)]
+pub struct DynList {
+    list: LinkedList<DynEntry>,
+}
+
+impl DynList {
+    pub fn new(list: LinkedList<DynEntry>) -> Self {
+        Self { list }
+    }
+
+    pub fn list(&self) -> &LinkedList<DynEntry> {
+        &self.list
+    }
+
+    pub fn api<'a, 'tx: 'a, F>(&'a self, io: impl AsRef<TxIo<'tx, F>>) -> DynListApi<'a, F> {
+        DynListApi {
+            list: self.list.api(io),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DynListApi<'i, F> {
+    list: LinkedListApi<'i, F, DynEntry>,
+}
+
+impl<'i, F: crate::Backend> DynListApi<'i, F> {
+    pub fn push<T: DynValue>(&self, value: &T) -> Result<EntryHandle> {
+        self.list.push(&DynEntry::new(value)?)
+    }
+
+    /// Push an entry as is e.g. one copied from another [`DynList`]
+    pub fn push_entry(&self, entry: &DynEntry) -> Result<EntryHandle> {
+        self.list.push(entry)
+    }
+
+    pub fn head(&self) -> Result<Option<DynEntry>> {
+        self.list.head()
+    }
+
+    pub fn pop(&self) -> Result<Option<DynEntry>> {
+        self.list.pop()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Every entry from newest to oldest whatever its type
+    pub fn iter(&self) -> impl Iterator<Item = Result<DynEntry>> + '_ {
+        self.list.iter()
+    }
+
+    /// The `T`s in the list from newest to oldest skipping entries of other types
+    pub fn iter_of<T: DynValue>(&self) -> impl Iterator<Item = Result<T>> + '_ {
+        self.iter()
+            .filter_map(|entry| entry.and_then(|entry| entry.decode()).transpose())
+    }
+}
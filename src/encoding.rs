@@ -0,0 +1,141 @@
+use bincode::{
+    config,
+    enc::write::Writer,
+    error::{DecodeError, EncodeError},
+};
+use std::io::{Read, Write};
+
+/// How values are encoded with bincode. Chosen when the database is initialized (see
+/// [`InitOptions::value_encoding`](crate::InitOptions::value_encoding)).
+///
+/// This only applies to the values in lists. The pointers linking entries together are always
+/// varints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValueEncoding {
+    pub int_encoding: IntEncoding,
+    pub endian: Endian,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntEncoding {
+    /// Small integers take up less space
+    #[default]
+    Varint,
+    /// Integers always take up their full width so values with only integers in them always
+    /// have the same encoded length
+    Fixint,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+/// Runs `$body` with `$config` bound to the bincode config matching `$encoding`
+macro_rules! with_config {
+    ($encoding:expr, |$config:ident| $body:expr) => {
+        match ($encoding.int_encoding, $encoding.endian) {
+            (IntEncoding::Varint, Endian::Little) => {
+                let $config = config::standard();
+                $body
+            }
+            (IntEncoding::Varint, Endian::Big) => {
+                let $config = config::standard().with_big_endian();
+                $body
+            }
+            (IntEncoding::Fixint, Endian::Little) => {
+                let $config = config::standard().with_fixed_int_encoding();
+                $body
+            }
+            (IntEncoding::Fixint, Endian::Big) => {
+                let $config = config::standard()
+                    .with_fixed_int_encoding()
+                    .with_big_endian();
+                $body
+            }
+        }
+    };
+}
+
+impl ValueEncoding {
+    /// How the encoding is recorded in the first page
+    pub(crate) fn to_byte(self) -> u8 {
+        let int_encoding = match self.int_encoding {
+            IntEncoding::Varint => 0,
+            IntEncoding::Fixint => 1,
+        };
+        let endian = match self.endian {
+            Endian::Little => 0,
+            Endian::Big => 1,
+        };
+        int_encoding | endian << 1
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Option<Self> {
+        if byte >> 2 != 0 {
+            return None;
+        }
+        Some(Self {
+            int_encoding: match byte & 1 {
+                0 => IntEncoding::Varint,
+                _ => IntEncoding::Fixint,
+            },
+            endian: match byte >> 1 {
+                0 => Endian::Little,
+                _ => Endian::Big,
+            },
+        })
+    }
+
+    pub(crate) fn encode_into_std_write<E: bincode::Encode, W: Write>(
+        self,
+        value: E,
+        writer: &mut W,
+    ) -> Result<usize, EncodeError> {
+        with_config!(self, |config| bincode::encode_into_std_write(
+            value, writer, config
+        ))
+    }
+
+    pub(crate) fn encode_into_writer<E: bincode::Encode, W: Writer>(
+        self,
+        value: E,
+        writer: W,
+    ) -> Result<(), EncodeError> {
+        with_config!(self, |config| bincode::encode_into_writer(
+            value, writer, config
+        ))
+    }
+
+    pub(crate) fn decode_from_std_read<D: bincode::Decode, R: Read>(
+        self,
+        reader: &mut R,
+    ) -> Result<D, DecodeError> {
+        with_config!(self, |config| bincode::decode_from_std_read(reader, config))
+    }
+}
+
+/// Something that can be encoded as one segment of an entry written with
+/// [`TxIo::push_segments`](crate::TxIo::push_segments). Implemented for everything that is
+/// [`bincode::Encode`]. Unlike `Encode` it can be used as a trait object so segments of
+/// different types can be passed together.
+pub trait EncodeSegment {
+    /// Encode `self` onto the end of `buf` returning the number of bytes written
+    fn encode_segment(
+        &self,
+        encoding: ValueEncoding,
+        buf: &mut Vec<u8>,
+    ) -> Result<usize, EncodeError>;
+}
+
+impl<T: bincode::Encode> EncodeSegment for T {
+    fn encode_segment(
+        &self,
+        encoding: ValueEncoding,
+        buf: &mut Vec<u8>,
+    ) -> Result<usize, EncodeError> {
+        encoding.encode_into_std_write(self, buf)
+    }
+}
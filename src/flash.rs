@@ -0,0 +1,491 @@
+use crate::Backend;
+use anyhow::{anyhow, Result};
+use core::fmt::Debug;
+use embedded_storage::nor_flash::NorFlash;
+use std::{
+    cell::RefCell,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+const ERASED: u8 = 0xff;
+/// Each copy of the first page is followed by a sequence number and its complement
+const STAMP_LEN: usize = 16;
+/// A staging marker is the address of the erase block being rewritten and its complement
+const MARKER_LEN: usize = 8;
+/// The erase blocks after the header blocks for staging erase blocks that are being rewritten
+const N_STAGING_BLOCKS: usize = 2;
+
+/// A [`Backend`] for raw NOR flash through [`embedded_storage`].
+///
+/// Flash can only be written to once after it has been erased and can only be erased a whole
+/// erase block at a time. Writes that land on erased words are programmed in place. Otherwise the
+/// erase block has to be erased and written again with the new data. Since the rest of the block
+/// can hold committed entries the new contents are first staged in a spare erase block and a
+/// marker saying where they go is written. If power is lost before the block has been written
+/// again it is finished off from the staged copy when the flash is opened. The spare block is
+/// erased every time this happens so avoid writing over space that's been written before where
+/// you can.
+///
+/// The first page is rewritten on every commit so it doesn't live at a fixed address. Instead each
+/// new version is appended to a log spread over the first `n_header_blocks` erase blocks along
+/// with a sequence number and the newest complete one is used when the flash is opened. This
+/// spreads the wear from commits over all the header blocks and means a commit never erases the
+/// last good copy of the first page. The rest of the flash is used for entries. To spread the
+/// wear from those too use [`AllocationPolicy::NextFit`](crate::AllocationPolicy::NextFit).
+///
+/// The two erase blocks after the header blocks are used for staging and the rest of the flash is
+/// for entries.
+///
+/// The backend never shrinks so [`Backend::truncate`] does nothing.
+pub struct FlashBackend<S> {
+    inner: RefCell<Inner<S>>,
+}
+
+struct Inner<S> {
+    flash: S,
+    page_size: usize,
+    n_header_blocks: usize,
+    /// The latest version of the first page
+    header: Vec<u8>,
+    /// Whether there is a first page at all
+    has_header: bool,
+    header_dirty: bool,
+    /// The sequence number of the latest version of the first page
+    seq: u64,
+    /// The record slot the next version of the first page goes in
+    next_record: usize,
+    /// The slot in the marker block the next staging marker goes in
+    next_marker: usize,
+    position: u64,
+    block_buf: Vec<u8>,
+    merge_buf: Vec<u8>,
+}
+
+impl<S: NorFlash> FlashBackend<S> {
+    /// Open a flash backend. `page_size` is the size of the first page and a copy of it (plus 8
+    /// bytes) has to fit in an erase block. `n_header_blocks` is the number of erase blocks to
+    /// store copies of the first page in and must be at least 2.
+    ///
+    /// The same `page_size` and `n_header_blocks` have to be used each time.
+    pub fn new(mut flash: S, page_size: u16, n_header_blocks: usize) -> Result<Self> {
+        let page_size = page_size as usize;
+        if S::ERASE_SIZE % S::WRITE_SIZE != 0 || S::ERASE_SIZE % S::READ_SIZE != 0 {
+            return Err(anyhow!(
+                "erase size must be a multiple of write and read size"
+            ));
+        }
+        if n_header_blocks < 2 {
+            return Err(anyhow!("need at least two header blocks"));
+        }
+        if record_len::<S>(page_size) > S::ERASE_SIZE {
+            return Err(anyhow!(
+                "a page of size {} doesn't fit in an erase block of size {}",
+                page_size,
+                S::ERASE_SIZE
+            ));
+        }
+        if flash.capacity() <= (n_header_blocks + N_STAGING_BLOCKS) * S::ERASE_SIZE {
+            return Err(anyhow!(
+                "flash isn't big enough for the header and staging blocks"
+            ));
+        }
+
+        let records_per_block = S::ERASE_SIZE / record_len::<S>(page_size);
+        let n_records = records_per_block * n_header_blocks;
+        let mut stamp = vec![0u8; round_up(STAMP_LEN, S::WRITE_SIZE)];
+        let mut latest = None;
+        for record in 0..n_records {
+            let addr = record_addr::<S>(page_size, record) + round_up(page_size, S::WRITE_SIZE);
+            read_aligned(&mut flash, addr, &mut stamp)?;
+            let seq = u64::from_le_bytes(stamp[..8].try_into().unwrap());
+            let check = u64::from_le_bytes(stamp[8..16].try_into().unwrap());
+            if seq == !check
+                && latest
+                    .map(|(_, latest_seq)| seq > latest_seq)
+                    .unwrap_or(true)
+            {
+                latest = Some((record, seq));
+            }
+        }
+
+        let mut header = vec![ERASED; page_size];
+        let (has_header, seq, next_record) = match latest {
+            Some((record, seq)) => {
+                read_aligned(&mut flash, record_addr::<S>(page_size, record), &mut header)?;
+                let mut next_record = (record + 1) % n_records;
+                // a torn write might have left the next slot dirty so skip to the next block
+                let mut next = vec![0u8; record_len::<S>(page_size)];
+                read_aligned(
+                    &mut flash,
+                    record_addr::<S>(page_size, next_record),
+                    &mut next,
+                )?;
+                if next.iter().any(|byte| *byte != ERASED) {
+                    next_record = round_up(next_record, records_per_block) % n_records;
+                }
+                (true, seq, next_record)
+            }
+            None => (false, 0, 0),
+        };
+
+        let mut inner = Inner {
+            flash,
+            page_size,
+            n_header_blocks,
+            header,
+            has_header,
+            header_dirty: false,
+            seq,
+            next_record,
+            next_marker: 0,
+            position: 0,
+            block_buf: vec![0u8; S::ERASE_SIZE],
+            merge_buf: vec![0u8; S::ERASE_SIZE],
+        };
+        inner.recover_staged()?;
+        Ok(Self {
+            inner: RefCell::new(inner),
+        })
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner.into_inner().flash
+    }
+}
+
+impl<S: NorFlash> Inner<S> {
+    /// The header and staging blocks
+    fn reserved_area(&self) -> usize {
+        (self.n_header_blocks + N_STAGING_BLOCKS) * S::ERASE_SIZE
+    }
+
+    /// Where the contents of an erase block being rewritten are staged
+    fn staging_addr(&self) -> usize {
+        self.n_header_blocks * S::ERASE_SIZE
+    }
+
+    /// Where the markers for staged erase blocks are
+    fn marker_addr(&self, marker: usize) -> usize {
+        (self.n_header_blocks + 1) * S::ERASE_SIZE + marker * marker_len::<S>()
+    }
+
+    fn capacity(&self) -> u64 {
+        (self.page_size + self.flash.capacity() - self.reserved_area()) as u64
+    }
+
+    fn len(&self) -> u64 {
+        if self.has_header {
+            self.capacity()
+        } else {
+            0
+        }
+    }
+
+    fn data_addr(&self, position: u64) -> usize {
+        position as usize - self.page_size + self.reserved_area()
+    }
+
+    fn records_per_block(&self) -> usize {
+        S::ERASE_SIZE / record_len::<S>(self.page_size)
+    }
+
+    /// Append the first page to the header log
+    fn write_header(&mut self) -> io::Result<()> {
+        let record = self.next_record;
+        let records_per_block = self.records_per_block();
+        let addr = record_addr::<S>(self.page_size, record);
+        if record.is_multiple_of(records_per_block) {
+            self.flash
+                .erase(addr as u32, (addr + S::ERASE_SIZE) as u32)
+                .map_err(flash_error)?;
+        }
+        let mut buf = vec![ERASED; record_len::<S>(self.page_size)];
+        buf[..self.page_size].copy_from_slice(&self.header);
+        self.flash
+            .write(addr as u32, &buf[..round_up(self.page_size, S::WRITE_SIZE)])
+            .map_err(flash_error)?;
+
+        // the stamp is written after the page so a torn write leaves an invalid stamp
+        let seq = self.seq + 1;
+        let stamp_start = round_up(self.page_size, S::WRITE_SIZE);
+        let stamp = &mut buf[stamp_start..];
+        stamp[..8].copy_from_slice(&seq.to_le_bytes());
+        stamp[8..16].copy_from_slice(&(!seq).to_le_bytes());
+        self.flash
+            .write((addr + stamp_start) as u32, stamp)
+            .map_err(flash_error)?;
+
+        self.seq = seq;
+        self.next_record = (record + 1) % (records_per_block * self.n_header_blocks);
+        self.header_dirty = false;
+        Ok(())
+    }
+
+    /// Write to the part of the flash after the header blocks a block at a time
+    fn write_data(&mut self, mut addr: usize, mut bytes: &[u8]) -> io::Result<()> {
+        while !bytes.is_empty() {
+            let block_start = addr - addr % S::ERASE_SIZE;
+            let offset = addr - block_start;
+            let n = (S::ERASE_SIZE - offset).min(bytes.len());
+            read_aligned(&mut self.flash, block_start, &mut self.block_buf)?;
+            self.merge_buf.copy_from_slice(&self.block_buf);
+            self.merge_buf[offset..offset + n].copy_from_slice(&bytes[..n]);
+
+            let needs_erase = self
+                .block_buf
+                .chunks(S::WRITE_SIZE)
+                .zip(self.merge_buf.chunks(S::WRITE_SIZE))
+                .any(|(old, new)| old != new && old.iter().any(|byte| *byte != ERASED));
+            if needs_erase {
+                let marker = self.stage(block_start)?;
+                self.rewrite_block(block_start, marker)?;
+            } else {
+                program(
+                    &mut self.flash,
+                    block_start,
+                    &self.block_buf,
+                    &self.merge_buf,
+                )?;
+            }
+
+            addr += n;
+            bytes = &bytes[n..];
+        }
+        Ok(())
+    }
+}
+
+impl<S: NorFlash> Inner<S> {
+    /// Copy `merge_buf` to the staging block and write a marker saying it belongs at
+    /// `block_start` returning the marker's slot
+    fn stage(&mut self, block_start: usize) -> io::Result<usize> {
+        let staging = self.staging_addr();
+        self.flash
+            .erase(staging as u32, (staging + S::ERASE_SIZE) as u32)
+            .map_err(flash_error)?;
+        self.block_buf.fill(ERASED);
+        program(&mut self.flash, staging, &self.block_buf, &self.merge_buf)?;
+
+        // every marker before this one is done so the marker block can be erased when it's full
+        if self.next_marker == S::ERASE_SIZE / marker_len::<S>() {
+            let marker_block = self.marker_addr(0);
+            self.flash
+                .erase(marker_block as u32, (marker_block + S::ERASE_SIZE) as u32)
+                .map_err(flash_error)?;
+            self.next_marker = 0;
+        }
+        let marker = self.next_marker;
+        let mut buf = vec![ERASED; round_up(MARKER_LEN, S::WRITE_SIZE)];
+        buf[..4].copy_from_slice(&(block_start as u32).to_le_bytes());
+        buf[4..8].copy_from_slice(&(!(block_start as u32)).to_le_bytes());
+        let marker_addr = self.marker_addr(marker);
+        self.flash
+            .write(marker_addr as u32, &buf)
+            .map_err(flash_error)?;
+        self.next_marker += 1;
+        Ok(marker)
+    }
+
+    /// Erase the block at `block_start` and program `merge_buf` into it then mark the staging
+    /// `marker` as done
+    fn rewrite_block(&mut self, block_start: usize, marker: usize) -> io::Result<()> {
+        self.flash
+            .erase(block_start as u32, (block_start + S::ERASE_SIZE) as u32)
+            .map_err(flash_error)?;
+        self.block_buf.fill(ERASED);
+        program(
+            &mut self.flash,
+            block_start,
+            &self.block_buf,
+            &self.merge_buf,
+        )?;
+        let done = vec![0u8; S::WRITE_SIZE];
+        let done_addr = self.marker_addr(marker) + round_up(MARKER_LEN, S::WRITE_SIZE);
+        self.flash
+            .write(done_addr as u32, &done)
+            .map_err(flash_error)
+    }
+
+    /// Finish rewriting an erase block from the staging block if power was lost part way through
+    fn recover_staged(&mut self) -> io::Result<()> {
+        let mut marker_buf = vec![0u8; marker_len::<S>()];
+        let mut pending = None;
+        for marker in 0..S::ERASE_SIZE / marker_len::<S>() {
+            let marker_addr = self.marker_addr(marker);
+            read_aligned(&mut self.flash, marker_addr, &mut marker_buf)?;
+            if marker_buf.iter().all(|byte| *byte == ERASED) {
+                break;
+            }
+            self.next_marker = marker + 1;
+            let block_start = u32::from_le_bytes(marker_buf[..4].try_into().unwrap());
+            let check = u32::from_le_bytes(marker_buf[4..8].try_into().unwrap());
+            let done_start = round_up(MARKER_LEN, S::WRITE_SIZE);
+            let done = marker_buf[done_start..].iter().any(|byte| *byte != ERASED);
+            // a torn marker means the block was never erased
+            pending = (block_start == !check && !done).then_some((marker, block_start as usize));
+        }
+        if let Some((marker, block_start)) = pending {
+            if block_start < self.reserved_area()
+                || block_start >= self.flash.capacity()
+                || block_start % S::ERASE_SIZE != 0
+            {
+                return Err(io::Error::other(format!(
+                    "staging marker points to {} which isn't a data block",
+                    block_start
+                )));
+            }
+            let staging = self.staging_addr();
+            read_aligned(&mut self.flash, staging, &mut self.merge_buf)?;
+            self.rewrite_block(block_start, marker)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: NorFlash> Read for FlashBackend<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inner = self.inner.get_mut();
+        let position = inner.position;
+        let remaining = inner.len().saturating_sub(position);
+        let mut n = (buf.len() as u64).min(remaining) as usize;
+        if position < inner.page_size as u64 {
+            let start = position as usize;
+            n = n.min(inner.page_size - start);
+            buf[..n].copy_from_slice(&inner.header[start..start + n]);
+        } else {
+            let addr = inner.data_addr(position);
+            read_aligned(&mut inner.flash, addr, &mut buf[..n])?;
+        }
+        inner.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<S: NorFlash> Write for FlashBackend<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let inner = self.inner.get_mut();
+        let position = inner.position;
+        let remaining = inner.capacity().saturating_sub(position);
+        let mut n = (buf.len() as u64).min(remaining) as usize;
+        if position < inner.page_size as u64 {
+            let start = position as usize;
+            n = n.min(inner.page_size - start);
+            inner.header[start..start + n].copy_from_slice(&buf[..n]);
+            inner.has_header = true;
+            inner.header_dirty = true;
+        } else {
+            let addr = inner.data_addr(position);
+            inner.write_data(addr, &buf[..n])?;
+        }
+        inner.position += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: NorFlash> Seek for FlashBackend<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let inner = self.inner.get_mut();
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(offset) => inner.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => inner.position.checked_add_signed(offset),
+        };
+        inner.position = position.ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "seek to a negative position",
+        ))?;
+        Ok(inner.position)
+    }
+}
+
+impl<S: NorFlash> Backend for FlashBackend<S> {
+    fn truncate(&mut self, _size: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn init_max_size(&self) -> u64 {
+        self.inner.borrow().capacity()
+    }
+
+    fn init_page_size(&self) -> u16 {
+        self.inner.borrow().page_size as u16
+    }
+
+    /// Writes the first page to the header log if it has changed
+    fn sync_data(&self) -> Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        if inner.header_dirty {
+            inner.write_header()?;
+        }
+        Ok(())
+    }
+}
+
+fn record_len<S: NorFlash>(page_size: usize) -> usize {
+    round_up(page_size, S::WRITE_SIZE) + round_up(STAMP_LEN, S::WRITE_SIZE)
+}
+
+fn marker_len<S: NorFlash>() -> usize {
+    round_up(MARKER_LEN, S::WRITE_SIZE) + S::WRITE_SIZE
+}
+
+/// Program each run of words in `new` that's different from `old` into the flash at `addr`. The
+/// words that are different must be erased.
+fn program<S: NorFlash>(flash: &mut S, addr: usize, old: &[u8], new: &[u8]) -> io::Result<()> {
+    let mut word = 0;
+    let n_words = new.len() / S::WRITE_SIZE;
+    let changed = |word: usize| {
+        let range = word * S::WRITE_SIZE..(word + 1) * S::WRITE_SIZE;
+        old[range.clone()] != new[range]
+    };
+    while word < n_words {
+        if !changed(word) {
+            word += 1;
+            continue;
+        }
+        let run_start = word;
+        while word < n_words && changed(word) {
+            word += 1;
+        }
+        let range = run_start * S::WRITE_SIZE..word * S::WRITE_SIZE;
+        flash
+            .write((addr + range.start) as u32, &new[range])
+            .map_err(flash_error)?;
+    }
+    Ok(())
+}
+
+fn record_addr<S: NorFlash>(page_size: usize, record: usize) -> usize {
+    let records_per_block = S::ERASE_SIZE / record_len::<S>(page_size);
+    (record / records_per_block) * S::ERASE_SIZE
+        + (record % records_per_block) * record_len::<S>(page_size)
+}
+
+fn round_up(value: usize, multiple: usize) -> usize {
+    value.div_ceil(multiple) * multiple
+}
+
+/// Read from anywhere in the flash even if it's not aligned to the flash's read size
+fn read_aligned<S: NorFlash>(flash: &mut S, addr: usize, buf: &mut [u8]) -> io::Result<()> {
+    let start = addr - addr % S::READ_SIZE;
+    let end = round_up(addr + buf.len(), S::READ_SIZE);
+    if start == addr && end == addr + buf.len() {
+        flash.read(addr as u32, buf).map_err(flash_error)?;
+    } else {
+        let mut aligned = vec![0u8; end - start];
+        flash
+            .read(start as u32, &mut aligned)
+            .map_err(flash_error)?;
+        let offset = addr - start;
+        buf.copy_from_slice(&aligned[offset..offset + buf.len()]);
+    }
+    Ok(())
+}
+
+fn flash_error(e: impl Debug) -> io::Error {
+    io::Error::other(format!("flash error: {:?}", e))
+}
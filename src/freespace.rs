@@ -1,3 +1,7 @@
+use crate::{
+    pointer::{read_le_uint, write_le_uint},
+    AllocationPolicy,
+};
 use core::mem::size_of;
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -6,10 +10,23 @@ type Pointer = u64;
 #[derive(Clone, Debug, PartialEq)]
 pub struct FreeSpace {
     end_to_start: BTreeMap<Pointer, Pointer>,
-    sizes: BTreeSet<Free>,
+    /// The free spaces binned by size class (see [`size_class`]) and ordered by size within each
+    /// bin so finding the smallest space that fits is a range query. Ordering a bin by address
+    /// instead would mean scanning all of it for the best fit. Spaces of the same size are still
+    /// ordered by address so the lowest one is used first. Empty bins are removed.
+    bins: BTreeMap<u32, BTreeSet<Free>>,
     tx_changes: Vec<Change>,
     pending_frees: Vec<Free>,
     persist: PersistFreeSpace,
+    policy: AllocationPolicy,
+    next_fit_cursor: Pointer,
+    /// Regions pinned by [`crate::TxIo::pin`] as `(start, end)` with how many times each is pinned
+    pins: BTreeMap<(Pointer, Pointer), usize>,
+    /// Frees that overlapped a pin when they were applied. They are applied once it's unpinned.
+    held: Vec<Free>,
+    /// The regions holding live data as `start -> end` if allocations are being checked against
+    /// them (see [`check_allocations`](Self::check_allocations))
+    live: Option<BTreeMap<Pointer, Pointer>>,
 }
 
 #[derive(Debug, Clone, Copy, bincode::Encode, bincode::Decode, PartialEq, Eq, PartialOrd, Ord)]
@@ -30,22 +47,22 @@ impl Free {
         size: 0,
         end_pointer: 0,
     };
-    pub fn write_to(&self, buf: &mut [u8]) {
-        buf[..size_of::<u64>()].copy_from_slice(self.size.to_le_bytes().as_ref());
-        buf[size_of::<u64>()..].copy_from_slice(self.end_pointer.to_le_bytes().as_ref());
+    /// Writes the free space into `buf` with the size and end pointer each taking up half of it.
+    pub fn write_to(&self, buf: &mut [u8]) -> anyhow::Result<()> {
+        let (size_buf, end_pointer_buf) = buf.split_at_mut(buf.len() / 2);
+        write_le_uint(size_buf, self.size)?;
+        write_le_uint(end_pointer_buf, self.end_pointer)
     }
 
     pub fn read_from(buf: &[u8]) -> Option<Free> {
-        assert_eq!(buf.len(), size_of::<Free>());
-        let mut size_buf = [0u8; size_of::<u64>()];
-        let mut end_pointer_buf = [0u8; size_of::<u64>()];
-        size_buf.copy_from_slice(&buf[..size_of::<u64>()]);
-        end_pointer_buf.copy_from_slice(&buf[size_of::<u64>()..]);
+        assert!(buf.len() <= size_of::<Free>() && buf.len().is_multiple_of(2));
+        let (size_buf, end_pointer_buf) = buf.split_at(buf.len() / 2);
         let free = Self {
-            size: u64::from_le_bytes(size_buf),
-            end_pointer: u64::from_le_bytes(end_pointer_buf),
+            size: read_le_uint(size_buf),
+            end_pointer: read_le_uint(end_pointer_buf),
         };
-        if free.size > free.end_pointer {
+        // a free space can't include the null pointer
+        if free != Self::NULL && free.size >= free.end_pointer {
             return None;
         }
 
@@ -57,6 +74,25 @@ impl Free {
     }
 }
 
+/// The indexes of two of `spaces` that overlap if there are any. Null spaces are ignored.
+pub fn find_overlap(spaces: &[Free]) -> Option<(usize, usize)> {
+    let mut by_start = spaces
+        .iter()
+        .enumerate()
+        .filter(|(_, space)| **space != Free::NULL)
+        .collect::<Vec<_>>();
+    by_start.sort_by_key(|(_, space)| space.start_pointer());
+    by_start
+        .windows(2)
+        .find(|pair| pair[0].1.end_pointer > pair[1].1.start_pointer())
+        .map(|pair| (pair[0].0, pair[1].0))
+}
+
+/// The number of bits needed to represent `size` so each class holds sizes in `[2^(c-1), 2^c)`
+fn size_class(size: u64) -> u32 {
+    u64::BITS - size.leading_zeros()
+}
+
 impl Default for Free {
     fn default() -> Self {
         Self::NULL
@@ -67,32 +103,68 @@ impl Default for Free {
 enum Change {
     Remove(Free),
     Add(Free),
+    Pin((Pointer, Pointer)),
+    Unpin((Pointer, Pointer)),
+    Hold(Free),
+    Release(Free),
+    Live((Pointer, Pointer)),
+    Dead((Pointer, Pointer)),
+}
+
+fn overlaps_pin(pins: &BTreeMap<(Pointer, Pointer), usize>, free: &Free) -> bool {
+    pins.range(..(free.end_pointer, 0))
+        .any(|(&(_, end), _)| end > free.start_pointer())
 }
 
 impl FreeSpace {
     pub fn new(n_persist: usize) -> Self {
         Self {
             end_to_start: Default::default(),
-            sizes: Default::default(),
+            bins: Default::default(),
             tx_changes: Default::default(),
             pending_frees: Default::default(),
             persist: PersistFreeSpace::new(n_persist),
+            policy: Default::default(),
+            next_fit_cursor: Pointer::MIN,
+            pins: Default::default(),
+            held: Default::default(),
+            live: None,
         }
     }
 
     pub fn new_from_persist_state(state: Vec<Free>) -> Self {
         let persist = PersistFreeSpace::restore(state);
-        let mut end_to_start = BTreeMap::default();
-        let mut sizes = BTreeSet::default();
-        for free in persist.state() {
-            end_to_start.insert(free.end_pointer, free.start_pointer());
-            sizes.insert(*free);
-        }
-        Self {
-            end_to_start,
-            sizes,
+        let mut free_space = Self {
             persist,
             ..Self::new(0)
+        };
+        for free in free_space.persist.state().to_vec() {
+            free_space
+                .end_to_start
+                .insert(free.end_pointer, free.start_pointer());
+            free_space.bin_insert(free);
+        }
+        free_space
+    }
+
+    fn bin_insert(&mut self, free: Free) -> bool {
+        self.bins
+            .entry(size_class(free.size))
+            .or_default()
+            .insert(free)
+    }
+
+    fn bin_remove(&mut self, free: Free) -> bool {
+        let class = size_class(free.size);
+        match self.bins.get_mut(&class) {
+            Some(bin) => {
+                let removed = bin.remove(&free);
+                if bin.is_empty() {
+                    self.bins.remove(&class);
+                }
+                removed
+            }
+            None => false,
         }
     }
 
@@ -100,6 +172,19 @@ impl FreeSpace {
         self.persist.state()
     }
 
+    pub fn policy(&self) -> AllocationPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: AllocationPolicy) {
+        self.policy = policy;
+    }
+
+    /// The number of free spaces that couldn't be placed in a persisted free slot
+    pub fn n_unplaced(&self) -> usize {
+        self.persist.unplaced_queue.len()
+    }
+
     fn insert(
         &mut self,
         Free {
@@ -139,7 +224,7 @@ impl FreeSpace {
         };
         self.tx_changes.push(Change::Add(free));
         assert!(self.end_to_start.insert(end, start).is_none());
-        assert!(self.sizes.insert(free));
+        assert!(self.bin_insert(free));
         self.persist.add(free);
     }
 
@@ -148,9 +233,90 @@ impl FreeSpace {
     }
 
     pub fn free(&mut self, space: Free) {
+        if let Some(live) = &mut self.live {
+            let (start, end) = (space.start_pointer(), space.end_pointer);
+            let overlapping = live
+                .range(..end)
+                .rev()
+                .take_while(|(_, &live_end)| live_end > start)
+                .map(|(&live_start, &live_end)| (live_start, live_end))
+                .collect::<Vec<_>>();
+            for region in overlapping {
+                live.remove(&region.0);
+                self.tx_changes.push(Change::Dead(region));
+            }
+        }
         self.pending_frees.push(space);
     }
 
+    /// From now on panic if space holding live data is ever handed out. `live` is every region
+    /// holding live data as `(start, end)`. After that regions are live from when they are taken
+    /// until they are freed.
+    pub fn check_allocations(
+        &mut self,
+        live: impl IntoIterator<Item = (crate::Pointer, crate::Pointer)>,
+    ) {
+        self.live = Some(
+            live.into_iter()
+                .map(|(start, end)| (start.0, end.0))
+                .collect(),
+        );
+    }
+
+    /// Record that `start..end` has been taken checking it doesn't hold live data
+    fn mark_live(&mut self, start: Pointer, end: Pointer) {
+        let Some(live) = &mut self.live else {
+            return;
+        };
+        if let Some((&live_start, &live_end)) = live.range(..end).next_back() {
+            if live_end > start {
+                panic!(
+                    "allocator bug: took {}..{} which overlaps live data at {}..{}",
+                    start, end, live_start, live_end
+                );
+            }
+        }
+        live.insert(start, end);
+        self.tx_changes.push(Change::Live((start, end)));
+    }
+
+    /// Keep the `size` bytes at `start` from being reused until they are unpinned
+    pub fn pin(&mut self, start: crate::Pointer, size: u64) {
+        let region = (start.0, start.0 + size);
+        *self.pins.entry(region).or_default() += 1;
+        self.tx_changes.push(Change::Pin(region));
+    }
+
+    /// Undo one [`pin`](Self::pin) of the same region. Returns whether it was pinned.
+    pub fn unpin(&mut self, start: crate::Pointer, size: u64) -> bool {
+        let region = (start.0, start.0 + size);
+        match self.pins.get_mut(&region) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    self.pins.remove(&region);
+                }
+                self.tx_changes.push(Change::Unpin(region));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the space at `pointer` has been given back whether or not it can be reused yet
+    pub fn is_released(&self, pointer: crate::Pointer) -> bool {
+        let contains =
+            |free: &Free| free.start_pointer() <= pointer.0 && pointer.0 < free.end_pointer;
+        self.is_free(pointer)
+            || self.pending_frees.iter().any(contains)
+            || self.held.iter().any(contains)
+    }
+
+    /// The number of frees waiting for a pin to be released
+    pub fn n_held(&self) -> usize {
+        self.held.len()
+    }
+
     fn resize(&mut self, end_pointer: Pointer, new_size: u64) -> Option<u64> {
         if let Some(start_pointer) = self.end_to_start.remove(&end_pointer) {
             let current_size = end_pointer - start_pointer;
@@ -158,7 +324,7 @@ impl FreeSpace {
                 end_pointer,
                 size: current_size,
             };
-            assert!(self.sizes.remove(&free));
+            assert!(self.bin_remove(free));
             self.persist.remove(free);
             self.tx_changes.push(Change::Remove(free));
             if new_size != 0 {
@@ -171,6 +337,20 @@ impl FreeSpace {
         None
     }
 
+    /// Iterate over the free regions as `(start, end)` pointers.
+    pub fn free_regions(&self) -> impl Iterator<Item = (crate::Pointer, crate::Pointer)> + '_ {
+        self.end_to_start
+            .iter()
+            .map(|(&end, &start)| (crate::Pointer(start), crate::Pointer(end)))
+    }
+
+    pub fn is_free(&self, pointer: crate::Pointer) -> bool {
+        match self.end_to_start.range(pointer.0 + 1..).next() {
+            Some((_, &start)) => start <= pointer.0,
+            None => false,
+        }
+    }
+
     pub fn where_to_trim(&self) -> Option<crate::Pointer> {
         self.end_to_start
             .last_key_value()
@@ -185,7 +365,7 @@ impl FreeSpace {
                         self.end_to_start.remove(&free.end_pointer),
                         Some(free.end_pointer - free.size)
                     );
-                    assert!(self.sizes.remove(&free));
+                    assert!(self.bin_remove(free));
                     self.persist.remove(free);
                 }
                 Change::Remove(free) => {
@@ -193,9 +373,28 @@ impl FreeSpace {
                         .end_to_start
                         .insert(free.end_pointer, free.start_pointer())
                         .is_none());
-                    assert!(self.sizes.insert(free));
+                    assert!(self.bin_insert(free));
                     self.persist.add(free);
                 }
+                Change::Pin(region) => {
+                    let count = self.pins.get_mut(&region).expect("was pinned");
+                    *count -= 1;
+                    if *count == 0 {
+                        self.pins.remove(&region);
+                    }
+                }
+                Change::Unpin(region) => *self.pins.entry(region).or_default() += 1,
+                Change::Hold(free) => {
+                    let i = self.held.iter().rposition(|held| *held == free);
+                    self.held.swap_remove(i.expect("was held"));
+                }
+                Change::Release(free) => self.held.push(free),
+                Change::Live((start, _)) => {
+                    self.live.as_mut().expect("checking").remove(&start);
+                }
+                Change::Dead((start, end)) => {
+                    self.live.as_mut().expect("checking").insert(start, end);
+                }
             }
         }
         let _ = self.persist.take_changed_slots();
@@ -205,9 +404,27 @@ impl FreeSpace {
     #[must_use]
     pub fn apply_pending_frees(&mut self) -> BTreeSet<usize> {
         let pending_frees = core::mem::take(&mut self.pending_frees);
-        for free in pending_frees {
+        let pins = &self.pins;
+        let mut released = vec![];
+        self.held.retain(|free| {
+            let still_pinned = overlaps_pin(pins, free);
+            if !still_pinned {
+                released.push(*free);
+            }
+            still_pinned
+        });
+        for free in released {
+            self.tx_changes.push(Change::Release(free));
             self.insert(free);
         }
+        for free in pending_frees {
+            if overlaps_pin(&self.pins, &free) {
+                self.held.push(free);
+                self.tx_changes.push(Change::Hold(free));
+            } else {
+                self.insert(free);
+            }
+        }
         self.persist.take_changed_slots()
     }
 
@@ -215,20 +432,91 @@ impl FreeSpace {
         self.tx_changes.clear();
     }
 
-    pub fn take_for_size(&mut self, size: u64) -> Option<crate::Pointer> {
-        let free = self
-            .sizes
-            .range(
-                &Free {
+    /// Take the `size` bytes starting at `start` if they are all free.
+    pub fn take_exact(&mut self, start: crate::Pointer, size: u64) -> bool {
+        let end = start.0 + size;
+        let (region_end, region_start) = match self.end_to_start.range(end..).next() {
+            Some((&region_end, &region_start)) if region_start <= start.0 => {
+                (region_end, region_start)
+            }
+            _ => return false,
+        };
+        self.remove(region_end);
+        self.insert(Free {
+            size: start.0 - region_start,
+            end_pointer: start.0,
+        });
+        self.insert(Free {
+            size: region_end - end,
+            end_pointer: region_end,
+        });
+        self.mark_live(start.0, end);
+        true
+    }
+
+    /// Take `size` bytes from the lowest address they fit at ignoring the policy. Addresses in
+    /// `skip` aren't used as the start.
+    pub fn take_lowest(
+        &mut self,
+        size: u64,
+        skip: &BTreeSet<crate::Pointer>,
+    ) -> Option<crate::Pointer> {
+        let start = self.end_to_start.iter().find_map(|(&end, &start)| {
+            // step past the skipped addresses at the start of the space
+            let mut start = start;
+            for skipped in skip.range(crate::Pointer(start)..crate::Pointer(end)) {
+                if skipped.0 != start {
+                    break;
+                }
+                start += 1;
+            }
+            (end - start >= size).then_some(crate::Pointer(start))
+        })?;
+        let _taken = self.take_exact(start, size);
+        debug_assert!(_taken);
+        Some(start)
+    }
+
+    /// The smallest space that fits (the lowest addressed of those of the same size)
+    fn best_fit(&self, size: u64) -> Option<Free> {
+        let class = size_class(size);
+        // spaces in the same class might be too small
+        let same_class = self.bins.get(&class).and_then(|bin| {
+            bin.range(
+                Free {
                     size,
-                    end_pointer: Pointer::MIN,
+                    end_pointer: 0,
                 }..,
             )
-            .next()?
-            .clone();
+            .next()
+        });
+        // but everything in a bigger class fits
+        same_class
+            .or_else(|| self.bins.range(class + 1..).next()?.1.first())
+            .copied()
+    }
+
+    pub fn take_for_size(&mut self, size: u64) -> Option<crate::Pointer> {
+        let free = match self.policy {
+            AllocationPolicy::BestFit => self.best_fit(size)?,
+            AllocationPolicy::NextFit => {
+                let cursor = self.next_fit_cursor;
+                let (&end_pointer, &start_pointer) = self
+                    .end_to_start
+                    .range(cursor + 1..)
+                    .chain(self.end_to_start.range(..=cursor))
+                    .find(|(&end, &start)| end - start >= size)?;
+                self.next_fit_cursor = start_pointer + size;
+                Free {
+                    size: end_pointer - start_pointer,
+                    end_pointer,
+                }
+            }
+        };
 
         let remaining_size = free.size - size;
         self.resize(free.end_pointer, remaining_size);
+        self.mark_live(free.start_pointer(), free.start_pointer() + size);
 
         Some(crate::Pointer(free.start_pointer()))
     }
@@ -386,6 +674,52 @@ mod test {
             run_test(init, success, rollback_actions, n_persist)
         }
 
+        #[test]
+        fn best_fit_finds_space_if_there_is_some(
+            actions in proptest::collection::vec(change_strat(), 0usize..150),
+            size in 1u64..512,
+        ) {
+            let mut free_space = FreeSpace::new(10);
+            free_space.insert(Free::from_start_pointer(crate::Pointer(0), 256 * 100));
+            let mut spaces = vec![];
+            let mut rng = TestRng::deterministic_rng(RngAlgorithm::ChaCha);
+            for action in actions {
+                if let Action::Take(size) = action {
+                    if free_space.best_fit(size).is_none() {
+                        continue;
+                    }
+                }
+                action.apply(&mut spaces, &mut free_space, &mut rng);
+                let _ = free_space.apply_pending_frees();
+            }
+
+            let n_binned = free_space.bins.values().map(BTreeSet::len).sum::<usize>();
+            prop_assert_eq!(n_binned, free_space.end_to_start.len());
+            for (class, bin) in &free_space.bins {
+                for free in bin {
+                    let start = free_space.end_to_start.get(&free.end_pointer);
+                    prop_assert_eq!(start, Some(&free.start_pointer()));
+                    prop_assert_eq!(size_class(free.size), *class);
+                }
+            }
+
+            let fits = free_space.end_to_start.iter().any(|(end, start)| end - start >= size);
+            let smallest = free_space.end_to_start.iter()
+                .map(|(end, start)| end - start)
+                .filter(|&free| free >= size)
+                .min();
+            match free_space.best_fit(size) {
+                Some(free) => {
+                    prop_assert_eq!(Some(free.size), smallest);
+                    prop_assert_eq!(
+                        free_space.end_to_start.get(&free.end_pointer),
+                        Some(&free.start_pointer())
+                    );
+                }
+                None => prop_assert!(!fits),
+            }
+        }
+
     }
 
     fn run_test(
@@ -425,4 +759,53 @@ mod test {
 
         assert_eq!(before_rollback, free_space);
     }
+
+    #[test]
+    fn overlapping_free_slots_are_found() {
+        let space = |start, size| Free::from_start_pointer(crate::Pointer(start), size);
+        let spaces = [space(10, 5), Free::NULL, space(1, 9), space(20, 5)];
+        assert_eq!(find_overlap(&spaces), None);
+        let spaces = [space(10, 5), Free::NULL, space(1, 9), space(14, 5)];
+        assert_eq!(find_overlap(&spaces), Some((0, 3)));
+
+        let mut buf = [0u8; 16];
+        space(1, 9).write_to(&mut buf).unwrap();
+        assert_eq!(Free::read_from(&buf), Some(space(1, 9)));
+        Free::NULL.write_to(&mut buf).unwrap();
+        assert_eq!(Free::read_from(&buf), Some(Free::NULL));
+        // starts at the null pointer
+        space(0, 9).write_to(&mut buf).unwrap();
+        assert_eq!(Free::read_from(&buf), None);
+        // doesn't fit in 32 bit pointers
+        let mut buf = [0u8; 8];
+        assert!(space(1, u64::from(u32::MAX) + 1)
+            .write_to(&mut buf)
+            .is_err());
+    }
+
+    #[test]
+    fn checked_allocations_roll_back() {
+        let mut free_space = FreeSpace::new(4);
+        free_space.insert(Free::from_start_pointer(crate::Pointer(1), 100));
+        free_space.tx_success();
+        free_space.check_allocations([(crate::Pointer(101), crate::Pointer(200))]);
+        let taken = free_space.take_for_size(100).unwrap();
+        free_space.tx_fail_rollback();
+        // the rolled back allocation isn't live anymore
+        assert_eq!(free_space.take_for_size(100), Some(taken));
+        free_space.free(Free::from_start_pointer(taken, 100));
+        let _ = free_space.apply_pending_frees();
+        free_space.tx_success();
+        assert_eq!(free_space.take_for_size(50), Some(taken));
+    }
+
+    #[test]
+    #[should_panic(expected = "overlaps live data")]
+    fn taking_live_data_panics() {
+        let mut free_space = FreeSpace::new(4);
+        free_space.insert(Free::from_start_pointer(crate::Pointer(1), 100));
+        // pretend the free space is wrong about the middle of it
+        free_space.check_allocations([(crate::Pointer(40), crate::Pointer(60))]);
+        free_space.take_for_size(50);
+    }
 }
@@ -0,0 +1,531 @@
+use super::IndexStore;
+use crate::{Backend, LinkedList, LinkedListApi, ListSlot, Pointer, TxIo};
+use anyhow::{anyhow, Result};
+use core::borrow::Borrow;
+use std::{
+    cell::RefMut,
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+    vec::Vec as StdVec,
+};
+
+/// A map kept in a B-tree on disk.
+///
+/// Unlike [`BTreeMap`](super::BTreeMap) which keeps every key in memory the only thing this keeps
+/// in memory is where the root node is and the number of entries. Each node is written to its own
+/// space and holds at most `max_node_entries` entries (or children). Nodes are never changed in
+/// place. Changing one writes a new copy of it and every node above it and frees the old ones so
+/// a failed transaction leaves the committed tree untouched.
+///
+/// Nodes aren't merged with their siblings when entries are removed. Empty nodes are removed and a
+/// node left with a single child is replaced by it but a map that has shrunk a lot should be
+/// rebuilt into a fresh one to pack its nodes.
+#[derive(Debug)]
+pub struct BigBTreeMap<K, V> {
+    list: LinkedList<BigBTreeMapRecord>,
+    store: BigBTreeStore,
+    ty: PhantomData<(K, V)>,
+}
+
+/// How a [`BigBTreeMap`] is stored in its list. Only the newest record matters.
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct BigBTreeMapRecord {
+    root: Option<NodeRef>,
+    len: u64,
+    max_node_entries: u64,
+}
+
+/// Where a node is and how long it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub(super) struct NodeRef {
+    pointer: Pointer,
+    len: u64,
+}
+
+pub(super) fn read_node<F: Backend, T: bincode::Decode>(
+    io: &TxIo<'_, F>,
+    node: NodeRef,
+) -> Result<T> {
+    io.raw_read_at(node.pointer)
+}
+
+/// Write `node` to newly allocated space
+pub(super) fn write_node<F: Backend, T: bincode::Encode>(
+    io: &TxIo<'_, F>,
+    node: &T,
+) -> Result<NodeRef> {
+    let mut buf = vec![];
+    let len = io.value_encoding().encode_into_std_write(node, &mut buf)? as u64;
+    let pointer = io.allocate(len)?;
+    io.write_bytes(pointer, &buf)?;
+    Ok(NodeRef { pointer, len })
+}
+
+/// Free the space `node` was written to once the transaction commits
+pub(super) fn free_node<F: Backend>(io: &TxIo<'_, F>, node: NodeRef) {
+    io.free_region(node.pointer, node.len);
+}
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+enum Node<K: 'static, V: 'static> {
+    Leaf(StdVec<(K, V)>),
+    /// `keys[i]` is the smallest key under `children[i + 1]`
+    Internal {
+        keys: StdVec<K>,
+        children: StdVec<NodeRef>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct BigBTreeStore {
+    root: Option<NodeRef>,
+    len: u64,
+    max_node_entries: u64,
+    /// The root and length before each change in the transaction
+    tx_changes: StdVec<(Option<NodeRef>, u64)>,
+}
+
+/// What a node turned into after an insert
+enum Inserted<K> {
+    One(NodeRef),
+    Split(NodeRef, K, NodeRef),
+}
+
+impl<K, V> BigBTreeMap<K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone,
+    V: bincode::Encode + bincode::Decode,
+{
+    /// Keep a B-tree in `list` with at most `max_node_entries` entries in each node. If the list
+    /// already holds one it must have been created with the same `max_node_entries`.
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<BigBTreeMapRecord>,
+        max_node_entries: u64,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        if max_node_entries < 3 {
+            return Err(anyhow!("nodes must be able to hold at least 3 entries"));
+        }
+        let store = Self::load_store(&list.api(tx.as_ref()), max_node_entries)?;
+        Ok(Self {
+            list,
+            store,
+            ty: PhantomData,
+        })
+    }
+
+    fn load_store<F: Backend>(
+        list: &LinkedListApi<'_, F, BigBTreeMapRecord>,
+        max_node_entries: u64,
+    ) -> Result<BigBTreeStore> {
+        let (root, len) = match list.iter().next().transpose()? {
+            None => (None, 0),
+            Some(record) => {
+                if record.max_node_entries != max_node_entries {
+                    return Err(anyhow!(
+                        "b-tree was created with at most {} entries per node",
+                        record.max_node_entries
+                    ));
+                }
+                (record.root, record.len)
+            }
+        };
+        Ok(BigBTreeStore {
+            root,
+            len,
+            max_node_entries,
+            tx_changes: Default::default(),
+        })
+    }
+}
+
+impl<K, V> IndexStore for BigBTreeMap<K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + Send + 'static,
+    V: bincode::Encode + bincode::Decode + Send + 'static,
+{
+    type Api<'i, F> = BigBTreeMapApi<'i, F, K, V>;
+
+    fn tx_fail_rollback(&mut self) {
+        if let Some((root, len)) = self.store.tx_changes.drain(..).next() {
+            self.store.root = root;
+            self.store.len = len;
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_changes.clear();
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        vec![self.list.slot()]
+    }
+
+    fn create_api<'s, F>(map: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (list, store) = RefMut::map_split(map, |map| (&mut map.list, &mut map.store));
+        BigBTreeMapApi {
+            list: LinkedList::create_api(list, io.clone()),
+            io,
+            store,
+            ty: PhantomData,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let store = Self::load_store(&self.list.api(io), self.store.max_node_entries)?;
+        self.store
+            .tx_changes
+            .push((self.store.root, self.store.len));
+        self.store.root = store.root;
+        self.store.len = store.len;
+        Ok(())
+    }
+}
+
+pub struct BigBTreeMapApi<'i, F, K, V> {
+    io: TxIo<'i, F>,
+    list: LinkedListApi<'i, F, BigBTreeMapRecord>,
+    store: RefMut<'i, BigBTreeStore>,
+    ty: PhantomData<(K, V)>,
+}
+
+impl<'i, F, K, V> BigBTreeMapApi<'i, F, K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + 'static,
+    V: bincode::Encode + bincode::Decode + 'static,
+    F: Backend + 'i,
+{
+    fn read_node(&self, node: NodeRef) -> Result<Node<K, V>> {
+        read_node(&self.io, node)
+    }
+
+    fn write_node(&self, node: &Node<K, V>) -> Result<NodeRef> {
+        write_node(&self.io, node)
+    }
+
+    fn free_node(&self, node: NodeRef) {
+        free_node(&self.io, node)
+    }
+
+    /// Record the new root and length in the list
+    fn set_root(&mut self, root: Option<NodeRef>, len: u64) -> Result<()> {
+        let store = &mut *self.store;
+        store.tx_changes.push((store.root, store.len));
+        store.root = root;
+        store.len = len;
+        self.list.pop()?;
+        self.list.push(&BigBTreeMapRecord {
+            root,
+            len,
+            max_node_entries: self.store.max_node_entries,
+        })?;
+        Ok(())
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let mut next = self.store.root;
+        while let Some(node) = next {
+            match self.read_node(node)? {
+                Node::Leaf(entries) => {
+                    return Ok(entries
+                        .binary_search_by(|(k, _)| k.borrow().cmp(key))
+                        .ok()
+                        .map(|i| entries.into_iter().nth(i).expect("found it").1))
+                }
+                Node::Internal { keys, children } => {
+                    next = Some(children[keys.partition_point(|k| k.borrow() <= key)]);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// Insert `value` under `key` returning the value that was there
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        let (inserted, prev) = match self.store.root {
+            None => (
+                Inserted::One(self.write_node(&Node::Leaf(vec![(key, value)]))?),
+                None,
+            ),
+            Some(root) => self.insert_into(root, key, value)?,
+        };
+        let root = match inserted {
+            Inserted::One(root) => root,
+            Inserted::Split(left, key, right) => self.write_node(&Node::Internal {
+                keys: vec![key],
+                children: vec![left, right],
+            })?,
+        };
+        let len = self.store.len + u64::from(prev.is_none());
+        self.set_root(Some(root), len)?;
+        Ok(prev)
+    }
+
+    fn insert_into(&self, node_ref: NodeRef, key: K, value: V) -> Result<(Inserted<K>, Option<V>)> {
+        let max = self.store.max_node_entries as usize;
+        let node = self.read_node(node_ref)?;
+        self.free_node(node_ref);
+        match node {
+            Node::Leaf(mut entries) => {
+                let prev = match entries.binary_search_by(|(k, _)| k.cmp(&key)) {
+                    Ok(i) => Some(core::mem::replace(&mut entries[i].1, value)),
+                    Err(i) => {
+                        entries.insert(i, (key, value));
+                        None
+                    }
+                };
+                if entries.len() <= max {
+                    return Ok((Inserted::One(self.write_node(&Node::Leaf(entries))?), prev));
+                }
+                let right = entries.split_off(entries.len() / 2);
+                let split_key = right[0].0.clone();
+                let left = self.write_node(&Node::Leaf(entries))?;
+                let right = self.write_node(&Node::Leaf(right))?;
+                Ok((Inserted::Split(left, split_key, right), prev))
+            }
+            Node::Internal {
+                mut keys,
+                mut children,
+            } => {
+                let i = keys.partition_point(|k| *k <= key);
+                let (inserted, prev) = self.insert_into(children[i], key, value)?;
+                match inserted {
+                    Inserted::One(child) => children[i] = child,
+                    Inserted::Split(left, split_key, right) => {
+                        children[i] = left;
+                        children.insert(i + 1, right);
+                        keys.insert(i, split_key);
+                    }
+                }
+                if children.len() <= max {
+                    let node = Node::Internal { keys, children };
+                    return Ok((Inserted::One(self.write_node(&node)?), prev));
+                }
+                let mid = keys.len() / 2;
+                let right_keys = keys.split_off(mid + 1);
+                let split_key = keys.pop().expect("mid is in range");
+                let right_children = children.split_off(mid + 1);
+                let left = self.write_node(&Node::Internal { keys, children })?;
+                let right = self.write_node(&Node::Internal {
+                    keys: right_keys,
+                    children: right_children,
+                })?;
+                Ok((Inserted::Split(left, split_key, right), prev))
+            }
+        }
+    }
+
+    /// Remove `key` returning its value
+    pub fn remove<Q>(&mut self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let root = match self.store.root {
+            Some(root) => root,
+            None => return Ok(None),
+        };
+        match self.remove_from(root, key)? {
+            Some((root, value)) => {
+                let len = self.store.len - 1;
+                self.set_root(root, len)?;
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Remove `key` from under `node_ref` returning what the node turned into (if anything) and
+    /// the value. Nothing is changed if `key` isn't there.
+    fn remove_from<Q>(&self, node_ref: NodeRef, key: &Q) -> Result<Option<(Option<NodeRef>, V)>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        match self.read_node(node_ref)? {
+            Node::Leaf(mut entries) => {
+                let i = match entries.binary_search_by(|(k, _)| k.borrow().cmp(key)) {
+                    Ok(i) => i,
+                    Err(_) => return Ok(None),
+                };
+                let (_, value) = entries.remove(i);
+                self.free_node(node_ref);
+                let node = match entries.is_empty() {
+                    true => None,
+                    false => Some(self.write_node(&Node::Leaf(entries))?),
+                };
+                Ok(Some((node, value)))
+            }
+            Node::Internal {
+                mut keys,
+                mut children,
+            } => {
+                let i = keys.partition_point(|k| k.borrow() <= key);
+                let (child, value) = match self.remove_from(children[i], key)? {
+                    Some(removed) => removed,
+                    None => return Ok(None),
+                };
+                self.free_node(node_ref);
+                match child {
+                    Some(child) => children[i] = child,
+                    None => {
+                        children.remove(i);
+                        keys.remove(i.saturating_sub(1));
+                    }
+                }
+                let node = match children.len() {
+                    1 => Some(children[0]),
+                    _ => Some(self.write_node(&Node::Internal { keys, children })?),
+                };
+                Ok(Some((node, value)))
+            }
+        }
+    }
+
+    /// Iterate over the entries with keys in `range` in order
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> BigRange<'_, 'i, F, K, V> {
+        BigRange {
+            map: self,
+            start: Some(range.start_bound().cloned()),
+            end: range.end_bound().cloned(),
+            stack: vec![],
+            leaf: vec![].into_iter(),
+            done: false,
+        }
+    }
+
+    pub fn iter(&self) -> BigRange<'_, 'i, F, K, V> {
+        self.range(..)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.store.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.len == 0
+    }
+
+    pub fn max_node_entries(&self) -> u64 {
+        self.store.max_node_entries
+    }
+}
+
+/// Iterator over a range of a [`BigBTreeMap`] returned from [`BigBTreeMapApi::range`]. Nodes are
+/// read as it gets to them.
+pub struct BigRange<'a, 'i, F, K, V> {
+    map: &'a BigBTreeMapApi<'i, F, K, V>,
+    /// Where to start if we haven't yet
+    start: Option<Bound<K>>,
+    end: Bound<K>,
+    /// The children of the internal nodes above the current leaf and which to go to next
+    stack: StdVec<(StdVec<NodeRef>, usize)>,
+    leaf: std::vec::IntoIter<(K, V)>,
+    done: bool,
+}
+
+impl<F, K, V> BigRange<'_, '_, F, K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + 'static,
+    V: bincode::Encode + bincode::Decode + 'static,
+    F: Backend,
+{
+    /// Go down from `node` to the leaf where `start` is
+    fn descend(&mut self, mut node: NodeRef, start: &Bound<K>) -> Result<()> {
+        loop {
+            match self.map.read_node(node)? {
+                Node::Leaf(entries) => {
+                    let skip = match start {
+                        Bound::Included(start) => entries.partition_point(|(k, _)| k < start),
+                        Bound::Excluded(start) => entries.partition_point(|(k, _)| k <= start),
+                        Bound::Unbounded => 0,
+                    };
+                    let mut entries = entries.into_iter();
+                    entries.by_ref().take(skip).for_each(drop);
+                    self.leaf = entries;
+                    return Ok(());
+                }
+                Node::Internal { keys, children } => {
+                    let i = match start {
+                        Bound::Included(start) | Bound::Excluded(start) => {
+                            keys.partition_point(|k| k <= start)
+                        }
+                        Bound::Unbounded => 0,
+                    };
+                    node = children[i];
+                    self.stack.push((children, i + 1));
+                }
+            }
+        }
+    }
+
+    fn next_entry(&mut self) -> Result<Option<(K, V)>> {
+        if let Some(start) = self.start.take() {
+            if let Some(root) = self.map.store.root {
+                self.descend(root, &start)?;
+            }
+        }
+        loop {
+            if let Some(entry) = self.leaf.next() {
+                return Ok(Some(entry));
+            }
+            let next = loop {
+                match self.stack.last_mut() {
+                    None => return Ok(None),
+                    Some((children, next)) if *next < children.len() => {
+                        *next += 1;
+                        break children[*next - 1];
+                    }
+                    Some(_) => {
+                        self.stack.pop();
+                    }
+                }
+            };
+            self.descend(next, &Bound::Unbounded)?;
+        }
+    }
+}
+
+impl<F, K, V> Iterator for BigRange<'_, '_, F, K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + 'static,
+    V: bincode::Encode + bincode::Decode + 'static,
+    F: Backend,
+{
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.next_entry().transpose()?;
+        let in_range = match (&res, &self.end) {
+            (Ok((key, _)), Bound::Included(end)) => key <= end,
+            (Ok((key, _)), Bound::Excluded(end)) => key < end,
+            _ => true,
+        };
+        if !in_range {
+            self.done = true;
+            return None;
+        }
+        if res.is_err() {
+            self.done = true;
+        }
+        Some(res)
+    }
+}
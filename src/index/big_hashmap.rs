@@ -0,0 +1,562 @@
+use super::{big_btreemap::*, bloom::fnv1a, IndexStore};
+use crate::{Backend, LinkedList, LinkedListApi, ListSlot, TxIo};
+use anyhow::{anyhow, Result};
+use std::{cell::RefMut, collections::BTreeMap as StdBTreeMap, marker::PhantomData};
+
+/// How many buckets each segment points to and how many children each directory node has
+#[cfg(not(test))]
+const SEGMENT_LEN: u64 = 128;
+/// Small in tests so the directory grows levels without millions of entries
+#[cfg(test)]
+const SEGMENT_LEN: u64 = 4;
+
+/// A map kept in a hash table on disk.
+///
+/// This is the unordered counterpart to [`BigBTreeMap`](super::BigBTreeMap). It uses linear
+/// hashing: each bucket is written to its own space and once there are more than
+/// `bucket_entries` entries per bucket on average the next bucket in line is split in two. The
+/// buckets are found through segments of [`SEGMENT_LEN`] buckets which are found through a tree of
+/// directory nodes with up to [`SEGMENT_LEN`] children each. The directory gains a level each time
+/// the segments outgrow it so finding a bucket only reads a handful of nodes. It's all on disk so
+/// all that's kept in memory is where the root of the directory is and how many buckets and
+/// entries there are. Like [`BigBTreeMap`](super::BigBTreeMap) nothing is changed in place.
+///
+/// Keys are hashed from their bincode encoding so they must always encode to the same bytes.
+/// Buckets aren't merged back together when entries are removed.
+#[derive(Debug)]
+pub struct BigHashMap<K, V> {
+    list: LinkedList<BigHashMapRecord>,
+    store: BigHashStore,
+    ty: PhantomData<(K, V)>,
+}
+
+/// How a [`BigHashMap`] is stored in its list. Only the newest record matters.
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct BigHashMapRecord {
+    state: HashState,
+    bucket_entries: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+struct HashState {
+    directory: Option<NodeRef>,
+    n_buckets: u64,
+    len: u64,
+}
+
+/// Each segment is a list of [`SEGMENT_LEN`] optional buckets
+type Segment = Vec<Option<NodeRef>>;
+
+/// A directory node points to the nodes of the level below it or to segments at the bottom level
+type DirectoryNode = Vec<NodeRef>;
+
+/// The number of directory levels needed above the segments of `n_buckets` buckets
+fn n_levels(n_buckets: u64) -> u32 {
+    let n_segments = n_buckets.div_ceil(SEGMENT_LEN);
+    let (mut levels, mut capacity) = (1, SEGMENT_LEN);
+    while capacity < n_segments {
+        capacity *= SEGMENT_LEN;
+        levels += 1;
+    }
+    levels
+}
+
+#[derive(Debug, Clone)]
+struct BigHashStore {
+    state: HashState,
+    bucket_entries: u64,
+    /// The state before each change in the transaction
+    tx_changes: Vec<HashState>,
+}
+
+impl<K, V> BigHashMap<K, V>
+where
+    K: Eq + bincode::Encode + bincode::Decode,
+    V: bincode::Encode + bincode::Decode,
+{
+    /// Keep a hash table in `list` splitting buckets once they have more than `bucket_entries`
+    /// entries on average. If the list already holds one it must have been created with the same
+    /// `bucket_entries`.
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<BigHashMapRecord>,
+        bucket_entries: u64,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        if bucket_entries == 0 {
+            return Err(anyhow!("buckets must be able to hold at least one entry"));
+        }
+        let store = Self::load_store(&list.api(tx.as_ref()), bucket_entries)?;
+        Ok(Self {
+            list,
+            store,
+            ty: PhantomData,
+        })
+    }
+
+    fn load_store<F: Backend>(
+        list: &LinkedListApi<'_, F, BigHashMapRecord>,
+        bucket_entries: u64,
+    ) -> Result<BigHashStore> {
+        let state = match list.iter().next().transpose()? {
+            None => HashState {
+                directory: None,
+                n_buckets: 1,
+                len: 0,
+            },
+            Some(record) => {
+                if record.bucket_entries != bucket_entries {
+                    return Err(anyhow!(
+                        "hash table was created with {} entries per bucket",
+                        record.bucket_entries
+                    ));
+                }
+                record.state
+            }
+        };
+        Ok(BigHashStore {
+            state,
+            bucket_entries,
+            tx_changes: Default::default(),
+        })
+    }
+}
+
+impl<K, V> IndexStore for BigHashMap<K, V>
+where
+    K: Eq + bincode::Encode + bincode::Decode + Send + 'static,
+    V: bincode::Encode + bincode::Decode + Send + 'static,
+{
+    type Api<'i, F> = BigHashMapApi<'i, F, K, V>;
+
+    fn tx_fail_rollback(&mut self) {
+        if let Some(state) = self.store.tx_changes.drain(..).next() {
+            self.store.state = state;
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_changes.clear();
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        vec![self.list.slot()]
+    }
+
+    fn create_api<'s, F>(map: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (list, store) = RefMut::map_split(map, |map| (&mut map.list, &mut map.store));
+        BigHashMapApi {
+            list: LinkedList::create_api(list, io.clone()),
+            io,
+            store,
+            ty: PhantomData,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let store = Self::load_store(&self.list.api(io), self.store.bucket_entries)?;
+        let prev = core::mem::replace(&mut self.store.state, store.state);
+        self.store.tx_changes.push(prev);
+        Ok(())
+    }
+}
+
+pub struct BigHashMapApi<'i, F, K, V> {
+    io: TxIo<'i, F>,
+    list: LinkedListApi<'i, F, BigHashMapRecord>,
+    store: RefMut<'i, BigHashStore>,
+    ty: PhantomData<(K, V)>,
+}
+
+impl<'i, F, K, V> BigHashMapApi<'i, F, K, V>
+where
+    K: Eq + bincode::Encode + bincode::Decode + 'static,
+    V: bincode::Encode + bincode::Decode + 'static,
+    F: Backend + 'i,
+{
+    fn hash(key: &K) -> Result<u64> {
+        let bytes = bincode::encode_to_vec(key, bincode::config::standard())?;
+        Ok(fnv1a(0xcbf2_9ce4_8422_2325, &bytes))
+    }
+
+    /// The largest power of two no bigger than the number of buckets. Buckets below
+    /// `n_buckets - level` have already been split this round.
+    fn level(&self) -> u64 {
+        1 << (63 - self.store.state.n_buckets.leading_zeros())
+    }
+
+    fn bucket_of(&self, hash: u64) -> u64 {
+        let level = self.level();
+        match hash % (level * 2) {
+            bucket if bucket < self.store.state.n_buckets => bucket,
+            _ => hash % level,
+        }
+    }
+
+    /// Find the segment with index `segment` by walking down the directory
+    fn segment_ref(&self, segment: u64) -> Result<Option<NodeRef>> {
+        let n_levels = n_levels(self.store.state.n_buckets);
+        let Some(mut node) = self.store.state.directory else {
+            return Ok(None);
+        };
+        if segment >= SEGMENT_LEN.pow(n_levels) {
+            return Ok(None);
+        }
+        for level in (0..n_levels).rev() {
+            let children = read_node::<F, DirectoryNode>(&self.io, node)?;
+            match children.get(((segment / SEGMENT_LEN.pow(level)) % SEGMENT_LEN) as usize) {
+                Some(child) => node = *child,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(node))
+    }
+
+    fn read_bucket(&self, bucket: u64) -> Result<Vec<(K, V)>> {
+        let segment = match self.segment_ref(bucket / SEGMENT_LEN)? {
+            Some(segment) => read_node::<F, Segment>(&self.io, segment)?,
+            None => return Ok(vec![]),
+        };
+        match segment[(bucket % SEGMENT_LEN) as usize] {
+            Some(node) => read_node(&self.io, node),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Replace the contents of `buckets` writing new copies of their segments and the directory
+    /// nodes above them
+    fn write_buckets(
+        &mut self,
+        buckets: StdBTreeMap<u64, Vec<(K, V)>>,
+        n_buckets: u64,
+        len: u64,
+    ) -> Result<()> {
+        let io = &self.io;
+        let mut by_segment = StdBTreeMap::<u64, Vec<(usize, Vec<(K, V)>)>>::new();
+        for (bucket, entries) in buckets {
+            by_segment
+                .entry(bucket / SEGMENT_LEN)
+                .or_default()
+                .push(((bucket % SEGMENT_LEN) as usize, entries));
+        }
+        let mut segments = StdBTreeMap::new();
+        for (i, buckets) in by_segment {
+            let mut segment = match self.segment_ref(i)? {
+                Some(old) => {
+                    free_node(io, old);
+                    read_node::<F, Segment>(io, old)?
+                }
+                None => vec![None; SEGMENT_LEN as usize],
+            };
+            for (slot, entries) in buckets {
+                if let Some(old) = segment[slot].take() {
+                    free_node(io, old);
+                }
+                if !entries.is_empty() {
+                    segment[slot] = Some(write_node(io, &entries)?);
+                }
+            }
+            segments.insert(i, write_node(io, &segment)?);
+        }
+
+        let (old_levels, levels) = (n_levels(self.store.state.n_buckets), n_levels(n_buckets));
+        let root = match self.store.state.directory {
+            // the directory got too small so the old root becomes the first child of the new one
+            Some(old) if levels > old_levels => vec![old],
+            Some(old) => {
+                free_node(io, old);
+                read_node(io, old)?
+            }
+            None => vec![],
+        };
+        let state = HashState {
+            directory: Some(write_directory(io, root, levels - 1, segments)?),
+            n_buckets,
+            len,
+        };
+        let store = &mut *self.store;
+        store.tx_changes.push(store.state);
+        store.state = state;
+        self.list.pop()?;
+        self.list.push(&BigHashMapRecord {
+            state,
+            bucket_entries: self.store.bucket_entries,
+        })?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        let bucket = self.read_bucket(self.bucket_of(Self::hash(key)?))?;
+        Ok(bucket.into_iter().find(|(k, _)| k == key).map(|(_, v)| v))
+    }
+
+    pub fn contains_key(&self, key: &K) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// Insert `value` under `key` returning the value that was there
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        let HashState {
+            mut n_buckets,
+            mut len,
+            ..
+        } = self.store.state;
+        let bucket = self.bucket_of(Self::hash(&key)?);
+        let mut entries = self.read_bucket(bucket)?;
+        let prev = match entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => Some(core::mem::replace(v, value)),
+            None => {
+                entries.push((key, value));
+                len += 1;
+                None
+            }
+        };
+        let mut changed = StdBTreeMap::from([(bucket, entries)]);
+
+        if len > n_buckets * self.store.bucket_entries {
+            let level = self.level();
+            let split = n_buckets - level;
+            let entries = match changed.remove(&split) {
+                Some(entries) => entries,
+                None => self.read_bucket(split)?,
+            };
+            let (mut stay, mut moved) = (vec![], vec![]);
+            for (k, v) in entries {
+                match Self::hash(&k)? % (level * 2) == split {
+                    true => stay.push((k, v)),
+                    false => moved.push((k, v)),
+                }
+            }
+            changed.insert(split, stay);
+            changed.insert(n_buckets, moved);
+            n_buckets += 1;
+        }
+
+        self.write_buckets(changed, n_buckets, len)?;
+        Ok(prev)
+    }
+
+    /// Remove `key` returning its value
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>> {
+        let bucket = self.bucket_of(Self::hash(key)?);
+        let mut entries = self.read_bucket(bucket)?;
+        let i = match entries.iter().position(|(k, _)| k == key) {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+        let (_, value) = entries.swap_remove(i);
+        let HashState { n_buckets, len, .. } = self.store.state;
+        self.write_buckets(StdBTreeMap::from([(bucket, entries)]), n_buckets, len - 1)?;
+        Ok(Some(value))
+    }
+
+    /// Iterate over the entries in no particular order. Buckets are read as it gets to them.
+    pub fn iter(&self) -> BigHashIter<'_, 'i, F, K, V> {
+        BigHashIter {
+            map: self,
+            directory: vec![],
+            started: false,
+            segment: vec![].into_iter(),
+            bucket: vec![].into_iter(),
+            done: false,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.store.state.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.state.len == 0
+    }
+
+    pub fn n_buckets(&self) -> u64 {
+        self.store.state.n_buckets
+    }
+}
+
+/// Write a new copy of the directory node `node` at `level` (counting up from the one pointing to
+/// segments) with the new `segments` under it keyed by their index within it. Returns where it
+/// was written.
+fn write_directory<F: Backend>(
+    io: &TxIo<'_, F>,
+    mut node: DirectoryNode,
+    level: u32,
+    segments: StdBTreeMap<u64, NodeRef>,
+) -> Result<NodeRef> {
+    let span = SEGMENT_LEN.pow(level);
+    let mut by_child = StdBTreeMap::<usize, StdBTreeMap<u64, NodeRef>>::new();
+    for (i, segment) in segments {
+        by_child
+            .entry((i / span) as usize)
+            .or_default()
+            .insert(i % span, segment);
+    }
+    for (i, segments) in by_child {
+        let child = match level {
+            0 => *segments.values().next().expect("has a segment"),
+            _ => {
+                let old = match node.get(i) {
+                    Some(old) => {
+                        free_node(io, *old);
+                        read_node(io, *old)?
+                    }
+                    None => vec![],
+                };
+                write_directory(io, old, level - 1, segments)?
+            }
+        };
+        match node.get_mut(i) {
+            Some(old) => *old = child,
+            None => {
+                // buckets are only ever added at the end
+                debug_assert_eq!(i, node.len());
+                node.push(child);
+            }
+        }
+    }
+    write_node(io, &node)
+}
+
+/// Iterator over a [`BigHashMap`] returned from [`BigHashMapApi::iter`]
+pub struct BigHashIter<'a, 'i, F, K, V> {
+    map: &'a BigHashMapApi<'i, F, K, V>,
+    /// The children left to read of each directory node on the way down to the current segment
+    directory: Vec<std::vec::IntoIter<NodeRef>>,
+    started: bool,
+    segment: std::vec::IntoIter<Option<NodeRef>>,
+    bucket: std::vec::IntoIter<(K, V)>,
+    done: bool,
+}
+
+impl<F, K, V> BigHashIter<'_, '_, F, K, V>
+where
+    K: Eq + bincode::Encode + bincode::Decode + 'static,
+    V: bincode::Encode + bincode::Decode + 'static,
+    F: Backend,
+{
+    fn next_entry(&mut self) -> Result<Option<(K, V)>> {
+        if !self.started {
+            self.started = true;
+            if let Some(root) = self.map.store.state.directory {
+                self.directory
+                    .push(read_node::<F, DirectoryNode>(&self.map.io, root)?.into_iter());
+            }
+        }
+        loop {
+            if let Some(entry) = self.bucket.next() {
+                return Ok(Some(entry));
+            }
+            match self.segment.next() {
+                Some(Some(bucket)) => {
+                    self.bucket = read_node::<F, Vec<_>>(&self.map.io, bucket)?.into_iter()
+                }
+                Some(None) => continue,
+                None => match self.next_segment()? {
+                    Some(segment) => {
+                        self.segment = read_node::<F, Segment>(&self.map.io, segment)?.into_iter()
+                    }
+                    None => return Ok(None),
+                },
+            }
+        }
+    }
+
+    /// Walk the directory depth first to the next segment
+    fn next_segment(&mut self) -> Result<Option<NodeRef>> {
+        let n_levels = n_levels(self.map.store.state.n_buckets) as usize;
+        loop {
+            let depth = self.directory.len();
+            let Some(children) = self.directory.last_mut() else {
+                return Ok(None);
+            };
+            match children.next() {
+                Some(segment) if depth == n_levels => return Ok(Some(segment)),
+                Some(child) => self
+                    .directory
+                    .push(read_node::<F, DirectoryNode>(&self.map.io, child)?.into_iter()),
+                None => {
+                    self.directory.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<F, K, V> Iterator for BigHashIter<'_, '_, F, K, V>
+where
+    K: Eq + bincode::Encode + bincode::Decode + 'static,
+    V: bincode::Encode + bincode::Decode + 'static,
+    F: Backend,
+{
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.next_entry().transpose()?;
+        if res.is_err() {
+            self.done = true;
+        }
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::LlsDb;
+    use std::io::Cursor;
+
+    #[test]
+    fn big_hashmap_directory_grows_levels() {
+        let mut backend = vec![];
+        let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+        // enough segments for four levels of directory
+        let n = 300;
+        assert_eq!(n_levels(n.into()), 4);
+        let handle = db
+            .execute(|tx| {
+                let list = tx.take_list("big")?;
+                let handle = tx.store_index(BigHashMap::<u32, u32>::new(list, 1, &tx)?);
+                let mut map = tx.take_index(handle);
+                for i in 0..n {
+                    map.insert(i, i)?;
+                }
+                assert_eq!(u64::from(n), map.n_buckets());
+                Ok(handle)
+            })
+            .unwrap();
+
+        db.execute(|tx| {
+            let mut map = tx.take_index(handle);
+            for i in (0..n).step_by(7) {
+                assert_eq!(map.remove(&i)?, Some(i));
+            }
+            Ok(())
+        })
+        .unwrap();
+        drop(db);
+
+        let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+        db.execute(|tx| {
+            let list = tx.take_list("big")?;
+            let (_, map) = tx.store_and_take_index(BigHashMap::<u32, u32>::new(list, 1, &tx)?);
+            for i in 0..n {
+                let expected = (i % 7 != 0).then_some(i);
+                assert_eq!(map.get(&i)?, expected);
+            }
+            let mut keys = map
+                .iter()
+                .map(|entry| entry.map(|(k, _)| k))
+                .collect::<Result<Vec<_>>>()?;
+            keys.sort();
+            assert_eq!(keys, (0..n).filter(|i| i % 7 != 0).collect::<Vec<_>>());
+            Ok(())
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,401 @@
+use super::IndexStore;
+use crate::{
+    Backend, EntryHandle, LinkedList, LinkedListMut, LinkedListMutApi, ListSlot, Mut, TxIo,
+};
+use anyhow::{anyhow, Result};
+use core::cell::RefMut;
+use std::collections::BTreeMap as StdBTreeMap;
+use std::vec::Vec as StdVec;
+
+/// The number of bits in each chunk
+const CHUNK_BITS: u64 = 1 << 16;
+const CHUNK_WORDS: usize = (CHUNK_BITS / 64) as usize;
+
+/// A set of `u64` ids stored as a bitmap split into chunks of 2^16 bits.
+///
+/// Like a roaring bitmap each chunk that has any bits set is its own entry in the list and is
+/// encoded as whichever of a sorted array, a list of runs or a plain bitmap is smallest. In memory
+/// every chunk is a plain bitmap so lookups never touch the backend.
+///
+/// Changing a bit rewrites its whole chunk so set or clear many bits at once with
+/// [`set_all`](BitSetApi::set_all) and [`clear_all`](BitSetApi::clear_all) where you can.
+#[derive(Debug)]
+pub struct BitSet {
+    list: LinkedListMut<BitChunk>,
+    store: BitSetStore,
+}
+
+/// A chunk of a [`BitSet`] as it's stored in its list.
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct BitChunk {
+    /// The ids in the chunk are `key * 2^16` up to `(key + 1) * 2^16`
+    pub key: u64,
+    pub bits: BitContainer,
+}
+
+/// The ways a [`BitChunk`]'s bits can be encoded. Each is the offsets of the set bits from the
+/// start of the chunk.
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub enum BitContainer {
+    /// The offsets in ascending order
+    Array(StdVec<u16>),
+    /// Runs of consecutive offsets as the first offset and the number of offsets after it in
+    /// ascending order
+    Runs(StdVec<(u16, u16)>),
+    /// Every bit in the chunk. Bit `i` of word `j` is offset `j * 64 + i`.
+    Bitmap(StdVec<u64>),
+}
+
+#[derive(Debug, Default)]
+struct BitSetStore {
+    chunks: StdBTreeMap<u64, Chunk>,
+    tx_changes: StdVec<Change>,
+}
+
+#[derive(Debug, Clone)]
+struct Chunk {
+    handle: EntryHandle,
+    words: StdVec<u64>,
+}
+
+#[derive(Debug)]
+enum Change {
+    Write { key: u64, prev: Option<Chunk> },
+    Rebuild(StdBTreeMap<u64, Chunk>),
+}
+
+impl BitContainer {
+    /// Encode a chunk's bits in whichever way is smallest
+    fn encode(words: &[u64]) -> Self {
+        let offsets = iter_words(0, words).map(|offset| offset as u16);
+        let n_ones = words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum::<usize>();
+        let mut runs: StdVec<(u16, u16)> = vec![];
+        for offset in offsets.clone() {
+            match runs.last_mut() {
+                Some((start, n_after))
+                    if u32::from(*start) + u32::from(*n_after) + 1 == u32::from(offset) =>
+                {
+                    *n_after += 1
+                }
+                _ => runs.push((offset, 0)),
+            }
+        }
+        let (array_len, runs_len, bitmap_len) = (n_ones * 2, runs.len() * 4, CHUNK_WORDS * 8);
+        if runs_len <= array_len && runs_len <= bitmap_len {
+            BitContainer::Runs(runs)
+        } else if array_len <= bitmap_len {
+            BitContainer::Array(offsets.collect())
+        } else {
+            BitContainer::Bitmap(words.to_vec())
+        }
+    }
+
+    fn decode(self) -> Result<StdVec<u64>> {
+        let mut words = vec![0u64; CHUNK_WORDS];
+        let mut set = |offset: u32| words[(offset / 64) as usize] |= 1 << (offset % 64);
+        match self {
+            BitContainer::Array(offsets) => {
+                offsets.into_iter().for_each(|offset| set(offset.into()))
+            }
+            BitContainer::Runs(runs) => {
+                for (start, n_after) in runs {
+                    let end = u32::from(start) + u32::from(n_after);
+                    if end >= CHUNK_BITS as u32 {
+                        return Err(anyhow!("bitset run goes past the end of its chunk"));
+                    }
+                    (u32::from(start)..=end).for_each(&mut set);
+                }
+            }
+            BitContainer::Bitmap(bitmap) => {
+                if bitmap.len() != CHUNK_WORDS {
+                    return Err(anyhow!(
+                        "bitset chunk has {} words instead of {}",
+                        bitmap.len(),
+                        CHUNK_WORDS
+                    ));
+                }
+                words = bitmap;
+            }
+        }
+        Ok(words)
+    }
+}
+
+/// The ids of the set bits in `words` which start at `base`
+fn iter_words(base: u64, words: &[u64]) -> impl Iterator<Item = u64> + Clone + '_ {
+    words.iter().enumerate().flat_map(move |(i, word)| {
+        let mut word = *word;
+        core::iter::from_fn(move || {
+            if word == 0 {
+                return None;
+            }
+            let bit = word.trailing_zeros() as u64;
+            word &= word - 1;
+            Some(bit)
+        })
+        .map(move |bit| base + i as u64 * 64 + bit)
+    })
+}
+
+impl BitSet {
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<Mut<BitChunk>>,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        let chunks = Self::load_chunks(tx.as_ref(), list.slot())?;
+        Ok(Self {
+            list: LinkedListMut(list),
+            store: BitSetStore {
+                chunks,
+                tx_changes: Default::default(),
+            },
+        })
+    }
+
+    fn load_chunks<F: Backend>(
+        io: &TxIo<'_, F>,
+        slot: ListSlot,
+    ) -> Result<StdBTreeMap<u64, Chunk>> {
+        let mut it = io.iter(slot);
+        let mut chunks = StdBTreeMap::default();
+        while let Some((handle, chunk)) = it.next_with_handle::<Mut<BitChunk>>().transpose()? {
+            match chunk {
+                Mut::Remap(remap) => it.remap(remap),
+                Mut::Add(BitChunk { key, bits }) => {
+                    if chunks.contains_key(&key) {
+                        return Err(anyhow!("bitset has chunk {} twice", key));
+                    }
+                    let words = bits.decode()?;
+                    chunks.insert(key, Chunk { handle, words });
+                }
+            }
+        }
+        Ok(chunks)
+    }
+}
+
+impl IndexStore for BitSet {
+    type Api<'i, F> = BitSetApi<'i, F>;
+
+    fn tx_fail_rollback(&mut self) {
+        let BitSetStore { chunks, tx_changes } = &mut self.store;
+        for change in tx_changes.drain(..).rev() {
+            match change {
+                Change::Write { key, prev } => {
+                    match prev {
+                        Some(prev) => chunks.insert(key, prev),
+                        None => chunks.remove(&key),
+                    };
+                }
+                Change::Rebuild(prev_chunks) => *chunks = prev_chunks,
+            }
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_changes.clear();
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
+        self.list.owned_lists()
+    }
+
+    fn create_api<'s, F>(bitset: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (list, store) =
+            RefMut::map_split(bitset, |bitset| (&mut bitset.list, &mut bitset.store));
+        BitSetApi {
+            list: LinkedListMut::create_api(list, io),
+            store,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let chunks = Self::load_chunks(io, self.list.0.slot())?;
+        let prev_chunks = core::mem::replace(&mut self.store.chunks, chunks);
+        self.store.tx_changes.push(Change::Rebuild(prev_chunks));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct BitSetApi<'i, F> {
+    list: LinkedListMutApi<'i, F, BitChunk>,
+    store: RefMut<'i, BitSetStore>,
+}
+
+impl<'i, F: Backend> BitSetApi<'i, F> {
+    /// Set the bit for `id` returning whether it was already set
+    pub fn set(&mut self, id: u64) -> Result<bool> {
+        let was_set = self.contains(id);
+        if !was_set {
+            self.set_all([id])?;
+        }
+        Ok(was_set)
+    }
+
+    /// Clear the bit for `id` returning whether it was set
+    pub fn clear(&mut self, id: u64) -> Result<bool> {
+        let was_set = self.contains(id);
+        if was_set {
+            self.clear_all([id])?;
+        }
+        Ok(was_set)
+    }
+
+    /// Set the bits for all of `ids` writing each chunk that changes once
+    pub fn set_all(&mut self, ids: impl IntoIterator<Item = u64>) -> Result<()> {
+        self.change_bits(ids, |word, mask| *word |= mask)
+    }
+
+    /// Clear the bits for all of `ids` writing each chunk that changes once
+    pub fn clear_all(&mut self, ids: impl IntoIterator<Item = u64>) -> Result<()> {
+        self.change_bits(ids, |word, mask| *word &= !mask)
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        let offset = id % CHUNK_BITS;
+        self.store
+            .chunks
+            .get(&(id / CHUNK_BITS))
+            .is_some_and(|chunk| chunk.words[(offset / 64) as usize] & (1 << (offset % 64)) != 0)
+    }
+
+    /// The ids of the set bits in ascending order
+    pub fn iter_ones(&self) -> impl Iterator<Item = u64> + '_ {
+        self.store
+            .chunks
+            .iter()
+            .flat_map(|(key, chunk)| iter_words(key * CHUNK_BITS, &chunk.words))
+    }
+
+    /// The number of set bits
+    pub fn count_ones(&self) -> u64 {
+        self.store
+            .chunks
+            .values()
+            .flat_map(|chunk| &chunk.words)
+            .map(|word| u64::from(word.count_ones()))
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.chunks.is_empty()
+    }
+
+    /// Set every bit that's set in `other`
+    pub fn union_with(&mut self, other: &BitSetApi<'_, F>) -> Result<()> {
+        for (key, chunk) in &other.store.chunks {
+            self.combine_chunk(*key, |words| {
+                words
+                    .iter_mut()
+                    .zip(&chunk.words)
+                    .for_each(|(a, b)| *a |= b)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Clear every bit that isn't set in `other`
+    pub fn intersect_with(&mut self, other: &BitSetApi<'_, F>) -> Result<()> {
+        let keys = self.store.chunks.keys().copied().collect::<StdVec<_>>();
+        for key in keys {
+            match other.store.chunks.get(&key) {
+                Some(chunk) => self.combine_chunk(key, |words| {
+                    words
+                        .iter_mut()
+                        .zip(&chunk.words)
+                        .for_each(|(a, b)| *a &= b)
+                })?,
+                None => self.combine_chunk(key, |words| words.fill(0))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Clear every bit that's set in `other`
+    pub fn difference_with(&mut self, other: &BitSetApi<'_, F>) -> Result<()> {
+        for (key, chunk) in &other.store.chunks {
+            if self.store.chunks.contains_key(key) {
+                self.combine_chunk(*key, |words| {
+                    words
+                        .iter_mut()
+                        .zip(&chunk.words)
+                        .for_each(|(a, b)| *a &= !b)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flip every bit that's set in `other`
+    pub fn symmetric_difference_with(&mut self, other: &BitSetApi<'_, F>) -> Result<()> {
+        for (key, chunk) in &other.store.chunks {
+            self.combine_chunk(*key, |words| {
+                words
+                    .iter_mut()
+                    .zip(&chunk.words)
+                    .for_each(|(a, b)| *a ^= b)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn change_bits(
+        &mut self,
+        ids: impl IntoIterator<Item = u64>,
+        change: impl Fn(&mut u64, u64),
+    ) -> Result<()> {
+        let mut by_chunk = StdBTreeMap::<u64, StdVec<u64>>::new();
+        for id in ids {
+            by_chunk
+                .entry(id / CHUNK_BITS)
+                .or_default()
+                .push(id % CHUNK_BITS);
+        }
+        for (key, offsets) in by_chunk {
+            self.combine_chunk(key, |words| {
+                for offset in offsets {
+                    change(&mut words[(offset / 64) as usize], 1 << (offset % 64));
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Apply `f` to the bits of chunk `key` and write the chunk if any of them changed. The chunk's
+    /// entry is unlinked if it no longer has any bits set.
+    fn combine_chunk(&mut self, key: u64, f: impl FnOnce(&mut [u64])) -> Result<()> {
+        let prev = self.store.chunks.get(&key).cloned();
+        let mut words = match &prev {
+            Some(chunk) => chunk.words.clone(),
+            None => vec![0u64; CHUNK_WORDS],
+        };
+        f(&mut words);
+        if prev.as_ref().map(|chunk| &chunk.words) == Some(&words)
+            || (prev.is_none() && words.iter().all(|word| *word == 0))
+        {
+            return Ok(());
+        }
+        if let Some(prev) = &prev {
+            self.list.unlink(prev.handle)?;
+        }
+        let store = &mut *self.store;
+        if words.iter().all(|word| *word == 0) {
+            store.chunks.remove(&key);
+        } else {
+            let handle = self.list.push(BitChunk {
+                key,
+                bits: BitContainer::encode(&words),
+            })?;
+            store.chunks.insert(key, Chunk { handle, words });
+        }
+        store.tx_changes.push(Change::Write { key, prev });
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use super::{BTreeMapRemove, BTreeMapRemoveApi, IndexStore};
+use crate::{sha256::sha256, Backend, LinkedList, ListSlot, Mut, TxIo};
+use anyhow::{anyhow, Result};
+use core::fmt;
+use std::cell::RefMut;
+
+/// A store of byte blobs addressed by the SHA-256 hash of their contents.
+///
+/// Putting the same bytes in twice only stores them once. Each blob has a reference count that
+/// [`put`](BlobStoreApi::put) increments and [`release`](BlobStoreApi::release) decrements and
+/// the blob is removed once it gets to zero. The counts are kept in their own list so changing
+/// them doesn't rewrite the blob.
+#[derive(Debug)]
+pub struct BlobStore {
+    blobs: BTreeMapRemove<BlobHash, Vec<u8>>,
+    refs: BTreeMapRemove<BlobHash, u64>,
+}
+
+/// The SHA-256 hash of a blob in a [`BlobStore`]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, bincode::Encode, bincode::Decode)]
+pub struct BlobHash(pub [u8; 32]);
+
+impl BlobHash {
+    pub fn of(bytes: &[u8]) -> Self {
+        BlobHash(sha256(&[bytes]))
+    }
+}
+
+impl fmt::Display for BlobHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BlobHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BlobHash({})", self)
+    }
+}
+
+impl BlobStore {
+    pub fn new<'tx, F: Backend>(
+        blobs: LinkedList<Mut<(BlobHash, Vec<u8>)>>,
+        refs: LinkedList<Mut<(BlobHash, u64)>>,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        let io = tx.as_ref();
+        Ok(Self {
+            blobs: BTreeMapRemove::new(blobs, io)?,
+            refs: BTreeMapRemove::new(refs, io)?,
+        })
+    }
+}
+
+impl IndexStore for BlobStore {
+    type Api<'i, F> = BlobStoreApi<'i, F>;
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        let mut lists = self.blobs.owned_lists();
+        lists.extend(self.refs.owned_lists());
+        lists
+    }
+
+    fn create_api<'s, F>(store: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (blobs, refs) = RefMut::map_split(store, |store| (&mut store.blobs, &mut store.refs));
+        BlobStoreApi {
+            blobs: BTreeMapRemove::create_api(blobs, io.clone()),
+            refs: BTreeMapRemove::create_api(refs, io),
+        }
+    }
+
+    fn tx_fail_rollback(&mut self) {
+        self.blobs.tx_fail_rollback();
+        self.refs.tx_fail_rollback();
+    }
+
+    fn tx_success(&mut self) {
+        self.blobs.tx_success();
+        self.refs.tx_success();
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.blobs.rebuild(io)?;
+        self.refs.rebuild(io)
+    }
+}
+
+pub struct BlobStoreApi<'tx, F> {
+    blobs: BTreeMapRemoveApi<'tx, F, BlobHash, Vec<u8>>,
+    refs: BTreeMapRemoveApi<'tx, F, BlobHash, u64>,
+}
+
+impl<'tx, F: Backend> BlobStoreApi<'tx, F> {
+    /// Store `bytes` if they aren't already and take a reference to them
+    pub fn put(&mut self, bytes: &[u8]) -> Result<BlobHash> {
+        let hash = BlobHash::of(bytes);
+        if !self.refs.modify(&hash, |n_refs| *n_refs += 1)? {
+            self.blobs.insert(hash, bytes.to_vec())?;
+            self.refs.insert(hash, 1)?;
+        }
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &BlobHash) -> Result<Option<Vec<u8>>> {
+        self.blobs.get(hash)
+    }
+
+    /// Give up a reference to a blob removing it if it was the last one. Returns how many
+    /// references are left.
+    pub fn release(&mut self, hash: &BlobHash) -> Result<u64> {
+        let n_refs = self
+            .refs
+            .get(hash)?
+            .ok_or_else(|| anyhow!("no blob with hash {}", hash))?;
+        if n_refs == 1 {
+            self.refs.remove(hash)?;
+            self.blobs.remove(hash)?;
+        } else {
+            self.refs.insert(*hash, n_refs - 1)?;
+        }
+        Ok(n_refs - 1)
+    }
+
+    /// How many references there are to a blob
+    pub fn n_refs(&self, hash: &BlobHash) -> Result<u64> {
+        Ok(self.refs.get(hash)?.unwrap_or(0))
+    }
+
+    pub fn contains(&self, hash: &BlobHash) -> bool {
+        self.blobs.contains_key(hash)
+    }
+
+    /// The number of distinct blobs
+    pub fn len(&self) -> usize {
+        self.blobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blobs.is_empty()
+    }
+
+    pub fn hashes(&self) -> impl Iterator<Item = &BlobHash> + '_ {
+        self.blobs.keys()
+    }
+}
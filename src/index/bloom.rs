@@ -0,0 +1,249 @@
+use super::IndexStore;
+use crate::{Backend, LinkedList, LinkedListApi, Transaction, TxIo};
+use anyhow::{anyhow, Result};
+use core::cell::RefMut;
+use std::vec::Vec as StdVec;
+
+/// A bloom filter over byte strings.
+///
+/// The filter is persisted in its own list as a snapshot of all the bits followed by a record of
+/// the bits each [`insert`](BloomFilterApi::insert) set (inserts that set no new bits don't write
+/// one). Call [`compact`](BloomFilterApi::compact) now and again to fold the records back into a
+/// single snapshot.
+#[derive(Debug)]
+pub struct BloomFilter {
+    list: LinkedList<BloomRecord>,
+    store: BloomStore,
+}
+
+/// How a [`BloomFilter`] is stored in its list.
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub enum BloomRecord {
+    Snapshot { n_hashes: u32, bits: StdVec<u8> },
+    Set(StdVec<u64>),
+}
+
+#[derive(Debug)]
+struct BloomStore {
+    n_hashes: u32,
+    bits: StdVec<u8>,
+    tx_changes: StdVec<Change>,
+}
+
+#[derive(Debug)]
+enum Change {
+    Set(StdVec<u64>),
+    Rebuild(StdVec<u8>),
+}
+
+impl BloomFilter {
+    /// Index `list` as a bloom filter. If the list is empty a filter of `n_bits` bits using
+    /// `n_hashes` hashes is written to it, otherwise the existing filter is loaded and must
+    /// have been created with the same parameters.
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<BloomRecord>,
+        n_bits: u64,
+        n_hashes: u32,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        if n_bits == 0 || n_hashes == 0 {
+            return Err(anyhow!(
+                "bloom filter must have at least one bit and one hash"
+            ));
+        }
+        let store = match Self::load(&list.api(tx))? {
+            Some(store) => {
+                if store.n_hashes != n_hashes || store.n_bits() != n_bits.div_ceil(8) * 8 {
+                    return Err(anyhow!(
+                        "bloom filter was created with {} bits and {} hashes",
+                        store.n_bits(),
+                        store.n_hashes
+                    ));
+                }
+                store
+            }
+            None => {
+                let bits = vec![0u8; n_bits.div_ceil(8) as usize];
+                list.api(tx).push(&BloomRecord::Snapshot {
+                    n_hashes,
+                    bits: bits.clone(),
+                })?;
+                BloomStore {
+                    n_hashes,
+                    bits,
+                    tx_changes: Default::default(),
+                }
+            }
+        };
+
+        Ok(Self { list, store })
+    }
+
+    fn load<F: Backend>(list: &LinkedListApi<'_, F, BloomRecord>) -> Result<Option<BloomStore>> {
+        let mut set = vec![];
+        for record in list.iter() {
+            match record? {
+                BloomRecord::Set(bits) => set.extend(bits),
+                BloomRecord::Snapshot { n_hashes, bits } => {
+                    if bits.is_empty() || n_hashes == 0 {
+                        return Err(anyhow!(
+                            "bloom filter snapshot has {} bits and {} hashes",
+                            bits.len() * 8,
+                            n_hashes
+                        ));
+                    }
+                    let mut store = BloomStore {
+                        n_hashes,
+                        bits,
+                        tx_changes: Default::default(),
+                    };
+                    for bit in set {
+                        if bit >= store.n_bits() {
+                            return Err(anyhow!(
+                                "bloom filter record sets bit {} but the filter only has {} bits",
+                                bit,
+                                store.n_bits()
+                            ));
+                        }
+                        store.set_bit(bit);
+                    }
+                    return Ok(Some(store));
+                }
+            }
+        }
+        if !set.is_empty() {
+            return Err(anyhow!("bloom filter list is missing its snapshot"));
+        }
+        Ok(None)
+    }
+}
+
+impl BloomStore {
+    fn n_bits(&self) -> u64 {
+        self.bits.len() as u64 * 8
+    }
+
+    fn get_bit(&self, bit: u64) -> bool {
+        self.bits[(bit / 8) as usize] & (1 << (bit % 8)) != 0
+    }
+
+    fn set_bit(&mut self, bit: u64) {
+        self.bits[(bit / 8) as usize] |= 1 << (bit % 8);
+    }
+
+    fn unset_bit(&mut self, bit: u64) {
+        self.bits[(bit / 8) as usize] &= !(1 << (bit % 8));
+    }
+
+    /// The bits `item` maps to using double hashing
+    fn bits_for(&self, item: &[u8]) -> impl Iterator<Item = u64> {
+        let h1 = fnv1a(0xcbf2_9ce4_8422_2325, item);
+        // odd so it never gets stuck on the same bit
+        let h2 = fnv1a(0x6c62_272e_07bb_0142, item) | 1;
+        let n_bits = self.n_bits();
+        (0..u64::from(self.n_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % n_bits)
+    }
+}
+
+/// The hash has to be stable across platforms and versions since the bits are persisted.
+pub(super) fn fnv1a(offset_basis: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(offset_basis, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl IndexStore for BloomFilter {
+    type Api<'i, F> = BloomFilterApi<'i, F>;
+
+    fn tx_fail_rollback(&mut self) {
+        let store = &mut self.store;
+        for change in core::mem::take(&mut store.tx_changes).into_iter().rev() {
+            match change {
+                Change::Set(bits) => {
+                    for bit in bits {
+                        store.unset_bit(bit);
+                    }
+                }
+                Change::Rebuild(prev_bits) => store.bits = prev_bits,
+            }
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_changes.clear();
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
+        vec![self.list.slot()]
+    }
+
+    fn create_api<'s, F>(bloom: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (list, store) = RefMut::map_split(bloom, |bloom| (&mut bloom.list, &mut bloom.store));
+        BloomFilterApi {
+            list: LinkedList::create_api(list, io),
+            store,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let loaded =
+            Self::load(&self.list.api(io))?.ok_or(anyhow!("bloom filter list is empty"))?;
+        let prev_bits = core::mem::replace(&mut self.store.bits, loaded.bits);
+        self.store.tx_changes.push(Change::Rebuild(prev_bits));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct BloomFilterApi<'i, F> {
+    list: LinkedListApi<'i, F, BloomRecord>,
+    store: RefMut<'i, BloomStore>,
+}
+
+impl<'i, F: Backend> BloomFilterApi<'i, F> {
+    /// Add `item` to the filter. Returns whether it might have already been in there.
+    pub fn insert(&mut self, item: &[u8]) -> Result<bool> {
+        let mut newly_set = self
+            .store
+            .bits_for(item)
+            .filter(|bit| !self.store.get_bit(*bit))
+            .collect::<StdVec<_>>();
+        newly_set.sort_unstable();
+        newly_set.dedup();
+        if newly_set.is_empty() {
+            return Ok(true);
+        }
+        self.list.push(&BloomRecord::Set(newly_set.clone()))?;
+        for bit in &newly_set {
+            self.store.set_bit(*bit);
+        }
+        self.store.tx_changes.push(Change::Set(newly_set));
+        Ok(false)
+    }
+
+    /// Whether `item` might be in the filter. If this is `false` it definitely isn't.
+    pub fn maybe_contains(&self, item: &[u8]) -> bool {
+        self.store.bits_for(item).all(|bit| self.store.get_bit(bit))
+    }
+
+    /// Replace what's in the list with a single snapshot of the filter
+    pub fn compact(&self) -> Result<()> {
+        self.list.clear()?;
+        self.list.push(&BloomRecord::Snapshot {
+            n_hashes: self.store.n_hashes,
+            bits: self.store.bits.clone(),
+        })?;
+        Ok(())
+    }
+
+    pub fn n_bits(&self) -> u64 {
+        self.store.n_bits()
+    }
+
+    pub fn n_hashes(&self) -> u32 {
+        self.store.n_hashes
+    }
+}
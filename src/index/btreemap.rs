@@ -1,20 +1,30 @@
 use crate::Backend;
 use crate::EntryHandle;
+use crate::EntryPointer;
 use crate::LinkedList;
 use crate::LinkedListApi;
+use crate::LinkedListMut;
+use crate::LinkedListMutApi;
+use crate::ListSlot;
+use crate::Mut;
+use crate::Pointer;
+use crate::PooledReader;
 use crate::TxIo;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use bincode::enc::write::SizeWriter;
+use core::borrow::Borrow;
 use std::cell::RefMut;
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap as StdBTreeMap;
 use std::marker::PhantomData;
-use std::ops::RangeBounds;
+use std::ops::{Bound, RangeBounds};
 
-use super::IndexStore;
+use super::{IndexDiff, IndexStore, ListHeads, ParallelLoad};
 
 #[derive(Debug)]
 pub struct BTreeMap<K, V> {
     list: LinkedList<(K, V)>,
+    snapshots: Option<LinkedList<BTreeMapSnapshot<K>>>,
     store: Store<K>,
 }
 
@@ -22,6 +32,64 @@ pub struct BTreeMap<K, V> {
 struct Store<K> {
     index: StdBTreeMap<K, EntryHandle>,
     tx_changes: Vec<Change<K>>,
+    /// How many changes there were when a snapshot was written during the transaction
+    snapshot_written_at: Option<usize>,
+    from_snapshot: bool,
+}
+
+impl<K> Store<K> {
+    fn new(index: StdBTreeMap<K, EntryHandle>) -> Self {
+        Self {
+            index,
+            tx_changes: Default::default(),
+            snapshot_written_at: None,
+            from_snapshot: false,
+        }
+    }
+}
+
+/// The in-memory index of a [`BTreeMap`] as of a commit so it can be loaded without scanning the
+/// map's list (see [`BTreeMap::with_snapshots`]).
+#[derive(Debug, Clone)]
+pub struct BTreeMapSnapshot<K> {
+    /// The [generation](crate::LlsDb::generation) of the commit the snapshot was written in
+    generation: u64,
+    /// Each key with where its latest entry is and the length of the entry's value
+    entries: Vec<(K, Pointer, Pointer, u64)>,
+}
+
+// By hand because bincode only decodes `Vec<T>` where `T: 'static`
+impl<K: bincode::Encode> bincode::Encode for BTreeMapSnapshot<K> {
+    fn encode<E: bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> core::result::Result<(), bincode::error::EncodeError> {
+        self.generation.encode(encoder)?;
+        (self.entries.len() as u64).encode(encoder)?;
+        for entry in &self.entries {
+            entry.encode(encoder)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: bincode::Decode> bincode::Decode for BTreeMapSnapshot<K> {
+    fn decode<D: bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> core::result::Result<Self, bincode::error::DecodeError> {
+        let generation = u64::decode(decoder)?;
+        let len = u64::decode(decoder)? as usize;
+        decoder.claim_container_read::<(K, Pointer, Pointer, u64)>(len)?;
+        let mut entries = Vec::with_capacity(len);
+        for _ in 0..len {
+            decoder.unclaim_bytes_read(core::mem::size_of::<(K, Pointer, Pointer, u64)>());
+            entries.push(bincode::Decode::decode(decoder)?);
+        }
+        Ok(Self {
+            generation,
+            entries,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -30,6 +98,7 @@ enum Change<K> {
         key: K,
         prev_value: Option<EntryHandle>,
     },
+    Rebuild(StdBTreeMap<K, EntryHandle>),
 }
 
 impl<K, V> BTreeMap<K, V>
@@ -41,41 +110,161 @@ where
         list: LinkedList<(K, V)>,
         tx: impl AsRef<TxIo<'tx, F>>,
     ) -> Result<Self> {
-        let api = list.api(&tx);
-        let mut it = api.entry_iter();
+        let index = Self::load_index(tx.as_ref(), list.slot())?;
+        Ok(Self {
+            list,
+            snapshots: None,
+            store: Store::new(index),
+        })
+    }
+
+    /// Like [`new`](Self::new) but keeps snapshots of the index in `snapshots` (see
+    /// [`BTreeMapApi::write_snapshot`]). If the last snapshot was written in the last commit the
+    /// index is loaded from it rather than by scanning `list`. Otherwise the list is scanned as
+    /// usual. This makes loading a big map that hasn't changed since it was snapshotted a single
+    /// read.
+    pub fn with_snapshots<'tx, F: Backend>(
+        list: LinkedList<(K, V)>,
+        snapshots: LinkedList<BTreeMapSnapshot<K>>,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        let io = tx.as_ref();
+        let snapshot = match io.generation() {
+            Some(generation) => snapshots
+                .api(io)
+                .head()?
+                .filter(|snapshot| snapshot.generation == generation),
+            // there's nothing to tell whether it's up to date
+            None => None,
+        };
+        let store = match snapshot {
+            Some(snapshot) => {
+                let links = io.link_encoding();
+                let index = snapshot
+                    .entries
+                    .into_iter()
+                    .map(|(key, this_entry, next_entry_possibly_stale, value_len)| {
+                        let handle = EntryHandle {
+                            entry_pointer: EntryPointer {
+                                this_entry,
+                                next_entry_possibly_stale,
+                                links,
+                            },
+                            value_len,
+                        };
+                        (key, handle)
+                    })
+                    .collect();
+                let mut store = Store::new(index);
+                store.from_snapshot = true;
+                store
+            }
+            None => Store::new(Self::load_index(io, list.slot())?),
+        };
+        Ok(Self {
+            list,
+            snapshots: Some(snapshots),
+            store,
+        })
+    }
+
+    /// Whether the index was loaded from a snapshot by [`with_snapshots`](Self::with_snapshots)
+    pub fn loaded_from_snapshot(&self) -> bool {
+        self.store.from_snapshot
+    }
+
+    fn load_index<F: Backend>(
+        io: &TxIo<'_, F>,
+        slot: ListSlot,
+    ) -> Result<StdBTreeMap<K, EntryHandle>> {
+        let mut it = io.iter(slot);
+        // only decode the key. The value comes after it.
+        Self::index_from_keys(core::iter::from_fn(|| it.next_with_handle::<K>()))
+    }
+
+    /// Build the index from the keys of a list's entries, newest first
+    fn index_from_keys(
+        keys: impl Iterator<Item = Result<(EntryHandle, K)>>,
+    ) -> Result<StdBTreeMap<K, EntryHandle>> {
         let mut index = StdBTreeMap::default();
-        while let Some((key_handle, key)) = it.next_with_handle::<K>().transpose()? {
+        for key in keys {
+            let (key_handle, key) = key?;
             if let Entry::Vacant(vacant) = index.entry(key) {
                 vacant.insert(key_handle);
             }
         }
-        let store = Store {
-            index,
-            tx_changes: Default::default(),
-        };
+        Ok(index)
+    }
+}
+
+/// Loads a [`BTreeMap`] through a [`PooledReader`] (see [`LlsDb::load_indexes`]).
+///
+/// [`LlsDb::load_indexes`]: crate::LlsDb::load_indexes
+#[derive(Debug)]
+pub struct BTreeMapLoader<K, V> {
+    list: LinkedList<(K, V)>,
+}
+
+impl<K, V> BTreeMap<K, V> {
+    /// Load the map on another thread with [`LlsDb::load_indexes`](crate::LlsDb::load_indexes)
+    pub fn loader(list: LinkedList<(K, V)>) -> BTreeMapLoader<K, V> {
+        BTreeMapLoader { list }
+    }
+}
 
-        Ok(Self { list, store })
+impl<K, V> ParallelLoad for BTreeMapLoader<K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + Send + 'static,
+    V: bincode::Encode + bincode::Decode + Send + 'static,
+{
+    type Index = BTreeMap<K, V>;
+
+    fn load(self, heads: &ListHeads, reader: &mut PooledReader<'_>) -> Result<Self::Index> {
+        let head = heads.head(&self.list);
+        let index = BTreeMap::<K, V>::index_from_keys(reader.iter::<K>(head))?;
+        Ok(BTreeMap {
+            list: self.list,
+            snapshots: None,
+            store: Store::new(index),
+        })
     }
 }
 
-impl<K: Send + 'static + Ord, V: Send + 'static> IndexStore for BTreeMap<K, V> {
+impl<K, V> IndexStore for BTreeMap<K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + Send + 'static,
+    V: bincode::Encode + bincode::Decode + Send + 'static,
+{
     type Api<'i, F> = BTreeMapApi<'i, F, K, V>;
 
     fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
-        self.list.owned_lists()
+        let mut lists = self.list.owned_lists();
+        lists.extend(self.snapshots.iter().map(LinkedList::slot));
+        lists
     }
 
     fn create_api<'s, F>(btree: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
     where
         Self: Sized,
     {
+        let slot = btree.list.slot();
+        let snapshot_slot = btree.snapshots.as_ref().map(LinkedList::slot);
         let (list, store) = RefMut::map_split(btree, |btree| (&mut btree.list, &mut btree.store));
         let list = LinkedList::create_api(list, io.clone());
-        BTreeMapApi { io, list, store }
+        BTreeMapApi {
+            io,
+            list,
+            store,
+            slot,
+            snapshot_slot,
+        }
     }
 
     fn tx_fail_rollback(&mut self) {
-        let Store { tx_changes, index } = &mut self.store;
+        self.store.snapshot_written_at = None;
+        let Store {
+            tx_changes, index, ..
+        } = &mut self.store;
 
         for change in tx_changes.drain(..).rev() {
             match change {
@@ -88,19 +277,63 @@ impl<K: Send + 'static + Ord, V: Send + 'static> IndexStore for BTreeMap<K, V> {
                         None => index.remove(&key),
                     };
                 }
+                Change::Rebuild(prev_index) => *index = prev_index,
             }
         }
     }
 
     fn tx_success(&mut self) {
-        self.store.tx_changes.clear()
+        self.store.tx_changes.clear();
+        self.store.snapshot_written_at = None;
+    }
+
+    fn tx_pre_commit(&mut self) -> Result<()> {
+        match self.store.snapshot_written_at {
+            Some(n_changes) if n_changes != self.store.tx_changes.len() => Err(anyhow!(
+                "the map was changed after its snapshot was written in the same transaction"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let index = Self::load_index(io, self.list.slot())?;
+        let prev_index = core::mem::replace(&mut self.store.index, index);
+        self.store.tx_changes.push(Change::Rebuild(prev_index));
+        Ok(())
     }
 }
 
+fn get_many<F, K, Q, V>(
+    io: &TxIo<'_, F>,
+    index: &StdBTreeMap<K, EntryHandle>,
+    keys: &[Q],
+) -> Result<Vec<Option<V>>>
+where
+    F: Backend,
+    K: Borrow<Q> + Ord,
+    Q: Ord,
+    V: bincode::Decode,
+{
+    let mut found = keys
+        .iter()
+        .enumerate()
+        .filter_map(|(i, key)| Some((index.get(key)?.pointer_to_end(), i)))
+        .collect::<Vec<_>>();
+    found.sort_unstable();
+    let mut values = keys.iter().map(|_| None).collect::<Vec<_>>();
+    for (pointer, i) in found {
+        values[i] = Some(io.raw_read_at(pointer)?);
+    }
+    Ok(values)
+}
+
 pub struct BTreeMapApi<'tx, F, K, V> {
     io: TxIo<'tx, F>,
     list: LinkedListApi<'tx, F, (K, V)>,
     store: RefMut<'tx, Store<K>>,
+    slot: ListSlot,
+    snapshot_slot: Option<ListSlot>,
 }
 
 impl<'tx, F, K, V> BTreeMapApi<'tx, F, K, V>
@@ -110,7 +343,9 @@ where
     F: Backend,
 {
     pub fn insert(&mut self, key: K, value: &V) -> Result<Option<V>> {
-        let Store { index, tx_changes } = &mut *self.store;
+        let Store {
+            index, tx_changes, ..
+        } = &mut *self.store;
         let prev_value = match index.entry(key.clone()) {
             Entry::Occupied(mut occupied) => {
                 let existing_key_handle = occupied.get_mut();
@@ -139,7 +374,40 @@ where
         Ok(prev_value)
     }
 
-    pub fn get(&self, key: &K) -> Result<Option<V>> {
+    /// Apply `f` to the value at `key` and write it back if it changed. Returns whether `key` was
+    /// in the map.
+    pub fn modify<Q>(&mut self, key: &Q, f: impl FnOnce(&mut V)) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+        V: Clone,
+    {
+        let (key, key_handle) = match self.store.index.get_key_value(key) {
+            Some((key, key_handle)) => (key.clone(), *key_handle),
+            None => return Ok(false),
+        };
+        let existing_value: V = self.io.raw_read_at(key_handle.pointer_to_end())?;
+        let mut value = existing_value.clone();
+        f(&mut value);
+        if value != existing_value {
+            let new_key_handle = self.list.push_kv(&key, &value)?;
+            let Store {
+                index, tx_changes, ..
+            } = &mut *self.store;
+            *index.get_mut::<K>(&key).expect("checked above") = new_key_handle;
+            tx_changes.push(Change::Insert {
+                key,
+                prev_value: Some(key_handle),
+            });
+        }
+        Ok(true)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
         self.store
             .index
             .get(key)
@@ -147,9 +415,21 @@ where
             .transpose()
     }
 
-    pub fn range<R>(&self, range: R) -> Range<'_, F, K, V>
+    /// Look up several keys at once. The values are read in the order they are in the file rather
+    /// than the order of `keys` so the reads are mostly a forward scan of the backend.
+    pub fn get_many<Q>(&self, keys: &[Q]) -> Result<Vec<Option<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        get_many(&self.io, &self.store.index, keys)
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, F, K, V>
     where
-        R: RangeBounds<K>,
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+        R: RangeBounds<Q>,
     {
         Range {
             io: self.io.clone(),
@@ -158,6 +438,14 @@ where
         }
     }
 
+    /// Iterate over the entries whose keys start with `prefix` in order.
+    pub fn iter_prefix(&self, prefix: &K) -> Range<'_, F, K, V>
+    where
+        K: PrefixKey,
+    {
+        self.range::<K, _>(K::prefix_range(prefix))
+    }
+
     pub fn len(&self) -> usize {
         self.store.index.len()
     }
@@ -166,17 +454,49 @@ where
         self.store.index.is_empty()
     }
 
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.store.index.contains_key(key)
+    }
+
+    pub fn first_key(&self) -> Option<&K> {
+        self.store.index.keys().next()
+    }
+
+    pub fn last_key(&self) -> Option<&K> {
+        self.store.index.keys().next_back()
+    }
+
     pub fn keys(&self) -> std::collections::btree_map::Keys<'_, K, EntryHandle> {
         self.store.index.keys()
     }
 
     pub fn values(&self) -> impl Iterator<Item = Result<V>> + DoubleEndedIterator + '_ {
-        self.range(..).map(|res| res.map(|(_, v)| v))
+        self.range::<K, _>(..).map(|res| res.map(|(_, v)| v))
     }
 
     // TODO: make ExactSizeIterator version
     pub fn iter(&self) -> impl Iterator<Item = Result<(K, V)>> + DoubleEndedIterator + '_ {
-        self.range(..)
+        self.range::<K, _>(..)
+    }
+
+    /// Re-scan the list and check the in-memory index matches it. Errors with an
+    /// [`IndexDiff`] of keys and the handles of their latest entries if it doesn't.
+    pub fn debug_validate(&self) -> Result<()>
+    where
+        K: core::fmt::Debug + Send + Sync + 'static,
+    {
+        let on_disk = BTreeMap::<K, V>::load_index(&self.io, self.slot)?;
+        IndexDiff::check(
+            self.store
+                .index
+                .iter()
+                .map(|(key, handle)| (key.clone(), *handle)),
+            on_disk,
+        )
     }
 
     pub fn extend(
@@ -188,6 +508,449 @@ where
         }
         Ok(())
     }
+
+    /// Write a snapshot of the index to the snapshot list replacing the last one so the next load
+    /// with [`BTreeMap::with_snapshots`] can skip scanning the map's list. The snapshot is only
+    /// used if nothing is committed after this transaction so write it after changing the map. The
+    /// transaction fails if the map changes after the snapshot is written.
+    ///
+    /// Errors if the map wasn't created with snapshots or the database doesn't keep a commit
+    /// generation (see [`LlsDb::upgrade_format`](crate::LlsDb::upgrade_format)).
+    pub fn write_snapshot(&mut self) -> Result<()> {
+        let slot = self
+            .snapshot_slot
+            .ok_or(anyhow!("this map wasn't created with a snapshot list"))?;
+        let generation = self.io.generation().ok_or(anyhow!(
+            "snapshots need a database that keeps a commit generation"
+        ))?;
+        let snapshot = BTreeMapSnapshot {
+            generation: generation + 1,
+            entries: self
+                .store
+                .index
+                .iter()
+                .map(|(key, handle)| {
+                    let EntryPointer {
+                        this_entry,
+                        next_entry_possibly_stale,
+                        ..
+                    } = handle.entry_pointer;
+                    (
+                        key.clone(),
+                        this_entry,
+                        next_entry_possibly_stale,
+                        handle.value_len,
+                    )
+                })
+                .collect(),
+        };
+        while self.io.pop::<BTreeMapSnapshot<K>>(slot)?.is_some() {}
+        self.io.push(slot, &snapshot)?;
+        self.store.snapshot_written_at = Some(self.store.tx_changes.len());
+        Ok(())
+    }
+}
+
+/// A [`BTreeMap`] that supports removing keys. Removed entries are unlinked from the underlying
+/// [`LinkedListMut`] so their space can be reused.
+#[derive(Debug)]
+pub struct BTreeMapRemove<K, V> {
+    list: LinkedListMut<(K, V)>,
+    store: RemoveStore<K>,
+}
+
+#[derive(Debug)]
+struct RemoveStore<K> {
+    index: StdBTreeMap<K, EntryHandle>,
+    tx_changes: Vec<ChangeRemove<K>>,
+}
+
+#[derive(Debug)]
+enum ChangeRemove<K> {
+    Insert {
+        key: K,
+        prev_value: Option<EntryHandle>,
+    },
+    Remove {
+        key: K,
+        value: EntryHandle,
+    },
+    Rebuild(StdBTreeMap<K, EntryHandle>),
+}
+
+impl<K, V> BTreeMapRemove<K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone,
+    V: bincode::Encode + bincode::Decode,
+{
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<Mut<(K, V)>>,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        let index = Self::load_index(tx.as_ref(), list.slot())?;
+        let store = RemoveStore {
+            index,
+            tx_changes: Default::default(),
+        };
+
+        Ok(Self {
+            list: LinkedListMut(list),
+            store,
+        })
+    }
+
+    /// Read the start of each value as a `P` along with its key. For indexes built on this one that
+    /// keep something from the values in memory.
+    pub(crate) fn value_prefixes<'a, F: Backend, P: bincode::Decode>(
+        &'a self,
+        io: &'a TxIo<'_, F>,
+    ) -> impl Iterator<Item = Result<(K, P)>> + 'a {
+        self.store.index.iter().map(|(key, key_handle)| {
+            Ok((key.clone(), io.raw_read_at(key_handle.pointer_to_end())?))
+        })
+    }
+
+    fn load_index<F: Backend>(
+        io: &TxIo<'_, F>,
+        slot: ListSlot,
+    ) -> Result<StdBTreeMap<K, EntryHandle>> {
+        let mut it = io.iter(slot);
+        let mut index = StdBTreeMap::default();
+        // only decode the key. `Mut<K>` and `Mut<(K, V)>` share a prefix.
+        while let Some((key_handle, key)) = it.next_with_handle::<Mut<K>>().transpose()? {
+            match key {
+                Mut::Remap(remap) => it.remap(remap),
+                Mut::Add(key) => {
+                    if let Entry::Vacant(vacant) = index.entry(key) {
+                        vacant.insert(key_handle);
+                    }
+                }
+            }
+        }
+        Ok(index)
+    }
+}
+
+impl<K, V> IndexStore for BTreeMapRemove<K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + Send + 'static,
+    V: bincode::Encode + bincode::Decode + Send + 'static,
+{
+    type Api<'i, F> = BTreeMapRemoveApi<'i, F, K, V>;
+
+    fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
+        self.list.owned_lists()
+    }
+
+    fn create_api<'s, F>(btree: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let slot = btree.list.0.slot();
+        let (list, store) = RefMut::map_split(btree, |btree| (&mut btree.list, &mut btree.store));
+        let list = LinkedListMut::create_api(list, io.clone());
+        BTreeMapRemoveApi {
+            io,
+            list,
+            store,
+            slot,
+        }
+    }
+
+    fn tx_fail_rollback(&mut self) {
+        let RemoveStore { tx_changes, index } = &mut self.store;
+
+        for change in tx_changes.drain(..).rev() {
+            match change {
+                ChangeRemove::Insert { key, prev_value } => {
+                    match prev_value {
+                        Some(prev_value) => index.insert(key, prev_value),
+                        None => index.remove(&key),
+                    };
+                }
+                ChangeRemove::Remove { key, value } => {
+                    index.insert(key, value);
+                }
+                ChangeRemove::Rebuild(prev_index) => *index = prev_index,
+            }
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_changes.clear()
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let index = Self::load_index(io, self.list.0.slot())?;
+        let prev_index = core::mem::replace(&mut self.store.index, index);
+        self.store
+            .tx_changes
+            .push(ChangeRemove::Rebuild(prev_index));
+        Ok(())
+    }
+}
+
+pub struct BTreeMapRemoveApi<'tx, F, K, V> {
+    io: TxIo<'tx, F>,
+    list: LinkedListMutApi<'tx, F, (K, V)>,
+    store: RefMut<'tx, RemoveStore<K>>,
+    slot: ListSlot,
+}
+
+impl<'tx, F, K, V> BTreeMapRemoveApi<'tx, F, K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone,
+    V: bincode::Encode + bincode::Decode + PartialEq,
+    F: Backend,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        let prev_value = match self.store.index.get(&key) {
+            Some(key_handle) => {
+                let existing_value: V = self.io.raw_read_at(key_handle.pointer_to_end())?;
+                if existing_value == value {
+                    return Ok(Some(existing_value));
+                }
+                Some((*key_handle, existing_value))
+            }
+            None => None,
+        };
+
+        self.replace(
+            key,
+            prev_value.as_ref().map(|(key_handle, _)| *key_handle),
+            value,
+        )?;
+        Ok(prev_value.map(|(_, value)| value))
+    }
+
+    /// Apply `f` to the value at `key` and write it back if it changed. Returns whether `key` was
+    /// in the map.
+    pub fn modify<Q>(&mut self, key: &Q, f: impl FnOnce(&mut V)) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+        V: Clone,
+    {
+        let (key, key_handle) = match self.store.index.get_key_value(key) {
+            Some((key, key_handle)) => (key.clone(), *key_handle),
+            None => return Ok(false),
+        };
+        let existing_value: V = self.io.raw_read_at(key_handle.pointer_to_end())?;
+        let mut value = existing_value.clone();
+        f(&mut value);
+        if value != existing_value {
+            self.replace(key, Some(key_handle), value)?;
+        }
+        Ok(true)
+    }
+
+    /// Write `value` at `key` unlinking the entry `prev` it replaces
+    fn replace(&mut self, key: K, prev: Option<EntryHandle>, value: V) -> Result<()> {
+        if let Some(key_handle) = prev {
+            self.unlink(key_handle)?;
+        }
+        let entry_handle = self.list.push((key.clone(), value))?;
+        // the index has handles that only cover the key like the ones made when loading
+        let mut key_len = SizeWriter::default();
+        self.io
+            .value_encoding()
+            .encode_into_writer(Mut::Add(&key), &mut key_len)?;
+        let key_handle = EntryHandle {
+            entry_pointer: entry_handle.entry_pointer,
+            value_len: key_len.bytes_written as u64,
+        };
+        let store = &mut *self.store;
+        store.index.insert(key.clone(), key_handle);
+        store.tx_changes.push(ChangeRemove::Insert {
+            key,
+            prev_value: prev,
+        });
+
+        Ok(())
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.store
+            .index
+            .get(key)
+            .map(|key_handle| self.io.raw_read_at(key_handle.pointer_to_end()))
+            .transpose()
+    }
+
+    /// Look up several keys at once. The values are read in the order they are in the file rather
+    /// than the order of `keys` so the reads are mostly a forward scan of the backend.
+    pub fn get_many<Q>(&self, keys: &[Q]) -> Result<Vec<Option<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        get_many(&self.io, &self.store.index, keys)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let key_handle = match self.store.index.get(key) {
+            Some(key_handle) => *key_handle,
+            None => return Ok(None),
+        };
+        let value = self.unlink(key_handle)?;
+        let (key, key_handle) = self.store.index.remove_entry(key).expect("checked above");
+        self.store.tx_changes.push(ChangeRemove::Remove {
+            key,
+            value: key_handle,
+        });
+        Ok(Some(value))
+    }
+
+    pub fn pop_first(&mut self) -> Result<Option<(K, V)>> {
+        match self.first_key().cloned() {
+            Some(key) => Ok(self.remove(&key)?.map(|value| (key, value))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn pop_last(&mut self) -> Result<Option<(K, V)>> {
+        match self.last_key().cloned() {
+            Some(key) => Ok(self.remove(&key)?.map(|value| (key, value))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.store.index.contains_key(key)
+    }
+
+    pub fn first_key(&self) -> Option<&K> {
+        self.store.index.keys().next()
+    }
+
+    pub fn last_key(&self) -> Option<&K> {
+        self.store.index.keys().next_back()
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, F, K, V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+        R: RangeBounds<Q>,
+    {
+        Range {
+            io: self.io.clone(),
+            inner: self.store.index.range(range),
+            value_ty: PhantomData,
+        }
+    }
+
+    /// Iterate over the entries whose keys start with `prefix` in order.
+    pub fn iter_prefix(&self, prefix: &K) -> Range<'_, F, K, V>
+    where
+        K: PrefixKey,
+    {
+        self.range::<K, _>(K::prefix_range(prefix))
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.index.is_empty()
+    }
+
+    pub fn keys(&self) -> std::collections::btree_map::Keys<'_, K, EntryHandle> {
+        self.store.index.keys()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<(K, V)>> + '_ {
+        self.range::<K, _>(..)
+    }
+
+    /// Re-scan the list and check the in-memory index matches it. Errors with an
+    /// [`IndexDiff`] of keys and the handles of their entries if it doesn't.
+    pub fn debug_validate(&self) -> Result<()>
+    where
+        K: core::fmt::Debug + Send + Sync + 'static,
+    {
+        let on_disk = BTreeMapRemove::<K, V>::load_index(&self.io, self.slot)?;
+        IndexDiff::check(
+            self.store
+                .index
+                .iter()
+                .map(|(key, handle)| (key.clone(), *handle)),
+            on_disk,
+        )
+    }
+
+    /// Unlink the entry `key_handle` points to from the list and return its value.
+    fn unlink(&self, key_handle: EntryHandle) -> Result<V> {
+        // the key handle only covers the key so read the whole entry to free all of it
+        let (entry_handle, entry) = self.io.read_at::<Mut<(K, V)>>(key_handle.entry_pointer)?;
+        self.list.unlink(entry_handle)?;
+        let (_, value) = entry.unwrap_value();
+        Ok(value)
+    }
+}
+
+/// Keys that can be searched by prefix with `iter_prefix`.
+pub trait PrefixKey: Ord + Sized {
+    /// The range of keys that start with `prefix`
+    fn prefix_range(prefix: &Self) -> (Bound<Self>, Bound<Self>);
+}
+
+impl PrefixKey for String {
+    fn prefix_range(prefix: &Self) -> (Bound<Self>, Bound<Self>) {
+        let end = match str_prefix_end(prefix) {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        (Bound::Included(prefix.clone()), end)
+    }
+}
+
+impl PrefixKey for std::vec::Vec<u8> {
+    fn prefix_range(prefix: &Self) -> (Bound<Self>, Bound<Self>) {
+        let end = match bytes_prefix_end(prefix) {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        (Bound::Included(prefix.clone()), end)
+    }
+}
+
+/// The smallest byte string that is greater than every byte string starting with `prefix`.
+/// `None` if there isn't one (i.e. `prefix` is empty or all `0xff`).
+pub fn bytes_prefix_end(prefix: &[u8]) -> Option<std::vec::Vec<u8>> {
+    let last = prefix.iter().rposition(|byte| *byte != u8::MAX)?;
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+    Some(end)
+}
+
+/// The smallest string that is greater than every string starting with `prefix`. `None` if there
+/// isn't one (i.e. `prefix` is empty or all [`char::MAX`]).
+pub fn str_prefix_end(prefix: &str) -> Option<String> {
+    let (last, next) = prefix.char_indices().rev().find_map(|(i, c)| {
+        // skip over the surrogate range which isn't valid in a char
+        let next = match c {
+            '\u{d7ff}' => Some('\u{e000}'),
+            c => char::from_u32(c as u32 + 1),
+        };
+        next.map(|next| (i, next))
+    })?;
+    let mut end = prefix[..last].to_string();
+    end.push(next);
+    Some(end)
 }
 
 pub struct Range<'a, F, K, V> {
@@ -1,4 +1,4 @@
-use super::IndexStore;
+use super::{IndexDiff, IndexStore};
 use crate::{Backend, LinkedList, LinkedListApi, Pointer, Transaction, TxIo};
 use anyhow::{anyhow, Result};
 use core::cell::RefMut;
@@ -90,6 +90,17 @@ where
             None => Err(anyhow!("Cell has lost its item")),
         }
     }
+
+    /// Check the list still has exactly one item. Errors with an [`IndexDiff`] of entry
+    /// pointers if it doesn't.
+    pub fn debug_validate(&self) -> Result<()> {
+        let on_disk = self
+            .list
+            .iter_pointers()
+            .map(|res| res.map(|pointer| pointer.this_entry))
+            .collect::<Result<Vec<_>>>()?;
+        IndexDiff::check([self.list.head_pointer()], on_disk)
+    }
 }
 
 impl<T: Send + 'static> IndexStore for Cell<T> {
@@ -108,6 +119,10 @@ impl<T: Send + 'static> IndexStore for Cell<T> {
             list: LinkedList::create_api(refmut_list, io),
         }
     }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.list.rebuild(io)
+    }
 }
 
 /// Sepcialized cell representing an `Option<T>` on disk.
@@ -171,6 +186,18 @@ where
     pub fn take(&self) -> Result<Option<T>> {
         self.list.pop()
     }
+
+    /// Check the list has at most one item. Errors with an [`IndexDiff`] of entry pointers if it
+    /// doesn't.
+    pub fn debug_validate(&self) -> Result<()> {
+        let on_disk = self
+            .list
+            .iter_pointers()
+            .map(|res| res.map(|pointer| pointer.this_entry))
+            .collect::<Result<Vec<_>>>()?;
+        let head = self.list.head_pointer();
+        IndexDiff::check((head != Pointer::NULL).then_some(head), on_disk)
+    }
 }
 
 impl<T: Send + 'static> IndexStore for CellOption<T> {
@@ -187,4 +214,8 @@ impl<T: Send + 'static> IndexStore for CellOption<T> {
         let list = LinkedList::create_api(RefMut::map(cell, |cell| &mut cell.list), io);
         CellOptionApi { list }
     }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.list.rebuild(io)
+    }
 }
@@ -0,0 +1,106 @@
+use super::{IndexStore, RingBuffer, RingBufferApi, RingCapacity};
+use crate::{Backend, LinkedList, ListSlot, Transaction, TxIo};
+use anyhow::{anyhow, Result};
+use std::cell::{RefCell, RefMut};
+
+/// A [`Cell`](super::Cell) that remembers the last `n_previous` values it held.
+///
+/// The values are kept in a [`RingBuffer`] so the oldest ones are freed as new ones are written.
+/// Like the ring buffer this frees old values in batches so up to twice as many values as the
+/// history holds can be on disk at once.
+#[derive(Debug)]
+pub struct CellHistory<T> {
+    ring: RingBuffer<T>,
+}
+
+impl<T> CellHistory<T>
+where
+    T: bincode::Encode + bincode::Decode + Send + 'static,
+{
+    /// Index the two lists as a cell that keeps up to `n_previous` old values. If the lists are
+    /// empty `initial_value` becomes the current value.
+    pub fn new<'tx, F: Backend>(
+        lists: [LinkedList<(u64, T)>; 2],
+        n_previous: usize,
+        initial_value: T,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        let capacity = n_previous
+            .checked_add(1)
+            .ok_or(anyhow!("cell history is too long"))?;
+        let ring = RefCell::new(RingBuffer::new(lists, RingCapacity::Entries(capacity), tx)?);
+        {
+            let mut api = RingBuffer::create_api(ring.borrow_mut(), tx.io.clone());
+            if api.is_empty() {
+                api.push(initial_value)?;
+            }
+        }
+        Ok(Self {
+            ring: ring.into_inner(),
+        })
+    }
+}
+
+impl<T: bincode::Encode + bincode::Decode + Send + 'static> IndexStore for CellHistory<T> {
+    type Api<'i, F> = CellHistoryApi<'i, F, T>;
+
+    fn tx_fail_rollback(&mut self) {
+        self.ring.tx_fail_rollback()
+    }
+
+    fn tx_success(&mut self) {
+        self.ring.tx_success()
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        self.ring.owned_lists()
+    }
+
+    fn create_api<'s, F>(cell: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        CellHistoryApi {
+            ring: RingBuffer::create_api(RefMut::map(cell, |cell| &mut cell.ring), io),
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.ring.rebuild(io)
+    }
+}
+
+#[derive(Debug)]
+pub struct CellHistoryApi<'i, F, T> {
+    ring: RingBufferApi<'i, F, T>,
+}
+
+impl<'i, F, T> CellHistoryApi<'i, F, T>
+where
+    T: bincode::Encode + bincode::Decode,
+    F: Backend + 'i,
+{
+    pub fn get(&self) -> Result<T> {
+        match self.ring.iter().next_back() {
+            Some(value) => value,
+            None => Err(anyhow!("CellHistory has lost its value")),
+        }
+    }
+
+    /// Set a new value returning the old one which becomes the newest value in the history
+    pub fn replace(&mut self, value: T) -> Result<T> {
+        let old_value = self.get()?;
+        self.ring.push(value)?;
+        Ok(old_value)
+    }
+
+    /// Iterate over the previous values from the most recent to the oldest
+    pub fn history(&self) -> impl Iterator<Item = Result<T>> + '_ {
+        self.ring.iter().rev().skip(1)
+    }
+
+    /// The number of previous values being kept
+    pub fn history_len(&self) -> usize {
+        self.ring.len().saturating_sub(1)
+    }
+}
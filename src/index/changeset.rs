@@ -0,0 +1,99 @@
+use super::IndexStore;
+use crate::{Backend, LinkedList, LinkedListApi, ListSlot, TxIo};
+use anyhow::Result;
+use std::cell::RefMut;
+
+/// A changeset that can be combined with a later one. With the `bdk` feature bdk wallet
+/// changesets implement it through `WalletChangeSet`.
+pub trait Merge: Default {
+    /// Apply `other` on top of `self`
+    fn merge(&mut self, other: Self);
+    fn is_empty(&self) -> bool;
+}
+
+/// A log of changesets that are merged together to load the current state.
+///
+/// This is how wallet libraries like bdk persist their state: each update appends the changeset
+/// it produced and loading merges them all from oldest to newest. With the `bdk` feature
+/// `WalletStore` uses one to persist a bdk wallet.
+#[derive(Debug)]
+pub struct ChangeSetLog<C> {
+    list: LinkedList<C>,
+}
+
+impl<C> ChangeSetLog<C>
+where
+    C: Merge + bincode::Encode + bincode::Decode + Send + 'static,
+{
+    pub fn new(list: LinkedList<C>) -> Self {
+        Self { list }
+    }
+}
+
+impl<C: Send + 'static> IndexStore for ChangeSetLog<C> {
+    type Api<'i, F> = ChangeSetLogApi<'i, F, C>;
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        vec![self.list.slot()]
+    }
+
+    fn create_api<'s, F>(log: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        ChangeSetLogApi {
+            list: LinkedList::create_api(RefMut::map(log, |log| &mut log.list), io.clone()),
+            io,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.list.rebuild(io)
+    }
+}
+
+#[derive(Debug)]
+pub struct ChangeSetLogApi<'i, F, C> {
+    io: TxIo<'i, F>,
+    list: LinkedListApi<'i, F, C>,
+}
+
+impl<'i, F, C> ChangeSetLogApi<'i, F, C>
+where
+    F: Backend,
+    C: Merge + bincode::Encode + bincode::Decode,
+{
+    /// Append `changeset` to the log. Empty changesets aren't written. Returns whether it was.
+    pub fn append(&self, changeset: &C) -> Result<bool> {
+        if changeset.is_empty() {
+            return Ok(false);
+        }
+        self.list.push(changeset)?;
+        Ok(true)
+    }
+
+    /// Merge all the changesets in the log from oldest to newest. Only the pointers to the
+    /// changesets are kept in memory while they are read back one at a time.
+    pub fn aggregate(&self) -> Result<C> {
+        let pointers = self.list.iter_pointers().collect::<Result<Vec<_>>>()?;
+        let mut aggregate = C::default();
+        for pointer in pointers.into_iter().rev() {
+            let (_, changeset) = self.io.read_at(pointer)?;
+            aggregate.merge(changeset);
+        }
+        Ok(aggregate)
+    }
+
+    /// Replace the log with a single entry holding the aggregate of everything in it so loading
+    /// doesn't have to merge as many changesets. Returns the aggregate.
+    pub fn compact(&self) -> Result<C> {
+        let aggregate = self.aggregate()?;
+        self.list.clear()?;
+        self.append(&aggregate)?;
+        Ok(aggregate)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
@@ -0,0 +1,203 @@
+use super::IndexStore;
+use crate::{Backend, EntryHandle, LinkedList, ListSlot, Transaction, TxIo};
+use anyhow::{anyhow, Result};
+use std::{cell::RefMut, marker::PhantomData};
+
+/// A persisted integer that can be added to in place.
+///
+/// The value is stored as a single fixed-width entry that is overwritten on each change (see
+/// [`TxIo::overwrite`]) rather than popped and pushed again so a counter that is updated all the
+/// time doesn't churn through space. The tradeoff is the one `overwrite` makes: a failed
+/// transaction puts the old value back but if the process dies mid-transaction the new value may
+/// be left in place.
+#[derive(Debug)]
+pub struct Counter<N: CounterInt> {
+    list: LinkedList<N::Bytes>,
+    store: CounterStore,
+}
+
+/// What to do when an addition would take a [`Counter`] out of range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Return an error and leave the counter as it was
+    #[default]
+    Checked,
+    /// Stop at the minimum or maximum value
+    Saturating,
+}
+
+/// The integer types a [`Counter`] can hold
+pub trait CounterInt: Copy + core::fmt::Debug + Send + 'static {
+    /// The fixed-width encoding of the integer
+    type Bytes: bincode::Encode + bincode::Decode + Send + 'static;
+    const ZERO: Self;
+    fn to_bytes(self) -> Self::Bytes;
+    fn from_bytes(bytes: Self::Bytes) -> Self;
+    fn checked_add_delta(self, delta: i64) -> Option<Self>;
+    fn saturating_add_delta(self, delta: i64) -> Self;
+}
+
+impl CounterInt for u64 {
+    type Bytes = [u8; 8];
+    const ZERO: Self = 0;
+
+    fn to_bytes(self) -> Self::Bytes {
+        self.to_le_bytes()
+    }
+
+    fn from_bytes(bytes: Self::Bytes) -> Self {
+        Self::from_le_bytes(bytes)
+    }
+
+    fn checked_add_delta(self, delta: i64) -> Option<Self> {
+        self.checked_add_signed(delta)
+    }
+
+    fn saturating_add_delta(self, delta: i64) -> Self {
+        self.saturating_add_signed(delta)
+    }
+}
+
+impl CounterInt for i64 {
+    type Bytes = [u8; 8];
+    const ZERO: Self = 0;
+
+    fn to_bytes(self) -> Self::Bytes {
+        self.to_le_bytes()
+    }
+
+    fn from_bytes(bytes: Self::Bytes) -> Self {
+        Self::from_le_bytes(bytes)
+    }
+
+    fn checked_add_delta(self, delta: i64) -> Option<Self> {
+        self.checked_add(delta)
+    }
+
+    fn saturating_add_delta(self, delta: i64) -> Self {
+        self.saturating_add(delta)
+    }
+}
+
+#[derive(Debug)]
+struct CounterStore {
+    overflow: Overflow,
+    handle: EntryHandle,
+    /// The handle before the index was rebuilt in this transaction
+    tx_prev_handle: Option<EntryHandle>,
+}
+
+impl<N: CounterInt> Counter<N> {
+    /// Index `list` as a counter starting at zero if the list is empty
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<N::Bytes>,
+        overflow: Overflow,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        let handle = match Self::load_handle(&tx.io, &list)? {
+            Some(handle) => handle,
+            None => list.api(tx).push(&N::ZERO.to_bytes())?,
+        };
+        Ok(Self {
+            list,
+            store: CounterStore {
+                overflow,
+                handle,
+                tx_prev_handle: None,
+            },
+        })
+    }
+
+    fn load_handle<F: Backend>(
+        io: &TxIo<'_, F>,
+        list: &LinkedList<N::Bytes>,
+    ) -> Result<Option<EntryHandle>> {
+        let mut iter = io.iter(list.slot());
+        let handle = match iter.next_with_handle::<N::Bytes>().transpose()? {
+            Some((handle, _)) => handle,
+            None => return Ok(None),
+        };
+        if iter.next_pointer().is_some() {
+            return Err(anyhow!("Counter can only index a list with one item"));
+        }
+        Ok(Some(handle))
+    }
+}
+
+impl<N: CounterInt> IndexStore for Counter<N> {
+    type Api<'i, F> = CounterApi<'i, F, N>;
+
+    fn tx_fail_rollback(&mut self) {
+        if let Some(handle) = self.store.tx_prev_handle.take() {
+            self.store.handle = handle;
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_prev_handle = None;
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        vec![self.list.slot()]
+    }
+
+    fn create_api<'s, F>(counter: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        CounterApi {
+            io,
+            store: RefMut::map(counter, |counter| &mut counter.store),
+            ty: PhantomData,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let handle =
+            Self::load_handle(io, &self.list)?.ok_or(anyhow!("Counter has lost its value"))?;
+        let prev_handle = core::mem::replace(&mut self.store.handle, handle);
+        self.store.tx_prev_handle.get_or_insert(prev_handle);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct CounterApi<'i, F, N> {
+    io: TxIo<'i, F>,
+    store: RefMut<'i, CounterStore>,
+    ty: PhantomData<N>,
+}
+
+impl<'i, F, N> CounterApi<'i, F, N>
+where
+    F: Backend + 'i,
+    N: CounterInt,
+{
+    pub fn get(&self) -> Result<N> {
+        let (_, bytes) = self.io.read_at(self.store.handle.entry_pointer)?;
+        Ok(N::from_bytes(bytes))
+    }
+
+    /// Add `delta` to the counter returning the new value
+    pub fn add(&self, delta: i64) -> Result<N> {
+        let value = self.get()?;
+        let new_value = match self.store.overflow {
+            Overflow::Checked => value.checked_add_delta(delta).ok_or(anyhow!(
+                "adding {} to counter at {:?} overflows",
+                delta,
+                value
+            ))?,
+            Overflow::Saturating => value.saturating_add_delta(delta),
+        };
+        self.set(new_value)?;
+        Ok(new_value)
+    }
+
+    pub fn set(&self, value: N) -> Result<()> {
+        self.io.overwrite(self.store.handle, &value.to_bytes())
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.store.overflow
+    }
+}
@@ -0,0 +1,223 @@
+use super::IndexStore;
+use crate::{Backend, LinkedList, LinkedListApi, Transaction, TxIo};
+use anyhow::Result;
+use std::{cell::RefMut, vec::Vec as StdVec};
+
+/// A double ended queue that can be pushed to and popped from at both ends.
+///
+/// It's kept in two lists: one for the front and one for the back with the head of each being the
+/// end of the deque it's for. Popping from an end whose list is empty moves the half of the other
+/// list nearest to that end over first. Entries are only ever popped from the head of their list
+/// so nothing is left behind to be skipped when iterating.
+#[derive(Debug)]
+pub struct Deque<T> {
+    /// The front list and then the back list
+    lists: [LinkedList<T>; 2],
+    store: DequeStore,
+}
+
+#[derive(Debug)]
+struct DequeStore {
+    /// The number of entries in each list
+    lens: [usize; 2],
+    /// What `lens` was before the transaction changed it
+    lens_before_tx: Option<[usize; 2]>,
+}
+
+const FRONT: usize = 0;
+const BACK: usize = 1;
+
+impl<T> Deque<T>
+where
+    T: bincode::Encode + bincode::Decode + Send,
+{
+    /// Index `front` and `back` as the two ends of a deque
+    pub fn new<'tx, F: Backend>(
+        front: LinkedList<T>,
+        back: LinkedList<T>,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        let lists = [front, back];
+        let lens = Self::load_lens(&tx.io, &lists)?;
+        Ok(Self {
+            lists,
+            store: DequeStore {
+                lens,
+                lens_before_tx: None,
+            },
+        })
+    }
+
+    fn load_lens<F: Backend>(io: &TxIo<'_, F>, lists: &[LinkedList<T>; 2]) -> Result<[usize; 2]> {
+        let mut lens = [0; 2];
+        for (list, len) in lists.iter().zip(&mut lens) {
+            for pointer in list.api(io).iter_pointers() {
+                pointer?;
+                *len += 1;
+            }
+        }
+        Ok(lens)
+    }
+}
+
+impl<T: bincode::Encode + bincode::Decode + 'static + Send> IndexStore for Deque<T> {
+    type Api<'i, F> = DequeApi<'i, F, T>;
+
+    fn tx_fail_rollback(&mut self) {
+        if let Some(lens) = self.store.lens_before_tx.take() {
+            self.store.lens = lens;
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.lens_before_tx = None;
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
+        self.lists.iter().map(LinkedList::slot).collect()
+    }
+
+    fn create_api<'s, F>(deque: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (lists, store) = RefMut::map_split(deque, |deque| (&mut deque.lists, &mut deque.store));
+        let (front, back) = RefMut::map_split(lists, |[front, back]| (front, back));
+        DequeApi {
+            lists: [
+                LinkedList::create_api(front, io.clone()),
+                LinkedList::create_api(back, io.clone()),
+            ],
+            io,
+            store,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let lens = Self::load_lens(io, &self.lists)?;
+        let lens_before = core::mem::replace(&mut self.store.lens, lens);
+        self.store.lens_before_tx.get_or_insert(lens_before);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct DequeApi<'i, F, T> {
+    io: TxIo<'i, F>,
+    lists: [LinkedListApi<'i, F, T>; 2],
+    store: RefMut<'i, DequeStore>,
+}
+
+impl<'i, F, T> DequeApi<'i, F, T>
+where
+    T: bincode::Encode + bincode::Decode,
+    F: Backend + 'i,
+{
+    pub fn push_front(&mut self, value: &T) -> Result<()> {
+        self.push(FRONT, value)
+    }
+
+    pub fn push_back(&mut self, value: &T) -> Result<()> {
+        self.push(BACK, value)
+    }
+
+    pub fn pop_front(&mut self) -> Result<Option<T>> {
+        self.pop(FRONT)
+    }
+
+    pub fn pop_back(&mut self) -> Result<Option<T>> {
+        self.pop(BACK)
+    }
+
+    /// The value at the front without popping it. If the front list is empty this reads through
+    /// the whole back list to get to it.
+    pub fn front(&self) -> Result<Option<T>> {
+        self.peek(FRONT)
+    }
+
+    /// The value at the back without popping it. Like [`front`](Self::front) this can read
+    /// through the whole front list.
+    pub fn back(&self) -> Result<Option<T>> {
+        self.peek(BACK)
+    }
+
+    /// Iterate from the front to the back. The back list is walked up front to find where its
+    /// entries are so they can be read in the opposite order to how they are stored.
+    pub fn iter(&self) -> Result<impl Iterator<Item = Result<T>> + '_> {
+        let back = self.lists[BACK]
+            .iter_pointers()
+            .collect::<Result<StdVec<_>>>()?;
+        Ok(self.lists[FRONT].iter().chain(
+            back.into_iter()
+                .rev()
+                .map(|pointer| Ok(self.io.read_at::<T>(pointer)?.1)),
+        ))
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.lens.iter().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn changing_lens(&mut self) -> &mut [usize; 2] {
+        let store = &mut *self.store;
+        store.lens_before_tx.get_or_insert(store.lens);
+        &mut store.lens
+    }
+
+    fn push(&mut self, end: usize, value: &T) -> Result<()> {
+        self.lists[end].push(value)?;
+        self.changing_lens()[end] += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self, end: usize) -> Result<Option<T>> {
+        if self.store.lens[end] == 0 {
+            self.rebalance(end)?;
+        }
+        let popped = self.lists[end].pop()?;
+        if popped.is_some() {
+            self.changing_lens()[end] -= 1;
+        }
+        Ok(popped)
+    }
+
+    fn peek(&self, end: usize) -> Result<Option<T>> {
+        match self.store.lens {
+            lens if lens[end] > 0 => self.lists[end].head(),
+            // the value at this end is the oldest one in the other list
+            lens if lens[1 - end] > 0 => Ok(Some(
+                self.lists[1 - end]
+                    .iter()
+                    .last()
+                    .expect("list isn't empty")?,
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    /// Move the half of the other list nearest to `end` (rounding up) to `end`'s list which must
+    /// be empty
+    fn rebalance(&mut self, end: usize) -> Result<()> {
+        let other = 1 - end;
+        // nearest to the other end first
+        let mut values = StdVec::with_capacity(self.store.lens[other]);
+        while let Some(value) = self.lists[other].pop()? {
+            values.push(value);
+        }
+        let n_stay = values.len() / 2;
+        for value in values[..n_stay].iter().rev() {
+            self.lists[other].push(value)?;
+        }
+        for value in &values[n_stay..] {
+            self.lists[end].push(value)?;
+        }
+        let lens = self.changing_lens();
+        lens[other] = n_stay;
+        lens[end] = values.len() - n_stay;
+        Ok(())
+    }
+}
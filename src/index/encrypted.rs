@@ -0,0 +1,217 @@
+use super::IndexStore;
+use crate::{Backend, LinkedList, LinkedListMut, LinkedListMutApi, ListSlot, Mut, TxIo};
+use anyhow::{anyhow, Result};
+use std::{cell::RefMut, collections::BTreeMap, marker::PhantomData, sync::Arc};
+
+/// Encrypts and decrypts the entries of an [`EncryptedList`] with one key.
+///
+/// The crate doesn't come with any ciphers so bring your own (an AEAD like ChaCha20-Poly1305 is
+/// a good choice). The ciphertext has to carry everything needed to decrypt it (e.g. the nonce).
+pub trait Cipher: Send + Sync {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>>;
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// The keys an [`EncryptedList`] can use along with the one new entries are encrypted with
+#[derive(Clone)]
+pub struct Keyring {
+    keys: BTreeMap<u32, Arc<dyn Cipher>>,
+    current: u32,
+}
+
+impl Keyring {
+    pub fn new(key_id: u32, cipher: Arc<dyn Cipher>) -> Self {
+        Self {
+            keys: [(key_id, cipher)].into(),
+            current: key_id,
+        }
+    }
+
+    /// Add a key that can be used to decrypt entries (or rotate to)
+    pub fn with_key(mut self, key_id: u32, cipher: Arc<dyn Cipher>) -> Self {
+        self.keys.insert(key_id, cipher);
+        self
+    }
+
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    fn cipher(&self, key_id: u32) -> Result<&dyn Cipher> {
+        self.keys
+            .get(&key_id)
+            .map(|cipher| &**cipher)
+            .ok_or(anyhow!("no key with id {} in the keyring", key_id))
+    }
+}
+
+impl core::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Keyring")
+            .field("key_ids", &self.keys.keys().collect::<Vec<_>>())
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+/// An entry of an [`EncryptedList`] as it's stored
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct Sealed {
+    pub key_id: u32,
+    pub ciphertext: Vec<u8>,
+}
+
+/// A list whose entries are each encrypted with a key from a [`Keyring`].
+///
+/// Every entry records the id of the key it was encrypted with so the keys can be rotated a few
+/// entries at a time with [`rotate_key`](EncryptedListApi::rotate_key).
+#[derive(Debug)]
+pub struct EncryptedList<T> {
+    list: LinkedListMut<Sealed>,
+    keyring: Keyring,
+    ty: PhantomData<T>,
+}
+
+impl<T> EncryptedList<T> {
+    pub fn new(list: LinkedList<Mut<Sealed>>, keyring: Keyring) -> Self {
+        Self {
+            list: LinkedListMut(list),
+            keyring,
+            ty: PhantomData,
+        }
+    }
+}
+
+impl<T: Send + 'static> IndexStore for EncryptedList<T> {
+    type Api<'i, F> = EncryptedListApi<'i, F, T>;
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        vec![self.list.0.slot()]
+    }
+
+    fn create_api<'s, F>(encrypted: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (list, keyring) = RefMut::map_split(encrypted, |encrypted| {
+            (&mut encrypted.list, &mut encrypted.keyring)
+        });
+        EncryptedListApi {
+            list: LinkedListMut::create_api(list, io.clone()),
+            io,
+            keyring,
+            ty: PhantomData,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.list.rebuild(io)
+    }
+}
+
+#[derive(Debug)]
+pub struct EncryptedListApi<'i, F, T> {
+    io: TxIo<'i, F>,
+    list: LinkedListMutApi<'i, F, Sealed>,
+    keyring: RefMut<'i, Keyring>,
+    ty: PhantomData<T>,
+}
+
+impl<'i, F, T> EncryptedListApi<'i, F, T>
+where
+    F: Backend + 'i,
+    T: bincode::Encode + bincode::Decode,
+{
+    fn seal(&self, key_id: u32, value: &T) -> Result<Sealed> {
+        let mut plaintext = vec![];
+        self.io
+            .value_encoding()
+            .encode_into_std_write(value, &mut plaintext)?;
+        let ciphertext = self.keyring.cipher(key_id)?.encrypt(&plaintext)?;
+        Ok(Sealed { key_id, ciphertext })
+    }
+
+    fn open(&self, sealed: &Sealed) -> Result<T> {
+        let plaintext = self
+            .keyring
+            .cipher(sealed.key_id)?
+            .decrypt(&sealed.ciphertext)?;
+        Ok(self
+            .io
+            .value_encoding()
+            .decode_from_std_read(&mut plaintext.as_slice())?)
+    }
+
+    /// Encrypt `value` with the current key and push it
+    pub fn push(&self, value: &T) -> Result<()> {
+        let sealed = self.seal(self.keyring.current, value)?;
+        self.list.push(sealed)?;
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Result<Option<T>> {
+        self.list
+            .pop()?
+            .map(|sealed| self.open(&sealed))
+            .transpose()
+    }
+
+    /// Iterate over the decrypted entries from newest to oldest
+    pub fn iter(&self) -> impl Iterator<Item = Result<T>> + '_ {
+        self.list
+            .iter()
+            .map(|res| res.and_then(|sealed| self.open(&sealed)))
+    }
+
+    /// Set the key new entries are encrypted with. This only changes the in-memory keyring so it
+    /// isn't undone if the transaction fails.
+    pub fn set_current_key(&mut self, key_id: u32) -> Result<()> {
+        self.keyring.cipher(key_id)?;
+        self.keyring.current = key_id;
+        Ok(())
+    }
+
+    /// Add a key to the in-memory keyring
+    pub fn add_key(&mut self, key_id: u32, cipher: Arc<dyn Cipher>) {
+        self.keyring.keys.insert(key_id, cipher);
+    }
+
+    /// The number of entries encrypted with `key_id`
+    pub fn count_with_key(&self, key_id: u32) -> Result<usize> {
+        let mut count = 0;
+        for sealed in self.list.iter() {
+            if sealed?.key_id == key_id {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Re-encrypt up to `limit` of the entries encrypted with `old` using `new` returning how many
+    /// were re-encrypted. Call it until it returns `0` to rotate all of them.
+    ///
+    /// The re-encrypted entries are pushed again and the old ones removed so they end up at the
+    /// front of the list. Nothing committed is written over so if the process dies part way
+    /// through every entry can still be decrypted with the key it was encrypted with.
+    pub fn rotate_key(&self, old: u32, new: u32, limit: usize) -> Result<usize> {
+        self.keyring.cipher(new)?;
+        let to_rotate = self
+            .list
+            .iter_handles()
+            .filter(|res| !matches!(res, Ok((_, sealed)) if sealed.key_id != old))
+            .take(limit)
+            .collect::<Result<Vec<_>>>()?;
+        let n_rotated = to_rotate.len();
+        let mut resealed = vec![];
+        for (handle, sealed) in &to_rotate {
+            let value = self.open(sealed)?;
+            resealed.push(self.seal(new, &value)?);
+            // unlink newest first like `VecRemoveApi::retain` so the newest can be popped
+            self.list.unlink(*handle)?;
+        }
+        for sealed in resealed.into_iter().rev() {
+            self.list.push(sealed)?;
+        }
+        Ok(n_rotated)
+    }
+}
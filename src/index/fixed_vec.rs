@@ -0,0 +1,324 @@
+use super::IndexStore;
+use crate::{Backend, LinkedList, LinkedListApi, ListSlot, Pointer, Transaction, TxIo};
+use anyhow::{anyhow, Result};
+use std::{cell::RefMut, marker::PhantomData, vec::Vec as StdVec};
+
+/// The number of records in the first extent. Each extent after that is twice as big as the one
+/// before until they reach `FIRST_EXTENT << MAX_DOUBLINGS` records.
+const FIRST_EXTENT: u64 = 64;
+const MAX_DOUBLINGS: usize = 10;
+/// The number of records in all the extents before they stop growing
+const DOUBLING_RECORDS: u64 = FIRST_EXTENT * ((1 << MAX_DOUBLINGS) - 1);
+
+/// A vector of records that all encode to at most the same number of bytes.
+///
+/// Unlike [`Vec`](super::Vec) the records aren't list entries. They are written back to back into
+/// extents of space set aside for them so where a record is can be worked out from its index. The
+/// only things kept in memory are the length and where each extent starts. The list just records
+/// the extents and the length. Records that encode to fewer than `record_size` bytes are padded
+/// so with the default varint [`ValueEncoding`](crate::ValueEncoding) `record_size` should be the
+/// biggest a record can be.
+#[derive(Debug)]
+pub struct FixedVec<T> {
+    list: LinkedList<FixedVecRecord>,
+    store: FixedVecStore,
+    ty: PhantomData<T>,
+}
+
+/// How a [`FixedVec`] is stored in its list. The newest entry is always `Len`.
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub enum FixedVecRecord {
+    Len { len: u64, record_size: u64 },
+    Extent { start: Pointer, capacity: u64 },
+}
+
+#[derive(Debug, Clone)]
+struct FixedVecStore {
+    record_size: u64,
+    len: u64,
+    /// The length as of the last commit. Records below it may be committed so they are
+    /// overwritten in a way that can be rolled back.
+    committed_len: u64,
+    extents: StdVec<Pointer>,
+    tx_changes: StdVec<Change>,
+}
+
+#[derive(Debug, Clone)]
+enum Change {
+    Push,
+    Pop,
+    AddExtent,
+    RemoveExtent(Pointer),
+    Rebuild(Box<FixedVecStore>),
+}
+
+fn extent_capacity(extent: usize) -> u64 {
+    FIRST_EXTENT << extent.min(MAX_DOUBLINGS)
+}
+
+/// The index of the first record in `extent`
+fn extent_first_index(extent: usize) -> u64 {
+    if extent <= MAX_DOUBLINGS {
+        FIRST_EXTENT * ((1 << extent) - 1)
+    } else {
+        DOUBLING_RECORDS + (extent - MAX_DOUBLINGS) as u64 * extent_capacity(MAX_DOUBLINGS)
+    }
+}
+
+/// The extent record `index` is in and its offset within it
+fn locate(index: u64) -> (usize, u64) {
+    let extent = if index < DOUBLING_RECORDS {
+        (index / FIRST_EXTENT + 1).ilog2() as usize
+    } else {
+        MAX_DOUBLINGS + ((index - DOUBLING_RECORDS) / extent_capacity(MAX_DOUBLINGS)) as usize
+    };
+    (extent, index - extent_first_index(extent))
+}
+
+impl<T> FixedVec<T>
+where
+    T: bincode::Encode + bincode::Decode + Send,
+{
+    /// Index `list` as a vector of records that encode to at most `record_size` bytes. If the
+    /// list already holds a vector it must have been created with the same `record_size`.
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<FixedVecRecord>,
+        record_size: u64,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        if record_size == 0 {
+            return Err(anyhow!("record size must not be zero"));
+        }
+        let store = Self::load_index(&list.api(tx), record_size)?;
+        Ok(Self {
+            list,
+            store,
+            ty: PhantomData,
+        })
+    }
+
+    fn load_index<F: Backend>(
+        list: &LinkedListApi<'_, F, FixedVecRecord>,
+        record_size: u64,
+    ) -> Result<FixedVecStore> {
+        let mut records = list.iter();
+        let len = match records.next().transpose()? {
+            None => 0,
+            Some(FixedVecRecord::Len {
+                len,
+                record_size: stored_record_size,
+            }) => {
+                if stored_record_size != record_size {
+                    return Err(anyhow!(
+                        "fixed vec was created with a record size of {}",
+                        stored_record_size
+                    ));
+                }
+                len
+            }
+            Some(record) => return Err(anyhow!("fixed vec list starts with {:?}", record)),
+        };
+        let mut extents = records
+            .map(|record| match record? {
+                FixedVecRecord::Extent { start, capacity } => Ok((start, capacity)),
+                record => Err(anyhow!("unexpected {:?} in fixed vec list", record)),
+            })
+            .collect::<Result<StdVec<_>>>()?;
+        extents.reverse();
+        for (i, (_, capacity)) in extents.iter().enumerate() {
+            if *capacity != extent_capacity(i) {
+                return Err(anyhow!("fixed vec extent {} has the wrong capacity", i));
+            }
+        }
+        let n_extents = if len == 0 { 0 } else { locate(len - 1).0 + 1 };
+        if extents.len() != n_extents {
+            return Err(anyhow!(
+                "fixed vec of length {} should have {} extents but has {}",
+                len,
+                n_extents,
+                extents.len()
+            ));
+        }
+
+        Ok(FixedVecStore {
+            record_size,
+            len,
+            committed_len: len,
+            extents: extents.into_iter().map(|(start, _)| start).collect(),
+            tx_changes: Default::default(),
+        })
+    }
+}
+
+impl FixedVecStore {
+    fn record_pointer(&self, index: u64) -> Pointer {
+        let (extent, offset) = locate(index);
+        Pointer(self.extents[extent].0 + offset * self.record_size)
+    }
+}
+
+impl<T: bincode::Encode + bincode::Decode + Send + 'static> IndexStore for FixedVec<T> {
+    type Api<'i, F> = FixedVecApi<'i, F, T>;
+
+    fn tx_fail_rollback(&mut self) {
+        let store = &mut self.store;
+        for change in core::mem::take(&mut store.tx_changes).into_iter().rev() {
+            match change {
+                Change::Push => store.len -= 1,
+                Change::Pop => store.len += 1,
+                Change::AddExtent => {
+                    store.extents.pop();
+                }
+                Change::RemoveExtent(start) => store.extents.push(start),
+                Change::Rebuild(prev_store) => *store = *prev_store,
+            }
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_changes.clear();
+        self.store.committed_len = self.store.len;
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        vec![self.list.slot()]
+    }
+
+    fn create_api<'s, F>(vec: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (list, store) = RefMut::map_split(vec, |vec| (&mut vec.list, &mut vec.store));
+        FixedVecApi {
+            list: LinkedList::create_api(list, io.clone()),
+            io,
+            store,
+            ty: PhantomData,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let store = Self::load_index(&self.list.api(io), self.store.record_size)?;
+        let mut prev_store = core::mem::replace(&mut self.store, store);
+        self.store.tx_changes = core::mem::take(&mut prev_store.tx_changes);
+        self.store.committed_len = prev_store.committed_len;
+        self.store
+            .tx_changes
+            .push(Change::Rebuild(Box::new(prev_store)));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct FixedVecApi<'i, F, T> {
+    io: TxIo<'i, F>,
+    list: LinkedListApi<'i, F, FixedVecRecord>,
+    store: RefMut<'i, FixedVecStore>,
+    ty: PhantomData<T>,
+}
+
+impl<'i, F, T> FixedVecApi<'i, F, T>
+where
+    T: bincode::Encode + bincode::Decode,
+    F: Backend + 'i,
+{
+    /// Replace the `Len` record with one for the current length
+    fn write_len(&self) -> Result<()> {
+        self.list.push(&FixedVecRecord::Len {
+            len: self.store.len,
+            record_size: self.store.record_size,
+        })?;
+        Ok(())
+    }
+
+    /// Append `value` returning its index.
+    ///
+    /// If records were popped earlier in the transaction the record is written over the last one
+    /// popped. That one is put back if the transaction fails but like
+    /// [`TxIo::overwrite`](crate::TxIo::overwrite) it may be left half written over if the process
+    /// dies before the transaction finishes.
+    pub fn push(&mut self, value: &T) -> Result<u64> {
+        let mut buf = vec![];
+        let encoded_len = self
+            .io
+            .value_encoding()
+            .encode_into_std_write(value, &mut buf)?;
+        let record_size = self.store.record_size;
+        if encoded_len as u64 > record_size {
+            return Err(anyhow!(
+                "record encodes to {} bytes but the record size is {}",
+                encoded_len,
+                record_size
+            ));
+        }
+        buf.resize(record_size as usize, 0);
+
+        let index = self.store.len;
+        let (extent, _) = locate(index);
+        // take off the old length
+        self.list.pop()?;
+        if extent == self.store.extents.len() {
+            let capacity = extent_capacity(extent);
+            let start = self.io.allocate(capacity * record_size)?;
+            self.list
+                .push(&FixedVecRecord::Extent { start, capacity })?;
+            self.store.extents.push(start);
+            self.store.tx_changes.push(Change::AddExtent);
+        }
+        let pointer = self.store.record_pointer(index);
+        if index < self.store.committed_len {
+            self.io.overwrite_bytes(pointer, &buf)?;
+        } else {
+            self.io.write_bytes(pointer, &buf)?;
+        }
+        self.store.len += 1;
+        self.store.tx_changes.push(Change::Push);
+        self.write_len()?;
+        Ok(index)
+    }
+
+    /// Remove the last record and return it
+    pub fn pop(&mut self) -> Result<Option<T>> {
+        let index = match self.store.len.checked_sub(1) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let value = self.io.raw_read_at(self.store.record_pointer(index))?;
+        self.list.pop()?;
+        let (extent, offset) = locate(index);
+        if offset == 0 {
+            self.list.pop()?;
+            let start = self.store.extents.pop().expect("record was in it");
+            self.io
+                .free_region(start, extent_capacity(extent) * self.store.record_size);
+            self.store.tx_changes.push(Change::RemoveExtent(start));
+        }
+        self.store.len -= 1;
+        self.store.tx_changes.push(Change::Pop);
+        self.write_len()?;
+        Ok(Some(value))
+    }
+
+    pub fn get(&self, index: u64) -> Result<Option<T>> {
+        if index >= self.store.len {
+            return Ok(None);
+        }
+        Ok(Some(self.io.raw_read_at(self.store.record_pointer(index))?))
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<T>> + '_ {
+        (0..self.store.len).map(|index| self.io.raw_read_at(self.store.record_pointer(index)))
+    }
+
+    pub fn len(&self) -> u64 {
+        self.store.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.len == 0
+    }
+
+    pub fn record_size(&self) -> u64 {
+        self.store.record_size
+    }
+}
@@ -0,0 +1,98 @@
+use super::IndexStore;
+use crate::{sha256::sha256, Backend, LinkedList, LinkedListApi, ListSlot, TxIo, BINCODE_CONFIG};
+use anyhow::{anyhow, Result};
+use std::{cell::RefMut, marker::PhantomData};
+
+/// A list where every entry is stored along with a SHA-256 hash chained over all the entries up to
+/// and including it.
+///
+/// The hash at the head of the list commits to the whole list so comparing [`digest`]s is enough
+/// to tell whether two replicas hold the same entries. Each entry's hash is
+/// `SHA256(previous hash || value)` starting from 32 zero bytes. Values are hashed with the
+/// default bincode config rather than the database's [`ValueEncoding`](crate::ValueEncoding) so
+/// databases with different encodings can be compared.
+///
+/// [`digest`]: HashChainApi::digest
+#[derive(Debug)]
+pub struct HashChain<T> {
+    list: LinkedList<([u8; 32], T)>,
+}
+
+impl<T> HashChain<T> {
+    pub fn new(list: LinkedList<([u8; 32], T)>) -> Self {
+        Self { list }
+    }
+}
+
+impl<T: Send + 'static> IndexStore for HashChain<T> {
+    type Api<'i, F> = HashChainApi<'i, F, T>;
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        vec![self.list.slot()]
+    }
+
+    fn create_api<'s, F>(chain: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        HashChainApi {
+            list: LinkedList::create_api(RefMut::map(chain, |chain| &mut chain.list), io),
+            ty: PhantomData,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.list.rebuild(io)
+    }
+}
+
+#[derive(Debug)]
+pub struct HashChainApi<'i, F, T> {
+    list: LinkedListApi<'i, F, ([u8; 32], T)>,
+    ty: PhantomData<T>,
+}
+
+fn chain<T: bincode::Encode>(prev: &[u8; 32], value: &T) -> Result<[u8; 32]> {
+    let value = bincode::encode_to_vec(value, BINCODE_CONFIG)?;
+    Ok(sha256(&[prev, &value]))
+}
+
+impl<'i, F, T> HashChainApi<'i, F, T>
+where
+    F: Backend,
+    T: bincode::Encode + bincode::Decode,
+{
+    /// The hash committing to every entry in the list
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        Ok(self.list.head()?.map(|(hash, _)| hash).unwrap_or([0u8; 32]))
+    }
+
+    /// Push `value` returning the new digest
+    pub fn push(&self, value: T) -> Result<[u8; 32]> {
+        let hash = chain(&self.digest()?, &value)?;
+        self.list.push(&(hash, value))?;
+        Ok(hash)
+    }
+
+    pub fn pop(&self) -> Result<Option<T>> {
+        Ok(self.list.pop()?.map(|(_, value)| value))
+    }
+
+    /// Iterate over the values from newest to oldest
+    pub fn iter(&self) -> impl Iterator<Item = Result<T>> + '_ {
+        self.list.iter().map(|res| res.map(|(_, value)| value))
+    }
+
+    /// Recompute the hashes of all the entries and check they match the stored ones
+    pub fn verify(&self) -> Result<()> {
+        let entries = self.list.iter().collect::<Result<Vec<_>>>()?;
+        let mut prev = [0u8; 32];
+        for (i, (hash, value)) in entries.iter().rev().enumerate() {
+            prev = chain(&prev, value)?;
+            if prev != *hash {
+                return Err(anyhow!("hash of entry {} doesn't match", i));
+            }
+        }
+        Ok(())
+    }
+}
@@ -2,26 +2,103 @@ mod btreemap;
 pub use btreemap::*;
 mod vec;
 pub use vec::*;
+mod sorted_vec;
+pub use sorted_vec::*;
 mod cell;
 pub use cell::*;
+mod queue;
+pub use queue::*;
+mod deque;
+pub use deque::*;
+mod bitset;
+pub use bitset::*;
+mod bloom;
+pub use bloom::*;
+mod text;
+pub use text::*;
+mod ring;
+pub use ring::*;
+mod fixed_vec;
+pub use fixed_vec::*;
+mod cell_history;
+pub use cell_history::*;
+mod counter;
+pub use counter::*;
+mod projection;
+pub use projection::*;
+mod changeset;
+pub use changeset::*;
+mod encrypted;
+pub use encrypted::*;
+mod hash_chain;
+pub use hash_chain::*;
+mod unique;
+pub use unique::*;
+mod relation;
+pub use relation::*;
+mod big_btreemap;
+pub use big_btreemap::*;
+mod big_hashmap;
+pub use big_hashmap::*;
+mod blob;
+pub use blob::*;
+mod ref_counted;
+pub use ref_counted::*;
+mod parallel;
+pub use parallel::*;
+mod table;
+pub use table::*;
 
-use crate::TxIo;
+use crate::{Backend, Transaction, TxIo};
+use anyhow::{anyhow, Result};
+use core::fmt;
 use std::cell::RefMut;
 
 pub trait IndexStore: 'static + Send {
     type Api<'i, F>;
     fn tx_fail_rollback(&mut self) {}
     fn tx_success(&mut self) {}
+    /// Called on every index once the transaction's query has succeeded but before anything is
+    /// committed. Returning an error fails the transaction and [`tx_fail_rollback`] is called as
+    /// usual. Use this to check invariants that only have to hold once the transaction is done.
+    ///
+    /// [`tx_fail_rollback`]: Self::tx_fail_rollback
+    fn tx_pre_commit(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Called by [`Transaction::store_index`] just before the index is stored. An index can take
+    /// the lists it needs for itself here (e.g. to keep a cursor or snapshot in) so whoever
+    /// creates it doesn't have to take every one of its internal lists up front. Lists taken here
+    /// have to be returned from [`owned_lists`](Self::owned_lists) like any other. Returning an
+    /// error means the index isn't stored.
+    fn on_attach<F: Backend>(&mut self, _tx: &mut Transaction<'_, F>) -> Result<()>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
     fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot>;
     fn create_api<'s, F>(store: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
     where
         Self: Sized;
+    /// Throw away the in-memory state and rebuild it from the lists. This happens as part of the
+    /// transaction so if the transaction fails the old state must be restored in
+    /// [`tx_fail_rollback`](Self::tx_fail_rollback).
+    ///
+    /// The default implementation returns an error.
+    fn rebuild<F: Backend>(&mut self, _io: &TxIo<'_, F>) -> Result<()>
+    where
+        Self: Sized,
+    {
+        Err(anyhow!("this index doesn't support being rebuilt"))
+    }
 }
 
 /// plumbing trait for doing dynamic dispatch on a RefCell<T> where T: IndexStore
 pub trait RefCellIndexStore: 'static + Send {
     fn tx_fail_rollback(&self);
     fn tx_success(&self);
+    fn tx_pre_commit(&self) -> Result<()>;
     fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot>;
     fn as_any(&self) -> &dyn core::any::Any;
 }
@@ -35,6 +112,10 @@ impl<T: IndexStore> RefCellIndexStore for core::cell::RefCell<T> {
         self.borrow_mut().tx_success()
     }
 
+    fn tx_pre_commit(&self) -> Result<()> {
+        self.borrow_mut().tx_pre_commit()
+    }
+
     fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
         self.borrow().owned_lists()
     }
@@ -43,3 +124,70 @@ impl<T: IndexStore> RefCellIndexStore for core::cell::RefCell<T> {
         self
     }
 }
+
+/// The difference between the in-memory state of an index and what it finds when it re-scans its
+/// list. This is the error returned from the `debug_validate` method of the built-in indexes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexDiff<E> {
+    pub mismatches: std::vec::Vec<IndexMismatch<E>>,
+}
+
+/// A position in an index where the in-memory state and the list disagree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexMismatch<E> {
+    pub position: usize,
+    pub in_memory: Option<E>,
+    pub on_disk: Option<E>,
+}
+
+impl<E> IndexDiff<E>
+where
+    E: PartialEq + fmt::Debug + Send + Sync + 'static,
+{
+    /// Compares the two sequences of entries position by position and errors with the diff if they
+    /// don't match.
+    pub(crate) fn check(
+        in_memory: impl IntoIterator<Item = E>,
+        on_disk: impl IntoIterator<Item = E>,
+    ) -> Result<()> {
+        let mut in_memory = in_memory.into_iter();
+        let mut on_disk = on_disk.into_iter();
+        let mut mismatches = vec![];
+        for position in 0.. {
+            match (in_memory.next(), on_disk.next()) {
+                (None, None) => break,
+                (in_memory, on_disk) if in_memory != on_disk => mismatches.push(IndexMismatch {
+                    position,
+                    in_memory,
+                    on_disk,
+                }),
+                _ => {}
+            }
+        }
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(IndexDiff { mismatches }.into())
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for IndexDiff<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "index doesn't match its list at {} positions",
+            self.mismatches.len()
+        )?;
+        for mismatch in &self.mismatches {
+            write!(
+                f,
+                "\n  {}: in memory {:?} on disk {:?}",
+                mismatch.position, mismatch.in_memory, mismatch.on_disk
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for IndexDiff<E> {}
@@ -0,0 +1,89 @@
+use super::IndexStore;
+use crate::{Backend, IndexHandle, LinkedList, ListSlot, LlsDb, Pointer, PooledReader, ReaderPool};
+use anyhow::Result;
+use std::collections::BTreeMap as StdBTreeMap;
+
+/// The committed heads of the lists when [`LlsDb::load_indexes`] was called
+#[derive(Debug, Clone)]
+pub struct ListHeads(StdBTreeMap<ListSlot, Pointer>);
+
+impl ListHeads {
+    /// The head of `list`. [`Pointer::NULL`] if the list is empty or hasn't been committed.
+    pub fn head<T>(&self, list: &LinkedList<T>) -> Pointer {
+        self.0.get(&list.slot()).copied().unwrap_or(Pointer::NULL)
+    }
+}
+
+/// An index that can be loaded on its own thread through a [`PooledReader`] (see
+/// [`LlsDb::load_indexes`]).
+pub trait ParallelLoad: Send {
+    type Index: IndexStore;
+    /// Read the lists the index is built from starting at their `heads` and build it
+    fn load(self, heads: &ListHeads, reader: &mut PooledReader<'_>) -> Result<Self::Index>;
+}
+
+/// A tuple of [`ParallelLoad`]s that [`LlsDb::load_indexes`] loads at the same time
+pub trait ParallelLoads {
+    type Handles;
+    fn load_all<F: Backend>(self, db: &mut LlsDb<F>, pool: &ReaderPool) -> Result<Self::Handles>;
+}
+
+macro_rules! impl_parallel_loads {
+    ($($loader:ident $index:ident),+) => {
+        impl<$($loader: ParallelLoad),+> ParallelLoads for ($($loader,)+) {
+            type Handles = ($(IndexHandle<$loader::Index>,)+);
+
+            fn load_all<F: Backend>(
+                self,
+                db: &mut LlsDb<F>,
+                pool: &ReaderPool,
+            ) -> Result<Self::Handles> {
+                let heads = &ListHeads(db.committed_heads());
+                let ($($index,)+) = self;
+                let ($($index,)+) = std::thread::scope(|scope| {
+                    $(
+                        let $index = scope.spawn(move || {
+                            $index.load(heads, &mut pool.reader()?)
+                        });
+                    )+
+                    ($(
+                        $index
+                            .join()
+                            .unwrap_or_else(|panic| std::panic::resume_unwind(panic)),
+                    )+)
+                });
+                $(let $index = $index?;)+
+                db.execute(|tx| Ok(($(tx.store_index($index),)+)))
+            }
+        }
+    };
+}
+
+impl_parallel_loads!(A a);
+impl_parallel_loads!(A a, B b);
+impl_parallel_loads!(A a, B b, C c);
+impl_parallel_loads!(A a, B b, C c, D d);
+impl_parallel_loads!(A a, B b, C c, D d, E e);
+impl_parallel_loads!(A a, B b, C c, D d, E e, G g);
+impl_parallel_loads!(A a, B b, C c, D d, E e, G g, H h);
+impl_parallel_loads!(A a, B b, C c, D d, E e, G g, H h, I i);
+
+impl<F: Backend> LlsDb<F> {
+    /// Load several indexes at once each on its own thread and with its own reader from `pool`.
+    ///
+    /// Loading an index means reading every entry of its lists which for big lists takes a while.
+    /// Since each index only reads its own lists they don't have to wait for each other. `loaders`
+    /// is a tuple of [`ParallelLoad`]s like the one from [`Vec::loader`](super::Vec::loader). The
+    /// indexes are stored once they've all loaded and their handles returned in the same order.
+    ///
+    /// The lists are read as they were last committed so they should be taken in a committed
+    /// transaction beforehand. Like everything read through a [`ReaderPool`] this only works for
+    /// plain lists and not ones holding [`Mut`](crate::Mut) entries.
+    pub fn load_indexes<L: ParallelLoads>(
+        &mut self,
+        pool: &ReaderPool,
+        loaders: L,
+    ) -> Result<L::Handles> {
+        loaders.load_all(self, pool)
+    }
+}
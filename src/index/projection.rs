@@ -0,0 +1,176 @@
+use super::{Cell, CellApi, IndexStore};
+use crate::{Backend, LinkedList, LinkedListApi, ListSlot, Pointer, Transaction, TxIo};
+use anyhow::{anyhow, Result};
+use std::cell::{RefCell, RefMut};
+
+/// An index that is derived from a list of events by applying them in order. See [`Projection`].
+pub trait EventIndex<E>: IndexStore {
+    /// Update the index with the next event
+    fn apply<'i, F: Backend + 'i>(api: &mut Self::Api<'i, F>, event: E) -> Result<()>;
+}
+
+/// An append-only list of events along with an index derived from them.
+///
+/// The projection keeps a cursor recording the last event applied to the index. When it's
+/// created (and whenever [`catch_up`](ProjectionApi::catch_up) is called) only the events pushed
+/// after the cursor are applied. Events pushed through [`ProjectionApi::push`] are applied
+/// straight away. The cursor is advanced in the same transaction as the index is updated so
+/// the two never disagree.
+#[derive(Debug)]
+pub struct Projection<E, I> {
+    events: LinkedList<E>,
+    derived: Derived<I>,
+}
+
+#[derive(Debug)]
+struct Derived<I> {
+    cursor: Cell<Pointer>,
+    index: I,
+}
+
+impl<E, I> Projection<E, I>
+where
+    E: bincode::Encode + bincode::Decode + Send + 'static,
+    I: EventIndex<E>,
+{
+    /// Pair the `events` list with `index` storing the cursor in `cursor`. Any events that have
+    /// been pushed since the cursor was last advanced are applied to `index`.
+    pub fn new<'tx, F: Backend>(
+        events: LinkedList<E>,
+        cursor: LinkedList<Pointer>,
+        index: I,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        let cursor = Cell::new_with_initial_value(cursor, &Pointer::NULL, tx)?;
+        let projection = RefCell::new(Self {
+            events,
+            derived: Derived { cursor, index },
+        });
+        Self::create_api(projection.borrow_mut(), tx.io.clone()).catch_up()?;
+        Ok(projection.into_inner())
+    }
+}
+
+impl<E, I> IndexStore for Projection<E, I>
+where
+    E: bincode::Encode + bincode::Decode + Send + 'static,
+    I: EventIndex<E>,
+{
+    type Api<'i, F> = ProjectionApi<'i, F, E, I>;
+
+    fn tx_fail_rollback(&mut self) {
+        self.derived.cursor.tx_fail_rollback();
+        self.derived.index.tx_fail_rollback();
+    }
+
+    fn tx_success(&mut self) {
+        self.derived.cursor.tx_success();
+        self.derived.index.tx_success();
+    }
+
+    fn tx_pre_commit(&mut self) -> Result<()> {
+        self.derived.index.tx_pre_commit()
+    }
+
+    fn on_attach<F: Backend>(&mut self, tx: &mut Transaction<'_, F>) -> Result<()> {
+        self.derived.index.on_attach(tx)
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        let mut lists = vec![self.events.slot()];
+        lists.extend(self.derived.cursor.owned_lists());
+        lists.extend(self.derived.index.owned_lists());
+        lists
+    }
+
+    fn create_api<'s, F>(projection: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let events_slot = projection.events.slot();
+        let (events, derived) = RefMut::map_split(projection, |projection| {
+            (&mut projection.events, &mut projection.derived)
+        });
+        let (cursor, index) =
+            RefMut::map_split(derived, |derived| (&mut derived.cursor, &mut derived.index));
+        ProjectionApi {
+            events_slot,
+            events: LinkedList::create_api(events, io.clone()),
+            cursor: Cell::create_api(cursor, io.clone()),
+            index: I::create_api(index, io.clone()),
+            io,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.derived.cursor.rebuild(io)?;
+        self.derived.index.rebuild(io)
+    }
+}
+
+pub struct ProjectionApi<'i, F, E, I: IndexStore> {
+    io: TxIo<'i, F>,
+    events_slot: ListSlot,
+    events: LinkedListApi<'i, F, E>,
+    cursor: CellApi<'i, F, Pointer>,
+    index: I::Api<'i, F>,
+}
+
+impl<'i, F, E, I> ProjectionApi<'i, F, E, I>
+where
+    F: Backend + 'i,
+    E: bincode::Encode + bincode::Decode,
+    I: EventIndex<E>,
+{
+    /// Push an event and apply it to the index
+    pub fn push(&mut self, event: E) -> Result<()> {
+        self.events.push(&event)?;
+        I::apply(&mut self.index, event)?;
+        self.cursor.replace(&self.events.head_pointer())?;
+        Ok(())
+    }
+
+    /// Apply the events that were pushed onto the list without going through
+    /// [`push`](Self::push). Returns the number of events applied.
+    pub fn catch_up(&mut self) -> Result<usize> {
+        let cursor = self.cursor.get()?;
+        let head = self.events.head_pointer();
+        if head == cursor {
+            return Ok(0);
+        }
+        let mut new_events = vec![];
+        let mut iter = self.io.iter(self.events_slot);
+        loop {
+            match iter.next_with_handle::<E>().transpose()? {
+                Some((handle, _)) if handle.entry_pointer.this_entry == cursor => break,
+                Some((_, event)) => new_events.push(event),
+                None if cursor == Pointer::NULL => break,
+                None => return Err(anyhow!("projection cursor isn't in its event list")),
+            }
+        }
+        let n_events = new_events.len();
+        for event in new_events.into_iter().rev() {
+            I::apply(&mut self.index, event)?;
+        }
+        self.cursor.replace(&head)?;
+        Ok(n_events)
+    }
+
+    /// The derived index
+    pub fn index(&mut self) -> &mut I::Api<'i, F> {
+        &mut self.index
+    }
+
+    /// Iterate over the events from newest to oldest
+    pub fn events(&self) -> impl Iterator<Item = Result<E>> + '_ {
+        self.events.iter()
+    }
+}
+
+impl<F, E, I: IndexStore> core::fmt::Debug for ProjectionApi<'_, F, E, I> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ProjectionApi")
+            .field("events_slot", &self.events_slot)
+            .finish_non_exhaustive()
+    }
+}
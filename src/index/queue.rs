@@ -0,0 +1,245 @@
+use crate::{
+    Backend, EntryPointer, LinkedList, LinkedListMut, LinkedListMutApi, Mut, Pointer, Transaction,
+    TxIo,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    cell::RefMut,
+    collections::{BTreeMap, VecDeque},
+    vec::Vec as StdVec,
+};
+
+use super::IndexStore;
+
+/// A first in first out queue of jobs where taking a job off the queue doesn't remove it.
+///
+/// [`dequeue`] marks a job as in-flight and it is only removed from the list once it is
+/// [`ack`]ed. In-flight jobs can be put back with [`nack`] or [`requeue`]. Being in-flight isn't
+/// persisted so any jobs that are in-flight when the database is closed will be pending again
+/// when it's loaded.
+///
+/// [`dequeue`]: JobQueueApi::dequeue
+/// [`ack`]: JobQueueApi::ack
+/// [`nack`]: JobQueueApi::nack
+/// [`requeue`]: JobQueueApi::requeue
+#[derive(Debug)]
+pub struct JobQueue<T> {
+    list: LinkedListMut<T>,
+    store: JobQueueStore,
+}
+
+/// Refers to a job that has been dequeued
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobHandle(EntryPointer);
+
+#[derive(Debug)]
+struct JobQueueStore {
+    /// oldest job first
+    pending: VecDeque<EntryPointer>,
+    in_flight: BTreeMap<Pointer, EntryPointer>,
+    tx_changes: StdVec<Change>,
+}
+
+#[derive(Debug)]
+enum Change {
+    Enqueue,
+    Dequeue(EntryPointer),
+    Ack(EntryPointer),
+    Nack(EntryPointer),
+    Requeue(EntryPointer),
+    Rebuild(VecDeque<EntryPointer>, BTreeMap<Pointer, EntryPointer>),
+}
+
+impl<T> JobQueue<T>
+where
+    T: bincode::Encode + bincode::Decode + Send,
+{
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<Mut<T>>,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        let list = LinkedListMut(list);
+        let pending = Self::load_index(&list.api(&tx.io))?;
+
+        Ok(Self {
+            list,
+            store: JobQueueStore {
+                pending,
+                in_flight: Default::default(),
+                tx_changes: Default::default(),
+            },
+        })
+    }
+
+    fn load_index<F: Backend>(list: &LinkedListMutApi<'_, F, T>) -> Result<VecDeque<EntryPointer>> {
+        let mut pending = VecDeque::new();
+        for next_pointer in list.iter_pointers() {
+            pending.push_front(next_pointer?);
+        }
+        Ok(pending)
+    }
+}
+
+impl<T: bincode::Encode + bincode::Decode + 'static + Send> IndexStore for JobQueue<T> {
+    type Api<'i, F> = JobQueueApi<'i, F, T>;
+
+    fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
+        vec![self.list.0.slot()]
+    }
+
+    fn tx_fail_rollback(&mut self) {
+        let JobQueueStore {
+            pending,
+            in_flight,
+            tx_changes,
+        } = &mut self.store;
+        for change in tx_changes.drain(..).rev() {
+            match change {
+                Change::Enqueue => assert!(pending.pop_back().is_some()),
+                Change::Dequeue(job) => {
+                    in_flight.remove(&job.this_entry);
+                    pending.push_front(job);
+                }
+                Change::Ack(job) => {
+                    in_flight.insert(job.this_entry, job);
+                }
+                Change::Nack(job) => {
+                    assert_eq!(pending.pop_front(), Some(job));
+                    in_flight.insert(job.this_entry, job);
+                }
+                Change::Requeue(job) => {
+                    assert_eq!(pending.pop_back(), Some(job));
+                    in_flight.insert(job.this_entry, job);
+                }
+                Change::Rebuild(prev_pending, prev_in_flight) => {
+                    *pending = prev_pending;
+                    *in_flight = prev_in_flight;
+                }
+            }
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_changes.clear();
+    }
+
+    fn create_api<'s, F>(queue: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (list, store) = RefMut::map_split(queue, |queue| (&mut queue.list, &mut queue.store));
+        let list = LinkedListMut::create_api(list, io.clone());
+        JobQueueApi { io, list, store }
+    }
+
+    /// Rebuilding puts all the in-flight jobs back in the queue
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let pending = Self::load_index(&self.list.api(io))?;
+        let prev_pending = core::mem::replace(&mut self.store.pending, pending);
+        let prev_in_flight = core::mem::take(&mut self.store.in_flight);
+        self.store
+            .tx_changes
+            .push(Change::Rebuild(prev_pending, prev_in_flight));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct JobQueueApi<'i, F, T> {
+    io: TxIo<'i, F>,
+    list: LinkedListMutApi<'i, F, T>,
+    store: RefMut<'i, JobQueueStore>,
+}
+
+impl<'i, F, T> JobQueueApi<'i, F, T>
+where
+    T: bincode::Encode + bincode::Decode,
+    F: Backend + 'i,
+{
+    /// Add a job to the back of the queue
+    pub fn enqueue(&mut self, value: T) -> Result<()> {
+        let handle = self.list.push(value)?;
+        self.store.pending.push_back(handle.entry_pointer);
+        self.store.tx_changes.push(Change::Enqueue);
+        Ok(())
+    }
+
+    /// Take the job at the front of the queue and mark it as in-flight. The job stays in the
+    /// database until it is [`ack`](Self::ack)ed.
+    pub fn dequeue(&mut self) -> Result<Option<(JobHandle, T)>> {
+        let job = match self.store.pending.front() {
+            Some(job) => *job,
+            None => return Ok(None),
+        };
+        let (_, value) = self.io.read_at::<Mut<T>>(job)?;
+        let value = value.into_value().expect("JobQueue only points to values");
+        self.store.pending.pop_front();
+        self.store.in_flight.insert(job.this_entry, job);
+        self.store.tx_changes.push(Change::Dequeue(job));
+        Ok(Some((JobHandle(job), value)))
+    }
+
+    /// Look at the job at the front of the queue without dequeuing it
+    pub fn peek(&self) -> Result<Option<T>> {
+        match self.store.pending.front() {
+            Some(job) => {
+                let (_, value) = self.io.read_at::<Mut<T>>(*job)?;
+                Ok(Some(
+                    value.into_value().expect("JobQueue only points to values"),
+                ))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Finish an in-flight job, removing it from the database
+    pub fn ack(&mut self, handle: JobHandle) -> Result<()> {
+        let job = self.take_in_flight(handle)?;
+        let (entry_handle, _) = self.io.read_at::<Mut<T>>(job)?;
+        if let Err(e) = self.list.unlink(entry_handle) {
+            self.store.in_flight.insert(job.this_entry, job);
+            return Err(e);
+        }
+        self.store.tx_changes.push(Change::Ack(job));
+        Ok(())
+    }
+
+    /// Give up on an in-flight job and put it back at the front of the queue so it's the next
+    /// one dequeued
+    pub fn nack(&mut self, handle: JobHandle) -> Result<()> {
+        let job = self.take_in_flight(handle)?;
+        self.store.pending.push_front(job);
+        self.store.tx_changes.push(Change::Nack(job));
+        Ok(())
+    }
+
+    /// Give up on an in-flight job and put it at the back of the queue
+    pub fn requeue(&mut self, handle: JobHandle) -> Result<()> {
+        let job = self.take_in_flight(handle)?;
+        self.store.pending.push_back(job);
+        self.store.tx_changes.push(Change::Requeue(job));
+        Ok(())
+    }
+
+    fn take_in_flight(&mut self, JobHandle(job): JobHandle) -> Result<EntryPointer> {
+        self.store
+            .in_flight
+            .remove(&job.this_entry)
+            .ok_or(anyhow!("job is not in-flight"))
+    }
+
+    /// The number of jobs waiting to be dequeued
+    pub fn len_pending(&self) -> usize {
+        self.store.pending.len()
+    }
+
+    /// The number of jobs that have been dequeued but not acknowledged
+    pub fn len_in_flight(&self) -> usize {
+        self.store.in_flight.len()
+    }
+
+    /// Whether there are no pending or in-flight jobs
+    pub fn is_empty(&self) -> bool {
+        self.store.pending.is_empty() && self.store.in_flight.is_empty()
+    }
+}
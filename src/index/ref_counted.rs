@@ -0,0 +1,166 @@
+use super::{BTreeMapRemove, BTreeMapRemoveApi, IndexStore};
+use crate::{Backend, LinkedList, ListSlot, Mut, TxIo};
+use anyhow::{anyhow, Result};
+use core::fmt;
+use std::cell::RefMut;
+
+/// Values that can be owned by many other entries at once like an [`Rc`](std::rc::Rc).
+///
+/// Each value gets an [`RcId`] that other indexes can store to refer to it. The value starts out
+/// with one reference and is only removed once [`release_ref`] has been called once for it and
+/// once for every [`retain_ref`]. The reference counts are kept in their own list so changing
+/// them doesn't rewrite the value and like everything else they are rolled back if the
+/// transaction fails.
+///
+/// Ids of removed values may be given out again so an id must not be used after its last
+/// reference has been released.
+///
+/// [`release_ref`]: RefCountedApi::release_ref
+/// [`retain_ref`]: RefCountedApi::retain_ref
+#[derive(Debug)]
+pub struct RefCounted<T> {
+    values: BTreeMapRemove<RcId, T>,
+    refs: BTreeMapRemove<RcId, u64>,
+}
+
+/// Identifies a value in a [`RefCounted`]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, bincode::Encode, bincode::Decode,
+)]
+pub struct RcId(pub u64);
+
+impl fmt::Display for RcId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+impl<T> RefCounted<T>
+where
+    T: bincode::Encode + bincode::Decode,
+{
+    pub fn new<'tx, F: Backend>(
+        values: LinkedList<Mut<(RcId, T)>>,
+        refs: LinkedList<Mut<(RcId, u64)>>,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        let io = tx.as_ref();
+        Ok(Self {
+            values: BTreeMapRemove::new(values, io)?,
+            refs: BTreeMapRemove::new(refs, io)?,
+        })
+    }
+}
+
+impl<T> IndexStore for RefCounted<T>
+where
+    T: bincode::Encode + bincode::Decode + Send + 'static,
+{
+    type Api<'i, F> = RefCountedApi<'i, F, T>;
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        let mut lists = self.values.owned_lists();
+        lists.extend(self.refs.owned_lists());
+        lists
+    }
+
+    fn create_api<'s, F>(rc: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (values, refs) = RefMut::map_split(rc, |rc| (&mut rc.values, &mut rc.refs));
+        RefCountedApi {
+            values: BTreeMapRemove::create_api(values, io.clone()),
+            refs: BTreeMapRemove::create_api(refs, io),
+        }
+    }
+
+    fn tx_fail_rollback(&mut self) {
+        self.values.tx_fail_rollback();
+        self.refs.tx_fail_rollback();
+    }
+
+    fn tx_success(&mut self) {
+        self.values.tx_success();
+        self.refs.tx_success();
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.values.rebuild(io)?;
+        self.refs.rebuild(io)
+    }
+}
+
+pub struct RefCountedApi<'tx, F, T> {
+    values: BTreeMapRemoveApi<'tx, F, RcId, T>,
+    refs: BTreeMapRemoveApi<'tx, F, RcId, u64>,
+}
+
+impl<'tx, F, T> RefCountedApi<'tx, F, T>
+where
+    T: bincode::Encode + bincode::Decode + PartialEq,
+    F: Backend,
+{
+    /// Store `value` with a single reference to it
+    pub fn insert(&mut self, value: T) -> Result<RcId> {
+        let id = match self.values.last_key() {
+            Some(last) => RcId(last.0 + 1),
+            None => RcId(0),
+        };
+        self.values.insert(id, value)?;
+        self.refs.insert(id, 1)?;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: RcId) -> Result<Option<T>> {
+        self.values.get(&id)
+    }
+
+    /// Take another reference to the value at `id` returning how many there are now
+    pub fn retain_ref(&mut self, id: RcId) -> Result<u64> {
+        let n_refs = self.ref_count(id)?;
+        if n_refs == 0 {
+            return Err(anyhow!("no value with id {}", id));
+        }
+        self.refs.insert(id, n_refs + 1)?;
+        Ok(n_refs + 1)
+    }
+
+    /// Give up a reference to the value at `id` removing it if it was the last one. Returns how
+    /// many references are left.
+    pub fn release_ref(&mut self, id: RcId) -> Result<u64> {
+        match self.ref_count(id)? {
+            0 => Err(anyhow!("no value with id {}", id)),
+            1 => {
+                self.refs.remove(&id)?;
+                self.values.remove(&id)?;
+                Ok(0)
+            }
+            n_refs => {
+                self.refs.insert(id, n_refs - 1)?;
+                Ok(n_refs - 1)
+            }
+        }
+    }
+
+    /// How many references there are to the value at `id`
+    pub fn ref_count(&self, id: RcId) -> Result<u64> {
+        Ok(self.refs.get(&id)?.unwrap_or(0))
+    }
+
+    pub fn contains(&self, id: RcId) -> bool {
+        self.values.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<(RcId, T)>> + '_ {
+        self.values.iter()
+    }
+}
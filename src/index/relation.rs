@@ -0,0 +1,321 @@
+use super::{BTreeMapRemove, BTreeMapRemoveApi, IndexStore};
+use crate::{Backend, LinkedList, ListSlot, Mut, TxIo};
+use anyhow::Result;
+use core::{borrow::Borrow, fmt};
+use std::{
+    cell::RefMut,
+    collections::{BTreeMap as StdBTreeMap, BTreeSet},
+};
+
+/// Two maps where each entry in the second (the children) refers to a key in the first (the
+/// parents).
+///
+/// A child can only be inserted if its parent is there and a parent can't be removed out from
+/// under its children. What happens instead is up to [`OnRemove`]. The children referring to each
+/// parent are kept in memory so they can be found without scanning.
+#[derive(Debug)]
+pub struct Relation<K, V, CK, CV> {
+    parents: BTreeMapRemove<K, V>,
+    children: Children<K, CK, CV>,
+}
+
+/// What to do when a parent in a [`Relation`] that still has children is removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnRemove {
+    /// Fail with [`IntegrityError::Referenced`]
+    Restrict,
+    /// Remove the children along with it
+    Cascade,
+}
+
+/// The error returned when a change to a [`Relation`] would leave a child without its parent.
+/// Since it is returned through [`anyhow`] use `downcast_ref` to tell it apart from other errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityError<K> {
+    /// A child was inserted referring to a parent that isn't there
+    Missing { key: K },
+    /// A parent with children was removed under [`OnRemove::Restrict`]
+    Referenced { key: K, n_children: usize },
+}
+
+impl<K: fmt::Debug> fmt::Display for IntegrityError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityError::Missing { key } => write!(f, "parent {:?} doesn't exist", key),
+            IntegrityError::Referenced { key, n_children } => write!(
+                f,
+                "parent {:?} can't be removed while {} children refer to it",
+                key, n_children
+            ),
+        }
+    }
+}
+
+impl<K: fmt::Debug> std::error::Error for IntegrityError<K> {}
+
+#[derive(Debug)]
+struct Children<K, CK, CV> {
+    map: BTreeMapRemove<CK, (K, CV)>,
+    store: RelationStore<K, CK>,
+}
+
+#[derive(Debug)]
+struct RelationStore<K, CK> {
+    on_remove: OnRemove,
+    referenced_by: StdBTreeMap<K, BTreeSet<CK>>,
+    tx_changes: Vec<Change<K, CK>>,
+}
+
+#[derive(Debug)]
+enum Change<K, CK> {
+    Link(K, CK),
+    Unlink(K, CK),
+    Rebuild(StdBTreeMap<K, BTreeSet<CK>>),
+}
+
+impl<K: Ord, CK: Ord> RelationStore<K, CK> {
+    fn link(&mut self, key: K, child_key: CK) {
+        self.referenced_by.entry(key).or_default().insert(child_key);
+    }
+
+    fn unlink(&mut self, key: &K, child_key: &CK) {
+        if let Some(children) = self.referenced_by.get_mut(key) {
+            children.remove(child_key);
+            if children.is_empty() {
+                self.referenced_by.remove(key);
+            }
+        }
+    }
+}
+
+impl<K, V, CK, CV> Relation<K, V, CK, CV>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone,
+    V: bincode::Encode + bincode::Decode,
+    CK: Ord + bincode::Encode + bincode::Decode + Clone,
+    CV: bincode::Encode + bincode::Decode,
+{
+    pub fn new<'tx, F: Backend>(
+        parents: LinkedList<Mut<(K, V)>>,
+        children: LinkedList<Mut<(CK, (K, CV))>>,
+        on_remove: OnRemove,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        let io = tx.as_ref();
+        let parents = BTreeMapRemove::new(parents, io)?;
+        let children = BTreeMapRemove::new(children, io)?;
+        let referenced_by = Self::load_references(&children, io)?;
+        Ok(Self {
+            parents,
+            children: Children {
+                map: children,
+                store: RelationStore {
+                    on_remove,
+                    referenced_by,
+                    tx_changes: Default::default(),
+                },
+            },
+        })
+    }
+
+    fn load_references<F: Backend>(
+        children: &BTreeMapRemove<CK, (K, CV)>,
+        io: &TxIo<'_, F>,
+    ) -> Result<StdBTreeMap<K, BTreeSet<CK>>> {
+        let mut referenced_by = StdBTreeMap::<K, BTreeSet<CK>>::default();
+        // only decode the parent key at the start of each child's value
+        for res in children.value_prefixes::<F, K>(io) {
+            let (child_key, key) = res?;
+            referenced_by.entry(key).or_default().insert(child_key);
+        }
+        Ok(referenced_by)
+    }
+}
+
+impl<K, V, CK, CV> IndexStore for Relation<K, V, CK, CV>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + Send + 'static,
+    V: bincode::Encode + bincode::Decode + Send + 'static,
+    CK: Ord + bincode::Encode + bincode::Decode + Clone + Send + 'static,
+    CV: bincode::Encode + bincode::Decode + Send + 'static,
+{
+    type Api<'i, F> = RelationApi<'i, F, K, V, CK, CV>;
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        let mut lists = self.parents.owned_lists();
+        lists.extend(self.children.map.owned_lists());
+        lists
+    }
+
+    fn create_api<'s, F>(relation: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (parents, children) = RefMut::map_split(relation, |relation| {
+            (&mut relation.parents, &mut relation.children)
+        });
+        let (children, store) = RefMut::map_split(children, |children| {
+            (&mut children.map, &mut children.store)
+        });
+        RelationApi {
+            parents: BTreeMapRemove::create_api(parents, io.clone()),
+            children: BTreeMapRemove::create_api(children, io),
+            store,
+        }
+    }
+
+    fn tx_fail_rollback(&mut self) {
+        self.parents.tx_fail_rollback();
+        self.children.map.tx_fail_rollback();
+        let store = &mut self.children.store;
+        for change in core::mem::take(&mut store.tx_changes).into_iter().rev() {
+            match change {
+                Change::Link(key, child_key) => store.unlink(&key, &child_key),
+                Change::Unlink(key, child_key) => store.link(key, child_key),
+                Change::Rebuild(prev) => store.referenced_by = prev,
+            }
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.parents.tx_success();
+        self.children.map.tx_success();
+        self.children.store.tx_changes.clear();
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.parents.rebuild(io)?;
+        self.children.map.rebuild(io)?;
+        let referenced_by = Self::load_references(&self.children.map, io)?;
+        let store = &mut self.children.store;
+        let prev = core::mem::replace(&mut store.referenced_by, referenced_by);
+        store.tx_changes.push(Change::Rebuild(prev));
+        Ok(())
+    }
+}
+
+pub struct RelationApi<'tx, F, K, V, CK, CV> {
+    parents: BTreeMapRemoveApi<'tx, F, K, V>,
+    children: BTreeMapRemoveApi<'tx, F, CK, (K, CV)>,
+    store: RefMut<'tx, RelationStore<K, CK>>,
+}
+
+impl<'tx, F, K, V, CK, CV> RelationApi<'tx, F, K, V, CK, CV>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + fmt::Debug + Send + Sync + 'static,
+    V: bincode::Encode + bincode::Decode + PartialEq,
+    CK: Ord + bincode::Encode + bincode::Decode + Clone,
+    CV: bincode::Encode + bincode::Decode + PartialEq,
+    F: Backend,
+{
+    /// Insert or replace a parent returning the value it replaced
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        self.parents.insert(key, value)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.parents.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.parents.contains_key(key)
+    }
+
+    /// Remove a parent. If it has children this either fails with
+    /// [`IntegrityError::Referenced`] or removes them too depending on [`OnRemove`].
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>> {
+        let child_keys = self
+            .store
+            .referenced_by
+            .get(key)
+            .cloned()
+            .unwrap_or_default();
+        if !child_keys.is_empty() && self.store.on_remove == OnRemove::Restrict {
+            return Err(IntegrityError::Referenced {
+                key: key.clone(),
+                n_children: child_keys.len(),
+            }
+            .into());
+        }
+        for child_key in child_keys {
+            self.remove_child(&child_key)?;
+        }
+        self.parents.remove(key)
+    }
+
+    /// Insert or replace a child referring to the parent `key` returning the parent and value it
+    /// replaced. Fails with [`IntegrityError::Missing`] if the parent isn't there.
+    pub fn insert_child(&mut self, child_key: CK, key: K, value: CV) -> Result<Option<(K, CV)>> {
+        if !self.parents.contains_key(&key) {
+            return Err(IntegrityError::Missing { key }.into());
+        }
+        let prev = self
+            .children
+            .insert(child_key.clone(), (key.clone(), value))?;
+        if let Some((prev_key, _)) = &prev {
+            self.store.unlink(prev_key, &child_key);
+            self.store
+                .tx_changes
+                .push(Change::Unlink(prev_key.clone(), child_key.clone()));
+        }
+        self.store.link(key.clone(), child_key.clone());
+        self.store.tx_changes.push(Change::Link(key, child_key));
+        Ok(prev)
+    }
+
+    /// The parent a child refers to and the child's value
+    pub fn get_child<Q>(&self, child_key: &Q) -> Result<Option<(K, CV)>>
+    where
+        CK: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.children.get(child_key)
+    }
+
+    pub fn remove_child(&mut self, child_key: &CK) -> Result<Option<(K, CV)>> {
+        let removed = self.children.remove(child_key)?;
+        if let Some((key, _)) = &removed {
+            self.store.unlink(key, child_key);
+            self.store
+                .tx_changes
+                .push(Change::Unlink(key.clone(), child_key.clone()));
+        }
+        Ok(removed)
+    }
+
+    /// The keys of the children referring to `key`
+    pub fn child_keys(&self, key: &K) -> impl Iterator<Item = &CK> + '_ {
+        self.store.referenced_by.get(key).into_iter().flatten()
+    }
+
+    /// The children referring to `key`
+    pub fn children_of<'a>(&'a self, key: &K) -> impl Iterator<Item = Result<(CK, CV)>> + 'a {
+        self.child_keys(key).map(|child_key| {
+            let (_, value) = self
+                .children
+                .get(child_key)?
+                .expect("child keys are in the index");
+            Ok((child_key.clone(), value))
+        })
+    }
+
+    pub fn n_children(&self, key: &K) -> usize {
+        self.store.referenced_by.get(key).map_or(0, BTreeSet::len)
+    }
+
+    pub fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+}
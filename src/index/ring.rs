@@ -0,0 +1,284 @@
+use super::IndexStore;
+use crate::{Backend, EntryHandle, LinkedList, LinkedListApi, Transaction, TxIo};
+use anyhow::{anyhow, Result};
+use std::{cell::RefMut, collections::VecDeque, vec::Vec as StdVec};
+
+/// A log capped to a maximum number of entries or bytes. Pushing past the cap evicts the oldest
+/// entries.
+///
+/// Entries are written to one of two lists at a time. Once the current list holds a full buffer's
+/// worth of entries the other list is cleared and becomes the current one. This means evicted
+/// entries are freed in batches rather than one by one but the buffer never takes up more than
+/// twice its capacity (plus one entry) on disk. Each entry is stored along with the generation of
+/// the list it was written to so the order of the lists can be recovered when loading.
+#[derive(Debug)]
+pub struct RingBuffer<T> {
+    lists: [LinkedList<(u64, T)>; 2],
+    store: RingStore,
+}
+
+/// How big a [`RingBuffer`] can get
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingCapacity {
+    /// The maximum number of entries
+    Entries(usize),
+    /// The maximum number of bytes the entries can take up
+    Bytes(u64),
+}
+
+#[derive(Debug)]
+struct RingStore {
+    capacity: RingCapacity,
+    /// The entries that haven't been evicted, oldest first
+    window: VecDeque<EntryHandle>,
+    window_bytes: u64,
+    /// The list being written to
+    current: usize,
+    generation: u64,
+    current_len: usize,
+    current_bytes: u64,
+    tx_changes: StdVec<Change>,
+}
+
+#[derive(Debug)]
+enum Change {
+    Push,
+    Evict(EntryHandle),
+    Swap { prev_len: usize, prev_bytes: u64 },
+    Rebuild(Box<RingStore>),
+}
+
+impl<T> RingBuffer<T>
+where
+    T: bincode::Encode + bincode::Decode + Send,
+{
+    /// Index the two lists as a ring buffer. If they already hold more than `capacity` the
+    /// oldest entries are evicted straight away.
+    pub fn new<'tx, F: Backend>(
+        lists: [LinkedList<(u64, T)>; 2],
+        capacity: RingCapacity,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        match capacity {
+            RingCapacity::Entries(0) | RingCapacity::Bytes(0) => {
+                return Err(anyhow!("ring buffer capacity must not be zero"))
+            }
+            _ => {}
+        }
+        let store = Self::load_index(&tx.io, &lists, capacity)?;
+        Ok(Self { lists, store })
+    }
+
+    fn load_index<F: Backend>(
+        io: &TxIo<'_, F>,
+        lists: &[LinkedList<(u64, T)>; 2],
+        capacity: RingCapacity,
+    ) -> Result<RingStore> {
+        let mut loaded = [(0, VecDeque::new()), (0, VecDeque::new())];
+        for (list, (generation, entries)) in lists.iter().zip(&mut loaded) {
+            let mut it = io.iter(list.slot());
+            while let Some(res) = it.next_with_handle::<(u64, T)>() {
+                let (handle, (entry_generation, _)) = res?;
+                *generation = entry_generation;
+                entries.push_front(handle);
+            }
+        }
+        let [(first_generation, first), (second_generation, second)] = loaded;
+        let current = match (first.is_empty(), second.is_empty()) {
+            (_, true) => 0,
+            (true, false) => 1,
+            (false, false) if first_generation == second_generation => {
+                return Err(anyhow!(
+                    "both ring buffer lists are generation {}",
+                    first_generation
+                ))
+            }
+            (false, false) => usize::from(second_generation > first_generation),
+        };
+        let (generation, older, newer) = match current {
+            0 => (first_generation, second, first),
+            _ => (second_generation, first, second),
+        };
+
+        let mut store = RingStore {
+            capacity,
+            window: Default::default(),
+            window_bytes: 0,
+            current,
+            generation,
+            current_len: newer.len(),
+            current_bytes: newer.iter().map(EntryHandle::entry_len).sum(),
+            tx_changes: Default::default(),
+        };
+        for handle in older.into_iter().chain(newer) {
+            store.push_back(handle);
+            store.evict_excess();
+        }
+        store.tx_changes.clear();
+        Ok(store)
+    }
+}
+
+impl RingStore {
+    fn is_full(&self, len: usize, bytes: u64) -> bool {
+        match self.capacity {
+            RingCapacity::Entries(max) => len >= max,
+            RingCapacity::Bytes(max) => bytes >= max,
+        }
+    }
+
+    fn push_back(&mut self, handle: EntryHandle) {
+        self.window_bytes += handle.entry_len();
+        self.window.push_back(handle);
+    }
+
+    /// Evict entries from the front of the window until it fits. The newest entry is never
+    /// evicted even if it's bigger than the capacity on its own.
+    fn evict_excess(&mut self) -> usize {
+        let mut n_evicted = 0;
+        while self.window.len() > 1 {
+            let over = match self.capacity {
+                RingCapacity::Entries(max) => self.window.len() > max,
+                RingCapacity::Bytes(max) => self.window_bytes > max,
+            };
+            if !over {
+                break;
+            }
+            let evicted = self.window.pop_front().expect("not empty");
+            self.window_bytes -= evicted.entry_len();
+            self.tx_changes.push(Change::Evict(evicted));
+            n_evicted += 1;
+        }
+        n_evicted
+    }
+}
+
+impl<T: bincode::Encode + bincode::Decode + 'static + Send> IndexStore for RingBuffer<T> {
+    type Api<'i, F> = RingBufferApi<'i, F, T>;
+
+    fn tx_fail_rollback(&mut self) {
+        let store = &mut self.store;
+        for change in core::mem::take(&mut store.tx_changes).into_iter().rev() {
+            match change {
+                Change::Push => {
+                    let handle = store.window.pop_back().expect("must exist");
+                    store.window_bytes -= handle.entry_len();
+                    store.current_len -= 1;
+                    store.current_bytes -= handle.entry_len();
+                }
+                Change::Evict(handle) => {
+                    store.window_bytes += handle.entry_len();
+                    store.window.push_front(handle);
+                }
+                Change::Swap {
+                    prev_len,
+                    prev_bytes,
+                } => {
+                    store.current = 1 - store.current;
+                    store.generation -= 1;
+                    store.current_len = prev_len;
+                    store.current_bytes = prev_bytes;
+                }
+                Change::Rebuild(prev_store) => *store = *prev_store,
+            }
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_changes.clear();
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
+        self.lists.iter().map(LinkedList::slot).collect()
+    }
+
+    fn create_api<'s, F>(ring: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (lists, store) = RefMut::map_split(ring, |ring| (&mut ring.lists, &mut ring.store));
+        let (first, second) = RefMut::map_split(lists, |[first, second]| (first, second));
+        RingBufferApi {
+            lists: [
+                LinkedList::create_api(first, io.clone()),
+                LinkedList::create_api(second, io.clone()),
+            ],
+            io,
+            store,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let store = Self::load_index(io, &self.lists, self.store.capacity)?;
+        let mut prev_store = core::mem::replace(&mut self.store, store);
+        self.store.tx_changes = core::mem::take(&mut prev_store.tx_changes);
+        self.store
+            .tx_changes
+            .push(Change::Rebuild(Box::new(prev_store)));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct RingBufferApi<'i, F, T> {
+    io: TxIo<'i, F>,
+    lists: [LinkedListApi<'i, F, (u64, T)>; 2],
+    store: RefMut<'i, RingStore>,
+}
+
+impl<'i, F, T> RingBufferApi<'i, F, T>
+where
+    T: bincode::Encode + bincode::Decode,
+    F: Backend + 'i,
+{
+    /// Push a new entry evicting the oldest ones if it takes the buffer over capacity. Returns the
+    /// number of entries evicted.
+    pub fn push(&mut self, value: T) -> Result<usize> {
+        let store = &mut *self.store;
+        if store.is_full(store.current_len, store.current_bytes) {
+            // everything in the window is in the current list so the other one can go
+            let other = 1 - store.current;
+            self.lists[other].clear()?;
+            store.tx_changes.push(Change::Swap {
+                prev_len: store.current_len,
+                prev_bytes: store.current_bytes,
+            });
+            store.current = other;
+            store.generation += 1;
+            store.current_len = 0;
+            store.current_bytes = 0;
+        }
+
+        let handle = self.lists[store.current].push(&(store.generation, value))?;
+        store.push_back(handle);
+        store.current_len += 1;
+        store.current_bytes += handle.entry_len();
+        store.tx_changes.push(Change::Push);
+        Ok(store.evict_excess())
+    }
+
+    /// Iterate from the oldest entry to the newest
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<T>> + ExactSizeIterator + '_ {
+        self.store.window.iter().map(|handle| {
+            let (_, (_, value)) = self.io.read_at::<(u64, T)>(handle.entry_pointer)?;
+            Ok(value)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.window.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.window.is_empty()
+    }
+
+    /// The number of bytes the entries take up
+    pub fn byte_len(&self) -> u64 {
+        self.store.window_bytes
+    }
+
+    pub fn capacity(&self) -> RingCapacity {
+        self.store.capacity
+    }
+}
@@ -0,0 +1,244 @@
+use super::IndexStore;
+use crate::{
+    Backend, EntryHandle, LinkedList, LinkedListMut, LinkedListMutApi, ListSlot, Mut, TxIo,
+};
+use anyhow::Result;
+use std::{cell::RefMut, ops::Bound, ops::RangeBounds, vec::Vec as StdVec};
+
+/// A set of values kept in order. Only a handle to each value's entry is kept in memory (sorted by
+/// the value) and lookups binary search over them reading values from the backend as they go.
+///
+/// This is a lighter alternative to [`BTreeMapRemove`](super::BTreeMapRemove) when the values are
+/// their own keys since nothing but the handles is kept in memory. The price is that every
+/// comparison is a read so lookups take `O(log n)` reads.
+#[derive(Debug)]
+pub struct SortedVec<T> {
+    list: LinkedListMut<T>,
+    store: SortedVecStore,
+}
+
+#[derive(Debug)]
+struct SortedVecStore {
+    handles: StdVec<EntryHandle>,
+    tx_changes: StdVec<Change>,
+}
+
+#[derive(Debug)]
+enum Change {
+    Insert { index: usize },
+    Remove { index: usize, handle: EntryHandle },
+    Rebuild(StdVec<EntryHandle>),
+}
+
+impl<T> SortedVec<T>
+where
+    T: Ord + bincode::Encode + bincode::Decode,
+{
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<Mut<T>>,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        let handles = Self::load_handles(tx.as_ref(), list.slot())?;
+        Ok(Self {
+            list: LinkedListMut(list),
+            store: SortedVecStore {
+                handles,
+                tx_changes: Default::default(),
+            },
+        })
+    }
+
+    fn load_handles<F: Backend>(io: &TxIo<'_, F>, slot: ListSlot) -> Result<StdVec<EntryHandle>> {
+        let mut it = io.iter(slot);
+        let mut entries = StdVec::new();
+        while let Some((handle, value)) = it.next_with_handle::<Mut<T>>().transpose()? {
+            match value {
+                Mut::Remap(remap) => it.remap(remap),
+                Mut::Add(value) => entries.push((value, handle)),
+            }
+        }
+        // stable so the newest of any duplicates comes first and is the one kept
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        entries.dedup_by(|(a, _), (b, _)| a == b);
+        Ok(entries.into_iter().map(|(_, handle)| handle).collect())
+    }
+}
+
+impl<T> IndexStore for SortedVec<T>
+where
+    T: Ord + bincode::Encode + bincode::Decode + Send + 'static,
+{
+    type Api<'i, F> = SortedVecApi<'i, F, T>;
+
+    fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
+        self.list.owned_lists()
+    }
+
+    fn create_api<'s, F>(sorted_vec: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (list, store) = RefMut::map_split(sorted_vec, |sorted_vec| {
+            (&mut sorted_vec.list, &mut sorted_vec.store)
+        });
+        SortedVecApi {
+            list: LinkedListMut::create_api(list, io.clone()),
+            io,
+            store,
+        }
+    }
+
+    fn tx_fail_rollback(&mut self) {
+        let SortedVecStore {
+            handles,
+            tx_changes,
+        } = &mut self.store;
+        for change in tx_changes.drain(..).rev() {
+            match change {
+                Change::Insert { index } => {
+                    handles.remove(index);
+                }
+                Change::Remove { index, handle } => handles.insert(index, handle),
+                Change::Rebuild(prev_handles) => *handles = prev_handles,
+            }
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.store.tx_changes.clear()
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let handles = Self::load_handles(io, self.list.0.slot())?;
+        let prev_handles = core::mem::replace(&mut self.store.handles, handles);
+        self.store.tx_changes.push(Change::Rebuild(prev_handles));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct SortedVecApi<'i, F, T> {
+    io: TxIo<'i, F>,
+    list: LinkedListMutApi<'i, F, T>,
+    store: RefMut<'i, SortedVecStore>,
+}
+
+impl<'i, F, T> SortedVecApi<'i, F, T>
+where
+    T: Ord + bincode::Encode + bincode::Decode,
+    F: Backend + 'i,
+{
+    /// Insert `value` returning whether it wasn't already there
+    pub fn insert(&mut self, value: T) -> Result<bool> {
+        let index = match self.search(&value)? {
+            Ok(_) => return Ok(false),
+            Err(index) => index,
+        };
+        let handle = self.list.push(value)?;
+        let store = &mut *self.store;
+        store.handles.insert(index, handle);
+        store.tx_changes.push(Change::Insert { index });
+        Ok(true)
+    }
+
+    /// Remove `value` returning whether it was there
+    pub fn remove(&mut self, value: &T) -> Result<bool> {
+        let index = match self.search(value)? {
+            Ok(index) => index,
+            Err(_) => return Ok(false),
+        };
+        let handle = self.store.handles[index];
+        self.list.unlink(handle)?;
+        let store = &mut *self.store;
+        store.handles.remove(index);
+        store.tx_changes.push(Change::Remove { index, handle });
+        Ok(true)
+    }
+
+    pub fn contains(&self, value: &T) -> Result<bool> {
+        Ok(self.search(value)?.is_ok())
+    }
+
+    /// The value at `index` in sorted order
+    pub fn get(&self, index: usize) -> Result<Option<T>> {
+        self.store
+            .handles
+            .get(index)
+            .map(|handle| self.read(*handle))
+            .transpose()
+    }
+
+    pub fn first(&self) -> Result<Option<T>> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Result<Option<T>> {
+        match self.len() {
+            0 => Ok(None),
+            len => self.get(len - 1),
+        }
+    }
+
+    /// Iterate over the values in `range` in order. Finding where the range starts and ends is done
+    /// up front with two binary searches.
+    pub fn range<R: RangeBounds<T>>(
+        &self,
+        range: R,
+    ) -> Result<impl DoubleEndedIterator<Item = Result<T>> + '_> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.partition_point(|value| value < start)?,
+            Bound::Excluded(start) => self.partition_point(|value| value <= start)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.partition_point(|value| value <= end)?,
+            Bound::Excluded(end) => self.partition_point(|value| value < end)?,
+            Bound::Unbounded => self.len(),
+        };
+        Ok(self.store.handles[start..end.max(start)]
+            .iter()
+            .map(|handle| self.read(*handle)))
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<T>> + '_ {
+        self.store.handles.iter().map(|handle| self.read(*handle))
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.handles.is_empty()
+    }
+
+    fn read(&self, handle: EntryHandle) -> Result<T> {
+        Ok(self
+            .io
+            .read_prefix_at::<Mut<T>>(handle.entry_pointer)?
+            .unwrap_value())
+    }
+
+    /// Like [`slice::binary_search`]: `Ok` with where `value` is or `Err` with where it would go
+    fn search(&self, value: &T) -> Result<core::result::Result<usize, usize>> {
+        let index = self.partition_point(|existing| existing < value)?;
+        match self.store.handles.get(index) {
+            Some(handle) if self.read(*handle)? == *value => Ok(Ok(index)),
+            _ => Ok(Err(index)),
+        }
+    }
+
+    /// Like [`slice::partition_point`] over the values
+    fn partition_point(&self, pred: impl Fn(&T) -> bool) -> Result<usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if pred(&self.read(self.store.handles[mid])?) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+}
@@ -0,0 +1,54 @@
+use super::{BTreeMap, Cell, CellOption, IndexStore, Vec};
+use crate::{Backend, LinkedList, Transaction};
+use anyhow::Result;
+
+/// An index that can be declared in [`tables!`](crate::tables) and built straight from its list.
+pub trait Table: IndexStore + Sized {
+    /// The type of the entries of the list the table is kept in
+    type Entry;
+    /// Build the table from `list`
+    fn open<F: Backend>(list: LinkedList<Self::Entry>, tx: &mut Transaction<'_, F>)
+        -> Result<Self>;
+}
+
+impl<T> Table for Vec<T>
+where
+    T: bincode::Encode + bincode::Decode + 'static + Send,
+{
+    type Entry = T;
+    fn open<F: Backend>(list: LinkedList<T>, tx: &mut Transaction<'_, F>) -> Result<Self> {
+        Vec::new(list, tx)
+    }
+}
+
+impl<K, V> Table for BTreeMap<K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + 'static + Send,
+    V: bincode::Encode + bincode::Decode + 'static + Send,
+{
+    type Entry = (K, V);
+    fn open<F: Backend>(list: LinkedList<(K, V)>, tx: &mut Transaction<'_, F>) -> Result<Self> {
+        BTreeMap::new(list, tx)
+    }
+}
+
+/// The cell starts out with `T::default()` if the list is empty
+impl<T> Table for Cell<T>
+where
+    T: bincode::Encode + bincode::Decode + Default + 'static + Send,
+{
+    type Entry = T;
+    fn open<F: Backend>(list: LinkedList<T>, tx: &mut Transaction<'_, F>) -> Result<Self> {
+        Cell::new_with_default(list, tx)
+    }
+}
+
+impl<T> Table for CellOption<T>
+where
+    T: bincode::Encode + bincode::Decode + 'static + Send,
+{
+    type Entry = T;
+    fn open<F: Backend>(list: LinkedList<T>, tx: &mut Transaction<'_, F>) -> Result<Self> {
+        CellOption::new(list, tx)
+    }
+}
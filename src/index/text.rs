@@ -0,0 +1,282 @@
+use super::IndexStore;
+use crate::{
+    Backend, EntryPointer, LinkedList, LinkedListMut, LinkedListMutApi, Mut, Pointer, Transaction,
+    TxIo,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    cell::RefMut,
+    collections::{BTreeMap as StdBTreeMap, BTreeSet},
+    vec::Vec as StdVec,
+};
+
+type Tokenizer<T> = Box<dyn Fn(&T) -> StdVec<String> + Send>;
+
+/// An inverted index from tokens to the documents in a list that contain them.
+///
+/// The tokens of each document are produced by the tokenizer passed to [`TextIndex::new`]. Only
+/// the documents are persisted so the tokenizer must be the same each time the index is loaded.
+/// Searches match tokens exactly so search terms should be normalized the same way the tokenizer
+/// does it (see [`simple_tokens`] for a basic tokenizer).
+pub struct TextIndex<T> {
+    list: LinkedListMut<T>,
+    state: TextState<T>,
+}
+
+struct TextState<T> {
+    tokenizer: Tokenizer<T>,
+    store: TextStore,
+}
+
+impl<T> core::fmt::Debug for TextIndex<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TextIndex")
+            .field("slot", &self.list.0.slot())
+            .field("store", &self.state.store)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct TextStore {
+    postings: StdBTreeMap<String, BTreeSet<EntryPointer>>,
+    /// the tokens of each document keyed by where it is
+    documents: StdBTreeMap<Pointer, (EntryPointer, StdVec<String>)>,
+    tx_changes: StdVec<Change>,
+}
+
+#[derive(Debug, Clone)]
+enum Change {
+    Add(EntryPointer),
+    Remove(EntryPointer, StdVec<String>),
+    Rebuild(Box<TextStore>),
+}
+
+/// Splits `text` on anything that isn't alphanumeric and lowercases what's left.
+pub fn simple_tokens(text: &str) -> StdVec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+impl<T> TextIndex<T>
+where
+    T: bincode::Encode + bincode::Decode + Send,
+{
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<Mut<T>>,
+        tokenizer: impl Fn(&T) -> StdVec<String> + Send + 'static,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        let list = LinkedListMut(list);
+        let tokenizer: Tokenizer<T> = Box::new(tokenizer);
+        let store = Self::load_index(&list.api(&tx.io), &tokenizer)?;
+        Ok(Self {
+            list,
+            state: TextState { tokenizer, store },
+        })
+    }
+
+    fn load_index<F: Backend>(
+        list: &LinkedListMutApi<'_, F, T>,
+        tokenizer: &Tokenizer<T>,
+    ) -> Result<TextStore> {
+        let mut store = TextStore::default();
+        for res in list.iter_handles() {
+            let (handle, document) = res?;
+            store.insert(handle.entry_pointer, tokenizer(&document));
+        }
+        Ok(store)
+    }
+}
+
+impl TextStore {
+    fn insert(&mut self, document: EntryPointer, mut tokens: StdVec<String>) {
+        tokens.sort_unstable();
+        tokens.dedup();
+        for token in &tokens {
+            self.postings
+                .entry(token.clone())
+                .or_default()
+                .insert(document);
+        }
+        self.documents
+            .insert(document.this_entry, (document, tokens));
+    }
+
+    fn remove(&mut self, document: Pointer) -> Option<(EntryPointer, StdVec<String>)> {
+        let (entry_pointer, tokens) = self.documents.remove(&document)?;
+        for token in &tokens {
+            if let Some(documents) = self.postings.get_mut(token) {
+                documents.remove(&entry_pointer);
+                if documents.is_empty() {
+                    self.postings.remove(token);
+                }
+            }
+        }
+        Some((entry_pointer, tokens))
+    }
+}
+
+impl<T: bincode::Encode + bincode::Decode + 'static + Send> IndexStore for TextIndex<T> {
+    type Api<'i, F> = TextIndexApi<'i, F, T>;
+
+    fn tx_fail_rollback(&mut self) {
+        let store = &mut self.state.store;
+        for change in core::mem::take(&mut store.tx_changes).into_iter().rev() {
+            match change {
+                Change::Add(document) => {
+                    store.remove(document.this_entry);
+                }
+                Change::Remove(document, tokens) => store.insert(document, tokens),
+                Change::Rebuild(prev_store) => *store = *prev_store,
+            }
+        }
+    }
+
+    fn tx_success(&mut self) {
+        self.state.store.tx_changes.clear();
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
+        vec![self.list.0.slot()]
+    }
+
+    fn create_api<'s, F>(index: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (list, state) = RefMut::map_split(index, |index| (&mut index.list, &mut index.state));
+        TextIndexApi {
+            list: LinkedListMut::create_api(list, io.clone()),
+            io,
+            state,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let TextState { tokenizer, store } = &mut self.state;
+        let new_store = Self::load_index(&self.list.api(io), tokenizer)?;
+        let mut prev_store = core::mem::replace(store, new_store);
+        store.tx_changes = core::mem::take(&mut prev_store.tx_changes);
+        store.tx_changes.push(Change::Rebuild(Box::new(prev_store)));
+        Ok(())
+    }
+}
+
+pub struct TextIndexApi<'i, F, T> {
+    io: TxIo<'i, F>,
+    list: LinkedListMutApi<'i, F, T>,
+    state: RefMut<'i, TextState<T>>,
+}
+
+impl<F, T> core::fmt::Debug for TextIndexApi<'_, F, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TextIndexApi")
+            .field("store", &self.state.store)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'i, F, T> TextIndexApi<'i, F, T>
+where
+    T: bincode::Encode + bincode::Decode,
+    F: Backend + 'i,
+{
+    /// Add a document and index its tokens
+    pub fn add(&mut self, document: T) -> Result<EntryPointer> {
+        let tokens = (self.state.tokenizer)(&document);
+        let handle = self.list.push(document)?;
+        self.state.store.insert(handle.entry_pointer, tokens);
+        self.state
+            .store
+            .tx_changes
+            .push(Change::Add(handle.entry_pointer));
+        Ok(handle.entry_pointer)
+    }
+
+    /// Remove the document at `document` returning it if it was there
+    pub fn remove(&mut self, document: EntryPointer) -> Result<Option<T>> {
+        let document = match self.state.store.documents.get(&document.this_entry) {
+            Some((document, _)) => *document,
+            None => return Ok(None),
+        };
+        let (handle, value) = self.io.read_at::<Mut<T>>(document)?;
+        self.list.unlink(handle)?;
+        let (document, tokens) = self
+            .state
+            .store
+            .remove(document.this_entry)
+            .expect("checked it's there");
+        self.state
+            .store
+            .tx_changes
+            .push(Change::Remove(document, tokens));
+        Ok(Some(
+            value.into_value().expect("TextIndex only points to values"),
+        ))
+    }
+
+    /// The documents containing `term`
+    pub fn search(&self, term: &str) -> impl Iterator<Item = Result<(EntryPointer, T)>> + '_ {
+        let documents = self
+            .state
+            .store
+            .postings
+            .get(term)
+            .map(|documents| documents.iter().copied().collect())
+            .unwrap_or_default();
+        self.read_documents(documents)
+    }
+
+    /// The documents containing every one of `terms`
+    pub fn search_all(
+        &self,
+        terms: &[&str],
+    ) -> impl Iterator<Item = Result<(EntryPointer, T)>> + '_ {
+        let mut postings = terms
+            .iter()
+            .map(|term| self.state.store.postings.get(*term))
+            .collect::<Option<StdVec<_>>>()
+            .unwrap_or_default();
+        // start with the rarest term so there's less to intersect
+        postings.sort_by_key(|documents| documents.len());
+        let documents = match postings.split_first() {
+            Some((first, rest)) => first
+                .iter()
+                .filter(|document| rest.iter().all(|documents| documents.contains(document)))
+                .copied()
+                .collect(),
+            None => vec![],
+        };
+        self.read_documents(documents)
+    }
+
+    fn read_documents(
+        &self,
+        documents: StdVec<EntryPointer>,
+    ) -> impl Iterator<Item = Result<(EntryPointer, T)>> + '_ {
+        documents.into_iter().map(|document| {
+            let (_, value) = self.io.read_at::<Mut<T>>(document)?;
+            let value = value
+                .into_value()
+                .ok_or(anyhow!("TextIndex pointed to a remap"))?;
+            Ok((document, value))
+        })
+    }
+
+    /// The number of distinct tokens
+    pub fn n_tokens(&self) -> usize {
+        self.state.store.postings.len()
+    }
+
+    /// The number of documents
+    pub fn len(&self) -> usize {
+        self.state.store.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.store.documents.is_empty()
+    }
+}
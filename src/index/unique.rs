@@ -0,0 +1,138 @@
+use super::{BTreeMapRemove, BTreeMapRemoveApi, IndexStore};
+use crate::{Backend, EntryHandle, LinkedList, ListSlot, Mut, TxIo};
+use anyhow::Result;
+use core::{borrow::Borrow, fmt};
+use std::cell::RefMut;
+
+/// A map where each key can only be inserted once.
+///
+/// It wraps a [`BTreeMapRemove`] but [`insert`](UniqueApi::insert) fails with [`DuplicateKey`]
+/// rather than overwriting when the key is already there. A key can be used again once it has been
+/// removed.
+#[derive(Debug)]
+pub struct Unique<K, V> {
+    map: BTreeMapRemove<K, V>,
+}
+
+/// The error returned when inserting a key into a [`Unique`] that is already there. Since it is
+/// returned through [`anyhow`] use `downcast_ref` to tell it apart from other errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKey<K> {
+    pub key: K,
+}
+
+impl<K: fmt::Debug> fmt::Display for DuplicateKey<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "key {:?} already exists", self.key)
+    }
+}
+
+impl<K: fmt::Debug> std::error::Error for DuplicateKey<K> {}
+
+impl<K, V> Unique<K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone,
+    V: bincode::Encode + bincode::Decode,
+{
+    pub fn new<'tx, F: Backend>(
+        list: LinkedList<Mut<(K, V)>>,
+        tx: impl AsRef<TxIo<'tx, F>>,
+    ) -> Result<Self> {
+        Ok(Self {
+            map: BTreeMapRemove::new(list, tx)?,
+        })
+    }
+}
+
+impl<K, V> IndexStore for Unique<K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + Send + 'static,
+    V: bincode::Encode + bincode::Decode + Send + 'static,
+{
+    type Api<'i, F> = UniqueApi<'i, F, K, V>;
+
+    fn owned_lists(&self) -> std::vec::Vec<ListSlot> {
+        self.map.owned_lists()
+    }
+
+    fn create_api<'s, F>(unique: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        UniqueApi {
+            map: BTreeMapRemove::create_api(RefMut::map(unique, |unique| &mut unique.map), io),
+        }
+    }
+
+    fn tx_fail_rollback(&mut self) {
+        self.map.tx_fail_rollback()
+    }
+
+    fn tx_success(&mut self) {
+        self.map.tx_success()
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.map.rebuild(io)
+    }
+}
+
+pub struct UniqueApi<'tx, F, K, V> {
+    map: BTreeMapRemoveApi<'tx, F, K, V>,
+}
+
+impl<'tx, F, K, V> UniqueApi<'tx, F, K, V>
+where
+    K: Ord + bincode::Encode + bincode::Decode + Clone + fmt::Debug + Send + Sync + 'static,
+    V: bincode::Encode + bincode::Decode + PartialEq,
+    F: Backend,
+{
+    /// Insert `key` failing with [`DuplicateKey`] if it's already there
+    pub fn insert(&mut self, key: K, value: V) -> Result<()> {
+        if self.map.contains_key(&key) {
+            return Err(DuplicateKey { key }.into());
+        }
+        self.map.insert(key, value)?;
+        Ok(())
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.map.get(key)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.map.remove(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn keys(&self) -> std::collections::btree_map::Keys<'_, K, EntryHandle> {
+        self.map.keys()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<(K, V)>> + '_ {
+        self.map.iter()
+    }
+}
@@ -1,11 +1,16 @@
 use crate::{
     Backend, EntryHandle, EntryPointer, LinkedList, LinkedListApi, LinkedListMut, LinkedListMutApi,
-    Mut, Pointer, Transaction, TxIo,
+    ListSlot, Mut, Pointer, PooledReader, Transaction, TxIo,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    cell::RefMut,
+    collections::VecDeque,
+    ops::{Bound, RangeBounds},
+    vec::Vec as StdVec,
 };
-use anyhow::Result;
-use std::{cell::RefMut, collections::VecDeque, vec::Vec as StdVec};
 
-use super::IndexStore;
+use super::{IndexDiff, IndexStore, ListHeads, ParallelLoad};
 
 #[derive(Debug)]
 pub struct Vec<T> {
@@ -15,14 +20,140 @@ pub struct Vec<T> {
 
 #[derive(Debug)]
 struct VecStore {
-    index: VecDeque<Pointer>,
+    index: PointerIndex,
     tx_changes: StdVec<Change>,
 }
 
+/// The pointers a [`Vec`] keeps in memory to find its elements.
+///
+/// The elements are split into blocks of `stride`. Only the newest element of each complete block
+/// is kept in `samples` and the rest are found by following the links from it. The elements in
+/// the last incomplete block are kept in `tail`. When `stride` is 1 there is nothing to follow so
+/// `samples` holds value pointers rather than entry pointers.
+#[derive(Debug, Clone, PartialEq)]
+struct PointerIndex {
+    stride: usize,
+    samples: VecDeque<Pointer>,
+    tail: VecDeque<Pointer>,
+}
+
+impl PointerIndex {
+    /// Build the index from the pointers to a list's entries, newest first. `n_tail` is the number
+    /// of elements in the last incomplete block.
+    fn from_pointers(
+        stride: usize,
+        n_tail: usize,
+        pointers: impl Iterator<Item = Result<EntryPointer>>,
+    ) -> Result<Self> {
+        let mut index = PointerIndex {
+            stride,
+            samples: VecDeque::new(),
+            tail: VecDeque::new(),
+        };
+        for (i, pointer) in pointers.enumerate() {
+            let pointer = pointer?;
+            if stride == 1 {
+                index.samples.push_front(pointer.value_pointer());
+            } else if i < n_tail {
+                index.tail.push_front(pointer.value_pointer());
+            } else if (i - n_tail).is_multiple_of(stride) {
+                // the newest element of each block
+                index.samples.push_front(pointer.this_entry);
+            }
+        }
+        index.samples.make_contiguous();
+        Ok(index)
+    }
+}
+
 #[derive(Debug)]
 enum Change {
-    Push,
-    Pop(Pointer),
+    /// The tail before the push if the push completed a block
+    Push(Option<VecDeque<Pointer>>),
+    Pop(Popped),
+    Rebuild(PointerIndex),
+}
+
+#[derive(Debug)]
+enum Popped {
+    Tail(Pointer),
+    Sample(Pointer),
+}
+
+impl PointerIndex {
+    fn len(&self) -> usize {
+        self.samples.len() * self.stride + self.tail.len()
+    }
+
+    fn value_pointer<F: Backend>(
+        &self,
+        io: &TxIo<'_, F>,
+        slot: ListSlot,
+        index: usize,
+    ) -> Result<Option<Pointer>> {
+        let sampled = self.samples.len() * self.stride;
+        if index >= sampled {
+            return Ok(self.tail.get(index - sampled).copied());
+        }
+        if self.stride == 1 {
+            return Ok(Some(self.samples[index]));
+        }
+        let block = index / self.stride;
+        let mut it = io.iter_at(slot, self.samples[block]);
+        let newest_in_block = block * self.stride + self.stride - 1;
+        for _ in index..newest_in_block {
+            it.next_pointer().transpose()?;
+        }
+        let entry = it
+            .next_pointer()
+            .ok_or(anyhow!("vec element {} is missing from the list", index))??;
+        Ok(Some(entry.value_pointer()))
+    }
+
+    fn push(&mut self, handle: EntryHandle) -> Change {
+        if self.tail.len() + 1 < self.stride {
+            self.tail.push_back(handle.value_pointer());
+            return Change::Push(None);
+        }
+        self.samples.push_back(match self.stride {
+            1 => handle.value_pointer(),
+            _ => handle.entry_pointer.this_entry,
+        });
+        Change::Push(Some(core::mem::take(&mut self.tail)))
+    }
+
+    /// Remove the last element after it has been popped from the list
+    fn pop<F: Backend>(&mut self, io: &TxIo<'_, F>, slot: ListSlot) -> Result<Change> {
+        if let Some(pointer) = self.tail.pop_back() {
+            return Ok(Change::Pop(Popped::Tail(pointer)));
+        }
+        let sample = self.samples.pop_back().expect("must exist");
+        // the rest of the block becomes the tail
+        let mut it = io.iter(slot);
+        for _ in 1..self.stride {
+            let entry = it
+                .next_pointer()
+                .ok_or(anyhow!("vec list is too short"))??;
+            self.tail.push_front(entry.value_pointer());
+        }
+        Ok(Change::Pop(Popped::Sample(sample)))
+    }
+
+    fn rollback(&mut self, change: Change) {
+        match change {
+            Change::Push(None) => assert!(self.tail.pop_back().is_some()),
+            Change::Push(Some(prev_tail)) => {
+                assert!(self.samples.pop_back().is_some());
+                self.tail = prev_tail;
+            }
+            Change::Pop(Popped::Tail(pointer)) => self.tail.push_back(pointer),
+            Change::Pop(Popped::Sample(pointer)) => {
+                self.tail.clear();
+                self.samples.push_back(pointer);
+            }
+            Change::Rebuild(prev_index) => *self = prev_index,
+        }
+    }
 }
 
 impl<T> Vec<T>
@@ -33,21 +164,24 @@ where
         list: crate::LinkedList<T>,
         tx: &Transaction<'tx, F>,
     ) -> Result<Self> {
-        let mut it = tx.io.iter(list.slot());
-        let mut index = VecDeque::new();
-        while let Some(next_pointer) = it.next_pointer() {
-            match next_pointer {
-                Ok(next_pointer) => {
-                    index.push_front(next_pointer.value_pointer());
-                }
-                Err(e) => {
-                    index.clear();
-                    return Err(e);
-                }
-            }
-        }
+        Self::new_sparse(list, 1, tx)
+    }
 
-        index.make_contiguous();
+    /// Like [`new`](Self::new) but only keep a pointer to every `stride`th element in memory.
+    ///
+    /// A [`Vec`] normally keeps an 8 byte pointer per element which adds up for vecs with millions
+    /// of elements. With a `stride` of 16 it uses about half a byte per element but reading an
+    /// element has to follow up to 15 links from the nearest pointer first. Popping the last
+    /// element of a block also has to read the links of the rest of the block.
+    pub fn new_sparse<'tx, F: Backend>(
+        list: crate::LinkedList<T>,
+        stride: usize,
+        tx: &Transaction<'tx, F>,
+    ) -> Result<Self> {
+        if stride == 0 {
+            return Err(anyhow!("stride must be at least 1"));
+        }
+        let index = Self::load_index(&tx.io, &list, stride)?;
 
         let store = Vec {
             list,
@@ -59,19 +193,87 @@ where
 
         Ok(store)
     }
+
+    fn load_index<F: Backend>(
+        io: &TxIo<'_, F>,
+        list: &LinkedList<T>,
+        stride: usize,
+    ) -> Result<PointerIndex> {
+        let n_tail = match stride {
+            1 => 0,
+            // count the elements first so we know which block each one falls in
+            _ => {
+                let (mut it, mut len) = (io.iter(list.slot()), 0);
+                while let Some(next_pointer) = it.next_pointer() {
+                    next_pointer?;
+                    len += 1;
+                }
+                len % stride
+            }
+        };
+        let mut it = io.iter(list.slot());
+        PointerIndex::from_pointers(stride, n_tail, core::iter::from_fn(|| it.next_pointer()))
+    }
+}
+
+/// Loads a [`Vec`] through a [`PooledReader`] (see [`LlsDb::load_indexes`]).
+///
+/// [`LlsDb::load_indexes`]: crate::LlsDb::load_indexes
+#[derive(Debug)]
+pub struct VecLoader<T> {
+    list: LinkedList<T>,
+    stride: usize,
+}
+
+impl<T> Vec<T> {
+    /// Load the vec on another thread with [`LlsDb::load_indexes`](crate::LlsDb::load_indexes)
+    pub fn loader(list: LinkedList<T>) -> VecLoader<T> {
+        Self::sparse_loader(list, 1)
+    }
+
+    /// Like [`loader`](Self::loader) but for [`new_sparse`](Self::new_sparse)
+    pub fn sparse_loader(list: LinkedList<T>, stride: usize) -> VecLoader<T> {
+        VecLoader { list, stride }
+    }
+}
+
+impl<T: bincode::Encode + bincode::Decode + 'static + Send> ParallelLoad for VecLoader<T> {
+    type Index = Vec<T>;
+
+    fn load(self, heads: &ListHeads, reader: &mut PooledReader<'_>) -> Result<Self::Index> {
+        let VecLoader { list, stride } = self;
+        if stride == 0 {
+            return Err(anyhow!("stride must be at least 1"));
+        }
+        let head = heads.head(&list);
+        let n_tail = match stride {
+            1 => 0,
+            _ => {
+                reader
+                    .iter_pointers(head)
+                    .try_fold(0, |len, pointer| pointer.map(|_| len + 1))?
+                    % stride
+            }
+        };
+        let index = PointerIndex::from_pointers(stride, n_tail, reader.iter_pointers(head))?;
+        Ok(Vec {
+            list,
+            store: VecStore {
+                index,
+                tx_changes: Default::default(),
+            },
+        })
+    }
 }
 
-impl<T: 'static + Send> IndexStore for Vec<T> {
+impl<T: bincode::Encode + bincode::Decode + 'static + Send> IndexStore for Vec<T> {
     type Api<'i, F> = VecApi<'i, F, T>;
     fn tx_fail_rollback(&mut self) {
         let VecStore {
             tx_changes, index, ..
         } = &mut self.store;
         for change in tx_changes.drain(..).rev() {
-            match change {
-                Change::Push => assert!(index.pop_back().is_some()),
-                Change::Pop(pointer) => index.push_back(pointer),
-            }
+            index.rollback(change);
         }
     }
 
@@ -87,9 +289,22 @@ impl<T: 'static + Send> IndexStore for Vec<T> {
     where
         Self: Sized,
     {
+        let slot = vec.list.slot();
         let (list, store) = RefMut::map_split(vec, |vec| (&mut vec.list, &mut vec.store));
         let list = LinkedList::create_api(list, io.clone());
-        VecApi { io, list, store }
+        VecApi {
+            io,
+            list,
+            store,
+            slot,
+        }
+    }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let index = Self::load_index(io, &self.list, self.store.index.stride)?;
+        let prev_index = core::mem::replace(&mut self.store.index, index);
+        self.store.tx_changes.push(Change::Rebuild(prev_index));
+        Ok(())
     }
 }
 
@@ -98,6 +313,7 @@ pub struct VecApi<'i, F, T> {
     io: TxIo<'i, F>,
     store: RefMut<'i, VecStore>,
     list: LinkedListApi<'i, F, T>,
+    slot: ListSlot,
 }
 
 impl<'i, F, T> VecApi<'i, F, T>
@@ -106,39 +322,73 @@ where
     F: Backend,
 {
     pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<T>> + ExactSizeIterator + '_ {
-        let io = self.io.clone();
-
-        self.store
-            .index
-            .iter()
-            .map(move |pointer| io.raw_read_at(*pointer))
+        self.iter_range(..)
     }
 
     pub fn get(&self, index: usize) -> Result<Option<T>> {
-        let pointer = match self.store.index.get(index) {
+        let pointer = match self.store.index.value_pointer(&self.io, self.slot, index)? {
             Some(pointer) => pointer,
             _ => return Ok(None),
         };
 
-        Ok(Some(self.io.raw_read_at(*pointer)?))
+        Ok(Some(self.io.raw_read_at(pointer)?))
+    }
+
+    /// Iterate over the elements in `range`. The range is clamped to the length of the vec so
+    /// out of bounds ranges just yield fewer elements.
+    pub fn iter_range(
+        &self,
+        range: impl RangeBounds<usize>,
+    ) -> impl DoubleEndedIterator<Item = Result<T>> + ExactSizeIterator + '_ {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        }
+        .min(len);
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        }
+        .clamp(start, len);
+
+        (start..end).map(move |index| Ok(self.get(index)?.expect("index is in range")))
+    }
+
+    /// Read the elements at each of `indices`. Indices that are out of bounds are `None`.
+    pub fn get_many(&self, indices: &[usize]) -> Result<StdVec<Option<T>>> {
+        indices.iter().map(|&index| self.get(index)).collect()
+    }
+
+    pub fn first(&self) -> Result<Option<T>> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Result<Option<T>> {
+        match self.len() {
+            0 => Ok(None),
+            len => self.get(len - 1),
+        }
     }
 
     pub fn push(&mut self, value: &T) -> Result<()> {
         let handle = self.list.push(value)?;
-        self.store.tx_changes.push(Change::Push);
-        self.store.index.push_back(handle.value_pointer());
+        let change = self.store.index.push(handle);
+        self.store.tx_changes.push(change);
         Ok(())
     }
 
     pub fn pop(&mut self) -> Result<Option<T>> {
         match self.list.pop()? {
             Some(value) => {
-                let pointer = self.store.index.pop_back().expect("must exist");
-                self.store.tx_changes.push(Change::Pop(pointer));
+                let change = self.store.index.pop(&self.io, self.slot)?;
+                self.store.tx_changes.push(change);
                 Ok(Some(value))
             }
             None => {
-                assert_eq!(self.store.index.len(), 0);
+                assert_eq!(self.len(), 0);
                 Ok(None)
             }
         }
@@ -149,7 +399,23 @@ where
     }
 
     pub fn is_empty(&self) -> bool {
-        self.store.index.is_empty()
+        self.len() == 0
+    }
+
+    /// Re-scan the list and check the in-memory index matches it. Errors with an
+    /// [`IndexDiff`] of value pointers if it doesn't.
+    pub fn debug_validate(&self) -> Result<()> {
+        let on_disk = Vec::<T>::load_index(&self.io, &LinkedList::new(self.slot), 1)?;
+        let in_memory = (0..self.len())
+            .map(|index| {
+                Ok(self
+                    .store
+                    .index
+                    .value_pointer(&self.io, self.slot, index)?
+                    .expect("index is in range"))
+            })
+            .collect::<Result<StdVec<_>>>()?;
+        IndexDiff::check(in_memory, on_disk.samples)
     }
 }
 
@@ -177,6 +443,9 @@ enum ChangeMut {
     Push,
     Pop(EntryPointer),
     Remove(usize, EntryPointer),
+    /// The index, the removed element and where the moved element was
+    SwapRemove(usize, EntryPointer, EntryPointer),
+    Rebuild(VecDeque<EntryPointer>),
 }
 
 impl<T> VecRemove<T>
@@ -188,25 +457,7 @@ where
         tx: &Transaction<'tx, F>,
     ) -> Result<Self> {
         let list = LinkedListMut(list);
-        let list_api = list.api(&tx.io);
-        let mut it = list_api.iter_pointers();
-        let mut index = VecDeque::new();
-        while let Some(next_pointer) = it.next() {
-            match next_pointer {
-                Ok(next_pointer) => {
-                    index.push_front(next_pointer);
-                }
-                Err(e) => {
-                    index.clear();
-                    return Err(e);
-                }
-            }
-        }
-
-        drop(it);
-        drop(list_api);
-
-        index.make_contiguous();
+        let index = Self::load_index(&list.api(&tx.io))?;
 
         let store = Self {
             list,
@@ -218,9 +469,19 @@ where
 
         Ok(store)
     }
+
+    fn load_index<F: Backend>(list: &LinkedListMutApi<'_, F, T>) -> Result<VecDeque<EntryPointer>> {
+        let mut index = VecDeque::new();
+        for next_pointer in list.iter_pointers() {
+            index.push_front(next_pointer?);
+        }
+
+        index.make_contiguous();
+        Ok(index)
+    }
 }
 
-impl<T: 'static + Send> IndexStore for VecRemove<T> {
+impl<T: bincode::Encode + bincode::Decode + 'static + Send> IndexStore for VecRemove<T> {
     type Api<'i, F> = VecRemoveApi<'i, F, T>;
 
     fn owned_lists(&self) -> std::vec::Vec<crate::ListSlot> {
@@ -236,6 +497,11 @@ impl<T: 'static + Send> IndexStore for VecRemove<T> {
                 ChangeMut::Push => assert!(index.pop_back().is_some()),
                 ChangeMut::Pop(pointer) => index.push_back(pointer),
                 ChangeMut::Remove(i, pointer) => index.insert(i, pointer),
+                ChangeMut::SwapRemove(i, removed, moved_from) => {
+                    index[i] = removed;
+                    index.push_back(moved_from);
+                }
+                ChangeMut::Rebuild(prev_index) => *index = prev_index,
             }
         }
     }
@@ -252,6 +518,13 @@ impl<T: 'static + Send> IndexStore for VecRemove<T> {
         let list = LinkedListMut::create_api(list, io.clone());
         VecRemoveApi { list, store, io }
     }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        let index = Self::load_index(&self.list.api(io))?;
+        let prev_index = core::mem::replace(&mut self.store.index, index);
+        self.store.tx_changes.push(ChangeMut::Rebuild(prev_index));
+        Ok(())
+    }
 }
 
 impl<'i, F, T> VecRemoveApi<'i, F, T>
@@ -318,6 +591,28 @@ where
         Ok(value)
     }
 
+    /// Remove the element at `index` replacing it with the last element. This doesn't shift the
+    /// elements after `index` along in the in-memory index like [`remove`](Self::remove) does.
+    ///
+    /// The last element is popped from the list and takes the removed element's place in it (see
+    /// [`LinkedListMutApi::replace`]) so the new order is kept when the vec is loaded again.
+    pub fn swap_remove(&mut self, index: usize) -> Result<T> {
+        let pointer = self.store.index[index];
+        if index + 1 == self.len() {
+            return Ok(self.pop()?.expect("index is in range"));
+        }
+        let (handle, value) = self.io.read_at::<Mut<T>>(pointer)?;
+        let value = value.into_value().expect("VecMut only points to values");
+        let moved = self.list.pop()?.expect("the vec isn't empty");
+        let new_handle = self.list.replace(handle, &moved)?;
+        let moved_from = self.store.index.pop_back().expect("must exist");
+        let removed = core::mem::replace(&mut self.store.index[index], new_handle.entry_pointer);
+        self.store
+            .tx_changes
+            .push(ChangeMut::SwapRemove(index, removed, moved_from));
+        Ok(value)
+    }
+
     pub fn len(&self) -> usize {
         self.store.index.len()
     }
@@ -340,6 +635,16 @@ where
         self._iter().map(|res| res.map(|(_, value)| value))
     }
 
+    /// Re-scan the list and check the in-memory index matches it. Errors with an
+    /// [`IndexDiff`] of entry pointers if it doesn't.
+    pub fn debug_validate(&self) -> Result<()>
+    where
+        T: Send,
+    {
+        let on_disk = VecRemove::load_index(&self.list)?;
+        IndexDiff::check(self.store.index.iter().copied(), on_disk)
+    }
+
     pub fn clear(&mut self) -> Result<()> {
         self.list.clear()?;
         let mut index = core::mem::take(&mut self.store.index);
@@ -0,0 +1,154 @@
+//! Key encodings whose bytes sort in the same order as the keys do.
+//!
+//! Indexes like [`BTreeMap`](crate::index::BTreeMap) keep their keys ordered in memory so how the
+//! keys are encoded doesn't matter to them. Something that reads keys straight off disk (or
+//! compares them as bytes) needs the encoded bytes to sort the same way the keys do which isn't
+//! true of bincode's encoding of integers or strings. Wrapping keys in these adapters makes it
+//! true and because each encoding also knows where it ends a tuple of them sorts the same way
+//! encoded as it does in memory:
+//!
+//! ```
+//! use llsdb::{BigEndian, Lexicographic};
+//! let encode = |key: &(Lexicographic<String>, BigEndian<u64>)| {
+//!     bincode::encode_to_vec(key, bincode::config::standard()).unwrap()
+//! };
+//! let a = (Lexicographic("ab".to_string()), BigEndian(300));
+//! let b = (Lexicographic("ab".to_string()), BigEndian(1_000));
+//! let c = (Lexicographic("abc".to_string()), BigEndian(0));
+//! assert!(a < b && b < c);
+//! assert!(encode(&a) < encode(&b) && encode(&b) < encode(&c));
+//! ```
+//!
+//! The encodings don't depend on the database's [`ValueEncoding`](crate::ValueEncoding).
+use bincode::{
+    de::{read::Reader, Decoder},
+    enc::{write::Writer, Encoder},
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
+
+/// An integer encoded as fixed width big-endian bytes. Signed integers have their sign bit
+/// flipped so negative numbers sort first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BigEndian<T>(pub T);
+
+/// A string or byte string encoded so that it sorts lexicographically.
+///
+/// Zero bytes are escaped as `[0x00, 0xff]` and the end is marked with `[0x00, 0x01]` so a
+/// string always sorts before the strings it is a prefix of.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lexicographic<T>(pub T);
+
+macro_rules! impl_big_endian {
+    ($($ty:ty => $unsigned:ty),*) => {$(
+        impl Encode for BigEndian<$ty> {
+            fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+                let flipped = (self.0 as $unsigned) ^ (<$ty>::MIN as $unsigned);
+                encoder.writer().write(&flipped.to_be_bytes())
+            }
+        }
+
+        impl Decode for BigEndian<$ty> {
+            fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+                let mut bytes = [0u8; core::mem::size_of::<$ty>()];
+                decoder.claim_bytes_read(bytes.len())?;
+                decoder.reader().read(&mut bytes)?;
+                let flipped = <$unsigned>::from_be_bytes(bytes);
+                Ok(BigEndian((flipped ^ (<$ty>::MIN as $unsigned)) as $ty))
+            }
+        }
+
+        bincode::impl_borrow_decode!(BigEndian<$ty>);
+
+        impl From<$ty> for BigEndian<$ty> {
+            fn from(value: $ty) -> Self {
+                BigEndian(value)
+            }
+        }
+    )*};
+}
+
+impl_big_endian!(
+    u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128,
+    i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128
+);
+
+fn encode_lexicographic<E: Encoder>(bytes: &[u8], encoder: &mut E) -> Result<(), EncodeError> {
+    for chunk in bytes.split_inclusive(|byte| *byte == 0) {
+        encoder.writer().write(chunk)?;
+        if chunk.last() == Some(&0) {
+            encoder.writer().write(&[0xff])?;
+        }
+    }
+    encoder.writer().write(&[0x00, 0x01])
+}
+
+fn decode_lexicographic<D: Decoder>(decoder: &mut D) -> Result<Vec<u8>, DecodeError> {
+    let mut bytes = vec![];
+    loop {
+        let mut byte = [0u8];
+        decoder.claim_bytes_read(1)?;
+        decoder.reader().read(&mut byte)?;
+        if byte[0] != 0 {
+            bytes.push(byte[0]);
+            continue;
+        }
+        decoder.claim_bytes_read(1)?;
+        decoder.reader().read(&mut byte)?;
+        match byte[0] {
+            0xff => bytes.push(0),
+            0x01 => return Ok(bytes),
+            _ => return Err(DecodeError::Other("invalid escape in lexicographic key")),
+        }
+    }
+}
+
+impl Encode for Lexicographic<String> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        encode_lexicographic(self.0.as_bytes(), encoder)
+    }
+}
+
+impl Decode for Lexicographic<String> {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let bytes = decode_lexicographic(decoder)?;
+        let string = String::from_utf8(bytes).map_err(|e| DecodeError::Utf8 {
+            inner: e.utf8_error(),
+        })?;
+        Ok(Lexicographic(string))
+    }
+}
+
+bincode::impl_borrow_decode!(Lexicographic<String>);
+
+impl Encode for Lexicographic<Vec<u8>> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        encode_lexicographic(&self.0, encoder)
+    }
+}
+
+impl Decode for Lexicographic<Vec<u8>> {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(Lexicographic(decode_lexicographic(decoder)?))
+    }
+}
+
+bincode::impl_borrow_decode!(Lexicographic<Vec<u8>>);
+
+impl From<String> for Lexicographic<String> {
+    fn from(value: String) -> Self {
+        Lexicographic(value)
+    }
+}
+
+impl From<&str> for Lexicographic<String> {
+    fn from(value: &str) -> Self {
+        Lexicographic(value.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Lexicographic<Vec<u8>> {
+    fn from(value: Vec<u8>) -> Self {
+        Lexicographic(value)
+    }
+}
@@ -8,8 +8,56 @@ mod pointer;
 pub use pointer::*;
 mod backend;
 pub use backend::*;
+mod metrics;
+pub use metrics::*;
+mod encoding;
+pub use encoding::*;
+mod quota;
+pub use quota::*;
+mod reader;
+pub use reader::*;
+mod clock;
+pub use clock::*;
+mod replication;
+pub use replication::*;
+mod backup_sink;
+pub use backup_sink::*;
+mod object_store;
+pub use object_store::*;
+mod profile;
+pub use profile::*;
+mod key;
+mod read_cache;
+pub use key::*;
+mod migrate;
+pub use migrate::*;
+#[cfg(feature = "bdk")]
+mod bdk;
+#[cfg(feature = "bdk")]
+pub use bdk::*;
+mod namespace;
+pub use namespace::*;
+mod dyn_list;
+pub use dyn_list::*;
+mod shared;
+pub use shared::*;
+#[cfg(feature = "embedded-storage")]
+mod flash;
+mod open;
+mod segmented;
+pub use segmented::*;
+mod counting;
+pub use counting::*;
+mod throttle;
+pub use throttle::*;
+mod sha256;
+pub mod testing;
+mod text_dump;
+#[cfg(feature = "embedded-storage")]
+pub use flash::*;
 
-pub(crate) mod macros;
+#[doc(hidden)]
+pub mod macros;
 
 use bincode::config::{Configuration, LittleEndian, NoLimit, Varint};
 const BINCODE_CONFIG: Configuration<LittleEndian, Varint, NoLimit> = bincode::config::standard();
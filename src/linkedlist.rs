@@ -1,10 +1,10 @@
 use crate::{
-    index::IndexStore, Backend, EntryHandle, EntryIter, EntryPointer, ListSlot, Pointer, Remap,
-    TxIo,
+    index::IndexStore, sha256::sha256, Backend, EncodeSegment, EntryHandle, EntryIter,
+    EntryPointer, ListCursor, ListSlot, Pointer, Remap, TxIo,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use core::marker::PhantomData;
-use std::cell::RefMut;
+use std::{cell::RefMut, collections::BTreeSet};
 
 #[derive(Debug)]
 pub struct LinkedList<T> {
@@ -59,6 +59,11 @@ impl<T: Send + 'static> IndexStore for LinkedList<T> {
             value_type: PhantomData,
         }
     }
+
+    fn rebuild<F: Backend>(&mut self, _io: &TxIo<'_, F>) -> Result<()> {
+        // there's no in-memory state
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -95,19 +100,80 @@ where
         self.io.push(self.slot, value)
     }
 
+    /// Push an entry made up of several segments that can be read on their own. See
+    /// [`TxIo::push_segments`]. The segments should encode the same way as a `T`. For a list of
+    /// tuples that means one segment for each field.
+    pub fn push_segments(
+        &self,
+        segments: &[&dyn EncodeSegment],
+    ) -> Result<(EntryHandle, Vec<u64>)> {
+        self.io.push_segments(self.slot, segments)
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = Result<T>> + '_ {
         let mut it = self.io.iter(self.slot);
         core::iter::from_fn(move || it.next::<T>())
     }
 
+    /// Iterate over the values along with the handles to their entries. Useful for indexes that
+    /// only keep the handles in memory and read values with [`TxIo::read_at`] when asked.
+    pub fn iter_with_handles(&self) -> impl Iterator<Item = Result<(EntryHandle, T)>> + '_ {
+        let mut it = self.io.iter(self.slot);
+        core::iter::from_fn(move || it.next_with_handle::<T>())
+    }
+
+    /// The first value from the front that `pred` holds for. Stops reading the list as soon as it
+    /// finds it.
+    pub fn find(&self, mut pred: impl FnMut(&T) -> bool) -> Result<Option<T>> {
+        for value in self.iter() {
+            let value = value?;
+            if pred(&value) {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Where the first value from the front that `pred` holds for is (counting from `0` at the
+    /// front)
+    pub fn position(&self, mut pred: impl FnMut(&T) -> bool) -> Result<Option<usize>> {
+        for (i, value) in self.iter().enumerate() {
+            if pred(&value?) {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether `pred` holds for any value in the list
+    pub fn any(&self, pred: impl FnMut(&T) -> bool) -> Result<bool> {
+        Ok(self.position(pred)?.is_some())
+    }
+
+    /// Set aside `bytes` of contiguous space for this list's future pushes so iterating over it
+    /// later reads from one place in the file. See [`TxIo::reserve`].
+    pub fn reserve(&self, bytes: u64) -> Result<()> {
+        self.io.reserve(self.slot, bytes)
+    }
+
     pub fn pop(&self) -> Result<Option<T>> {
         self.io.pop(self.slot)
     }
 
+    /// Pop values from the front for as long as `pred` holds. See [`TxIo::pop_while`].
+    pub fn pop_while(&self, pred: impl FnMut(&T) -> bool) -> Result<Vec<T>> {
+        self.io.pop_while(self.slot, pred)
+    }
+
     pub fn entry_iter(&self) -> EntryIter<'i, F> {
         self.io.iter(self.slot)
     }
 
+    /// Resume an [`EntryIter`] from a [`ListCursor`] taken from an iterator over this list.
+    pub fn entry_iter_from(&self, cursor: &ListCursor) -> Result<EntryIter<'i, F>> {
+        self.io.iter_from_cursor(self.slot, cursor)
+    }
+
     pub fn clear(&self) -> Result<()> {
         loop {
             if self.pop()?.is_none() {
@@ -159,6 +225,50 @@ pub enum MutNoValue {
     Remove(Remap),
 }
 
+/// Identifies what an entry in a [`LinkedListMut`] held when it was read (see
+/// [`LinkedListMutApi::version`]). If the entry is updated, overwritten or unlinked in a later
+/// transaction its version changes so it can be used to detect that something else got there
+/// first with [`LinkedListMutApi::compare_and_update`].
+///
+/// It's made up of where the entry is and a checksum of its value so it can be told apart from an
+/// entry with a different value that ends up in the same place. An entry with the *same* value
+/// that ends up in the same place has the same version (see
+/// [`compare_and_update`](LinkedListMutApi::compare_and_update)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, bincode::Encode, bincode::Decode)]
+pub struct EntryVersion {
+    pointer: Pointer,
+    checksum: u64,
+}
+
+/// The error returned by [`LinkedListMutApi::compare_and_update`] when the entry isn't at the
+/// expected version. Like [`QuotaExceeded`](crate::QuotaExceeded) use `downcast_ref` to tell it
+/// apart from other errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionConflict {
+    pub expected: EntryVersion,
+    /// The version the entry is at now or `None` if it has been unlinked
+    pub found: Option<EntryVersion>,
+}
+
+impl core::fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.found {
+            Some(found) => write!(
+                f,
+                "entry was changed: expected version {:?} but found {:?}",
+                self.expected, found
+            ),
+            None => write!(
+                f,
+                "entry was removed (expected version {:?})",
+                self.expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VersionConflict {}
+
 #[derive(Debug)]
 pub struct LinkedListMut<T>(pub LinkedList<Mut<T>>);
 
@@ -187,6 +297,10 @@ impl<T: Send + 'static> IndexStore for LinkedListMut<T> {
         let list = RefMut::map(list, |list| &mut list.0);
         LinkedListMutApi(LinkedList::create_api(list, io))
     }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.0.rebuild(io)
+    }
 }
 
 impl<'i, F, T> LinkedListMutApi<'i, F, T>
@@ -194,22 +308,113 @@ where
     F: Backend,
     T: bincode::Encode + bincode::Decode,
 {
+    /// Unlink the entry at `handle` from the list.
+    ///
+    /// The remap this writes points to the entry after it on disk so `handle` should be fresh. If
+    /// the entry after it has been unlinked since and its space reused, later iterations can
+    /// resolve the remap to the wrong entry. Unlinking an entry next to one unlinked earlier in the
+    /// same transaction rewrites that entry's remap to lead past both rather than adding another
+    /// one to follow.
     pub fn unlink(&self, handle: EntryHandle) -> Result<()> {
+        self.unlink_to(handle, handle.entry_pointer.next_entry_possibly_stale)
+    }
+
+    /// [`unlink`](Self::unlink) where `next` is where the entry after `handle` really is
+    fn unlink_to(&self, handle: EntryHandle, next: Pointer) -> Result<()> {
         let io = &self.0.io;
         let end_of_list = io.curr_head(self.0.slot);
-        let entry_pointer = handle.entry_pointer;
-        if end_of_list == entry_pointer.this_entry {
+        let this_entry = handle.entry_pointer.this_entry;
+        if end_of_list == this_entry {
             self.0.pop()?;
-        } else {
-            io.push(
-                self.0.slot,
-                &Mut::<T>::Remap(Remap {
-                    from: entry_pointer.this_entry,
-                    to: entry_pointer.next_entry_possibly_stale,
-                }),
-            )?;
-            io.free(handle);
+            return Ok(());
+        }
+        if !self.squash_remap(this_entry, next)? {
+            let remap = Remap {
+                from: this_entry,
+                to: next,
+            };
+            let remap_handle =
+                io.push_ignoring_quota(self.0.slot, &Mut::<T>::Remap(remap.clone()))?;
+            io.record_remap(self.0.slot, remap_handle, remap);
+        }
+        io.free_from_list(self.0.slot, handle);
+        Ok(())
+    }
+
+    /// Unlinking entries next to each other in the same transaction would leave a chain of remaps
+    /// that every iteration has to follow. Instead rewrite the remaps already written in this
+    /// transaction so that they lead straight past `this_entry`. Returns whether that leaves
+    /// nothing that needs a remap of its own from `this_entry`.
+    ///
+    /// Only the entries unlinked just before `this_entry` (whose remaps lead to it) and the one
+    /// just after it (whose remap its own would lead to) are in the way and once it's unlinked
+    /// nothing else can lead to either of them. A remap is only rewritten if the new one encodes to
+    /// the same length.
+    fn squash_remap(&self, this_entry: Pointer, next: Pointer) -> Result<bool> {
+        let io = &self.0.io;
+        let tx_remaps = io.tx_remaps(self.0.slot);
+        // a relocated entry points straight to what was after it so it leads there too
+        if tx_remaps.relocated_to.contains(&this_entry) || tx_remaps.relocated_to.contains(&next) {
+            return Ok(false);
+        }
+        let remaps = tx_remaps
+            .unlinked
+            .into_iter()
+            .filter(|(handle, _)| !io.is_released(handle.entry_pointer.this_entry))
+            .collect::<Vec<_>>();
+        let after = remaps.iter().find(|(_, remap)| remap.from == next);
+        let to = after.map_or(next, |(_, remap)| remap.to);
+
+        let leading_here = remaps
+            .iter()
+            .filter(|(_, remap)| remap.to == this_entry)
+            .map(|(handle, remap)| {
+                let remap = Remap {
+                    from: remap.from,
+                    to,
+                };
+                (*handle, remap)
+            })
+            .collect::<Vec<_>>();
+        let mut fits = true;
+        for (handle, remap) in &leading_here {
+            fits = fits && self.fits(*handle, remap)?;
+        }
+        if !leading_here.is_empty() && fits {
+            for (handle, remap) in leading_here {
+                self.rewrite_remap(handle, remap)?;
+            }
+            return Ok(true);
+        }
+
+        if let Some((handle, _)) = after {
+            let remap = Remap {
+                from: this_entry,
+                to,
+            };
+            if self.fits(*handle, &remap)? {
+                self.rewrite_remap(*handle, remap)?;
+                return Ok(true);
+            }
         }
+        Ok(false)
+    }
+
+    /// Whether `remap` encodes to the same length as the value of the entry at `handle`
+    fn fits(&self, handle: EntryHandle, remap: &Remap) -> Result<bool> {
+        let mut buf = vec![];
+        let len = self
+            .0
+            .io
+            .value_encoding()
+            .encode_into_std_write(Mut::<T>::Remap(remap.clone()), &mut buf)?;
+        Ok(len as u64 == handle.value_len)
+    }
+
+    fn rewrite_remap(&self, handle: EntryHandle, remap: Remap) -> Result<()> {
+        let io = &self.0.io;
+        io.overwrite(handle, &Mut::<T>::Remap(remap.clone()))?;
+        io.record_remap(self.0.slot, handle, remap);
         Ok(())
     }
 
@@ -217,6 +422,111 @@ where
         self.0.io.push(self.0.slot, &Mut::Add(value))
     }
 
+    /// The current [`EntryVersion`] of the entry at `handle` or `None` if it has been unlinked
+    pub fn version(&self, handle: EntryHandle) -> Result<Option<EntryVersion>> {
+        let entry_pointer = handle.entry_pointer;
+        if self.0.io.is_released(entry_pointer.this_entry) {
+            return Ok(None);
+        }
+        let bytes = self
+            .0
+            .io
+            .read_bytes(entry_pointer.value_pointer(), handle.value_len)?;
+        let hash = sha256(&[&bytes]);
+        Ok(Some(EntryVersion {
+            pointer: entry_pointer.this_entry,
+            checksum: u64::from_le_bytes(hash[..8].try_into().expect("8 bytes")),
+        }))
+    }
+
+    /// Replace the entry at `handle` with `value` but only if it is still at version `expected`.
+    /// Like [`unlink`](Self::unlink) followed by [`push`](Self::push) the new value goes to the
+    /// front of the list. Errors with [`VersionConflict`] if the entry has changed or is gone.
+    ///
+    /// Versions don't say when an entry was written so this can't tell an entry apart from one
+    /// that replaced it with the same value. If the entry is unlinked, its space is freed and the
+    /// same value is pushed into that space again, a version read before all that is still
+    /// accepted. Only use it where writing the same value back doesn't matter or the values carry
+    /// something of their own (like a counter) that changes on every update.
+    pub fn compare_and_update(
+        &self,
+        handle: EntryHandle,
+        expected: EntryVersion,
+        value: T,
+    ) -> Result<EntryHandle> {
+        let found = self.version(handle)?;
+        if found != Some(expected) {
+            return Err(VersionConflict { expected, found }.into());
+        }
+        self.unlink(handle)?;
+        self.push(value)
+    }
+
+    /// Replace the value at `handle` in place rather than unlinking it and pushing a new one.
+    /// Errors if `value` doesn't encode to the same length as the existing one (or the same padded
+    /// length). See [`TxIo::overwrite`] for the caveats.
+    pub fn overwrite(&self, handle: EntryHandle, value: &T) -> Result<()> {
+        self.0.io.overwrite(handle, &Mut::Add(value))
+    }
+
+    /// Move the entry at `handle` to the lowest free space it fits in with [`TxIo::relocate`] and
+    /// record a remap so the list finds it there. Returns the handle of the moved entry. Errors if
+    /// the entry isn't in the list.
+    ///
+    /// The copy never goes where an entry unlinked from this list used to be if that entry's
+    /// remap comes before it since iteration would follow the old remap from there.
+    pub fn relocate(&self, handle: EntryHandle) -> Result<EntryHandle> {
+        self.relocate_with(handle, None)
+    }
+
+    /// Replace the value of the entry at `handle` with `value` keeping its place in the list. Like
+    /// [`relocate`](Self::relocate) the new entry goes in the lowest free space it fits in with a
+    /// remap to it. Returns the handle of the new entry.
+    pub fn replace(&self, handle: EntryHandle, value: &T) -> Result<EntryHandle> {
+        let mut value_bytes = vec![];
+        self.0
+            .io
+            .value_encoding()
+            .encode_into_std_write(Mut::Add(value), &mut value_bytes)?;
+        self.relocate_with(handle, Some(value_bytes))
+    }
+
+    /// [`relocate`](Self::relocate) with the new entry holding `value_bytes` if there are some
+    fn relocate_with(
+        &self,
+        handle: EntryHandle,
+        value_bytes: Option<std::vec::Vec<u8>>,
+    ) -> Result<EntryHandle> {
+        let io = &self.0.io;
+        let this_entry = handle.entry_pointer.this_entry;
+        let mut remapped_from = BTreeSet::new();
+        let mut it = io.iter(self.0.slot);
+        while let Some((entry, value)) = it.next_with_handle::<MutNoValue>().transpose()? {
+            if entry.entry_pointer.this_entry == this_entry {
+                let was_head = io.curr_head(self.0.slot) == this_entry;
+                let next = it.next_entry();
+                let new_handle =
+                    io.relocate_to_lowest(self.0.slot, handle, next, value_bytes, &remapped_from)?;
+                io.record_relocation(self.0.slot, next);
+                if !was_head {
+                    io.push_ignoring_quota(
+                        self.0.slot,
+                        &Mut::<T>::Remap(Remap {
+                            from: this_entry,
+                            to: new_handle.entry_pointer.this_entry,
+                        }),
+                    )?;
+                }
+                return Ok(new_handle);
+            }
+            if let MutNoValue::Remove(remap) = value {
+                remapped_from.insert(remap.from);
+                it.remap(remap);
+            }
+        }
+        Err(anyhow!("entry at {:?} isn't in the list", this_entry))
+    }
+
     pub fn iter_handles(&self) -> impl Iterator<Item = Result<(EntryHandle, T)>> + '_ {
         let mut it = self.0.io.iter(self.0.slot);
         core::iter::from_fn(move || loop {
@@ -247,10 +557,48 @@ where
         self.iter_handles().map(|res| res.map(|(_, value)| value))
     }
 
+    /// The first value from the front that `pred` holds for along with its handle e.g. to
+    /// [`unlink`](Self::unlink) it. Stops reading the list as soon as it finds it.
+    pub fn find_handle(
+        &self,
+        mut pred: impl FnMut(&T) -> bool,
+    ) -> Result<Option<(EntryHandle, T)>> {
+        for entry in self.iter_handles() {
+            let (handle, value) = entry?;
+            if pred(&value) {
+                return Ok(Some((handle, value)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Where the first value from the front that `pred` holds for is (counting from `0` at the
+    /// front)
+    pub fn position(&self, mut pred: impl FnMut(&T) -> bool) -> Result<Option<usize>> {
+        for (i, value) in self.iter().enumerate() {
+            if pred(&value?) {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether `pred` holds for any value in the list
+    pub fn any(&self, pred: impl FnMut(&T) -> bool) -> Result<bool> {
+        Ok(self.position(pred)?.is_some())
+    }
+
     pub fn pop(&self) -> Result<Option<T>> {
-        if let Some((handle, value)) = self.iter_handles().next().transpose()? {
-            self.unlink(handle)?;
-            return Ok(Some(value));
+        let mut it = self.0.io.iter(self.0.slot);
+        while let Some((handle, value)) = it.next_with_handle::<Mut<T>>().transpose()? {
+            match value {
+                Mut::Remap(remap) => it.remap(remap),
+                Mut::Add(value) => {
+                    // the iterator has followed the remaps to what's really after the entry
+                    self.unlink_to(handle, it.next_entry())?;
+                    return Ok(Some(value));
+                }
+            }
         }
 
         Ok(None)
@@ -1,27 +1,63 @@
 use crate::{
-    freespace::{Free, FreeSpace},
+    freespace::{find_overlap, Free, FreeSpace},
     index::{IndexStore, RefCellIndexStore},
-    Backend, EntryHandle, EntryPointer, LinkedList, ListSlot, Pointer, Remap, BINCODE_CONFIG,
+    metrics::Metered,
+    pointer::{read_le_uint, write_le_uint},
+    quota::{in_namespace, NamespaceQuota, QuotaState, Quotas},
+    read_cache::{CachedReader, Invalidating, ReadCache},
+    replication::{Captured, ChangesetWrite},
+    sha256::sha256,
+    Backend, BackupHeader, Changeset, Clock, EncodeSegment, EntryHandle, EntryPointer,
+    LinkEncoding, LinkedList, LinkedListMut, ListQuota, ListSlot, ListUsage, Metrics, Mut,
+    MutNoValue, Pointer, ProfileReport, ReadTrace, ReaderPool, Remap, ValueEncoding,
+    BINCODE_CONFIG,
 };
 use anyhow::{anyhow, Context, Result};
 use core::mem::size_of;
 use std::{
-    cell::RefCell,
-    collections::{BTreeSet, HashMap},
+    cell::{Ref, RefCell, RefMut},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     io::{Read, SeekFrom, Write},
     marker::PhantomData,
     rc::Rc,
+    sync::Arc,
+    time::SystemTime,
 };
 const META_LIST: LinkedList<Meta> = LinkedList::new(0);
 const MAGIC_BYTES: [u8; 5] = [0x26, 0xd3, 0x64, 0x62, 0x21];
+/// The biggest scratch buffer a transaction keeps around for encoding entries
+const MAX_SCRATCH_CAPACITY: usize = 64 * 1024;
 
-pub struct LlsDb<F> {
+pub struct LlsDb<F: Backend> {
     io: Option<Io<F>>,
     slots_by_name: HashMap<String, Meta>,
-    indexers: Vec<Box<dyn RefCellIndexStore>>,
+    /// Indexes keyed by id. Ids aren't reused so handles to indexes stored in failed transactions
+    /// stay invalid.
+    indexers: BTreeMap<usize, Box<dyn RefCellIndexStore>>,
+    next_index_id: usize,
     list_refs: BTreeSet<ListSlot>,
     used_slots: BTreeSet<ListSlot>,
     free_space: Option<FreeSpace>,
+    quotas: Quotas,
+    /// Space set aside for each list's pushes as (start, size). Between transactions it is left as
+    /// free space so nothing is lost if the database is closed.
+    reservations: BTreeMap<ListSlot, (Pointer, u64)>,
+    /// Changesets of committed transactions that haven't been taken yet if the replication log
+    /// is on
+    changesets: Option<Vec<Changeset>>,
+    backup_tracking: Option<BackupTracking>,
+    tx_memory_limit: Option<u64>,
+    trim_policy: TrimPolicy,
+    /// Why the database can't be used anymore (see [`Poisoned`])
+    poisoned: Option<String>,
+}
+
+/// The pages changed since backup tracking was turned on
+struct BackupTracking {
+    /// The generation when tracking was turned on
+    since: u64,
+    /// The generation each page was last changed in keyed by page index
+    dirty_pages: BTreeMap<u64, u64>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -29,11 +65,40 @@ pub struct InitOptions {
     /// Page size of the underlying storage media
     ///
     /// default: `4096`
-    page_size: u16,
+    pub page_size: u16,
     /// The maximum on disk size of the database
     ///
     /// default: `u64::MAX`
-    max_size: u64,
+    pub max_size: u64,
+    /// The number of slots in the first page used to persist free space. Whatever is left over
+    /// is used for list slots. More free slots means less space is lost when the free space is
+    /// fragmented, at the cost of being able to have fewer lists.
+    ///
+    /// default: `None` (half of the first page)
+    pub n_free_slots: Option<u16>,
+    /// The number of extra header pages to hold list slots that don't fit in the first page. Each
+    /// extra page holds `page_size / 8` list slots.
+    ///
+    /// default: `0`
+    pub n_extra_header_pages: u16,
+    /// Use 32-bit pointers in the header pages and for the pointer each entry keeps to the next
+    /// one. This fits twice as many list and free slots in a page and keeps the overhead of an
+    /// entry to 4 bytes however big the database gets but limits it to 4GiB.
+    ///
+    /// default: `false`
+    pub compact_pointers: bool,
+    /// How values are encoded. Fixed-width integers make values with the same type the same
+    /// length at the cost of space.
+    ///
+    /// default: [`ValueEncoding::default`] (varint, little endian)
+    pub value_encoding: ValueEncoding,
+    /// Pad the length of every entry to a multiple of this many bytes. The padding gives
+    /// [`TxIo::overwrite`] room to write a value that encodes a little longer than the one it
+    /// replaces at the cost of the space it takes up. Freeing an entry frees its padding along
+    /// with it.
+    ///
+    /// default: `1` (no padding)
+    pub pad_entries_to: u8,
 }
 
 impl Default for InitOptions {
@@ -41,10 +106,77 @@ impl Default for InitOptions {
         Self {
             page_size: 4096,
             max_size: u64::MAX,
+            n_free_slots: None,
+            n_extra_header_pages: 0,
+            compact_pointers: false,
+            value_encoding: ValueEncoding::default(),
+            pad_entries_to: 1,
         }
     }
 }
 
+/// Statistics about how the database is keeping track of free space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreeSpaceStats {
+    /// The number of free slots in the first page
+    pub n_free_slots: usize,
+    /// The number of free slots currently holding a free space
+    pub n_used_free_slots: usize,
+    /// The number of free spaces that didn't fit into a free slot. These are still used while the
+    /// database is open but will be forgotten (leaked) once it is closed.
+    pub n_unplaced: usize,
+    /// The number of freed entries whose space isn't being reused yet because they are pinned
+    /// (see [`TxIo::pin`]). Like unplaced spaces these are leaked if the database is closed.
+    pub n_held: usize,
+}
+
+impl FreeSpaceStats {
+    /// Whether free spaces are spilling over the free slots. If this is regularly the case the
+    /// database should be initialized with more free slots (see [`InitOptions::n_free_slots`]).
+    pub fn is_spilling(&self) -> bool {
+        self.n_unplaced > 0
+    }
+}
+
+/// How the database chooses where to write new data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AllocationPolicy {
+    /// Use the smallest free space that fits, preferring lower addresses among spaces of the same
+    /// size. This keeps the database compact.
+    #[default]
+    BestFit,
+    /// Use the first free space that fits after the last place something was written, wrapping
+    /// around at the end. This spreads writes over the whole backend rather than rewriting the
+    /// same spots over and over which is what you want for flash storage.
+    NextFit,
+}
+
+/// When the database truncates the free space at the end of the backend to give it back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrimPolicy {
+    /// Truncate it after every transaction that commits
+    #[default]
+    Always,
+    /// Only truncate it once at least this many bytes at the end are free. Workloads that shrink
+    /// and then grow straight back don't keep truncating and extending the backend. Use
+    /// [`LlsDb::shrink_to_fit`] to truncate whatever is free at the end.
+    Threshold(u64),
+}
+
+/// Whether committing a transaction waits for what it wrote to reach the backend's storage (see
+/// [`LlsDb::execute`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Sync what a transaction wrote before writing the first page that makes it visible and then
+    /// sync the first page. Once [`LlsDb::execute`] returns the transaction survives power failure.
+    #[default]
+    Sync,
+    /// Never sync. Transactions still survive the process dying since their writes are with the
+    /// operating system but power failure can lose them or leave the first page pointing at data
+    /// that never made it to storage.
+    NoSync,
+}
+
 impl<F> LlsDb<F>
 where
     F: Backend,
@@ -58,23 +190,43 @@ where
             free_space: Some(free_space),
             list_refs: Default::default(),
             indexers: Default::default(),
+            next_index_id: 0,
+            quotas: Default::default(),
+            reservations: Default::default(),
+            changesets: None,
+            backup_tracking: None,
+            tx_memory_limit: None,
+            trim_policy: TrimPolicy::Always,
+            poisoned: None,
         }
     }
 
     pub fn load(file: F) -> Result<Self> {
         let io = Io::load(file, MAGIC_BYTES)?;
         let mut loaded = Self::new(io);
-        let (used_slots, slots_by_name) = loaded.execute(|tx| {
-            let mut used_slots = BTreeSet::default();
+        let (output, pending) = loaded.run_tx(|tx| {
+            let mut used_slots = BTreeSet::from_iter([META_LIST.slot()]);
             let mut slots_by_name = HashMap::default();
+            let list_quotas = tx.list_quotas_in_meta();
             let mut it = tx.io.iter(META_LIST.slot());
-            while let Some(meta) = it.next::<Meta>() {
+            loop {
+                let meta = if list_quotas {
+                    it.next::<Meta>()
+                } else {
+                    it.next::<LegacyMeta>().map(|meta| meta.map(Meta::from))
+                };
+                let Some(meta) = meta else {
+                    break;
+                };
                 let meta = meta?;
                 used_slots.insert(meta.slot);
                 slots_by_name.insert(meta.name.clone(), meta);
             }
             Ok((used_slots, slots_by_name))
         })?;
+        // loading only reads so there's nothing to commit and the generation stays the same
+        loaded.finish_tx(pending, output.is_ok());
+        let (used_slots, slots_by_name) = output?;
         loaded.used_slots = used_slots;
         loaded.slots_by_name = slots_by_name;
 
@@ -82,18 +234,36 @@ where
     }
 
     pub fn init(file: F) -> Result<Self> {
-        let io = Io::init(
-            Preamble {
-                magic_bytes: MAGIC_BYTES,
-                config: VersionedConfig::zero(file.init_page_size()),
-            },
-            file.init_max_size(),
-            file,
-        )?;
+        let options = InitOptions {
+            page_size: file.init_page_size(),
+            max_size: file.init_max_size(),
+            n_free_slots: None,
+            n_extra_header_pages: 0,
+            compact_pointers: false,
+            value_encoding: ValueEncoding::default(),
+            pad_entries_to: 1,
+        };
+        Self::init_with_options(file, options)
+    }
+
+    pub fn init_with_options(file: F, options: InitOptions) -> Result<Self> {
+        let pad_entries_to = options.pad_entries_to;
+        let (preamble, max_size) = init_preamble(options, random_id())?;
+        let io = Io::init(preamble, max_size, pad_entries_to, file)?;
 
         Ok(Self::new(io))
     }
 
+    /// The random id the database was given when it was created. Databases created before ids
+    /// were introduced don't have one.
+    pub fn id(&self) -> Option<DbId> {
+        self.io
+            .as_ref()
+            .expect("can't call id during a tx")
+            .id
+            .map(DbId)
+    }
+
     pub fn backend(&self) -> &F {
         &self
             .io
@@ -103,9 +273,13 @@ where
     }
 
     fn io(&mut self) -> &mut Io<F> {
-        self.io
-            .as_mut()
-            .expect("attempt to take io during a transaction")
+        match (&mut self.io, &self.poisoned) {
+            (Some(io), _) => io,
+            (None, Some(reason)) => {
+                panic!("the database was poisoned and lost its backend: {}", reason)
+            }
+            (None, None) => panic!("attempt to take io during a transaction"),
+        }
     }
 
     fn free_space(&mut self) -> &mut FreeSpace {
@@ -122,8 +296,644 @@ where
         }
     }
 
-    pub fn into_backend(self) -> F {
-        self.io.unwrap().file
+    pub fn free_space_stats(&self) -> FreeSpaceStats {
+        let free_space = self
+            .free_space
+            .as_ref()
+            .expect("can't call free_space_stats during a tx");
+        let persist_state = free_space.persist_state();
+        FreeSpaceStats {
+            n_free_slots: persist_state.len(),
+            n_used_free_slots: persist_state
+                .iter()
+                .filter(|free| **free != Free::NULL)
+                .count(),
+            n_unplaced: free_space.n_unplaced(),
+            n_held: free_space.n_held(),
+        }
+    }
+
+    /// Every free space the database knows about as its start and length in order of where they
+    /// start. This includes the unplaced ones (see [`FreeSpaceStats::n_unplaced`]) but not the
+    /// ones held back by pins. The last one is the space after the last entry which runs up to
+    /// [`InitOptions::max_size`]. Handy for showing how the file is laid out and how fragmented it
+    /// is.
+    pub fn free_regions(&self) -> impl Iterator<Item = (Pointer, u64)> + '_ {
+        self.free_space
+            .as_ref()
+            .expect("can't call free_regions during a tx")
+            .free_regions()
+            .map(|(start, end)| (start, end.0 - start.0))
+            // empty free slots
+            .filter(|&(_, len)| len > 0)
+    }
+
+    /// Set how the database chooses where to write new data. This isn't persisted so it has to be
+    /// set each time the database is opened.
+    pub fn set_allocation_policy(&mut self, policy: AllocationPolicy) {
+        self.free_space().set_policy(policy);
+    }
+
+    /// Set when the free space at the end of the backend is truncated. Like the allocation policy
+    /// this isn't persisted.
+    pub fn set_trim_policy(&mut self, policy: TrimPolicy) {
+        self.trim_policy = policy;
+    }
+
+    /// Set whether commits are synced. This isn't persisted so it has to be set each time the
+    /// database is opened.
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.io().durability = durability;
+    }
+
+    /// Truncate the free space at the end of the backend now whatever the [`TrimPolicy`]
+    pub fn shrink_to_fit(&mut self) -> Result<()> {
+        self.trim(0)
+    }
+
+    /// Where `pointer` points in the backend or `None` if it's [`Pointer::NULL`]. Pointers count
+    /// bytes from the end of the first page so this is what to look for in a hexdump of the file.
+    /// See also [`EntryPointer::file_offset`] and [`EntryHandle::file_range`].
+    pub fn pointer_to_offset(&self, pointer: Pointer) -> Option<u64> {
+        self.io
+            .as_ref()
+            .expect("can't call pointer_to_offset during a tx")
+            .pointer_to_file_position(pointer)
+    }
+
+    /// Tell the backend about every free region of at least `min_len` bytes with
+    /// [`Backend::discard`] so it can give back the storage behind them. The free space at the end
+    /// is left to truncating (see [`TrimPolicy`]). Every region is discarded again each time this
+    /// is called so call it after transactions that free a lot rather than after every one.
+    pub fn discard_free_space(&mut self, min_len: u64) -> Result<()> {
+        let regions = self.free_regions().collect::<Vec<_>>();
+        let io = self.io();
+        let file_len = io.file.seek(SeekFrom::End(0))?;
+        for (start, len) in regions {
+            let position = io
+                .pointer_to_file_position(start)
+                .expect("free space never starts at null");
+            let len = len.min(file_len.saturating_sub(position));
+            if len > 0 && len >= min_len {
+                io.clear_read_cache();
+                io.file.discard(position, len)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Truncate the backend to the start of the free space at its end if that frees at least
+    /// `threshold` bytes
+    fn trim(&mut self, threshold: u64) -> Result<()> {
+        let Some(trim_to) = self.free_space().where_to_trim() else {
+            return Ok(());
+        };
+        let io = self.io();
+        let truncate_to = io
+            .pointer_to_file_position(trim_to)
+            .expect("always returns a non-null pointer");
+        if threshold > 0 && io.file.seek(SeekFrom::End(0))?.saturating_sub(truncate_to) < threshold
+        {
+            return Ok(());
+        }
+        io.clear_read_cache();
+        io.file.truncate(truncate_to)
+    }
+
+    /// Check every allocation from now on against the space holding live data and panic if the
+    /// two ever overlap. This is for catching bugs in how free space is kept track of before they
+    /// destroy data. It isn't persisted so it has to be turned on each time the database is opened.
+    ///
+    /// The live data is found by walking every list like [`Transaction::dump`] does so it's slow
+    /// for big databases and lists with entries unlinked from their middle are only checked up to
+    /// the first unlinked entry. Errors if the free space already overlaps a list.
+    pub fn check_allocations(&mut self) -> Result<()> {
+        let mut live = self.execute(|tx| {
+            Ok(tx
+                .entry_extents()?
+                .into_iter()
+                .map(|(_, entry_pointer, end)| (entry_pointer.this_entry, end))
+                .collect::<Vec<_>>())
+        })?;
+        let io = self.io();
+        if let Some(overflow) = &io.header_overflow {
+            if overflow.location != Pointer::NULL {
+                let len = (overflow.heads.len() * io.pointer_size) as u64;
+                live.push((overflow.location, Pointer(overflow.location.0 + len)));
+            }
+        }
+        // walking through stale pointers into reused space can find the same entry from two lists
+        // (or start partway into one) so merge the extents that overlap
+        live.sort();
+        let live = live.into_iter().fold(vec![], |mut merged, (start, end)| {
+            match merged.last_mut() {
+                Some((_, last_end)) if *last_end > start => *last_end = end.max(*last_end),
+                _ => merged.push((start, end)),
+            }
+            merged
+        });
+        let free_space = self.free_space();
+        // the live regions no longer overlap each other and the free ones never do
+        let mut spaces = free_space
+            .free_regions()
+            .chain(live.iter().copied())
+            .map(|(start, end)| Free::from_start_pointer(start, end.0 - start.0))
+            .collect::<Vec<_>>();
+        if let Some((a, b)) = find_overlap(&spaces) {
+            let (a, b) = (spaces.swap_remove(a.max(b)), spaces.swap_remove(a.min(b)));
+            return Err(anyhow!(
+                "free space overlaps live data ({:?} and {:?})",
+                a,
+                b
+            ));
+        }
+        free_space.check_allocations(live);
+        Ok(())
+    }
+
+    /// Install hooks to collect metrics about the database's operations.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        self.io().metrics = Some(metrics);
+    }
+
+    /// Keep up to `n_pages` of the pages read from the backend most recently in memory so reading
+    /// entries that are close together doesn't go to the backend each time. `0` turns the cache
+    /// off which is the default. Hits and misses are reported to [`Metrics`]. Like metrics this
+    /// isn't persisted.
+    pub fn set_read_cache(&mut self, n_pages: usize) {
+        let io = self.io();
+        io.read_cache = match n_pages {
+            0 => None,
+            n_pages => Some(ReadCache::new(io.page_buf.len() as u64, n_pages)),
+        };
+    }
+
+    /// Replace the clock the database gets the time from (by default [`SystemClock`]). Like
+    /// metrics this isn't persisted.
+    ///
+    /// [`SystemClock`]: crate::SystemClock
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.io().clock = clock;
+    }
+
+    /// Fail transactions that hold on to more than about `limit` bytes of memory with
+    /// [`TxMemoryExceeded`]. Without a limit a transaction that pushes or pops millions of entries
+    /// keeps growing the state needed to roll it back until the process runs out of memory. See
+    /// [`TxIo::memory_used`] for what is counted. Like metrics this isn't persisted.
+    pub fn set_tx_memory_limit(&mut self, limit: Option<u64>) {
+        self.tx_memory_limit = limit;
+    }
+
+    /// The current time according to the installed [`Clock`]
+    pub fn now(&mut self) -> SystemTime {
+        self.io().clock.now()
+    }
+
+    /// Start (or stop) keeping a [`Changeset`] for every committed transaction so they can be
+    /// shipped to a follower with [`apply_changeset`](Self::apply_changeset). Stopping throws away
+    /// any that haven't been taken.
+    pub fn set_replication_log(&mut self, enabled: bool) {
+        match (enabled, &self.changesets) {
+            (true, None) => self.changesets = Some(vec![]),
+            (false, _) => self.changesets = None,
+            _ => {}
+        }
+    }
+
+    /// Take the changesets of the transactions committed since the last call (oldest first). Empty
+    /// unless [`set_replication_log`](Self::set_replication_log) is on.
+    pub fn take_changesets(&mut self) -> Vec<Changeset> {
+        self.changesets
+            .as_mut()
+            .map(core::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Apply a [`Changeset`] taken from a primary database to this one.
+    ///
+    /// The follower must start out as a byte for byte copy of the primary as it was when its
+    /// replication log was turned on and changesets have to be applied in order without gaps.
+    /// Afterwards the database is loaded again from the backend so lists and indexes taken before
+    /// have to be taken again. Settings that aren't persisted like the metrics, [`Durability`],
+    /// allocation and trim policies, transaction memory limit and namespace quotas are kept (the
+    /// usage of every quota is counted again). If this fails the database is poisoned and every
+    /// transaction fails with [`Poisoned`].
+    pub fn apply_changeset(&mut self, changeset: &Changeset) -> Result<()> {
+        self.rewrite_backend(|file| {
+            for write in &changeset.writes {
+                file.seek(SeekFrom::Start(write.offset))?;
+                file.write_all(&write.bytes)?;
+            }
+            file.truncate(changeset.file_len)
+        })
+    }
+
+    /// Upgrade the header of a database created by an older version of llsdb to format
+    /// `target_version` (see [`VersionedConfig`]) in place so it gets what the newer header adds
+    /// like the id, checksum and commit generation of version 4. Nothing else about the layout
+    /// changes. Use [`upgrade_format`](Self::upgrade_format) to change the page size, turn on the
+    /// features of the newest format that are only set when a database is created (like the free
+    /// slot checksum) or go to the newest format when the header doesn't have room.
+    ///
+    /// Like a commit the upgrade is a single write of the first page so the database is either
+    /// upgraded or not if it's interrupted. The list slots stay where they are and the bigger
+    /// header takes space from the free slots. If more free spaces are persisted than fit in the
+    /// free slots that are left the smallest are lost. The database is loaded again afterwards so
+    /// any indexes that were stored have to be stored again but settings are kept like in
+    /// [`apply_changeset`](Self::apply_changeset). If this fails the database is poisoned.
+    pub fn upgrade_header(&mut self, target_version: u32) -> Result<()> {
+        let page = self
+            .io
+            .as_ref()
+            .expect("can't upgrade during a tx")
+            .upgraded_first_page(target_version)?;
+        self.rewrite_backend(|file| {
+            file.rewind()?;
+            file.write_all(&page)?;
+            Ok(())
+        })
+    }
+
+    /// Copy the database into the empty backend `fresh` laid out in the newest format (see
+    /// [`VersionedConfig::LATEST_VERSION`]) with `options` and carry on with the copy handing back
+    /// the old backend. The copy can have a different page size, number of free slots or extra
+    /// header pages and gets every feature of the newest format like the free slot checksum.
+    ///
+    /// Everything after the first page is copied byte for byte so entries stay at the same
+    /// pointers and whatever points at them (like remaps, index snapshots or the nodes of a
+    /// [`BigBTreeMap`](crate::index::BigBTreeMap)) is still right. That means `options` has to
+    /// keep the value encoding, the entry padding and whether entries have fixed width links (see
+    /// [`InitOptions::compact_pointers`]) the same as they are. The lists keep their slots and the
+    /// id and commit generation are kept. Free spaces that don't fit in the copy's free slots are
+    /// lost.
+    ///
+    /// Settings are kept like in [`apply_changeset`](Self::apply_changeset) apart from the
+    /// replication log which is turned off since followers have to start again from a copy of the
+    /// new backend. If this fails the database carries on with the old backend.
+    pub fn upgrade_format(&mut self, fresh: F, options: InitOptions) -> Result<F> {
+        self.check_poisoned()?;
+        let free_regions = self.free_space().free_regions().collect::<Vec<_>>();
+        let copy = self
+            .io
+            .as_mut()
+            .expect("can't upgrade during a tx")
+            .copy_into(fresh, options, &free_regions)?;
+        let loaded = Self::load(copy)?;
+        self.changesets = None;
+        let (settings, old) = self.io.take().expect("checked above").into_settings();
+        self.carry_over(loaded, settings)?;
+        Ok(old)
+    }
+
+    /// Change the backend underneath the database with `rewrite` and load it again. The settings
+    /// that aren't persisted are carried over to the loaded database. If anything fails the
+    /// database is poisoned since what's in memory may no longer match the backend.
+    fn rewrite_backend(&mut self, rewrite: impl FnOnce(&mut F) -> Result<()>) -> Result<()> {
+        self.check_poisoned()?;
+        let io = self
+            .io
+            .as_mut()
+            .expect("can't rewrite the backend during a tx");
+        if let Err(e) = rewrite(&mut io.file).and_then(|_| io.file.sync_data()) {
+            self.poisoned = Some(format!("{:#}", e));
+            return Err(e);
+        }
+        let (settings, file) = self.io.take().expect("must be there").into_settings();
+        let loaded = match Self::load(file) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                self.poisoned = Some(format!("{:#}", e));
+                return Err(e);
+            }
+        };
+        self.carry_over(loaded, settings)
+    }
+
+    /// Replace the database with `loaded` giving it the settings that aren't persisted
+    fn carry_over(&mut self, mut loaded: Self, settings: IoSettings) -> Result<()> {
+        let allocation_policy = self.free_space().policy();
+        let quotas = core::mem::take(&mut self.quotas);
+        loaded.io().metrics = settings.metrics;
+        loaded.io().clock = settings.clock;
+        loaded.io().profile = settings.profile;
+        loaded.io().read_trace = settings.read_trace;
+        loaded.io().strict_remaps = settings.strict_remaps;
+        loaded.io().durability = settings.durability;
+        loaded.set_read_cache(settings.read_cache_pages);
+        loaded.set_allocation_policy(allocation_policy);
+        loaded.tx_memory_limit = self.tx_memory_limit;
+        loaded.trim_policy = self.trim_policy;
+        loaded.changesets = self.changesets.take();
+        if self.backup_tracking.is_some() {
+            loaded.set_backup_tracking(true);
+        }
+        // the lists may have changed so the usage is counted again
+        let recounted = loaded.execute_read_only(|tx| tx.recount_quotas(&quotas));
+        *self = loaded;
+        recounted
+    }
+
+    /// Fail with [`Poisoned`] if an earlier [`apply_changeset`](Self::apply_changeset) or
+    /// [`upgrade_header`](Self::upgrade_header) failed partway through
+    fn check_poisoned(&self) -> Result<()> {
+        match &self.poisoned {
+            Some(reason) => Err(Poisoned {
+                reason: reason.clone(),
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// The number of transactions that have been committed to the database. `None` if it was
+    /// created before the count was kept.
+    pub fn generation(&self) -> Option<u64> {
+        self.io
+            .as_ref()
+            .expect("can't call generation during a tx")
+            .generation()
+    }
+
+    /// Start (or stop) keeping track of which pages committed transactions change so
+    /// [`backup_since`](Self::backup_since) only has to copy those. Only changes made while this
+    /// is on are known about so the first backup after opening the database is a full one.
+    pub fn set_backup_tracking(&mut self, enabled: bool) {
+        match (enabled, &self.backup_tracking) {
+            (true, None) => {
+                self.backup_tracking = Some(BackupTracking {
+                    since: self.generation().unwrap_or(0),
+                    dirty_pages: Default::default(),
+                })
+            }
+            (false, _) => self.backup_tracking = None,
+            _ => {}
+        }
+    }
+
+    /// Write a backup of the pages changed after `generation` to `writer` and return its header.
+    ///
+    /// If the changes after `generation` weren't all tracked (see
+    /// [`set_backup_tracking`](Self::set_backup_tracking)) the whole database is written instead
+    /// which the header's `since` being `None` tells you. Keep [`BackupHeader::generation`] to
+    /// pass in next time.
+    pub fn backup_since(&mut self, generation: u64, writer: impl Write) -> Result<BackupHeader> {
+        self.write_backup(Some(generation), writer)
+    }
+
+    /// Write a full backup of the database to `writer`. See [`backup_since`](Self::backup_since).
+    pub fn backup(&mut self, writer: impl Write) -> Result<BackupHeader> {
+        self.write_backup(None, writer)
+    }
+
+    pub(crate) fn write_backup(
+        &mut self,
+        generation: Option<u64>,
+        mut writer: impl Write,
+    ) -> Result<BackupHeader> {
+        let io = self
+            .io
+            .as_mut()
+            .expect("can't back up the database during a tx");
+        let current = io.generation().unwrap_or(0);
+        if let Some(generation) = generation.filter(|generation| *generation > current) {
+            return Err(anyhow!(
+                "can't back up since generation {} when the database is at {}",
+                generation,
+                current
+            ));
+        }
+        let page_size = io.page_buf.len() as u64;
+        let file_len = io.file.seek(SeekFrom::End(0))?;
+        let n_pages = file_len.div_ceil(page_size);
+        let tracked_since = self
+            .backup_tracking
+            .as_ref()
+            .filter(|_| io.generation().is_some())
+            .zip(generation)
+            .filter(|(tracking, generation)| *generation >= tracking.since);
+        let (since, pages) = match tracked_since {
+            Some((tracking, generation)) => {
+                let pages = tracking
+                    .dirty_pages
+                    .range(..n_pages)
+                    .filter(|(_, &changed)| changed > generation)
+                    .map(|(&page, _)| page)
+                    .collect::<Vec<_>>();
+                (Some(generation), pages)
+            }
+            _ => (None, (0..n_pages).collect()),
+        };
+        let header = BackupHeader {
+            id: io.id,
+            since,
+            generation: current,
+            file_len,
+            n_writes: pages.len() as u64,
+        };
+        bincode::encode_into_std_write(&header, &mut writer, BINCODE_CONFIG)?;
+        let mut buf = vec![0u8; page_size as usize];
+        for page in pages {
+            let offset = page * page_size;
+            let len = page_size.min(file_len - offset) as usize;
+            io.seek_to_offset(offset)?;
+            io.reader().read_exact(&mut buf[..len])?;
+            let write = ChangesetWrite {
+                offset,
+                bytes: buf[..len].to_vec(),
+            };
+            bincode::encode_into_std_write(&write, &mut writer, BINCODE_CONFIG)?;
+        }
+        Ok(header)
+    }
+
+    /// Apply a backup written by [`backup_since`](Self::backup_since).
+    ///
+    /// A full backup replaces whatever is in the database. A differential one can only be applied
+    /// to a copy of the same database at a generation between the backup's `since` and
+    /// `generation` that hasn't been changed other than by applying backups. Like
+    /// [`apply_changeset`](Self::apply_changeset) the database is loaded again afterwards and
+    /// shouldn't be used if this fails.
+    pub fn apply_backup(&mut self, mut reader: impl Read) -> Result<BackupHeader> {
+        let header: BackupHeader = bincode::decode_from_std_read(&mut reader, BINCODE_CONFIG)?;
+        if let Some(since) = header.since {
+            let io = self
+                .io
+                .as_ref()
+                .expect("can't call apply_backup during a tx");
+            if header.id != io.id {
+                return Err(anyhow!("backup is of a different database"));
+            }
+            match io.generation() {
+                Some(generation) if (since..=header.generation).contains(&generation) => {}
+                generation => {
+                    return Err(anyhow!(
+                    "backup has the changes from generation {} to {} but the database is at {:?}",
+                    since,
+                    header.generation,
+                    generation
+                ))
+                }
+            }
+        }
+        self.rewrite_backend(|file| write_backup_pages(&header, reader, file))?;
+        Ok(header)
+    }
+
+    /// Restore a full backup written by [`backup_since`](Self::backup_since) into an empty `file`.
+    pub fn restore_backup(mut file: F, mut reader: impl Read) -> Result<Self> {
+        let header: BackupHeader = bincode::decode_from_std_read(&mut reader, BINCODE_CONFIG)?;
+        if header.since.is_some() {
+            return Err(anyhow!(
+                "can only restore a full backup (this one is since generation {:?})",
+                header.since
+            ));
+        }
+        write_backup_pages(&header, reader, &mut file)?;
+        file.sync_data()?;
+        Self::load(file)
+    }
+
+    /// Start (or stop) profiling what is done to each list. Stopping throws away what has been
+    /// collected. See [`profile_report`](Self::profile_report).
+    pub fn set_profiling(&mut self, enabled: bool) {
+        let io = self.io();
+        match (enabled, &io.profile) {
+            (true, None) => io.profile = Some(Default::default()),
+            (false, _) => io.profile = None,
+            _ => {}
+        }
+    }
+
+    /// What has been done to each list since profiling was turned on (see
+    /// [`set_profiling`](Self::set_profiling)). Empty if it's off.
+    pub fn profile_report(&self) -> ProfileReport {
+        let mut report = self
+            .io
+            .as_ref()
+            .expect("can't call profile_report during a tx")
+            .profile
+            .clone()
+            .unwrap_or_default();
+        for meta in self.slots_by_name.values() {
+            if let Some(list) = report.lists.get_mut(&meta.slot) {
+                list.name = Some(meta.name.clone());
+            }
+        }
+        report
+    }
+
+    /// Start (or stop) counting the seeks and reads each index does from the backend during each
+    /// transaction so you can find the one doing the most random IO. See
+    /// [`Transaction::read_trace`]. Like profiling this isn't persisted.
+    pub fn set_read_tracing(&mut self, enabled: bool) {
+        let io = self.io();
+        match (enabled, &io.read_trace) {
+            (true, None) => io.read_trace = Some(Default::default()),
+            (false, _) => io.read_trace = None,
+            _ => {}
+        }
+    }
+
+    /// Check the remaps of [`LinkedListMut`](crate::LinkedListMut)s while reading lists (e.g. when
+    /// loading an index on one) rather than trusting them. Iteration errors if a remap sends it to
+    /// an entry that has been freed or if a list leads back to an entry it has already been
+    /// through. Without this a list corrupted like that (e.g. after an unclean shutdown on a
+    /// backend that doesn't keep its writes in order) is read as whatever ends up at the target or
+    /// is iterated forever. Like read tracing this isn't persisted.
+    pub fn set_strict_remaps(&mut self, enabled: bool) {
+        self.io().strict_remaps = enabled;
+    }
+
+    /// Create a [`ReaderPool`] of read-only handles to the database file at `path`. `path` must be
+    /// the same file this database was opened from.
+    pub fn reader_pool(&self, path: impl Into<std::path::PathBuf>) -> ReaderPool {
+        let io = self
+            .io
+            .as_ref()
+            .expect("can't call reader_pool during a tx");
+        ReaderPool::new(
+            path.into(),
+            io.page_buf.len() as u64,
+            io.value_encoding,
+            io.link_encoding,
+        )
+    }
+
+    /// The last committed head of `list`
+    pub fn head<T>(&mut self, list: &LinkedList<T>) -> Pointer {
+        self.io().get_head(list.slot())
+    }
+
+    /// The last committed head of every list
+    pub(crate) fn committed_heads(&mut self) -> BTreeMap<ListSlot, Pointer> {
+        let slots = self
+            .slots_by_name
+            .values()
+            .map(|meta| meta.slot)
+            .collect::<Vec<_>>();
+        slots
+            .into_iter()
+            .map(|slot| (slot, self.io().get_head(slot)))
+            .collect()
+    }
+
+    /// The last committed head of every list by its name
+    pub(crate) fn named_heads(&mut self) -> impl Iterator<Item = (&str, Pointer)> {
+        let io = self.io.as_mut().expect("can't get heads during a tx");
+        self.slots_by_name
+            .iter()
+            .map(move |(name, meta)| (name.as_str(), io.get_head(meta.slot)))
+    }
+
+    /// Hand back the backend as it is without closing the database (see [`close`](Self::close)).
+    ///
+    /// Unlike [`close`](Self::close) and dropping the database this doesn't flush or sync the
+    /// backend and the database isn't marked as closed cleanly. Everything that was committed has
+    /// already been synced unless the [`Durability`] is [`Durability::NoSync`] in which case it's
+    /// up to the caller to sync the backend.
+    pub fn into_backend(mut self) -> F {
+        self.io
+            .take()
+            .expect("can't call into_backend during a tx")
+            .file
+    }
+
+    /// Shut the database down and hand back the backend. The first page is marked as closed
+    /// cleanly (see [`closed_cleanly`](Self::closed_cleanly)), the backend is flushed and synced
+    /// and anything it holds like the lock taken by [`open`](Self::open) is released (see
+    /// [`Backend::close`]).
+    ///
+    /// Dropping the database instead makes a best effort to flush and sync the backend ignoring
+    /// any errors but it doesn't mark the database as closed cleanly and leaves releasing the
+    /// backend to the backend's own `Drop`.
+    pub fn close(mut self) -> Result<F> {
+        let mut io = match self.io.take() {
+            Some(io) => io,
+            None => {
+                self.check_poisoned()?;
+                panic!("can't call close during a tx");
+            }
+        };
+        // a database that was closed cleanly and hasn't been changed since is left alone so
+        // closing one on a read-only backend works
+        if io.is_closed() == Some(false) {
+            io.set_closed(true);
+            io.write_first_page()?;
+        }
+        io.file.close()?;
+        Ok(io.file)
+    }
+
+    /// Whether the database had been shut down with [`close`](Self::close) when it was loaded.
+    /// `false` means it was dropped or the process died after a transaction was committed, which
+    /// may be worth checking the database over for (e.g. with
+    /// [`check_allocations`](Self::check_allocations)). `None` for databases from before the flag
+    /// was kept or ones that were just created.
+    pub fn closed_cleanly(&self) -> Option<bool> {
+        self.io
+            .as_ref()
+            .expect("can't call closed_cleanly during a tx")
+            .closed_cleanly
     }
 
     pub fn get_list<T>(&mut self, list: &str) -> Result<LinkedList<T>> {
@@ -141,42 +951,224 @@ where
         self.slots_by_name.keys().map(|x| x.as_str())
     }
 
+    pub(crate) fn list_name(&self, slot: ListSlot) -> Option<&str> {
+        self.slots_by_name
+            .values()
+            .find(|meta| meta.slot == slot)
+            .map(|meta| meta.name.as_str())
+    }
+
+    /// Give up `list` so that it can be taken again
+    pub(crate) fn release_list<T>(&mut self, list: LinkedList<T>) {
+        self.list_refs.remove(&list.slot());
+    }
+
+    /// Run `query` in a transaction committing it if it returns `Ok` and rolling it back if it
+    /// errors.
+    ///
+    /// Committing writes everything but the first page, syncs the backend and only then writes and
+    /// syncs the first page which makes the transaction visible. That way the first page never
+    /// points at data that isn't in storage yet. Free space at the end of the backend is only
+    /// truncated after that (see [`TrimPolicy`]) so a crash can't lose anything the old first
+    /// page still points to. With [`Durability::NoSync`] the order is the same but nothing is
+    /// synced.
     pub fn execute<Func, R>(&mut self, query: Func) -> Result<R>
     where
         Func: for<'a, 'tx> FnOnce(&'a mut Transaction<'tx, F>) -> Result<R>,
     {
+        let (mut output, mut pending) = self.run_tx(query)?;
+        if output.is_ok() {
+            if let Err(e) = self
+                .prepare_commit(&mut pending)
+                .and_then(|_| self.io().sync())
+                .and_then(|_| self.io().write_first_page())
+            {
+                output = Err(e);
+            }
+        }
+        self.finish_tx(pending, output.is_ok());
+        output
+    }
+
+    /// Run `query` like [`execute`](Self::execute) and write everything it did to the backend
+    /// except for the first page which is what makes it visible. This is the first phase of a
+    /// two-phase commit: once something else has been made durable call [`PreparedTx::commit`] to
+    /// write the first page. Dropping the [`PreparedTx`] (or calling [`PreparedTx::abort`]) rolls
+    /// the transaction back.
+    ///
+    /// The first page the transaction will commit with is written (and synced) to the end of the
+    /// backend too so that if the process dies while the transaction is prepared it can still be
+    /// committed or aborted after the database is loaded again with the [`PrepareToken`] from
+    /// [`PreparedTx::token`] (see [`commit_prepared`](Self::commit_prepared)). Store the token
+    /// alongside whatever the transaction is being coordinated with.
+    pub fn prepare<Func, R>(&mut self, query: Func) -> Result<PreparedTx<'_, F, R>>
+    where
+        Func: for<'a, 'tx> FnOnce(&'a mut Transaction<'tx, F>) -> Result<R>,
+    {
+        // the writes are needed to check they're still there when committing after a reload
+        self.io().capture = Some(vec![]);
+        let (output, mut pending) = self.run_tx(query)?;
+        let output = output.and_then(|output| {
+            self.prepare_commit(&mut pending)?;
+            let (replaces, _) = pending.header_before.as_ref().expect("set when preparing");
+            let replaces = sha256(&[replaces]);
+            let token = self.io().stage_first_page(replaces)?;
+            self.io().sync()?;
+            Ok((output, token))
+        });
+        match output {
+            Ok((output, token)) => Ok(PreparedTx {
+                db: self,
+                pending: Some(pending),
+                output: Some(output),
+                token,
+            }),
+            Err(e) => {
+                self.finish_tx(pending, false);
+                Err(e)
+            }
+        }
+    }
+
+    /// Commit a transaction prepared with [`prepare`](Self::prepare) after the database was loaded
+    /// again. Fails if another transaction has been committed since it was prepared, if what it
+    /// wrote has been written over (e.g. by a transaction that was rolled back) or if it was
+    /// aborted with [`abort_prepared`](Self::abort_prepared). Call it before anything else is done
+    /// with the database.
+    ///
+    /// Like [`apply_changeset`](Self::apply_changeset) the database is loaded again afterwards so
+    /// lists and indexes taken before have to be taken again.
+    pub fn commit_prepared(&mut self, token: &PrepareToken) -> Result<()> {
+        let io = self
+            .io
+            .as_mut()
+            .expect("can't commit a prepared tx during a tx");
+        if sha256(&[&io.page_buf]) != token.replaces {
+            return Err(anyhow!(
+                "the database has changed since the transaction was prepared"
+            ));
+        }
+        let page = io
+            .read_staged(token)?
+            .ok_or(anyhow!("the prepared transaction is gone"))?;
+        self.rewrite_backend(|file| {
+            file.rewind()?;
+            file.write_all(&page)?;
+            Ok(())
+        })
+    }
+
+    /// Make sure a transaction prepared with [`prepare`](Self::prepare) is never committed after
+    /// the database was loaded again. What it wrote is free space already so this only has to
+    /// write over the first page it was going to commit with.
+    pub fn abort_prepared(&mut self, token: &PrepareToken) -> Result<()> {
+        let io = self
+            .io
+            .as_mut()
+            .expect("can't abort a prepared tx during a tx");
+        if sha256(&[&io.page_buf]) != token.replaces || io.read_staged(token)?.is_none() {
+            // it can't be committed anymore
+            return Ok(());
+        }
+        let zeros = vec![0u8; io.page_buf.len()];
+        io.seek_to_offset(token.location)?;
+        io.writer().write_all(&zeros)?;
+        io.sync()
+    }
+
+    /// Run `query` in a transaction that only reads. Nothing is committed so nothing is written to
+    /// the backend which means this works on backends that can't be written to like
+    /// [`ReadOnlySlice`](crate::ReadOnlySlice). The transaction fails if `query` changes the
+    /// database. Indexes stored in it are kept like they are when a transaction commits.
+    pub fn execute_read_only<Func, R>(&mut self, query: Func) -> Result<R>
+    where
+        Func: for<'a, 'tx> FnOnce(&'a mut Transaction<'tx, F>) -> Result<R>,
+    {
+        let (mut output, pending) = self.run_tx(query)?;
+        if output.is_ok() && pending.changes_anything() {
+            output = Err(anyhow!("a read-only transaction can't change the database"));
+        }
+        self.finish_tx(pending, output.is_ok());
+        output
+    }
+
+    /// Run `query` and hand the state back to the database leaving what's needed to commit or roll
+    /// back the transaction in the returned [`PendingTx`].
+    fn run_tx<Func, R>(&mut self, query: Func) -> Result<(Result<R>, PendingTx)>
+    where
+        Func: for<'a, 'tx> FnOnce(&'a mut Transaction<'tx, F>) -> Result<R>,
+    {
+        self.check_poisoned()?;
         let starting_length = self.io().file.seek(SeekFrom::End(0))?;
+        if self.changesets.is_some() {
+            self.io().capture = Some(vec![]);
+        }
+        if self.backup_tracking.is_some() {
+            self.io().written = Some(vec![]);
+        }
+        if let Some(trace) = &mut self.io().read_trace {
+            *trace = Default::default();
+        }
 
-        let indexers_before_tx = self.indexers.len();
+        let first_tx_index_id = self.next_index_id;
+        let free_space = self.free_space.as_mut().expect("must be there");
+        self.reservations
+            .retain(|_, (start, size)| free_space.take_exact(*start, *size));
+        let reservations_before = self.reservations.clone();
         let mut tx = {
             let io = TxIo {
                 inner: Rc::new(RefCell::new(TxIoInner {
                     io: Rc::new(RefCell::new(self.io.take().expect("must be there"))),
                     changed_heads: Default::default(),
+                    overwritten: Default::default(),
+                    quotas: self.quotas.clone(),
+                    reservations: core::mem::take(&mut self.reservations),
+                    scratch: Default::default(),
+                    tx_remaps: Default::default(),
+                    memory: TxMemory {
+                        limit: self.tx_memory_limit,
+                        used: 0,
+                    },
                     free_space: Rc::new(RefCell::new(
                         self.free_space.take().expect("must be there"),
                     )),
                 })),
+                index: None,
                 lifetime: PhantomData,
             };
             Transaction {
                 io,
                 slots_by_name: &self.slots_by_name,
                 tx_slots_by_name: Default::default(),
+                tx_removed_names: Default::default(),
                 used_slots: &self.used_slots,
                 tx_used_slots: Default::default(),
                 indexers: &mut self.indexers,
+                next_index_id: &mut self.next_index_id,
                 tx_list_refs: Default::default(),
                 list_refs: &self.list_refs,
+                tx_list_quotas: false,
+                aborted: Default::default(),
             }
         };
         let mut output = (query)(&mut tx);
+        if let Some(aborted) = tx.aborted.take() {
+            output = Err(aborted.into());
+        }
+        // frees can't fail so they may have taken it over the limit without an error
+        if output.is_ok() {
+            if let Err(e) = tx.io.charge_memory(0) {
+                output = Err(e);
+            }
+        }
 
         let Transaction {
             io,
-            tx_list_refs: mut new_list_refs,
+            tx_list_refs: new_list_refs,
             tx_slots_by_name: new_slots,
-            tx_used_slots: mut new_used_slots,
+            tx_removed_names: removed_names,
+            tx_used_slots: new_used_slots,
+            tx_list_quotas: list_quotas,
             ..
         } = tx;
 
@@ -184,7 +1176,12 @@ where
             changed_heads,
             free_space,
             io,
-            ..
+            overwritten,
+            quotas,
+            reservations,
+            scratch: _,
+            memory: _,
+            tx_remaps: _,
         } = io.into_inner();
 
         self.io = Some(RefCell::into_inner(
@@ -194,71 +1191,724 @@ where
             Rc::into_inner(free_space).expect("refs cannot still exist"),
         ));
 
-        if output.is_ok() {
-            for (slot, head) in changed_heads {
-                self.io().set_head(slot, head);
+        let pending = PendingTx {
+            starting_length,
+            first_tx_index_id,
+            reservations_before,
+            changed_heads,
+            overwritten,
+            quotas,
+            reservations,
+            new_list_refs,
+            new_slots,
+            removed_names,
+            new_used_slots,
+            list_quotas,
+            header_before: None,
+        };
+        Ok((output, pending))
+    }
+
+    /// Write everything but the first page
+    fn prepare_commit(&mut self, pending: &mut PendingTx) -> Result<()> {
+        for indexer in self.indexers.values() {
+            indexer.tx_pre_commit()?;
+        }
+        let io = self.io.as_mut().expect("must be there");
+        pending.header_before = Some((io.page_buf.clone(), io.header_overflow.clone()));
+        io.increment_generation();
+        io.set_closed(false);
+        if pending.list_quotas {
+            io.add_features(VersionedConfig::FEATURE_LIST_QUOTAS)?;
+        }
+        let changed_heads = pending.changed_heads.iter();
+        self.io()
+            .set_heads(changed_heads.map(|(&slot, &head)| (slot, head)))?;
+        pending.reservations.retain(|_, (_, size)| *size > 0);
+        for &(start, size) in pending.reservations.values() {
+            self.free_space()
+                .free(Free::from_start_pointer(start, size));
+        }
+
+        let io = self.io.as_mut().expect("must be there");
+        let free_space = self.free_space.as_mut().expect("must be there");
+        io.write_header_overflow(free_space)?;
+
+        let changed_free_slots = self.free_space().apply_pending_frees();
+        for free_slot in changed_free_slots {
+            let free = self.free_space().persist_state()[free_slot];
+            self.io().set_free(free_slot, free)?;
+        }
+        Ok(())
+    }
+
+    fn finish_tx(&mut self, pending: PendingTx, success: bool) {
+        let changed = pending.changes_anything();
+        let PendingTx {
+            starting_length,
+            first_tx_index_id,
+            reservations_before,
+            changed_heads: _,
+            overwritten,
+            quotas,
+            reservations,
+            mut new_list_refs,
+            new_slots,
+            removed_names,
+            mut new_used_slots,
+            list_quotas,
+            header_before,
+        } = pending;
+        let captured = self.io().capture.take();
+        let written = self.io().written.take();
+
+        if !success {
+            for indexer in self.indexers.split_off(&first_tx_index_id).into_values() {
+                for list in indexer.owned_lists() {
+                    self.list_refs.remove(&list);
+                }
+            }
+
+            for indexer in self.indexers.values_mut() {
+                indexer.tx_fail_rollback();
+            }
+
+            self.free_space().tx_fail_rollback();
+            self.reservations = reservations_before;
+            if let Some((page_buf, header_overflow)) = header_before {
+                let io = self.io();
+                io.page_buf = page_buf;
+                io.header_overflow = header_overflow;
+            }
+            for (pointer, original) in overwritten.into_iter().rev() {
+                let io = self.io();
+                let _ = io
+                    .seek_to(pointer)
+                    .and_then(|_| Ok(io.writer().write_all(&original)?));
+            }
+            self.io().clear_read_cache();
+            let _ = self.io().file.truncate(starting_length);
+            if let Some(metrics) = &self.io().metrics {
+                metrics.tx_rollback();
+            }
+        } else {
+            self.free_space().tx_success();
+            if let Some(metrics) = &self.io().metrics {
+                metrics.tx_commit();
+            }
+            if list_quotas {
+                self.io().list_quotas = true;
+            }
+            self.list_refs.append(&mut new_list_refs);
+            self.quotas = quotas;
+            self.reservations = reservations;
+            for name in removed_names {
+                self.slots_by_name.remove(&name);
+            }
+            self.slots_by_name.extend(new_slots);
+            self.used_slots.append(&mut new_used_slots);
+            for indexer in self.indexers.values_mut() {
+                indexer.tx_success();
+            }
+
+            // a transaction that changed nothing (like the one loading the database) leaves what's
+            // past the end alone since it may be a prepared transaction's (see `prepare`)
+            if changed {
+                let threshold = match self.trim_policy {
+                    TrimPolicy::Always => 0,
+                    TrimPolicy::Threshold(threshold) => threshold,
+                };
+                let _ = self.trim(threshold);
             }
-            let changed_free_slots = self.free_space().apply_pending_frees();
-            for free_slot in changed_free_slots {
-                let free = self.free_space().persist_state()[free_slot];
-                self.io().set_free(free_slot, free);
+            if let (Some(tracking), Some(written)) = (&mut self.backup_tracking, written) {
+                let io = self.io.as_ref().expect("must be there");
+                let generation = io.generation().unwrap_or(0);
+                let page_size = io.page_buf.len() as u64;
+                for (offset, len) in written.into_iter().filter(|(_, len)| *len > 0) {
+                    for page in offset / page_size..=(offset + len - 1) / page_size {
+                        tracking.dirty_pages.insert(page, generation);
+                    }
+                }
+            }
+            if let (Some(changesets), Some(writes)) = (&mut self.changesets, captured) {
+                let io = self.io.as_mut().expect("must be there");
+                if let Ok(file_len) = io.file.seek(SeekFrom::End(0)) {
+                    changesets.push(Changeset { writes, file_len });
+                }
             }
+        }
+    }
+}
+
+/// Best effort flush and sync (unless the [`Durability`] is [`Durability::NoSync`]) of the backend
+/// for databases that weren't shut down with [`LlsDb::close`]. Errors are ignored since there's
+/// nowhere to report them.
+impl<F: Backend> Drop for LlsDb<F> {
+    fn drop(&mut self) {
+        if let Some(io) = &mut self.io {
+            let _ = io.file.flush();
+            let _ = io.sync();
+        }
+    }
+}
+
+/// Write the pages following `header` in a backup to `file`
+fn write_backup_pages<F: Backend>(
+    header: &BackupHeader,
+    mut reader: impl Read,
+    file: &mut F,
+) -> Result<()> {
+    for _ in 0..header.n_writes {
+        let write: ChangesetWrite = bincode::decode_from_std_read(&mut reader, BINCODE_CONFIG)?;
+        file.seek(SeekFrom::Start(write.offset))?;
+        file.write_all(&write.bytes)?;
+    }
+    file.truncate(header.file_len)
+}
+
+/// What's left to do to finish a transaction once its query has run
+struct PendingTx {
+    starting_length: u64,
+    first_tx_index_id: usize,
+    reservations_before: BTreeMap<ListSlot, (Pointer, u64)>,
+    changed_heads: HashMap<ListSlot, Pointer>,
+    overwritten: Vec<(Pointer, Vec<u8>)>,
+    quotas: Quotas,
+    reservations: BTreeMap<ListSlot, (Pointer, u64)>,
+    new_list_refs: BTreeSet<ListSlot>,
+    new_slots: HashMap<String, Meta>,
+    removed_names: HashSet<String>,
+    new_used_slots: BTreeSet<ListSlot>,
+    /// Whether the transaction started keeping quotas in the meta list
+    list_quotas: bool,
+    /// The first page and header overflow before the transaction changed them
+    header_before: Option<(Vec<u8>, Option<HeaderOverflow>)>,
+}
+
+impl PendingTx {
+    /// Whether committing the transaction would change the database
+    fn changes_anything(&self) -> bool {
+        !self.changed_heads.is_empty()
+            || !self.overwritten.is_empty()
+            || !self.new_slots.is_empty()
+            || !self.removed_names.is_empty()
+            || self.reservations != self.reservations_before
+    }
+}
+
+/// A transaction that has been written but not committed. Returned from [`LlsDb::prepare`].
+///
+/// Nothing can be done with the database while a transaction is prepared. Dropping it rolls the
+/// transaction back.
+pub struct PreparedTx<'db, F: Backend, R> {
+    db: &'db mut LlsDb<F>,
+    pending: Option<PendingTx>,
+    output: Option<R>,
+    token: PrepareToken,
+}
+
+impl<F: Backend, R> PreparedTx<'_, F, R> {
+    /// What the transaction's query returned
+    pub fn output(&self) -> &R {
+        self.output.as_ref().expect("only taken when consumed")
+    }
+
+    /// The token to commit or abort the transaction with after the database is loaded again
+    pub fn token(&self) -> PrepareToken {
+        self.token
+    }
+
+    /// Write the first page making the transaction visible
+    pub fn commit(mut self) -> Result<R> {
+        let pending = self.pending.take().expect("only taken when consumed");
+        let res = self.db.io().write_first_page();
+        self.db.finish_tx(pending, res.is_ok());
+        res?;
+        Ok(self.output.take().expect("only taken when consumed"))
+    }
+
+    /// Roll the transaction back
+    pub fn abort(mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.db.finish_tx(pending, false);
+        }
+    }
+}
+
+impl<F: Backend, R> Drop for PreparedTx<'_, F, R> {
+    fn drop(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.db.finish_tx(pending, false);
+        }
+    }
+}
+
+impl<F: Backend, R: core::fmt::Debug> core::fmt::Debug for PreparedTx<'_, F, R> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PreparedTx")
+            .field("output", &self.output)
+            .field("token", &self.token)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Identifies a transaction prepared with [`LlsDb::prepare`] so it can be committed with
+/// [`LlsDb::commit_prepared`] or aborted with [`LlsDb::abort_prepared`] after the database is
+/// loaded again. It's encodable so it can be stored with whatever the transaction is coordinated
+/// with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct PrepareToken {
+    /// Where in the backend the first page the transaction commits with was written
+    location: u64,
+    /// The hash of that first page and everything the transaction wrote
+    hash: [u8; 32],
+    /// The hash of the first page it replaces
+    replaces: [u8; 32],
+}
+
+fn default_n_free_slots(page_size: usize, header_len: usize, pointer_size: usize) -> usize {
+    (page_size - header_len) / (2 * free_slot_size(pointer_size))
+}
+
+/// A free slot is a size and an end pointer
+fn free_slot_size(pointer_size: usize) -> usize {
+    2 * pointer_size
+}
+
+fn first_page_checksum(page: &[u8], checksum_range: core::ops::Range<usize>) -> u32 {
+    crc32(
+        &[
+            &page[..checksum_range.start],
+            &[0u8; 4],
+            &page[checksum_range.end..],
+        ]
+        .concat(),
+    )
+}
+
+/// CRC-32 (IEEE) of `bytes`
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// The preamble and maximum size of a new database created with `options` and given `id`
+fn init_preamble(options: InitOptions, id: [u8; 16]) -> Result<(Preamble, u64)> {
+    let InitOptions {
+        page_size,
+        mut max_size,
+        n_free_slots,
+        n_extra_header_pages,
+        compact_pointers,
+        value_encoding,
+        pad_entries_to,
+    } = options;
+    if pad_entries_to == 0 {
+        return Err(anyhow!("entries can't be padded to a multiple of 0"));
+    }
+    let pointer_size = if compact_pointers {
+        max_size = max_size.min(u32::MAX.into());
+        size_of::<u32>()
+    } else {
+        size_of::<u64>()
+    };
+    let config = |n_free_slots: u16| {
+        let mut config = VersionedConfig::four(
+            page_size,
+            n_free_slots,
+            n_extra_header_pages,
+            pointer_size as u8,
+            value_encoding,
+            id,
+        );
+        config.add_features(
+            VersionedConfig::FEATURE_FREE_SLOT_CHECKSUM | VersionedConfig::FEATURE_CLEAN_SHUTDOWN,
+        );
+        if pad_entries_to > 1 {
+            config.add_features(VersionedConfig::FEATURE_ENTRY_PADDING);
+        }
+        if compact_pointers {
+            config.add_features(VersionedConfig::FEATURE_FIXED_LINKS);
+        }
+        config
+    };
+    let n_free_slots = n_free_slots.unwrap_or_else(|| {
+        let header_len = config(0).header_len();
+        default_n_free_slots(page_size.into(), header_len, pointer_size) as u16
+    });
+    let config = config(n_free_slots);
+    let preamble = Preamble {
+        magic_bytes: MAGIC_BYTES,
+        config,
+    };
+    Ok((preamble, max_size))
+}
+
+/// A random (version 4) UUID. We don't need anything cryptographically strong here, just something
+/// that won't collide with other databases.
+fn random_id() -> [u8; 16] {
+    use std::hash::{BuildHasher, Hasher};
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|since| since.as_nanos())
+        .unwrap_or(0);
+    let mut id = [0u8; 16];
+    for (i, chunk) in id.chunks_exact_mut(8).enumerate() {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        hasher.write_u128(now);
+        hasher.write_u32(std::process::id());
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    id[6] = (id[6] & 0x0f) | 0x40;
+    id[8] = (id[8] & 0x3f) | 0x80;
+    id
+}
+
+/// The id of a database (see [`LlsDb::id`]). It displays as a UUID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DbId(pub [u8; 16]);
+
+impl core::fmt::Display for DbId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(bincode::Encode, bincode::Decode)]
+pub struct Preamble {
+    magic_bytes: [u8; 5],
+    config: VersionedConfig,
+}
+
+/// The error loading a database fails with when it was created by a newer version of llsdb that
+/// uses a format version or required format features this version doesn't understand. Use
+/// `downcast_ref` to tell it apart from other errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewerFormat {
+    /// The format version of the database
+    pub version: u32,
+    /// The required [feature flags](VersionedConfig::REQUIRED_FEATURES) this version doesn't know
+    pub unknown_features: u32,
+}
+
+impl core::fmt::Display for NewerFormat {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "database created by a newer llsdb ")?;
+        if self.version > VersionedConfig::LATEST_VERSION {
+            write!(
+                f,
+                "(format version {} but this version only supports up to {})",
+                self.version,
+                VersionedConfig::LATEST_VERSION
+            )
+        } else {
+            write!(
+                f,
+                "(format version {} with format features {:#x} this version doesn't support)",
+                self.version, self.unknown_features
+            )
+        }
+    }
+}
+
+impl std::error::Error for NewerFormat {}
+
+#[derive(bincode::Encode, bincode::Decode, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub enum VersionedConfig {
+    Zero {
+        page_size: [u8; 2],
+    },
+    One {
+        page_size: [u8; 2],
+        n_free_slots: [u8; 2],
+    },
+    Two {
+        page_size: [u8; 2],
+        n_free_slots: [u8; 2],
+        n_extra_header_pages: [u8; 2],
+        pointer_size: u8,
+    },
+    Three {
+        page_size: [u8; 2],
+        n_free_slots: [u8; 2],
+        n_extra_header_pages: [u8; 2],
+        pointer_size: u8,
+        value_encoding: u8,
+    },
+    /// Adds an id, feature flags and a checksum of the first page. The checksum is a CRC-32 over
+    /// the whole first page taken with the checksum itself set to zero. It is rewritten each time
+    /// the first page is.
+    Four {
+        page_size: [u8; 2],
+        n_free_slots: [u8; 2],
+        n_extra_header_pages: [u8; 2],
+        pointer_size: u8,
+        value_encoding: u8,
+        features: [u8; 4],
+        id: [u8; 16],
+        checksum: [u8; 4],
+    },
+}
+
+impl VersionedConfig {
+    const TWO_PREAMBLE_LEN: usize = 13;
+    const THREE_PREAMBLE_LEN: usize = 14;
+    const FOUR_PREAMBLE_LEN: usize = 38;
+    /// The first page holds a counter of the number of commits after the list slots pointer (see
+    /// [`LlsDb::generation`]).
+    pub const FEATURE_COMMIT_GENERATION: u32 = 1;
+    /// The first page holds a byte before the commit generation giving the multiple the length of
+    /// every entry is padded to (see [`InitOptions::pad_entries_to`]). Only set if it isn't 1.
+    pub const FEATURE_ENTRY_PADDING: u32 = 2;
+    /// The entries of the meta list carry the [`ListQuota`] of each list (see
+    /// [`Transaction::take_list_with_quota`]). Only set once a list is given a quota.
+    pub const FEATURE_LIST_QUOTAS: u32 = 4;
+    /// The first page holds a CRC-32 of the free slots after the entry padding byte. Unlike the
+    /// checksum of the whole page it's checked against the free slots as they are decoded so a
+    /// free space that got corrupted is never handed out over live entries.
+    pub const FEATURE_FREE_SLOT_CHECKSUM: u32 = 8;
+    /// The first page holds a byte after the free slot checksum that is 1 if the database was
+    /// shut down with [`LlsDb::close`] and 0 once a transaction has been committed since (see
+    /// [`LlsDb::closed_cleanly`]).
+    pub const FEATURE_CLEAN_SHUTDOWN: u32 = 16;
+    /// The pointer at the start of each entry to the next one is a little endian `u32` rather
+    /// than a varint (see [`InitOptions::compact_pointers`]).
+    pub const FEATURE_FIXED_LINKS: u32 = 32;
+    /// The format features understood by this version. Loading a database with any other required
+    /// feature flag set fails with [`NewerFormat`].
+    pub const KNOWN_FEATURES: u32 = Self::FEATURE_COMMIT_GENERATION
+        | Self::FEATURE_ENTRY_PADDING
+        | Self::FEATURE_LIST_QUOTAS
+        | Self::FEATURE_FREE_SLOT_CHECKSUM
+        | Self::FEATURE_CLEAN_SHUTDOWN
+        | Self::FEATURE_FIXED_LINKS;
+    /// Feature flags in these bits change how the database is laid out so a version that doesn't
+    /// know one of them can't open the database.
+    pub const REQUIRED_FEATURES: u32 = 0x0000_ffff;
+    /// Feature flags in these bits are for optional capabilities (e.g. extra checksums) that can
+    /// be ignored without misreading anything. A version that doesn't know one of them clears it
+    /// when it next writes the first page so a newer version can tell the capability hasn't been
+    /// kept up.
+    pub const OPTIONAL_FEATURES: u32 = !Self::REQUIRED_FEATURES;
+    /// The newest format version this version of llsdb can open
+    pub const LATEST_VERSION: u32 = 4;
+
+    /// The format version i.e. the index of the variant
+    pub fn version(&self) -> u32 {
+        match self {
+            VersionedConfig::Zero { .. } => 0,
+            VersionedConfig::One { .. } => 1,
+            VersionedConfig::Two { .. } => 2,
+            VersionedConfig::Three { .. } => 3,
+            VersionedConfig::Four { .. } => 4,
+        }
+    }
+
+    pub fn page_size(&self) -> usize {
+        match self {
+            VersionedConfig::Zero { page_size }
+            | VersionedConfig::One { page_size, .. }
+            | VersionedConfig::Two { page_size, .. }
+            | VersionedConfig::Three { page_size, .. }
+            | VersionedConfig::Four { page_size, .. } => u16::from_le_bytes(*page_size).into(),
+        }
+    }
 
-            if let Err(e) = self.io().write_first_page() {
-                output = Err(e);
+    /// The number of free slots if it was chosen explicitly at init
+    pub fn n_free_slots(&self) -> Option<usize> {
+        match self {
+            VersionedConfig::Zero { .. } => None,
+            VersionedConfig::One { n_free_slots, .. }
+            | VersionedConfig::Two { n_free_slots, .. }
+            | VersionedConfig::Three { n_free_slots, .. }
+            | VersionedConfig::Four { n_free_slots, .. } => {
+                Some(u16::from_le_bytes(*n_free_slots).into())
             }
         }
+    }
 
-        if output.is_err() {
-            for indexer in self.indexers.drain(indexers_before_tx..) {
-                for list in indexer.owned_lists() {
-                    self.list_refs.remove(&list);
-                }
+    /// The number of pages of list slots in addition to the ones in the first page
+    pub fn n_extra_header_pages(&self) -> usize {
+        match self {
+            VersionedConfig::Zero { .. } | VersionedConfig::One { .. } => 0,
+            VersionedConfig::Two {
+                n_extra_header_pages,
+                ..
             }
-
-            for indexer in &mut self.indexers {
-                indexer.tx_fail_rollback();
+            | VersionedConfig::Three {
+                n_extra_header_pages,
+                ..
             }
+            | VersionedConfig::Four {
+                n_extra_header_pages,
+                ..
+            } => u16::from_le_bytes(*n_extra_header_pages).into(),
+        }
+    }
 
-            self.free_space().tx_fail_rollback();
-            let _ = self.io().file.truncate(starting_length);
+    /// The number of bytes used for pointers in the header pages
+    pub fn pointer_size(&self) -> usize {
+        match self {
+            VersionedConfig::Zero { .. } | VersionedConfig::One { .. } => size_of::<u64>(),
+            VersionedConfig::Two { pointer_size, .. }
+            | VersionedConfig::Three { pointer_size, .. }
+            | VersionedConfig::Four { pointer_size, .. } => (*pointer_size).into(),
+        }
+    }
+
+    /// How the pointer at the start of each entry to the next one is encoded
+    pub fn link_encoding(&self) -> LinkEncoding {
+        if self.features() & Self::FEATURE_FIXED_LINKS != 0 {
+            LinkEncoding::FixedU32
         } else {
-            self.free_space().tx_success();
-            self.list_refs.append(&mut new_list_refs);
-            self.slots_by_name.extend(new_slots);
-            self.used_slots.append(&mut new_used_slots);
-            for indexer in &mut self.indexers {
-                indexer.tx_success();
-            }
+            LinkEncoding::Varint
+        }
+    }
 
-            if let Some(trim_to) = self.free_space().where_to_trim() {
-                let truncate_to = self
-                    .io()
-                    .pointer_to_file_position(trim_to)
-                    .expect("always returns a non-null pointer");
-                let _ = self.io().file.truncate(truncate_to);
+    /// How values are encoded. `None` if the recorded encoding isn't one we know about.
+    pub fn value_encoding(&self) -> Option<ValueEncoding> {
+        match self {
+            VersionedConfig::Three { value_encoding, .. }
+            | VersionedConfig::Four { value_encoding, .. } => {
+                ValueEncoding::from_byte(*value_encoding)
             }
+            _ => Some(ValueEncoding::default()),
         }
-        output
     }
-}
 
-#[derive(bincode::Encode, bincode::Decode)]
-pub struct Preamble {
-    magic_bytes: [u8; 5],
-    config: VersionedConfig,
-}
+    /// The format feature flags the database was created with
+    pub fn features(&self) -> u32 {
+        match self {
+            VersionedConfig::Four { features, .. } => u32::from_le_bytes(*features),
+            _ => 0,
+        }
+    }
 
-#[derive(bincode::Encode, bincode::Decode, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
-pub enum VersionedConfig {
-    Zero { page_size: [u8; 2] },
-}
+    fn add_features(&mut self, new_features: u32) {
+        if let VersionedConfig::Four { features, .. } = self {
+            *features = (u32::from_le_bytes(*features) | new_features).to_le_bytes();
+        }
+    }
 
-impl VersionedConfig {
-    pub fn page_size(&self) -> usize {
+    fn remove_features(&mut self, old_features: u32) {
+        if let VersionedConfig::Four { features, .. } = self {
+            *features = (u32::from_le_bytes(*features) & !old_features).to_le_bytes();
+        }
+    }
+
+    /// The id of the database if it has one
+    pub fn id(&self) -> Option<[u8; 16]> {
+        match self {
+            VersionedConfig::Four { id, .. } => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Where the checksum of the first page is if it has one
+    fn checksum_range(&self) -> Option<core::ops::Range<usize>> {
+        match self {
+            VersionedConfig::Four { .. } => {
+                let end = self.preamble_len();
+                Some(end - 4..end)
+            }
+            _ => None,
+        }
+    }
+
+    /// The length of the [`Preamble`] containing this config
+    pub fn preamble_len(&self) -> usize {
         match self {
-            VersionedConfig::Zero { page_size } => u16::from_le_bytes(*page_size).into(),
+            VersionedConfig::Zero { .. } => 8,
+            VersionedConfig::One { .. } => 10,
+            VersionedConfig::Two { .. } => Self::TWO_PREAMBLE_LEN,
+            VersionedConfig::Three { .. } => Self::THREE_PREAMBLE_LEN,
+            VersionedConfig::Four { .. } => Self::FOUR_PREAMBLE_LEN,
+        }
+    }
+
+    /// Where the commit generation is in the first page if it has one
+    fn generation_offset(&self) -> Option<usize> {
+        if self.features() & Self::FEATURE_COMMIT_GENERATION == 0 {
+            return None;
+        }
+        Some(self.header_len() - size_of::<u64>())
+    }
+
+    /// Where the byte giving the entry padding is in the first page if it has one
+    fn entry_padding_offset(&self) -> Option<usize> {
+        if self.features() & Self::FEATURE_ENTRY_PADDING == 0 {
+            return None;
         }
+        let mut offset = self.preamble_len();
+        if self.n_extra_header_pages() > 0 {
+            offset += self.pointer_size();
+        }
+        Some(offset)
+    }
+
+    /// Where the checksum of the free slots is in the first page if it has one
+    fn free_slot_checksum_offset(&self) -> Option<usize> {
+        if self.features() & Self::FEATURE_FREE_SLOT_CHECKSUM == 0 {
+            return None;
+        }
+        let mut offset = self.preamble_len();
+        if self.n_extra_header_pages() > 0 {
+            offset += self.pointer_size();
+        }
+        if self.features() & Self::FEATURE_ENTRY_PADDING != 0 {
+            offset += 1;
+        }
+        Some(offset)
+    }
+
+    /// Where the clean shutdown byte is in the first page if it has one
+    fn clean_shutdown_offset(&self) -> Option<usize> {
+        if self.features() & Self::FEATURE_CLEAN_SHUTDOWN == 0 {
+            return None;
+        }
+        let mut offset = self.preamble_len();
+        if self.n_extra_header_pages() > 0 {
+            offset += self.pointer_size();
+        }
+        if self.features() & Self::FEATURE_ENTRY_PADDING != 0 {
+            offset += 1;
+        }
+        if self.features() & Self::FEATURE_FREE_SLOT_CHECKSUM != 0 {
+            offset += size_of::<u32>();
+        }
+        Some(offset)
+    }
+
+    /// The length of everything in the first page before the list slots
+    fn header_len(&self) -> usize {
+        let mut header_len = self.preamble_len();
+        if self.n_extra_header_pages() > 0 {
+            // the pointer to the extra header pages
+            header_len += self.pointer_size();
+        }
+        if self.features() & Self::FEATURE_ENTRY_PADDING != 0 {
+            header_len += 1;
+        }
+        if self.features() & Self::FEATURE_FREE_SLOT_CHECKSUM != 0 {
+            header_len += size_of::<u32>();
+        }
+        if self.features() & Self::FEATURE_CLEAN_SHUTDOWN != 0 {
+            header_len += 1;
+        }
+        if self.features() & Self::FEATURE_COMMIT_GENERATION != 0 {
+            header_len += size_of::<u64>();
+        }
+        header_len
     }
 
     pub fn zero(page_size: u16) -> Self {
@@ -266,133 +1916,868 @@ impl VersionedConfig {
             page_size: page_size.to_le_bytes(),
         }
     }
+
+    pub fn one(page_size: u16, n_free_slots: u16) -> Self {
+        Self::One {
+            page_size: page_size.to_le_bytes(),
+            n_free_slots: n_free_slots.to_le_bytes(),
+        }
+    }
+
+    pub fn two(
+        page_size: u16,
+        n_free_slots: u16,
+        n_extra_header_pages: u16,
+        pointer_size: u8,
+    ) -> Self {
+        Self::Two {
+            page_size: page_size.to_le_bytes(),
+            n_free_slots: n_free_slots.to_le_bytes(),
+            n_extra_header_pages: n_extra_header_pages.to_le_bytes(),
+            pointer_size,
+        }
+    }
+
+    pub fn three(
+        page_size: u16,
+        n_free_slots: u16,
+        n_extra_header_pages: u16,
+        pointer_size: u8,
+        value_encoding: ValueEncoding,
+    ) -> Self {
+        Self::Three {
+            page_size: page_size.to_le_bytes(),
+            n_free_slots: n_free_slots.to_le_bytes(),
+            n_extra_header_pages: n_extra_header_pages.to_le_bytes(),
+            pointer_size,
+            value_encoding: value_encoding.to_byte(),
+        }
+    }
+
+    pub fn four(
+        page_size: u16,
+        n_free_slots: u16,
+        n_extra_header_pages: u16,
+        pointer_size: u8,
+        value_encoding: ValueEncoding,
+        id: [u8; 16],
+    ) -> Self {
+        Self::Four {
+            page_size: page_size.to_le_bytes(),
+            n_free_slots: n_free_slots.to_le_bytes(),
+            n_extra_header_pages: n_extra_header_pages.to_le_bytes(),
+            pointer_size,
+            value_encoding: value_encoding.to_byte(),
+            features: Self::FEATURE_COMMIT_GENERATION.to_le_bytes(),
+            id,
+            checksum: [0u8; 4],
+        }
+    }
 }
 
 pub struct Io<F> {
     page_buf: Vec<u8>,
+    preamble_len: usize,
+    header_len: usize,
+    pointer_size: usize,
+    value_encoding: ValueEncoding,
+    link_encoding: LinkEncoding,
+    /// The multiple the length of every entry is padded to
+    entry_padding: u64,
+    id: Option<[u8; 16]>,
+    checksum_range: Option<core::ops::Range<usize>>,
+    /// Where the checksum of the free slots is in the first page
+    free_slot_checksum_offset: Option<usize>,
+    /// Where the clean shutdown byte is in the first page
+    clean_shutdown_offset: Option<usize>,
+    /// Whether the first page said the database had been closed cleanly when it was loaded
+    closed_cleanly: Option<bool>,
     n_free_slots: usize,
+    /// The number of list slots in the first page
     n_list_slots: usize,
+    header_overflow: Option<HeaderOverflow>,
+    metrics: Option<Arc<dyn Metrics>>,
+    clock: Arc<dyn Clock>,
+    durability: Durability,
+    /// Where the commit generation is in the first page
+    generation_offset: Option<usize>,
+    /// Where writes are recorded while the replication log is on
+    capture: Option<Vec<ChangesetWrite>>,
+    /// The ranges written during the transaction while backup tracking is on
+    written: Option<Vec<(u64, u64)>>,
+    profile: Option<ProfileReport>,
+    /// The reads done in the current transaction if they're being traced
+    read_trace: Option<ReadTrace>,
+    /// The index doing reads right now for the read trace
+    reading_for: Option<usize>,
+    /// Whether list iteration checks remaps (see [`LlsDb::set_strict_remaps`])
+    strict_remaps: bool,
+    /// Whether the meta list has quotas (see [`VersionedConfig::FEATURE_LIST_QUOTAS`])
+    list_quotas: bool,
+    /// The pages read most recently if there's a read cache (see [`LlsDb::set_read_cache`])
+    read_cache: Option<ReadCache>,
     file: F,
 }
 
-const PREAMBLE_LEN: usize = 8;
+/// The settings of an [`Io`] that aren't persisted so have to be carried over when the database
+/// is loaded again
+struct IoSettings {
+    read_cache_pages: usize,
+    metrics: Option<Arc<dyn Metrics>>,
+    clock: Arc<dyn Clock>,
+    profile: Option<ProfileReport>,
+    read_trace: Option<ReadTrace>,
+    strict_remaps: bool,
+    durability: Durability,
+}
+
+/// The list heads that live in the extra header pages.
+///
+/// Rather than being overwritten in place the extra header pages are written to freshly allocated
+/// space whenever they change and the first page is updated to point to them. This means they are
+/// committed atomically along with everything else when the first page is written.
+#[derive(Clone)]
+struct HeaderOverflow {
+    location: Pointer,
+    heads: Vec<Pointer>,
+    dirty: bool,
+}
 
 impl<F: Backend> Io<F> {
     pub fn load(mut file: F, check_magic: [u8; 5]) -> Result<Self> {
         file.rewind()?;
-        let preamble: Preamble = bincode::decode_from_std_read(&mut file, BINCODE_CONFIG)
-            .context("failed to read in llsdb preamble (is this really a llsdb database?)")?;
-        if preamble.magic_bytes != check_magic {
+        // check the version before decoding the rest so a newer one gets a helpful error
+        let (magic_bytes, version): ([u8; 5], u32) =
+            bincode::decode_from_std_read(&mut file, BINCODE_CONFIG)
+                .context("failed to read in llsdb preamble (is this really a llsdb database?)")?;
+        if magic_bytes != check_magic {
             return Err(anyhow!(
                 "magic bytes didn't match, expected {:?} got {:?}",
                 check_magic,
-                preamble.magic_bytes
+                magic_bytes
             ));
         }
+        if version > VersionedConfig::LATEST_VERSION {
+            return Err(NewerFormat {
+                version,
+                unknown_features: 0,
+            }
+            .into());
+        }
+        file.rewind()?;
+        let mut preamble: Preamble = bincode::decode_from_std_read(&mut file, BINCODE_CONFIG)
+            .context("failed to read in llsdb preamble")?;
         let page_size = preamble.config.page_size();
-        let (n_list_slots, n_free_slots) = Self::apportion_first_page(page_size);
+        let preamble_len = preamble.config.preamble_len();
+        let header_len = preamble.config.header_len();
+        let pointer_size = preamble.config.pointer_size();
+        let value_encoding = preamble
+            .config
+            .value_encoding()
+            .ok_or(anyhow!("unknown value encoding in llsdb preamble"))?;
+        let unknown_features = preamble.config.features() & !VersionedConfig::KNOWN_FEATURES;
+        if unknown_features & VersionedConfig::REQUIRED_FEATURES != 0 {
+            return Err(NewerFormat {
+                version,
+                unknown_features: unknown_features & VersionedConfig::REQUIRED_FEATURES,
+            }
+            .into());
+        }
+        let (n_list_slots, n_free_slots) = Self::apportion_first_page(
+            page_size,
+            header_len,
+            pointer_size,
+            preamble.config.n_free_slots(),
+        )?;
         let mut page_buf = vec![0u8; page_size];
         file.rewind()?;
         file.read_exact(&mut page_buf)?;
+        let checksum_range = preamble.config.checksum_range();
+        if let Some(range) = &checksum_range {
+            let stored = u32::from_le_bytes(page_buf[range.clone()].try_into().expect("4 bytes"));
+            if stored != first_page_checksum(&page_buf, range.clone()) {
+                return Err(anyhow!("checksum of llsdb header page doesn't match"));
+            }
+        }
+        if unknown_features != 0 {
+            // only optional ones are left. They'll be cleared on disk with the next commit.
+            preamble.config.remove_features(unknown_features);
+            bincode::encode_into_slice(&preamble, &mut page_buf[..preamble_len], BINCODE_CONFIG)?;
+        }
+
+        let entry_padding = match preamble.config.entry_padding_offset() {
+            Some(offset) => match page_buf[offset] {
+                0 => return Err(anyhow!("entry padding in llsdb header page is 0")),
+                padding => padding.into(),
+            },
+            None => 1,
+        };
 
-        let io = Io {
+        let closed_cleanly = preamble
+            .config
+            .clean_shutdown_offset()
+            .map(|offset| page_buf[offset] == 1);
+        let mut io = Io {
             page_buf,
+            preamble_len,
+            header_len,
+            pointer_size,
+            value_encoding,
+            link_encoding: preamble.config.link_encoding(),
+            entry_padding,
+            id: preamble.config.id(),
+            checksum_range,
+            free_slot_checksum_offset: preamble.config.free_slot_checksum_offset(),
+            clean_shutdown_offset: preamble.config.clean_shutdown_offset(),
+            closed_cleanly,
             n_list_slots,
             n_free_slots,
+            header_overflow: None,
+            metrics: None,
+            clock: Arc::new(crate::SystemClock),
+            durability: Durability::Sync,
+            generation_offset: preamble.config.generation_offset(),
+            list_quotas: preamble.config.features() & VersionedConfig::FEATURE_LIST_QUOTAS != 0,
+            capture: None,
+            written: None,
+            profile: None,
+            read_trace: None,
+            strict_remaps: false,
+            reading_for: None,
+            read_cache: None,
             file,
         };
 
+        let n_extra_header_pages = preamble.config.n_extra_header_pages();
+        if n_extra_header_pages > 0 {
+            let location = io.header_overflow_location();
+            let mut heads = vec![Pointer::NULL; n_extra_header_pages * page_size / pointer_size];
+            if location != Pointer::NULL {
+                let mut buf = vec![0u8; heads.len() * pointer_size];
+                io.seek_to(location)?;
+                io.reader()
+                    .read_exact(&mut buf)
+                    .context("reading extra header pages")?;
+                for (head, bytes) in heads.iter_mut().zip(buf.chunks_exact(pointer_size)) {
+                    *head = Pointer(read_le_uint(bytes));
+                }
+            }
+            io.header_overflow = Some(HeaderOverflow {
+                location,
+                heads,
+                dirty: false,
+            });
+        }
+
+        if let Some(offset) = io.free_slot_checksum_offset {
+            let stored = read_le_uint(&io.page_buf[offset..offset + size_of::<u32>()]);
+            if stored != u64::from(crc32(io.free_slots_buf())) {
+                return Err(anyhow!("checksum of llsdb free slots doesn't match"));
+            }
+        }
         for free_slot in 0..n_free_slots {
             // check the free slots aren't totally cactus
             io.get_free_slot(free_slot)
                 .context("reading free slots from disk")?;
         }
+        // Databases from before the free slot checksum only have the sanity checks. Reusing space
+        // that's still in use would destroy entries so make sure no two free spaces overlap and
+        // none covers the extra header pages.
+        let mut spaces = io.free_state();
+        if let Some(overflow) = &io.header_overflow {
+            if overflow.location != Pointer::NULL {
+                let len = (overflow.heads.len() * pointer_size) as u64;
+                spaces.push(Free::from_start_pointer(overflow.location, len));
+            }
+        }
+        if let Some((a, b)) = find_overlap(&spaces) {
+            return Err(match b.max(a) {
+                overflow if overflow == n_free_slots => {
+                    anyhow!("free slot {} overlaps the extra header pages", a.min(b))
+                }
+                _ => anyhow!("free slots {} and {} overlap", a.min(b), a.max(b)),
+            });
+        }
 
         Ok(io)
     }
 
-    pub fn init(preamble: Preamble, max_size: u64, file: F) -> Result<Self> {
+    pub fn init(preamble: Preamble, max_size: u64, entry_padding: u8, file: F) -> Result<Self> {
         let page_size = preamble.config.page_size();
+        let n_free_slots = preamble.config.n_free_slots();
+        let header_len = preamble.config.header_len();
+        let pointer_size = preamble.config.pointer_size();
+        let value_encoding = preamble
+            .config
+            .value_encoding()
+            .expect("we only init with encodings we know");
         let mut page_buf = vec![0u8; page_size];
-        let preamble_len = bincode::encode_into_slice(preamble, &mut page_buf[..], BINCODE_CONFIG)
+        let id = preamble.config.id();
+        let checksum_range = preamble.config.checksum_range();
+        let preamble_len = bincode::encode_into_slice(&preamble, &mut page_buf[..], BINCODE_CONFIG)
             .context("Unable to write llsdb preamble")?;
-        assert_eq!(preamble_len, PREAMBLE_LEN);
+        assert_eq!(preamble_len, preamble.config.preamble_len());
+        if let Some(offset) = preamble.config.entry_padding_offset() {
+            page_buf[offset] = entry_padding;
+        }
 
-        let (n_list_slots, n_free_slots) = Self::apportion_first_page(page_size as usize);
+        let (n_list_slots, n_free_slots) =
+            Self::apportion_first_page(page_size, header_len, pointer_size, n_free_slots)?;
+        let n_extra_header_pages = preamble.config.n_extra_header_pages();
+        let header_overflow = if n_extra_header_pages > 0 {
+            Some(HeaderOverflow {
+                location: Pointer::NULL,
+                heads: vec![Pointer::NULL; n_extra_header_pages * page_size / pointer_size],
+                dirty: false,
+            })
+        } else {
+            None
+        };
 
         let remaining_free_space = max_size
             .checked_sub(page_size as u64)
             .expect("page size is larger than max size");
         let mut init = Io {
             page_buf,
+            preamble_len,
+            header_len,
+            pointer_size,
+            value_encoding,
+            link_encoding: preamble.config.link_encoding(),
+            entry_padding: entry_padding.into(),
+            id,
+            checksum_range,
+            free_slot_checksum_offset: preamble.config.free_slot_checksum_offset(),
+            clean_shutdown_offset: preamble.config.clean_shutdown_offset(),
+            closed_cleanly: None,
             n_list_slots,
             n_free_slots,
+            header_overflow,
+            metrics: None,
+            clock: Arc::new(crate::SystemClock),
+            durability: Durability::Sync,
+            generation_offset: preamble.config.generation_offset(),
+            list_quotas: preamble.config.features() & VersionedConfig::FEATURE_LIST_QUOTAS != 0,
+            capture: None,
+            written: None,
+            profile: None,
+            read_trace: None,
+            strict_remaps: false,
+            reading_for: None,
+            read_cache: None,
             file,
         };
 
         let initial_free_space = Free::from_start_pointer(Pointer::MIN, remaining_free_space);
-        init.set_free(0, initial_free_space);
+        init.set_free(0, initial_free_space)?;
         init.write_first_page()?;
 
         Ok(init)
     }
 
-    fn apportion_first_page(page_size: usize) -> (usize, usize) {
-        let space_left = page_size - PREAMBLE_LEN;
-        let n_free_slots = space_left / (2 * size_of::<Free>());
-        let rounded_free_slot_space = n_free_slots * size_of::<Free>();
-        let list_slot_space = space_left - rounded_free_slot_space;
-        let n_list_slots = list_slot_space / size_of::<Pointer>();
-        assert!(
-            n_free_slots > 0 && n_list_slots > 1,
-            "page size not big enough to support adding entries!"
-        );
-        (n_list_slots, n_free_slots)
+    fn apportion_first_page(
+        page_size: usize,
+        header_len: usize,
+        pointer_size: usize,
+        n_free_slots: Option<usize>,
+    ) -> Result<(usize, usize)> {
+        let space_left = page_size - header_len;
+        let n_free_slots =
+            n_free_slots.unwrap_or(default_n_free_slots(page_size, header_len, pointer_size));
+        let list_slot_space = space_left
+            .checked_sub(n_free_slots * free_slot_size(pointer_size))
+            .ok_or(anyhow!(
+                "{} free slots don't fit in a page of size {}",
+                n_free_slots,
+                page_size
+            ))?;
+        let n_list_slots = list_slot_space / pointer_size;
+        if n_free_slots == 0 || n_list_slots <= 1 {
+            return Err(anyhow!(
+                "page size not big enough to support adding entries!"
+            ));
+        }
+        Ok((n_list_slots, n_free_slots))
+    }
+
+    /// The total number of list slots including those in the extra header pages
+    pub(crate) fn n_list_slots(&self) -> usize {
+        self.n_list_slots
+            + self
+                .header_overflow
+                .as_ref()
+                .map(|overflow| overflow.heads.len())
+                .unwrap_or(0)
     }
 
     pub(crate) fn get_head(&mut self, list_slot: ListSlot) -> Pointer {
-        let start = list_slot * size_of::<u64>();
-        let end = start + size_of::<u64>();
-        let mut slot = [0u8; size_of::<u64>()];
-        slot.copy_from_slice(&self.list_slots_buf()[start..end]);
-        Pointer(u64::from_le_bytes(slot))
+        if list_slot >= self.n_list_slots {
+            let overflow = self
+                .header_overflow
+                .as_ref()
+                .expect("list slot out of range");
+            return overflow.heads[list_slot - self.n_list_slots];
+        }
+        let start = list_slot * self.pointer_size;
+        let end = start + self.pointer_size;
+        Pointer(read_le_uint(&self.list_slots_buf()[start..end]))
+    }
+
+    /// Write the heads of the lists in `heads` into the header. Transactions can change hundreds
+    /// of lists so the list slots are found in the first page once rather than for every head.
+    fn set_heads(&mut self, heads: impl IntoIterator<Item = (ListSlot, Pointer)>) -> Result<()> {
+        let (pointer_size, n_list_slots) = (self.pointer_size, self.n_list_slots);
+        let start = self.header_len;
+        let list_slots_buf = &mut self.page_buf[start..start + n_list_slots * pointer_size];
+        let mut overflow = self.header_overflow.as_mut();
+        for (list_slot, head) in heads {
+            match list_slot.checked_sub(n_list_slots) {
+                Some(overflow_slot) => {
+                    let overflow = overflow.as_mut().expect("list slot out of range");
+                    overflow.heads[overflow_slot] = head;
+                    overflow.dirty = true;
+                }
+                None => {
+                    let start = list_slot * pointer_size;
+                    write_le_uint(&mut list_slots_buf[start..start + pointer_size], head.0)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Where to record what's done to `list_slot` if profiling is on
+    fn list_profile(&mut self, list_slot: ListSlot) -> Option<&mut crate::ListProfile> {
+        Some(self.profile.as_mut()?.lists.entry(list_slot).or_default())
+    }
+
+    fn generation(&self) -> Option<u64> {
+        let start = self.generation_offset?;
+        Some(u64::from_le_bytes(
+            self.page_buf[start..start + size_of::<u64>()]
+                .try_into()
+                .expect("8 bytes"),
+        ))
+    }
+
+    /// Set the commit generation if the first page has one
+    fn set_generation(&mut self, generation: u64) {
+        if let Some(start) = self.generation_offset {
+            self.page_buf[start..start + size_of::<u64>()]
+                .copy_from_slice(&generation.to_le_bytes());
+        }
+    }
+
+    fn increment_generation(&mut self) {
+        if let (Some(start), Some(generation)) = (self.generation_offset, self.generation()) {
+            self.page_buf[start..start + size_of::<u64>()]
+                .copy_from_slice(&(generation + 1).to_le_bytes());
+        }
+    }
+
+    /// What the clean shutdown byte in the first page says if it has one. Any commit after loading
+    /// sets it back to `false`.
+    fn is_closed(&self) -> Option<bool> {
+        Some(self.page_buf[self.clean_shutdown_offset?] == 1)
+    }
+
+    /// Set the clean shutdown byte in the first page if it has one. It's written with the first
+    /// page.
+    fn set_closed(&mut self, closed: bool) {
+        if let Some(offset) = self.clean_shutdown_offset {
+            self.page_buf[offset] = closed.into();
+        }
+    }
+
+    fn header_overflow_location(&self) -> Pointer {
+        let start = self.preamble_len;
+        Pointer(read_le_uint(
+            &self.page_buf[start..start + self.pointer_size],
+        ))
     }
 
-    fn set_head(&mut self, list_slot: ListSlot, head: Pointer) {
-        let list_slots_buf = self.list_slots_buf_mut();
-        let start = list_slot * size_of::<u64>();
-        let end = start + size_of::<u64>();
-        list_slots_buf[start..end].copy_from_slice(head.0.to_le_bytes().as_slice());
+    /// Writes the extra header pages to a new location if they have changed. The old location is
+    /// freed.
+    fn write_header_overflow(&mut self, free_space: &mut FreeSpace) -> Result<()> {
+        let (buf, old_location, len) = match &self.header_overflow {
+            Some(overflow) if overflow.dirty => {
+                let mut buf = vec![0u8; overflow.heads.len() * self.pointer_size];
+                for (head, bytes) in overflow
+                    .heads
+                    .iter()
+                    .zip(buf.chunks_exact_mut(self.pointer_size))
+                {
+                    write_le_uint(bytes, head.0)?;
+                }
+                let len = buf.len() as u64;
+                (buf, overflow.location, len)
+            }
+            _ => return Ok(()),
+        };
+        let location = free_space
+            .take_for_size(len)
+            .ok_or(anyhow!("no more space in file"))?;
+        if old_location != Pointer::NULL {
+            free_space.free(Free::from_start_pointer(old_location, len));
+        }
+        self.seek_to(location)?;
+        self.writer().write_all(&buf)?;
+
+        let start = self.preamble_len;
+        write_le_uint(
+            &mut self.page_buf[start..start + self.pointer_size],
+            location.0,
+        )?;
+        let overflow = self.header_overflow.as_mut().expect("checked above");
+        overflow.location = location;
+        overflow.dirty = false;
+        Ok(())
     }
 
     fn write_first_page(&mut self) -> Result<()> {
+        self.seal_first_page();
         self.file.rewind()?;
-        self.file.write_all(&self.page_buf)?;
-        self.file.sync_data()?;
+        let page_buf = core::mem::take(&mut self.page_buf);
+        let res = self.writer().write_all(&page_buf);
+        self.page_buf = page_buf;
+        res?;
+        self.sync()
+    }
+
+    /// Set the format feature flags `features` in the first page. Like the rest of the first page
+    /// they're written with the next commit.
+    fn add_features(&mut self, features: u32) -> Result<()> {
+        let (mut preamble, _): (Preamble, _) =
+            bincode::decode_from_slice(&self.page_buf, BINCODE_CONFIG)?;
+        preamble.config.add_features(features);
+        bincode::encode_into_slice(
+            &preamble,
+            &mut self.page_buf[..self.preamble_len],
+            BINCODE_CONFIG,
+        )?;
         Ok(())
     }
 
-    fn list_slots_buf_mut(&mut self) -> &mut [u8] {
-        let start = PREAMBLE_LEN;
-        let end = start + self.n_list_slots * size_of::<Pointer>();
-        &mut self.page_buf[start..end]
+    /// Fill in the checksums of the free slots and the first page if it has them
+    fn seal_first_page(&mut self) {
+        if let Some(offset) = self.free_slot_checksum_offset {
+            let checksum = crc32(self.free_slots_buf());
+            self.page_buf[offset..offset + size_of::<u32>()]
+                .copy_from_slice(&checksum.to_le_bytes());
+        }
+        if let Some(range) = self.checksum_range.clone() {
+            let checksum = first_page_checksum(&self.page_buf, range.clone());
+            self.page_buf[range].copy_from_slice(&checksum.to_le_bytes());
+        }
+    }
+
+    /// Write the first page to the end of the backend rather than where it goes so a prepared
+    /// transaction can be committed later (see [`LlsDb::prepare`]). It's followed by where the
+    /// transaction wrote so they can be checked before committing. It's past the end of
+    /// everything so it's never read otherwise and is trimmed off like free space.
+    fn stage_first_page(&mut self, replaces: [u8; 32]) -> Result<PrepareToken> {
+        self.seal_first_page();
+        let written = self
+            .capture
+            .iter()
+            .flatten()
+            .map(|write| (write.offset, write.bytes.len() as u64))
+            .collect::<Vec<_>>();
+        let location = self.file.seek(SeekFrom::End(0))?;
+        let mut staged = self.page_buf.clone();
+        bincode::encode_into_std_write(&written, &mut staged, BINCODE_CONFIG)?;
+        self.writer().write_all(&staged)?;
+        let hash = self.hash_staged(&staged, &written)?;
+        Ok(PrepareToken {
+            location,
+            hash,
+            replaces,
+        })
+    }
+
+    /// The first page staged by [`stage_first_page`](Self::stage_first_page) if it and everything
+    /// the transaction wrote are still there
+    fn read_staged(&mut self, token: &PrepareToken) -> Result<Option<Vec<u8>>> {
+        let page_size = self.page_buf.len();
+        let end = self.file.seek(SeekFrom::End(0))?;
+        if token.location.saturating_add(page_size as u64) > end {
+            return Ok(None);
+        }
+        let mut staged = vec![0u8; page_size];
+        self.seek_to_offset(token.location)?;
+        self.reader().read_exact(&mut staged)?;
+        let written: Vec<(u64, u64)> =
+            match bincode::decode_from_std_read(&mut self.reader(), BINCODE_CONFIG) {
+                Ok(written) => written,
+                Err(_) => return Ok(None),
+            };
+        bincode::encode_into_std_write(&written, &mut staged, BINCODE_CONFIG)?;
+        if written
+            .iter()
+            .any(|&(offset, len)| offset.saturating_add(len) > end)
+            || self.hash_staged(&staged, &written)? != token.hash
+        {
+            return Ok(None);
+        }
+        staged.truncate(page_size);
+        Ok(Some(staged))
+    }
+
+    fn hash_staged(&mut self, staged: &[u8], written: &[(u64, u64)]) -> Result<[u8; 32]> {
+        let mut parts = vec![staged.to_vec()];
+        for &(offset, len) in written {
+            let mut bytes = vec![0u8; len as usize];
+            self.seek_to_offset(offset)?;
+            self.reader().read_exact(&mut bytes)?;
+            parts.push(bytes);
+        }
+        Ok(sha256(&parts.iter().map(Vec::as_slice).collect::<Vec<_>>()))
+    }
+
+    /// Split into the settings that aren't persisted and the backend
+    fn into_settings(self) -> (IoSettings, F) {
+        let settings = IoSettings {
+            read_cache_pages: self.read_cache.as_ref().map_or(0, ReadCache::capacity),
+            metrics: self.metrics,
+            clock: self.clock,
+            profile: self.profile,
+            read_trace: self.read_trace,
+            strict_remaps: self.strict_remaps,
+            durability: self.durability,
+        };
+        (settings, self.file)
+    }
+
+    /// Copy the database into `fresh` with a first page laid out with `options` (see
+    /// [`LlsDb::upgrade_format`]). `free_regions` are the free spaces as `(start, end)`.
+    fn copy_into(
+        &mut self,
+        fresh: F,
+        options: InitOptions,
+        free_regions: &[(Pointer, Pointer)],
+    ) -> Result<F> {
+        let pad_entries_to = options.pad_entries_to;
+        let (mut preamble, max_size) = init_preamble(options, self.id.unwrap_or_else(random_id))?;
+        if preamble.config.value_encoding() != Some(self.value_encoding)
+            || preamble.config.link_encoding() != self.link_encoding
+            || u64::from(pad_entries_to) != self.entry_padding
+        {
+            return Err(anyhow!(
+                "entries are copied as they are so the value encoding, entry padding and \
+                 compact_pointers have to stay the same"
+            ));
+        }
+        if self.list_quotas {
+            preamble
+                .config
+                .add_features(VersionedConfig::FEATURE_LIST_QUOTAS);
+        }
+        let mut copy = Io::init(preamble, max_size, pad_entries_to, fresh)?;
+
+        let mut heads = vec![];
+        for slot in 0..self.n_list_slots() {
+            let head = self.get_head(slot);
+            if head == Pointer::NULL {
+                continue;
+            }
+            if slot >= copy.n_list_slots() {
+                return Err(anyhow!(
+                    "list slot {} is used but the new layout only has {} list slots",
+                    slot,
+                    copy.n_list_slots()
+                ));
+            }
+            heads.push((slot, head));
+        }
+
+        // the free space running off the end of the file isn't copied
+        let file_end = self.file.seek(SeekFrom::End(0))?;
+        let mut data_end = self.file_position_to_pointer(file_end.max(self.page_buf.len() as u64));
+        let mut free_regions = free_regions.to_vec();
+        if let Some(&(start, end)) = free_regions.last() {
+            if end >= data_end {
+                data_end = data_end.min(start);
+                free_regions.pop();
+            }
+        }
+        let capacity_end = copy.file_position_to_pointer(max_size);
+        if data_end > capacity_end {
+            return Err(anyhow!(
+                "the database takes up more than the max size of {} bytes",
+                max_size
+            ));
+        }
+        let data_len = data_end.0 - Pointer::MIN.0;
+        self.file
+            .seek(SeekFrom::Start(self.page_buf.len() as u64))?;
+        copy.file
+            .seek(SeekFrom::Start(copy.page_buf.len() as u64))?;
+        let copied = std::io::copy(&mut Read::take(&mut self.file, data_len), &mut copy.file)?;
+        if copied != data_len {
+            return Err(anyhow!(
+                "the backend ended {} bytes early",
+                data_len - copied
+            ));
+        }
+        copy.file.truncate(copy.page_buf.len() as u64 + data_len)?;
+
+        let mut free_space = FreeSpace::new(copy.n_free_slots);
+        for (start, end) in free_regions {
+            free_space.free(Free::from_start_pointer(start, end.0 - start.0));
+        }
+        // the old extra header pages were copied along with everything else
+        if let Some(overflow) = &self.header_overflow {
+            if overflow.location != Pointer::NULL {
+                let len = (overflow.heads.len() * self.pointer_size) as u64;
+                free_space.free(Free::from_start_pointer(overflow.location, len));
+            }
+        }
+        free_space.free(Free::from_start_pointer(
+            data_end,
+            capacity_end.0 - data_end.0,
+        ));
+        // every free slot is written below so which ones changed doesn't matter
+        let _ = free_space.apply_pending_frees();
+        copy.set_heads(heads)?;
+        copy.write_header_overflow(&mut free_space)?;
+        for (slot, free) in free_space.persist_state().to_vec().into_iter().enumerate() {
+            copy.set_free(slot, free)?;
+        }
+        copy.set_generation(self.generation().unwrap_or(0));
+        copy.write_first_page()?;
+        Ok(copy.file)
+    }
+
+    /// The first page laid out as format `target_version` with the same list heads and as many of
+    /// the free spaces as still fit. The list slots stay where they are so the bigger header comes
+    /// out of the free slots.
+    fn upgraded_first_page(&self, target_version: u32) -> Result<Vec<u8>> {
+        let (preamble, _): (Preamble, _) =
+            bincode::decode_from_slice(&self.page_buf, BINCODE_CONFIG)?;
+        let current_version = preamble.config.version();
+        if target_version <= current_version {
+            return Err(anyhow!(
+                "database is already format version {}",
+                current_version
+            ));
+        }
+        if target_version > VersionedConfig::LATEST_VERSION {
+            return Err(anyhow!(
+                "format version {} is newer than this version supports ({})",
+                target_version,
+                VersionedConfig::LATEST_VERSION
+            ));
+        }
+        let page_size = self.page_buf.len();
+        let n_extra_header_pages = preamble.config.n_extra_header_pages();
+        let id = random_id();
+        let config = |n_free_slots: usize| {
+            let (page_size, n_free_slots) = (page_size as u16, n_free_slots as u16);
+            let (n_extra_header_pages, pointer_size) =
+                (n_extra_header_pages as u16, self.pointer_size as u8);
+            match target_version {
+                1 => VersionedConfig::one(page_size, n_free_slots),
+                2 => VersionedConfig::two(
+                    page_size,
+                    n_free_slots,
+                    n_extra_header_pages,
+                    pointer_size,
+                ),
+                3 => VersionedConfig::three(
+                    page_size,
+                    n_free_slots,
+                    n_extra_header_pages,
+                    pointer_size,
+                    self.value_encoding,
+                ),
+                _ => VersionedConfig::four(
+                    page_size,
+                    n_free_slots,
+                    n_extra_header_pages,
+                    pointer_size,
+                    self.value_encoding,
+                    id,
+                ),
+            }
+        };
+        let header_len = config(0).header_len();
+        let list_slots_len = self.n_list_slots * self.pointer_size;
+        let n_free_slots = page_size.saturating_sub(header_len + list_slots_len)
+            / free_slot_size(self.pointer_size);
+        let config = config(n_free_slots);
+        let (n_list_slots, _) = Self::apportion_first_page(
+            page_size,
+            header_len,
+            self.pointer_size,
+            Some(n_free_slots),
+        )
+        .context("the first page doesn't have room for the bigger header")?;
+        // the list slots past the first page are numbered from the end of it
+        if n_list_slots != self.n_list_slots && n_extra_header_pages > 0 {
+            return Err(anyhow!(
+                "upgrading would change the number of list slots in the first page ({} to {})",
+                self.n_list_slots,
+                n_list_slots
+            ));
+        }
+
+        let mut page = vec![0u8; page_size];
+        let preamble_len = bincode::encode_into_slice(
+            Preamble {
+                magic_bytes: preamble.magic_bytes,
+                config,
+            },
+            &mut page,
+            BINCODE_CONFIG,
+        )?;
+        if n_extra_header_pages > 0 {
+            let pointer = &self.page_buf[self.preamble_len..self.preamble_len + self.pointer_size];
+            page[preamble_len..preamble_len + self.pointer_size].copy_from_slice(pointer);
+        }
+        page[header_len..header_len + list_slots_len].copy_from_slice(self.list_slots_buf());
+        // keep the biggest free spaces (free spaces are ordered by size first)
+        let mut frees = self.free_state();
+        frees.retain(|free| *free != Free::NULL);
+        frees.sort_unstable_by(|a, b| b.cmp(a));
+        let free_slots_start = header_len + n_list_slots * self.pointer_size;
+        let free_slot_size = free_slot_size(self.pointer_size);
+        for (free, buf) in frees
+            .iter()
+            .zip(page[free_slots_start..].chunks_exact_mut(free_slot_size))
+            .take(n_free_slots)
+        {
+            free.write_to(buf)?;
+        }
+        if let Some(range) = config.checksum_range() {
+            let checksum = first_page_checksum(&page, range.clone());
+            page[range].copy_from_slice(&checksum.to_le_bytes());
+        }
+        Ok(page)
+    }
+
+    /// Sync the backend unless the [`Durability`] says not to
+    fn sync(&mut self) -> Result<()> {
+        match self.durability {
+            Durability::Sync => self.file.sync_data(),
+            Durability::NoSync => Ok(()),
+        }
     }
 
     fn list_slots_buf(&self) -> &[u8] {
-        let start = PREAMBLE_LEN;
-        let end = start + self.n_list_slots * size_of::<Pointer>();
+        let start = self.header_len;
+        let end = start + self.n_list_slots * self.pointer_size;
         &self.page_buf[start..end]
     }
 
     fn free_slots_buf_mut(&mut self) -> &mut [u8] {
-        let start = PREAMBLE_LEN + self.n_list_slots * size_of::<Pointer>();
-        let end = start + self.n_free_slots * size_of::<Free>();
+        let start = self.header_len + self.n_list_slots * self.pointer_size;
+        let end = start + self.n_free_slots * free_slot_size(self.pointer_size);
         &mut self.page_buf[start..end]
     }
 
     fn free_slots_buf(&self) -> &[u8] {
-        let start = PREAMBLE_LEN + self.n_list_slots * size_of::<Pointer>();
-        let end = start + self.n_free_slots * size_of::<Free>();
+        let start = self.header_len + self.n_list_slots * self.pointer_size;
+        let end = start + self.n_free_slots * free_slot_size(self.pointer_size);
         &self.page_buf[start..end]
     }
 
@@ -408,19 +2793,20 @@ impl<F: Backend> Io<F> {
     }
 
     fn get_free_slot(&self, slot: usize) -> Result<Free> {
-        let start = slot * size_of::<Free>();
-        let end = start + size_of::<Free>();
+        let start = slot * free_slot_size(self.pointer_size);
+        let end = start + free_slot_size(self.pointer_size);
         let free_slots_buf = self.free_slots_buf();
         let free = Free::read_from(&free_slots_buf[start..end])
             .ok_or(anyhow!("Free slot {} has an invalid value in it", slot))?;
         Ok(free)
     }
 
-    fn set_free(&mut self, slot: usize, free: Free) {
+    fn set_free(&mut self, slot: usize, free: Free) -> Result<()> {
+        let free_slot_size = free_slot_size(self.pointer_size);
         let free_slots_buf = self.free_slots_buf_mut();
-        let start = slot * size_of::<Free>();
-        let end = start + size_of::<Free>();
-        free.write_to(&mut free_slots_buf[start..end]);
+        let start = slot * free_slot_size;
+        let end = start + free_slot_size;
+        free.write_to(&mut free_slots_buf[start..end])
     }
 
     fn file_position_to_pointer(&self, file_pos: u64) -> Pointer {
@@ -435,24 +2821,74 @@ impl<F: Backend> Io<F> {
         }
     }
 
-    fn seek_to(&mut self, pos: Pointer) -> Result<()> {
-        self.file.seek(SeekFrom::Start(
-            self.pointer_to_file_position(pos)
-                .expect("tried to seek to null pointer"),
-        ))?;
-        Ok(())
+    fn seek_to(&mut self, pos: Pointer) -> Result<()> {
+        if let Some(trace) = &mut self.read_trace {
+            trace.counts_mut(self.reading_for).seeks += 1;
+        }
+        self.seek_to_offset(
+            self.pointer_to_file_position(pos)
+                .expect("tried to seek to null pointer"),
+        )
+    }
+
+    /// Move the backend to `offset` bytes from its start for reading or writing
+    fn seek_to_offset(&mut self, offset: u64) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        if let Some(read_cache) = &mut self.read_cache {
+            read_cache.seeked(offset);
+        }
+        Ok(())
+    }
+
+    fn writer(&mut self) -> impl Write + '_ {
+        Captured {
+            inner: Invalidating {
+                inner: Metered {
+                    inner: &mut self.file,
+                    metrics: self.metrics.as_deref(),
+                    reads: None,
+                },
+                cache: self.read_cache.as_mut(),
+            },
+            writes: self.capture.as_mut(),
+            ranges: self.written.as_mut(),
+        }
+    }
+
+    fn reader(&mut self) -> impl Read + '_ {
+        let reading_for = self.reading_for;
+        let metrics = self.metrics.as_deref();
+        CachedReader {
+            inner: Metered {
+                inner: &mut self.file,
+                metrics,
+                reads: self
+                    .read_trace
+                    .as_mut()
+                    .map(|trace| trace.counts_mut(reading_for)),
+            },
+            cache: self.read_cache.as_mut(),
+            metrics,
+        }
     }
 
-    fn writer(&mut self) -> &mut impl Write {
-        &mut self.file
+    /// Forget what's in the read cache after the backend was changed other than by a write
+    fn clear_read_cache(&mut self) {
+        if let Some(read_cache) = &mut self.read_cache {
+            read_cache.clear();
+        }
     }
 
-    fn reader(&mut self) -> &mut impl Read {
-        &mut self.file
+    fn metrics(&self) -> Option<&dyn Metrics> {
+        self.metrics.as_deref()
     }
 
     fn current_position(&mut self) -> Result<Pointer> {
-        let stream_position = self.file.stream_position()?;
+        // reads served from the read cache don't move the backend
+        let stream_position = match self.read_cache.as_ref().and_then(ReadCache::position) {
+            Some(position) => position,
+            None => self.file.stream_position()?,
+        };
         Ok(self.file_position_to_pointer(stream_position))
     }
 }
@@ -460,18 +2896,115 @@ impl<F: Backend> Io<F> {
 pub struct Transaction<'tx, F> {
     pub io: TxIo<'tx, F>,
     slots_by_name: &'tx HashMap<String, Meta>,
-    indexers: &'tx mut Vec<Box<dyn RefCellIndexStore>>,
+    indexers: &'tx mut BTreeMap<usize, Box<dyn RefCellIndexStore>>,
+    next_index_id: &'tx mut usize,
     list_refs: &'tx BTreeSet<ListSlot>,
     used_slots: &'tx BTreeSet<ListSlot>,
     tx_used_slots: BTreeSet<ListSlot>,
     tx_list_refs: BTreeSet<ListSlot>,
     tx_slots_by_name: HashMap<String, Meta>,
+    /// Names in `slots_by_name` that have been renamed away during the transaction
+    tx_removed_names: HashSet<String>,
+    /// Whether the meta list was rewritten with quotas in this transaction (see
+    /// [`VersionedConfig::FEATURE_LIST_QUOTAS`])
+    tx_list_quotas: bool,
+    /// Set by [`Transaction::abort`]
+    aborted: RefCell<Option<Aborted>>,
+}
+
+/// The error a transaction fails with after [`Transaction::abort`] is called. Like
+/// [`QuotaExceeded`](crate::QuotaExceeded) use `downcast_ref` to tell it apart from other errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aborted {
+    pub reason: String,
+}
+
+impl core::fmt::Display for Aborted {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "transaction aborted: {}", self.reason)
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+/// The error every transaction fails with after [`LlsDb::apply_changeset`] or
+/// [`LlsDb::upgrade_header`] fails partway through. The backend may have been left half rewritten
+/// so the database has to be opened again from it (if it's still there) to be used. Like
+/// [`Aborted`] use `downcast_ref` to tell it apart from other errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Poisoned {
+    /// The error that poisoned the database
+    pub reason: String,
+}
+
+impl core::fmt::Display for Poisoned {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "the database can't be used after rewriting its backend failed: {}",
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for Poisoned {}
+
+/// The error a transaction fails with when it goes over the limit set with
+/// [`LlsDb::set_tx_memory_limit`]. Like [`Aborted`] use `downcast_ref` to tell it apart from
+/// other errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxMemoryExceeded {
+    pub limit: u64,
+    /// The estimate of the memory used when the limit was hit
+    pub used: u64,
+}
+
+impl core::fmt::Display for TxMemoryExceeded {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "transaction would hold about {} bytes of memory which is over its limit of {}",
+            self.used, self.limit
+        )
+    }
+}
+
+impl std::error::Error for TxMemoryExceeded {}
+
+/// What a change is counted as in [`TxMemory`] on top of any bytes it keeps. Indexes keep about
+/// this much per change to roll it back plus the pending free or new head it leads to.
+const CHANGE_MEMORY: u64 = 64;
+
+/// An estimate of the memory a transaction is holding on to
+#[derive(Debug, Clone, Copy)]
+struct TxMemory {
+    limit: Option<u64>,
+    used: u64,
 }
 
 struct TxIoInner<F> {
     io: Rc<RefCell<Io<F>>>,
     free_space: Rc<RefCell<FreeSpace>>,
     changed_heads: HashMap<ListSlot, Pointer>,
+    /// The bytes that were overwritten in place so they can be put back if the transaction fails
+    overwritten: Vec<(Pointer, Vec<u8>)>,
+    quotas: Quotas,
+    reservations: BTreeMap<ListSlot, (Pointer, u64)>,
+    /// Reused to encode entries so each push doesn't have to allocate
+    scratch: Vec<u8>,
+    memory: TxMemory,
+    /// What has been unlinked and relocated in each list in this transaction
+    tx_remaps: HashMap<ListSlot, TxRemaps>,
+}
+
+/// The remaps a [`LinkedListMut`](crate::LinkedListMut) has written to a list in the current
+/// transaction (see [`TxIo::record_remap`])
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TxRemaps {
+    /// The remaps of the entries unlinked along with the handles of the remap entries
+    pub unlinked: Vec<(EntryHandle, Remap)>,
+    /// The entries the copies of relocated entries point to
+    pub relocated_to: HashSet<Pointer>,
 }
 
 impl<'tx, F: Backend> TxIoInner<F> {
@@ -486,9 +3019,12 @@ impl<'tx, F: Backend> TxIoInner<F> {
         let mut io = self.io.borrow_mut();
         let value_pointer = pointer.value_pointer();
         io.seek_to(value_pointer)?;
-        let val = bincode::decode_from_std_read(io.reader(), BINCODE_CONFIG)?;
+        let val = io.value_encoding.decode_from_std_read(&mut io.reader())?;
         let end = io.current_position()?;
         let len = end.0 - value_pointer.0;
+        if let Some(profile) = &mut io.profile {
+            profile.reads_by_pointer += 1;
+        }
         Ok((
             EntryHandle {
                 entry_pointer: pointer,
@@ -501,13 +3037,18 @@ impl<'tx, F: Backend> TxIoInner<F> {
     fn raw_read_at<T: bincode::Decode>(&self, value_pointer: Pointer) -> Result<T> {
         let mut io = self.io.borrow_mut();
         io.seek_to(value_pointer)?;
-        let val = bincode::decode_from_std_read(io.reader(), BINCODE_CONFIG)?;
+        let val = io.value_encoding.decode_from_std_read(&mut io.reader())?;
+        if let Some(profile) = &mut io.profile {
+            profile.reads_by_pointer += 1;
+        }
         Ok(val)
     }
 }
 
 pub struct TxIo<'tx, F> {
     inner: Rc<RefCell<TxIoInner<F>>>,
+    /// The index this was handed to if any so reads can be traced to it
+    index: Option<usize>,
     lifetime: PhantomData<&'tx ()>,
 }
 
@@ -521,6 +3062,7 @@ impl<'tx, F> Clone for TxIo<'tx, F> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            index: self.index,
             lifetime: PhantomData,
         }
     }
@@ -533,6 +3075,29 @@ impl<'tx, F> AsRef<TxIo<'tx, F>> for TxIo<'tx, F> {
 }
 
 impl<'tx, F: crate::Backend> TxIo<'tx, F> {
+    /// Borrow the state of the transaction. If reads are being traced anything read from here on
+    /// is put down to the index this was handed to.
+    fn inner(&self) -> Ref<'_, TxIoInner<F>> {
+        let inner = self.inner.borrow();
+        inner.io.borrow_mut().reading_for = self.index;
+        inner
+    }
+
+    /// [`inner`](Self::inner) but mutable
+    fn inner_mut(&self) -> RefMut<'_, TxIoInner<F>> {
+        let inner = self.inner.borrow_mut();
+        inner.io.borrow_mut().reading_for = self.index;
+        inner
+    }
+
+    /// A handle for the index with `id` to do its IO through
+    fn for_index(&self, id: usize) -> Self {
+        Self {
+            index: Some(id),
+            ..self.clone()
+        }
+    }
+
     fn into_inner(self) -> TxIoInner<F> {
         Rc::into_inner(self.inner)
             .expect("should only be called at end of tx")
@@ -540,172 +3105,815 @@ impl<'tx, F: crate::Backend> TxIo<'tx, F> {
     }
 
     pub fn iter(&self, slot: ListSlot) -> EntryIter<'tx, F> {
-        let inner = self.inner.borrow();
+        let head = self.curr_head(slot);
+        self.iter_at(slot, head)
+    }
+
+    /// Iterate the list in `slot` starting from the entry at `entry` rather than the head
+    pub(crate) fn iter_at(&self, slot: ListSlot, entry: Pointer) -> EntryIter<'tx, F> {
+        let inner = self.inner();
         EntryIter {
             io: inner.io.clone(),
-            curr: inner.curr_head(slot),
+            slot,
+            curr: entry,
             remap: Default::default(),
             reverse_remap: Default::default(),
+            index: self.index,
+            strict: self.strict_check(),
             lifetime: PhantomData,
         }
     }
 
-    fn _push<T: bincode::Encode>(
+    /// Resume iterating a list from where a previous [`EntryIter`] left off.
+    ///
+    /// The cursor is only valid as long as the entries it has yet to visit have not been removed
+    /// from the list since it was taken. Errors if `cursor` was taken from a different list.
+    pub fn iter_from_cursor(
         &self,
         list_slot: ListSlot,
-        value: &T,
-        extra_space: usize,
+        cursor: &ListCursor,
+    ) -> Result<EntryIter<'tx, F>> {
+        if cursor.slot != list_slot {
+            return Err(anyhow!(
+                "cursor belongs to list slot {} not {}",
+                cursor.slot,
+                list_slot
+            ));
+        }
+        let inner = self.inner();
+        Ok(EntryIter {
+            io: inner.io.clone(),
+            slot: cursor.slot,
+            curr: cursor.curr,
+            remap: cursor.remap.clone(),
+            reverse_remap: cursor.remap.iter().fold(
+                HashMap::new(),
+                |mut reverse_remap: HashMap<_, Vec<_>>, (&from, &to)| {
+                    reverse_remap.entry(to).or_default().push(from);
+                    reverse_remap
+                },
+            ),
+            index: self.index,
+            strict: self.strict_check(),
+            lifetime: PhantomData,
+        })
+    }
+
+    fn strict_check(&self) -> Option<StrictCheck> {
+        let inner = self.inner();
+        let strict = inner.io.borrow().strict_remaps;
+        strict.then(|| StrictCheck {
+            free_space: inner.free_space.clone(),
+            visited: Default::default(),
+        })
+    }
+
+    /// Encode an entry into the scratch buffer with `encode_value` (which returns the length of
+    /// the value) and push it to the list.
+    fn push_with(
+        &self,
+        list_slot: ListSlot,
+        enforce_quota: bool,
+        encode_value: impl FnOnce(ValueEncoding, &mut Vec<u8>) -> Result<usize>,
     ) -> Result<EntryHandle> {
-        let curr_head = {
-            let inner = self.inner.borrow();
-            inner.curr_head(list_slot)
+        let (prev, value_encoding, link_encoding, mut buf) = {
+            let mut inner = self.inner_mut();
+            let (value_encoding, link_encoding) = {
+                let io = inner.io.borrow();
+                (io.value_encoding, io.link_encoding)
+            };
+            let buf = core::mem::take(&mut inner.scratch);
+            (
+                inner.curr_head(list_slot),
+                value_encoding,
+                link_encoding,
+                buf,
+            )
         };
-        let handle = self.push_dangling(curr_head, value, extra_space)?;
-        self.inner
-            .borrow_mut()
+        buf.clear();
+        let res = (|| {
+            let rev_pointer_len = link_encoding.encode(prev, &mut buf)?;
+            debug_assert_eq!(rev_pointer_len as u64, prev.encoded_len(link_encoding));
+            let value_len = encode_value(value_encoding, &mut buf)?;
+            self.push_encoded(list_slot, enforce_quota, prev, &buf, value_len)
+        })();
+        // don't hang on to the memory used by the odd big value
+        if buf.capacity() <= MAX_SCRATCH_CAPACITY {
+            self.inner_mut().scratch = buf;
+        }
+        res
+    }
+
+    fn push_encoded(
+        &self,
+        list_slot: ListSlot,
+        enforce_quota: bool,
+        prev: Pointer,
+        entry_bytes: &[u8],
+        value_len: usize,
+    ) -> Result<EntryHandle> {
+        let entry_len = self.padded_len(entry_bytes.len() as u64);
+        if enforce_quota {
+            self.inner
+                .borrow()
+                .quotas
+                .check_push(list_slot, entry_len)?;
+        }
+        self.charge_memory(CHANGE_MEMORY)?;
+        let handle = self.push_dangling(list_slot, prev, entry_bytes, value_len)?;
+        let mut inner = self.inner_mut();
+        if enforce_quota {
+            inner.quotas.pushed(list_slot, entry_len);
+        } else {
+            inner.quotas.pushed_bytes(list_slot, entry_len);
+        }
+        inner
             .changed_heads
             .insert(list_slot, handle.entry_pointer.this_entry);
         Ok(handle)
     }
 
+    /// Push `value` to the front of the list.
+    ///
+    /// Errors with [`QuotaExceeded`] if the list was taken with a [`ListQuota`] the entry doesn't
+    /// fit in.
     pub fn push<T: bincode::Encode>(&self, list_slot: ListSlot, value: &T) -> Result<EntryHandle> {
-        self._push(list_slot, value, 0)
+        self.push_with(list_slot, true, |encoding, buf| {
+            Ok(encoding.encode_into_std_write(value, buf)?)
+        })
     }
 
+    /// Like [`push`](Self::push) but never refused by the list's quota and only its bytes count
+    /// towards it since it isn't a value. Used for bookkeeping entries that have to be written to
+    /// remove something.
+    pub(crate) fn push_ignoring_quota<T: bincode::Encode>(
+        &self,
+        list_slot: ListSlot,
+        value: &T,
+    ) -> Result<EntryHandle> {
+        self.push_with(list_slot, false, |encoding, buf| {
+            Ok(encoding.encode_into_std_write(value, buf)?)
+        })
+    }
+
+    /// Push an entry made up of `key` followed by `value`. The returned handle only covers the key.
     pub fn push_kv<K: bincode::Encode, V: bincode::Encode>(
         &self,
         list_slot: ListSlot,
         key: &K,
         value: &V,
     ) -> Result<EntryHandle> {
-        let mut value_buf = vec![];
-        let value_len = bincode::encode_into_std_write(value, &mut value_buf, BINCODE_CONFIG)?;
-        let key_handle = self._push(list_slot, key, value_len)?;
-        let inner = self.inner.borrow();
-        let mut io = inner.io.borrow_mut();
-        io.writer().write_all(&value_buf)?;
-        Ok(key_handle)
+        let (mut handle, offsets) = self.push_segments(list_slot, &[key, value])?;
+        handle.value_len = offsets[1];
+        Ok(handle)
     }
 
-    pub(crate) fn encode_entry<T: bincode::Encode>(
-        value: T,
-        prev: Pointer,
-    ) -> Result<(Vec<u8>, usize)> {
-        let mut buf = vec![];
-        let rev_pointer_len = bincode::encode_into_std_write(prev, &mut buf, BINCODE_CONFIG)?;
-        debug_assert_eq!(rev_pointer_len as u64, prev.encoded_len());
-        let value_len = bincode::encode_into_std_write(value, &mut buf, BINCODE_CONFIG)?;
-        Ok((buf, value_len))
+    /// Push an entry made up of each of `segments` one after the other. Along with the handle to
+    /// the whole entry it returns where each segment starts relative to the entry's value so a
+    /// single segment can be read later with [`raw_read_at`] and [`EntryHandle::segment_pointer`].
+    ///
+    /// Since bincode encodes a tuple as its fields one after the other the entry can be read as a
+    /// tuple of the segments' types.
+    ///
+    /// [`raw_read_at`]: Self::raw_read_at
+    pub fn push_segments(
+        &self,
+        list_slot: ListSlot,
+        segments: &[&dyn EncodeSegment],
+    ) -> Result<(EntryHandle, Vec<u64>)> {
+        let mut offsets = Vec::with_capacity(segments.len());
+        let handle = self.push_with(list_slot, true, |encoding, buf| {
+            let mut len = 0;
+            for segment in segments {
+                offsets.push(len as u64);
+                len += segment.encode_segment(encoding, buf)?;
+            }
+            Ok(len)
+        })?;
+        Ok((handle, offsets))
+    }
+
+    /// An estimate of how many bytes of memory the transaction is holding on to so far. Each push,
+    /// pop, free and overwrite counts as a fixed amount for what it adds to the transaction's
+    /// rollback state plus the old bytes kept by an overwrite. Use it to decide when to commit and
+    /// carry on in a new transaction.
+    pub fn memory_used(&self) -> u64 {
+        self.inner().memory.used
+    }
+
+    /// Count `bytes` more towards the transaction's memory erroring with [`TxMemoryExceeded`]
+    /// instead if that would take it over the limit
+    fn charge_memory(&self, bytes: u64) -> Result<()> {
+        let mut inner = self.inner_mut();
+        let TxMemory { limit, used } = inner.memory;
+        if let Some(limit) = limit {
+            if used + bytes > limit {
+                return Err(TxMemoryExceeded {
+                    limit,
+                    used: used + bytes,
+                }
+                .into());
+            }
+        }
+        inner.memory.used = used + bytes;
+        Ok(())
+    }
+
+    /// How values are encoded in this database
+    pub(crate) fn value_encoding(&self) -> ValueEncoding {
+        self.inner().io.borrow().value_encoding
+    }
+
+    /// How the pointer at the start of each entry to the next one is encoded in this database
+    pub(crate) fn link_encoding(&self) -> LinkEncoding {
+        self.inner().io.borrow().link_encoding
+    }
+
+    /// The space an entry `entry_len` bytes long takes up once it's padded (see
+    /// [`InitOptions::pad_entries_to`])
+    pub(crate) fn padded_len(&self, entry_len: u64) -> u64 {
+        entry_len.next_multiple_of(self.inner().io.borrow().entry_padding)
     }
 
-    fn push_dangling<T: bincode::Encode>(
+    fn push_dangling(
         &self,
+        list_slot: ListSlot,
         prev: Pointer,
-        value: &T,
-        extra_space: usize,
+        entry_bytes: &[u8],
+        value_len: usize,
     ) -> Result<EntryHandle> {
-        let (entry_bytes, value_len) = Self::encode_entry(value, prev)?;
-
-        let inner = self.inner.borrow_mut();
+        let entry_len = self.padded_len(entry_bytes.len() as u64);
+        let mut inner = self.inner_mut();
 
-        let location = inner
-            .free_space
-            .borrow_mut()
-            .take_for_size(entry_bytes.len() as u64 + extra_space as u64)
-            .ok_or(anyhow!("no more space in file"))?;
+        let location = match inner.reservations.get_mut(&list_slot) {
+            Some((start, size)) if *size >= entry_len => {
+                let location = *start;
+                *start = Pointer(start.0 + entry_len);
+                *size -= entry_len;
+                location
+            }
+            _ => inner
+                .free_space
+                .borrow_mut()
+                .take_for_size(entry_len)
+                .ok_or(anyhow!("no more space in file"))?,
+        };
 
         let mut io = inner.io.borrow_mut();
         io.seek_to(location)?;
-        io.writer().write_all(&entry_bytes)?;
+        io.writer().write_all(entry_bytes)?;
+        if let Some(metrics) = io.metrics() {
+            metrics.push(entry_len);
+        }
+        if let Some(profile) = io.list_profile(list_slot) {
+            profile.pushes += 1;
+            profile.value_sizes.record(value_len as u64);
+        }
 
         Ok(EntryHandle {
             entry_pointer: EntryPointer {
                 this_entry: location,
                 next_entry_possibly_stale: prev,
+                links: io.link_encoding,
             },
             value_len: value_len as u64,
         })
     }
 
-    pub fn pop<T: bincode::Encode + bincode::Decode>(
-        &self,
-        list_slot: ListSlot,
-    ) -> Result<Option<T>> {
-        let mut iter = self.iter(list_slot);
-        Ok(
-            if let Some((handle, value)) = iter.next_with_handle::<T>().transpose()? {
-                let mut inner = self.inner.borrow_mut();
-                let entry_pointer = handle.entry_pointer;
-                inner.free_space.borrow_mut().free(Free::from_start_pointer(
-                    entry_pointer.this_entry,
-                    handle.entry_len(),
-                ));
-                inner
-                    .changed_heads
-                    .insert(list_slot, entry_pointer.next_entry_possibly_stale);
-                Some(value)
-            } else {
-                None
-            },
-        )
+    pub fn pop<T: bincode::Encode + bincode::Decode>(
+        &self,
+        list_slot: ListSlot,
+    ) -> Result<Option<T>> {
+        let mut iter = self.iter(list_slot);
+        Ok(
+            if let Some((handle, value)) = iter.next_with_handle::<T>().transpose()? {
+                self.free_head(list_slot, handle)?;
+                Some(value)
+            } else {
+                None
+            },
+        )
+    }
+
+    /// Pop entries from the front of the list for as long as `pred` holds for their values
+    /// returning them newest first. The list is only walked once and stops at the first value
+    /// `pred` doesn't hold for which stays in the list.
+    pub fn pop_while<T: bincode::Encode + bincode::Decode>(
+        &self,
+        list_slot: ListSlot,
+        mut pred: impl FnMut(&T) -> bool,
+    ) -> Result<Vec<T>> {
+        let mut iter = self.iter(list_slot);
+        let mut popped = vec![];
+        while let Some((handle, value)) = iter.next_with_handle::<T>().transpose()? {
+            if !pred(&value) {
+                break;
+            }
+            self.free_head(list_slot, handle)?;
+            popped.push(value);
+        }
+        Ok(popped)
+    }
+
+    /// Free the entry at the front of the list and make the next one the front
+    fn free_head(&self, list_slot: ListSlot, handle: EntryHandle) -> Result<()> {
+        self.charge_memory(CHANGE_MEMORY)?;
+        let entry_len = self.padded_len(handle.entry_len());
+        let mut inner = self.inner_mut();
+        let entry_pointer = handle.entry_pointer;
+        inner.free_space.borrow_mut().free(Free::from_start_pointer(
+            entry_pointer.this_entry,
+            entry_len,
+        ));
+        if let Some(metrics) = inner.io.borrow().metrics() {
+            metrics.pop();
+            metrics.free(entry_len);
+        }
+        if let Some(profile) = inner.io.borrow_mut().list_profile(list_slot) {
+            profile.pops += 1;
+        }
+        inner.quotas.freed(list_slot, entry_len);
+        inner
+            .changed_heads
+            .insert(list_slot, entry_pointer.next_entry_possibly_stale);
+        Ok(())
+    }
+
+    /// Replace the value of an entry without moving it. The new value must encode to exactly the
+    /// same length as the old one unless entries are padded (see [`InitOptions::pad_entries_to`])
+    /// in which case it just has to take up the same padded space.
+    ///
+    /// Unlike everything else this writes over data that may already be committed. If the
+    /// transaction fails the old value is written back but if the process dies before the
+    /// transaction finishes the new value may be left in place.
+    pub fn overwrite<T: bincode::Encode>(&self, handle: EntryHandle, value: &T) -> Result<()> {
+        let mut value_buf = vec![];
+        let value_len = self
+            .value_encoding()
+            .encode_into_std_write(value, &mut value_buf)?;
+        // compare the space the entries take up including any padding
+        let (old_len, new_len) = (
+            self.padded_len(handle.entry_len()),
+            self.padded_len(handle.entry_pointer.link_len() + value_len as u64),
+        );
+        if new_len != old_len {
+            return Err(anyhow!(
+                "can't overwrite an entry taking up {} bytes with one that would take up {}",
+                old_len,
+                new_len
+            ));
+        }
+        self.overwrite_bytes(handle.value_pointer(), &value_buf)
+    }
+
+    /// Write `bytes` at `pointer` over data that may already be committed keeping what was there
+    /// so it can be put back if the transaction fails (see [`overwrite`](Self::overwrite)).
+    pub(crate) fn overwrite_bytes(&self, pointer: Pointer, bytes: &[u8]) -> Result<()> {
+        let original = self.read_bytes(pointer, bytes.len() as u64)?;
+        if original == bytes {
+            return Ok(());
+        }
+        self.charge_memory(CHANGE_MEMORY + original.len() as u64)?;
+        let mut inner = self.inner_mut();
+        {
+            let mut io = inner.io.borrow_mut();
+            io.seek_to(pointer)?;
+            io.writer().write_all(bytes)?;
+            if let Some(profile) = &mut io.profile {
+                profile.overwrites += 1;
+            }
+        }
+        inner.overwritten.push((pointer, original));
+        Ok(())
+    }
+
+    /// Copy the entry at `handle` in `list_slot` to the lowest free space it fits in and free
+    /// where it was. Moving entries towards the start of the file lets the free space at the end
+    /// be trimmed away or keeps the entries an index reads together close to each other.
+    ///
+    /// The copy points to the same next entry. If the entry is the head of the list the head is
+    /// moved to the copy but otherwise nothing points to the copy yet and it's up to the caller to
+    /// send the newer entries to it.
+    /// [`LinkedListMutApi::relocate`](crate::LinkedListMutApi::relocate) does that by recording a
+    /// remap. `handle` must cover the whole entry so it can't be one
+    /// returned by [`push_kv`](Self::push_kv).
+    pub fn relocate(&self, list_slot: ListSlot, handle: EntryHandle) -> Result<EntryHandle> {
+        self.relocate_to_lowest(
+            list_slot,
+            handle,
+            handle.entry_pointer.next_entry_possibly_stale,
+            None,
+            &BTreeSet::new(),
+        )
+    }
+
+    /// [`relocate`](Self::relocate) where the copy points to `next` and doesn't start at any of
+    /// the pointers in `skip`. If there are `value_bytes` the copy holds them instead of the
+    /// entry's value.
+    pub(crate) fn relocate_to_lowest(
+        &self,
+        list_slot: ListSlot,
+        handle: EntryHandle,
+        next: Pointer,
+        value_bytes: Option<Vec<u8>>,
+        skip: &BTreeSet<Pointer>,
+    ) -> Result<EntryHandle> {
+        let value_bytes = match value_bytes {
+            Some(value_bytes) => value_bytes,
+            None => self.read_bytes(handle.value_pointer(), handle.value_len)?,
+        };
+        let value_len = value_bytes.len() as u64;
+        let links = self.link_encoding();
+        let mut entry_bytes = vec![];
+        links.encode(next, &mut entry_bytes)?;
+        entry_bytes.extend(value_bytes);
+        let entry_len = self.padded_len(entry_bytes.len() as u64);
+        self.charge_memory(CHANGE_MEMORY)?;
+        let location = self
+            .inner
+            .borrow()
+            .free_space
+            .borrow_mut()
+            .take_lowest(entry_len, skip)
+            .ok_or(anyhow!("no more space in file"))?;
+        self.write_bytes(location, &entry_bytes)?;
+        self.free_from_list(list_slot, handle);
+        let mut inner = self.inner_mut();
+        inner.quotas.pushed(list_slot, entry_len);
+        if inner.curr_head(list_slot) == handle.entry_pointer.this_entry {
+            inner.changed_heads.insert(list_slot, location);
+        }
+        Ok(EntryHandle {
+            entry_pointer: EntryPointer {
+                this_entry: location,
+                next_entry_possibly_stale: next,
+                links,
+            },
+            value_len,
+        })
+    }
+
+    pub fn free(&self, handle: EntryHandle) {
+        let entry_len = self.padded_len(handle.entry_len());
+        let mut inner = self.inner_mut();
+        inner.memory.used += CHANGE_MEMORY;
+        inner.free_space.borrow_mut().free(Free::from_start_pointer(
+            handle.entry_pointer.this_entry,
+            entry_len,
+        ));
+        let io = inner.io.borrow();
+        if let Some(metrics) = io.metrics() {
+            metrics.free(entry_len);
+        }
+    }
+
+    /// Keep the space of the entry at `handle` from being reused until it is [`unpin`]ned even if
+    /// it is popped or freed. This makes it safe to hold on to a handle across transactions and
+    /// read it with [`read_at`] after the list has moved on. It should be pinned while the entry
+    /// is still in its list.
+    ///
+    /// Pins are only kept in memory and are undone if the transaction fails. An entry can be
+    /// pinned more than once and stays pinned until it's unpinned as many times.
+    ///
+    /// [`unpin`]: Self::unpin
+    /// [`read_at`]: Self::read_at
+    pub fn pin(&self, handle: EntryHandle) {
+        let entry_len = self.padded_len(handle.entry_len());
+        let inner = self.inner();
+        inner
+            .free_space
+            .borrow_mut()
+            .pin(handle.entry_pointer.this_entry, entry_len);
+    }
+
+    /// Release a [`pin`](Self::pin). If the entry has been freed in the meantime its space can be
+    /// reused once this transaction commits.
+    pub fn unpin(&self, handle: EntryHandle) -> Result<()> {
+        let entry_len = self.padded_len(handle.entry_len());
+        let inner = self.inner();
+        let unpinned = inner
+            .free_space
+            .borrow_mut()
+            .unpin(handle.entry_pointer.this_entry, entry_len);
+        if !unpinned {
+            return Err(anyhow!(
+                "entry at {:?} isn't pinned",
+                handle.entry_pointer.this_entry
+            ));
+        }
+        Ok(())
+    }
+
+    /// Set aside `bytes` of contiguous space for the future pushes to `list_slot` so they end up
+    /// next to each other. Pushes that don't fit in what's left of the reservation are placed as
+    /// usual. Any reservation the list already had is given up.
+    ///
+    /// The reservation lasts until it is used up or the database is closed. It isn't persisted and
+    /// the unused part is recorded as free space on disk after each transaction so nothing is lost
+    /// if the database is closed.
+    pub fn reserve(&self, list_slot: ListSlot, bytes: u64) -> Result<()> {
+        let mut inner = self.inner_mut();
+        if let Some((start, size)) = inner.reservations.remove(&list_slot) {
+            inner
+                .free_space
+                .borrow_mut()
+                .free(Free::from_start_pointer(start, size));
+        }
+        if bytes == 0 {
+            return Ok(());
+        }
+        let start = inner
+            .free_space
+            .borrow_mut()
+            .take_for_size(bytes)
+            .ok_or(anyhow!("no more space in file"))?;
+        inner.reservations.insert(list_slot, (start, bytes));
+        Ok(())
+    }
+
+    /// The unused space reserved for `list_slot` (see [`reserve`](Self::reserve))
+    pub fn reserved(&self, list_slot: ListSlot) -> u64 {
+        self.inner
+            .borrow()
+            .reservations
+            .get(&list_slot)
+            .map(|(_, size)| *size)
+            .unwrap_or(0)
+    }
+
+    /// Like [`free`](Self::free) but takes the entry off the usage of `list_slot`'s quota.
+    pub(crate) fn free_from_list(&self, list_slot: ListSlot, handle: EntryHandle) {
+        self.free(handle);
+        let entry_len = self.padded_len(handle.entry_len());
+        self.inner_mut().quotas.freed(list_slot, entry_len);
+    }
+
+    /// The quota of `list_slot` and how much of it is used if the list has one.
+    pub fn quota(&self, list_slot: ListSlot) -> Option<(ListQuota, ListUsage)> {
+        self.inner
+            .borrow()
+            .quotas
+            .lists
+            .get(&list_slot)
+            .map(|state| (state.quota, state.usage))
+    }
+
+    /// The quota of `namespace` and how much of it is used if it has one (see
+    /// [`Transaction::set_namespace_quota`]).
+    pub fn namespace_quota(&self, namespace: &str) -> Option<(ListQuota, ListUsage)> {
+        self.inner
+            .borrow()
+            .quotas
+            .namespaces
+            .get(namespace)
+            .map(|namespace| (namespace.state.quota, namespace.state.usage))
+    }
+
+    pub fn read_at<T: bincode::Decode>(&self, pointer: EntryPointer) -> Result<(EntryHandle, T)> {
+        self.inner().read_at(pointer)
+    }
+
+    pub fn raw_read_at<T: bincode::Decode>(&self, pointer: Pointer) -> Result<T> {
+        self.inner().raw_read_at(pointer)
+    }
+
+    /// Decode just the start of the entry at `pointer` as a `T`. `T` can be the first field (or
+    /// first few fields) of the list's values like a small header in front of a big body. Only the
+    /// bytes making up the `T` are read from the file.
+    pub fn read_prefix_at<T: bincode::Decode>(&self, pointer: EntryPointer) -> Result<T> {
+        self.inner().raw_read_at(pointer.value_pointer())
+    }
+
+    pub fn curr_head(&self, slot: ListSlot) -> Pointer {
+        self.inner().curr_head(slot)
+    }
+
+    /// The current time according to the database's [`Clock`]
+    pub fn now(&self) -> SystemTime {
+        self.inner().io.borrow().clock.now()
+    }
+
+    /// The generation of the last commit before this transaction (see [`LlsDb::generation`])
+    pub fn generation(&self) -> Option<u64> {
+        self.inner().io.borrow().generation()
+    }
+
+    /// Where `pointer` points in the backend (see [`LlsDb::pointer_to_offset`])
+    pub fn pointer_to_offset(&self, pointer: Pointer) -> Option<u64> {
+        self.inner().io.borrow().pointer_to_file_position(pointer)
+    }
+
+    /// What has been recorded about the list in `slot` in this transaction
+    pub(crate) fn tx_remaps(&self, slot: ListSlot) -> TxRemaps {
+        self.inner()
+            .tx_remaps
+            .get(&slot)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Remember that the entry at `handle` in the list in `slot` is `remap` until the transaction
+    /// ends replacing what was recorded for the entry before
+    pub(crate) fn record_remap(&self, slot: ListSlot, handle: EntryHandle, remap: Remap) {
+        let mut inner = self.inner_mut();
+        let unlinked = &mut inner.tx_remaps.entry(slot).or_default().unlinked;
+        unlinked.retain(|(recorded, _)| {
+            recorded.entry_pointer.this_entry != handle.entry_pointer.this_entry
+        });
+        unlinked.push((handle, remap));
+    }
+
+    /// Remember that an entry relocated in the list in `slot` now points to `next`
+    pub(crate) fn record_relocation(&self, slot: ListSlot, next: Pointer) {
+        self.inner_mut()
+            .tx_remaps
+            .entry(slot)
+            .or_default()
+            .relocated_to
+            .insert(next);
+    }
+
+    /// Whether the entry at `this_entry` has been freed in this transaction or before
+    pub(crate) fn is_released(&self, this_entry: Pointer) -> bool {
+        self.inner
+            .borrow()
+            .free_space
+            .borrow()
+            .is_released(this_entry)
+    }
+
+    pub(crate) fn read_bytes(&self, pointer: Pointer, len: u64) -> Result<Vec<u8>> {
+        let inner = self.inner();
+        let mut io = inner.io.borrow_mut();
+        let mut buf = vec![0u8; len as usize];
+        io.seek_to(pointer)?;
+        io.reader().read_exact(&mut buf)?;
+        Ok(buf)
     }
 
-    pub fn free(&self, handle: EntryHandle) {
+    /// Take `size` bytes of free space that don't belong to any list. It's up to the caller to
+    /// keep track of it and [`free_region`](Self::free_region) it.
+    pub(crate) fn allocate(&self, size: u64) -> Result<Pointer> {
         self.inner
             .borrow()
             .free_space
             .borrow_mut()
-            .free(Free::from_start_pointer(
-                handle.entry_pointer.this_entry,
-                handle.entry_len(),
-            ));
+            .take_for_size(size)
+            .ok_or(anyhow!("no more space in file"))
     }
 
-    pub fn read_at<T: bincode::Decode>(&self, pointer: EntryPointer) -> Result<(EntryHandle, T)> {
-        self.inner.borrow().read_at(pointer)
+    pub(crate) fn free_region(&self, start: Pointer, size: u64) {
+        let inner = self.inner();
+        inner
+            .free_space
+            .borrow_mut()
+            .free(Free::from_start_pointer(start, size));
+        let io = inner.io.borrow();
+        if let Some(metrics) = io.metrics() {
+            metrics.free(size);
+        }
     }
 
-    pub fn raw_read_at<T: bincode::Decode>(&self, pointer: Pointer) -> Result<T> {
-        self.inner.borrow().raw_read_at(pointer)
+    /// Write `bytes` at `pointer`. This must only be used on space from
+    /// [`allocate`](Self::allocate) that holds nothing that's been committed.
+    pub(crate) fn write_bytes(&self, pointer: Pointer, bytes: &[u8]) -> Result<()> {
+        let inner = self.inner();
+        let mut io = inner.io.borrow_mut();
+        io.seek_to(pointer)?;
+        io.writer().write_all(bytes)?;
+        Ok(())
     }
 
-    pub fn curr_head(&self, slot: ListSlot) -> Pointer {
-        self.inner.borrow().curr_head(slot)
+    /// Pointer to the end of the backend
+    pub(crate) fn end_pointer(&self) -> Result<Pointer> {
+        let inner = self.inner();
+        let mut io = inner.io.borrow_mut();
+        let end = io.file.seek(SeekFrom::End(0))?;
+        Ok(io.file_position_to_pointer(end))
     }
 }
 
 impl<'tx, F: Backend> Transaction<'tx, F> {
+    /// The reads each index has done from the backend so far in this transaction. `None` unless
+    /// read tracing is on (see [`LlsDb::set_read_tracing`]).
+    pub fn read_trace(&self) -> Option<ReadTrace> {
+        self.io.inner.borrow().io.borrow().read_trace.clone()
+    }
+
+    /// Roll the transaction back. The transaction fails with [`Aborted`] even if the query goes
+    /// on to return `Ok` so this is usually returned straight away:
+    ///
+    /// ```
+    /// # use llsdb::{Aborted, LlsDb};
+    /// # let mut db = LlsDb::init(std::io::Cursor::new(vec![])).unwrap();
+    /// let res = db.execute(|tx| {
+    ///     let list = tx.take_list::<u32>("balances")?;
+    ///     list.api(&tx).push(&10)?;
+    ///     if list.api(&tx).iter().count() > 0 {
+    ///         return tx.abort("balances must be empty");
+    ///     }
+    ///     Ok(())
+    /// });
+    /// let aborted = res.unwrap_err().downcast::<Aborted>().unwrap();
+    /// assert_eq!(aborted.reason, "balances must be empty");
+    /// ```
+    pub fn abort<T>(&self, reason: impl Into<String>) -> Result<T> {
+        let aborted = Aborted {
+            reason: reason.into(),
+        };
+        let mut slot = self.aborted.borrow_mut();
+        // the first reason is the one that counts
+        Err(slot.get_or_insert(aborted).clone().into())
+    }
+
+    /// Take the API for an index.
+    ///
+    /// # Panics
+    ///
+    /// If the handle is stale or the index has already been taken. See [`try_take_index`] for a
+    /// version that returns an error instead.
+    ///
+    /// [`try_take_index`]: Self::try_take_index
     pub fn take_index<'i, I>(&'i self, index_handle: IndexHandle<I>) -> I::Api<'i, F>
     where
         I: IndexStore,
     {
-        let dyn_store = &self.indexers[index_handle.id];
-        let as_any = dyn_store.as_any();
-        let store = as_any
-            .downcast_ref::<RefCell<I>>()
-            .expect("invalid index_handle passed in");
+        match self.try_take_index(index_handle) {
+            Ok(api) => api,
+            Err(e) => panic!("{}", e),
+        }
+    }
 
-        let store = store
-            .try_borrow_mut()
-            .expect("index can only be taken once");
+    /// Take the API for an index. Errors if the handle is stale (e.g. it was stored in a
+    /// transaction that failed) or the index has already been taken in this transaction.
+    pub fn try_take_index<'i, I>(&'i self, index_handle: IndexHandle<I>) -> Result<I::Api<'i, F>>
+    where
+        I: IndexStore,
+    {
+        let store = self.index_store(index_handle)?;
+        let io: TxIo<'i, F> = self.io.for_index(index_handle.id);
+        Ok(I::create_api(store, io))
+    }
 
-        let io: TxIo<'i, F> = self.io.clone();
+    /// Throw away the in-memory state of the index and rebuild it from its lists. See
+    /// [`IndexStore::rebuild`].
+    pub fn rebuild_index<I>(&self, index_handle: IndexHandle<I>) -> Result<()>
+    where
+        I: IndexStore,
+    {
+        self.index_store(index_handle)?
+            .rebuild(&self.io.for_index(index_handle.id))
+    }
 
-        let api = I::create_api(store, io);
+    fn index_store<I>(&self, index_handle: IndexHandle<I>) -> Result<RefMut<'_, I>>
+    where
+        I: IndexStore,
+    {
+        let store = self
+            .indexers
+            .get(&index_handle.id)
+            .and_then(|dyn_store| dyn_store.as_any().downcast_ref::<RefCell<I>>())
+            .ok_or(anyhow!("invalid index_handle passed in"))?;
 
-        api
+        store
+            .try_borrow_mut()
+            .map_err(|_| anyhow!("index can only be taken once"))
     }
 
+    /// Store an index so its API can be taken with [`take_index`](Self::take_index) in this and
+    /// later transactions.
+    ///
+    /// # Panics
+    ///
+    /// If the index's [`IndexStore::on_attach`] fails. See [`try_store_index`] for a version that
+    /// returns the error instead.
+    ///
+    /// [`try_store_index`]: Self::try_store_index
     pub fn store_index<I>(&mut self, index: I) -> IndexHandle<I>
     where
         I: IndexStore,
     {
+        match self.try_store_index(index) {
+            Ok(handle) => handle,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Store an index after calling its [`IndexStore::on_attach`] returning the error from it if
+    /// it fails
+    pub fn try_store_index<I>(&mut self, mut index: I) -> Result<IndexHandle<I>>
+    where
+        I: IndexStore,
+    {
+        index.on_attach(self)?;
         let index = RefCell::new(index);
-        self.indexers.push(Box::new(index));
-        IndexHandle {
-            id: self.indexers.len() - 1,
+        let id = *self.next_index_id;
+        *self.next_index_id += 1;
+        self.indexers.insert(id, Box::new(index));
+        Ok(IndexHandle {
+            id,
             index_ty: PhantomData,
-        }
+        })
     }
 
     pub fn store_and_take_index<'i, I>(&'i mut self, index: I) -> (IndexHandle<I>, I::Api<'i, F>)
@@ -717,11 +3925,170 @@ impl<'tx, F: Backend> Transaction<'tx, F> {
         (handle, api)
     }
 
+    fn lookup_meta(&self, list_name: &str) -> Option<&Meta> {
+        self.tx_slots_by_name.get(list_name).or_else(|| {
+            self.slots_by_name
+                .get(list_name)
+                .filter(|_| !self.tx_removed_names.contains(list_name))
+        })
+    }
+
+    /// Change the name of a list from `old` to `new`. This rewrites the meta list so it takes
+    /// effect atomically with the rest of the transaction. Any [`LinkedList`] already taken for the
+    /// list keeps working.
+    ///
+    /// It is an error if `new` is already taken. To replace a list with a new version (e.g. one
+    /// built under `"list_v2"`) rename the existing list out of the way first in the same
+    /// transaction.
+    pub fn rename_list(&mut self, old: &str, new: &str) -> Result<()> {
+        let meta = self
+            .lookup_meta(old)
+            .ok_or(anyhow!("no such list '{}'", old))?
+            .clone();
+        if self.lookup_meta(new).is_some() {
+            return Err(anyhow!("there is already a list called '{}'", new));
+        }
+
+        self.rewrite_meta_list(|existing| {
+            if existing.slot == meta.slot {
+                existing.name = new.into();
+            }
+        })?;
+
+        if self.tx_slots_by_name.remove(old).is_none() {
+            self.tx_removed_names.insert(old.into());
+        }
+        self.tx_slots_by_name.insert(
+            new.into(),
+            Meta {
+                name: new.into(),
+                ..meta
+            },
+        );
+        Ok(())
+    }
+
+    /// Exchange the names of lists `a` and `b` so each name refers to what the other one did. Like
+    /// [`rename_list`](Self::rename_list) it takes effect atomically with the rest of the
+    /// transaction so there's no point where either name is missing or empty. This is how to put
+    /// a rebuilt version of a list in place of the old one. Any [`LinkedList`] already taken stays
+    /// with the entries it was taken for (and so ends up under the other name).
+    pub fn swap_lists(&mut self, a: &str, b: &str) -> Result<()> {
+        let meta_a = self
+            .lookup_meta(a)
+            .ok_or(anyhow!("no such list '{}'", a))?
+            .clone();
+        let meta_b = self
+            .lookup_meta(b)
+            .ok_or(anyhow!("no such list '{}'", b))?
+            .clone();
+        let (slot_a, slot_b) = (meta_a.slot, meta_b.slot);
+        if slot_a == slot_b {
+            return Ok(());
+        }
+
+        self.rewrite_meta_list(|existing| {
+            if existing.slot == slot_a {
+                existing.name = b.into();
+            } else if existing.slot == slot_b {
+                existing.name = a.into();
+            }
+        })?;
+
+        for (name, meta) in [(a, meta_b), (b, meta_a)] {
+            self.tx_slots_by_name.insert(
+                name.into(),
+                Meta {
+                    name: name.into(),
+                    ..meta
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Rewrite every entry of the meta list with `change` applied to it keeping their order
+    fn rewrite_meta_list(&mut self, mut change: impl FnMut(&mut Meta)) -> Result<()> {
+        let mut metas = vec![];
+        while let Some(meta) = self.pop_meta()? {
+            metas.push(meta);
+        }
+        for mut existing in metas.into_iter().rev() {
+            change(&mut existing);
+            self.push_meta(&existing)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the entries of the meta list have quotas (see
+    /// [`VersionedConfig::FEATURE_LIST_QUOTAS`])
+    fn list_quotas_in_meta(&self) -> bool {
+        self.tx_list_quotas || self.io.inner.borrow().io.borrow().list_quotas
+    }
+
+    fn push_meta(&self, meta: &Meta) -> Result<()> {
+        if self.list_quotas_in_meta() {
+            self.io.push(META_LIST.slot(), meta)?;
+        } else {
+            self.io
+                .push(META_LIST.slot(), &LegacyMeta::from(meta.clone()))?;
+        }
+        Ok(())
+    }
+
+    fn pop_meta(&self) -> Result<Option<Meta>> {
+        if self.list_quotas_in_meta() {
+            self.io.pop::<Meta>(META_LIST.slot())
+        } else {
+            Ok(self.io.pop::<LegacyMeta>(META_LIST.slot())?.map(Meta::from))
+        }
+    }
+
+    /// Rewrite the meta list so its entries have quotas if they don't already. The first page
+    /// says they do once the transaction commits.
+    fn keep_list_quotas(&mut self) -> Result<()> {
+        if self.list_quotas_in_meta() {
+            return Ok(());
+        }
+        if self.io.inner.borrow().io.borrow().id.is_none() {
+            return Err(anyhow!(
+                "list quotas need format version 4 (see LlsDb::upgrade_format)"
+            ));
+        }
+        let mut metas = vec![];
+        while let Some(meta) = self.pop_meta()? {
+            metas.push(meta);
+        }
+        self.tx_list_quotas = true;
+        for meta in metas.into_iter().rev() {
+            self.push_meta(&meta)?;
+        }
+        Ok(())
+    }
+
+    /// Take the list called `list_name` creating it if it doesn't exist. Errors if it has already
+    /// been taken or has a quota (take those with
+    /// [`take_list_with_quota`](Self::take_list_with_quota)).
     pub fn take_list<T>(&mut self, list_name: &str) -> Result<LinkedList<T>> {
-        let lookup_slot = self
-            .slots_by_name
-            .get(list_name)
-            .or_else(|| self.tx_slots_by_name.get(list_name));
+        if self
+            .lookup_meta(list_name)
+            .is_some_and(|meta| meta.quota.is_some())
+        {
+            return Err(anyhow!(
+                "list '{}' has a quota so it has to be taken with take_list_with_quota",
+                list_name
+            ));
+        }
+        self.take_list_with_meta(list_name, None)
+    }
+
+    /// Take the list creating it with `quota` in its [`Meta`] if it doesn't exist
+    fn take_list_with_meta<T>(
+        &mut self,
+        list_name: &str,
+        quota: Option<ListQuota>,
+    ) -> Result<LinkedList<T>> {
+        let lookup_slot = self.lookup_meta(list_name);
         let slot = match lookup_slot {
             Some(meta) => meta.slot,
             None => {
@@ -729,9 +4096,15 @@ impl<'tx, F: Backend> Transaction<'tx, F> {
                     let meta = Meta {
                         name: list_name.into(),
                         slot: new_slot,
+                        quota,
                     };
-                    self.io.push(META_LIST.slot(), &meta)?;
+                    self.push_meta(&meta)?;
                     self.tx_slots_by_name.insert(list_name.into(), meta);
+                    self.io
+                        .inner
+                        .borrow_mut()
+                        .quotas
+                        .list_created(new_slot, list_name);
                     new_slot
                 } else {
                     return Err(anyhow!("no more slots available"));
@@ -749,9 +4122,320 @@ impl<'tx, F: Backend> Transaction<'tx, F> {
         Ok(LinkedList::new(slot))
     }
 
+    /// Like [`take_list`](Self::take_list) but limits how many entries and bytes the list can hold.
+    /// Pushes that would go over the quota fail with [`QuotaExceeded`].
+    ///
+    /// The quota is stored with the list (see [`VersionedConfig::FEATURE_LIST_QUOTAS`]) so from
+    /// then on the list can only be taken with this which replaces the quota if it's different. A
+    /// quota without any limits removes it. The usage is found by walking the list and counting
+    /// each value and the bytes of its entry. Use
+    /// [`take_list_mut_with_quota`](Self::take_list_mut_with_quota) for a
+    /// [`LinkedListMut`](crate::LinkedListMut).
+    pub fn take_list_with_quota<T: bincode::Encode + bincode::Decode>(
+        &mut self,
+        list_name: &str,
+        quota: ListQuota,
+    ) -> Result<LinkedList<T>> {
+        let list = self.take_list_setting_quota::<T>(list_name, quota)?;
+        let usage = self.list_usage::<T>(list.slot())?;
+        self.set_list_quota(list.slot(), quota, usage, false);
+        Ok(list)
+    }
+
+    /// [`take_list_with_quota`](Self::take_list_with_quota) for a
+    /// [`LinkedListMut`](crate::LinkedListMut). Only the values it holds count as entries but the
+    /// remap entries written when values are unlinked from its middle take up bytes of the quota
+    /// (without ever being refused by it).
+    pub fn take_list_mut_with_quota<T>(
+        &mut self,
+        list_name: &str,
+        quota: ListQuota,
+    ) -> Result<LinkedListMut<T>> {
+        let list = self.take_list_setting_quota::<Mut<T>>(list_name, quota)?;
+        let usage = self.mut_list_usage(list.slot())?;
+        self.set_list_quota(list.slot(), quota, usage, true);
+        Ok(LinkedListMut(list))
+    }
+
+    /// Walk the list in `list_slot` counting each value and the bytes of its entry
+    fn list_usage<T: bincode::Encode + bincode::Decode>(
+        &self,
+        list_slot: ListSlot,
+    ) -> Result<ListUsage> {
+        let mut usage = ListUsage::default();
+        let mut it = self.io.iter(list_slot);
+        while let Some(res) = it.next_with_handle::<T>() {
+            let (handle, _) = res?;
+            usage.entries += 1;
+            usage.bytes += self.io.padded_len(handle.entry_len());
+        }
+        Ok(usage)
+    }
+
+    /// [`list_usage`](Self::list_usage) for a [`LinkedListMut`](crate::LinkedListMut). Remaps are
+    /// followed and their entries only count as bytes.
+    fn mut_list_usage(&self, list_slot: ListSlot) -> Result<ListUsage> {
+        let mut usage = ListUsage::default();
+        let mut it = self.io.iter(list_slot);
+        while let Some(res) = it.next_with_handle::<MutNoValue>() {
+            let (handle, value) = res?;
+            usage.bytes += self.io.padded_len(handle.entry_len());
+            match value {
+                MutNoValue::Add => usage.entries += 1,
+                MutNoValue::Remove(remap) => it.remap(remap),
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Take the list after storing `quota` in its [`Meta`]
+    fn take_list_setting_quota<T>(
+        &mut self,
+        list_name: &str,
+        quota: ListQuota,
+    ) -> Result<LinkedList<T>> {
+        let quota = Some(quota).filter(|quota| *quota != ListQuota::default());
+        match self.lookup_meta(list_name).cloned() {
+            Some(meta) if meta.quota == quota => {}
+            Some(meta) => {
+                self.keep_list_quotas()?;
+                self.rewrite_meta_list(|existing| {
+                    if existing.slot == meta.slot {
+                        existing.quota = quota;
+                    }
+                })?;
+                if self.tx_slots_by_name.remove(list_name).is_none() {
+                    self.tx_removed_names.insert(list_name.into());
+                }
+                self.tx_slots_by_name
+                    .insert(list_name.into(), Meta { quota, ..meta });
+            }
+            None if quota.is_some() => self.keep_list_quotas()?,
+            None => {}
+        }
+        self.take_list_with_meta(list_name, quota)
+    }
+
+    fn set_list_quota(
+        &mut self,
+        list_slot: ListSlot,
+        quota: ListQuota,
+        usage: ListUsage,
+        mut_list: bool,
+    ) {
+        let quotas = &mut self.io.inner.borrow_mut().quotas;
+        quotas.lists.remove(&list_slot);
+        quotas.mut_lists.remove(&list_slot);
+        if quota != ListQuota::default() {
+            quotas.lists.insert(list_slot, QuotaState { quota, usage });
+            if mut_list {
+                quotas.mut_lists.insert(list_slot);
+            }
+        }
+    }
+
+    /// Dump the raw entries of every list in the database (including the meta list which has no
+    /// name).
+    ///
+    /// Since the length of values is not stored on disk, each entry's bytes run until the start of
+    /// the next entry or free space. Lists that have had entries unlinked from their middle (e.g.
+    /// through [`LinkedListMut`](crate::LinkedListMut)) link through stale pointers that can't be
+    /// resolved without knowing the type of the entries so the dump of those lists stops when it
+    /// reaches one pointing to free space or into an entry of another list. Where a stale pointer
+    /// leads to an entry that another list reaches too the entry goes to whichever list reaches it
+    /// in the fewest steps from its head.
+    pub fn dump(&self) -> Result<impl Iterator<Item = Result<DumpEntry>> + '_> {
+        let names = self.list_names();
+        Ok(self
+            .entry_extents()?
+            .into_iter()
+            .map(move |(slot, entry_pointer, entry_end)| {
+                let start = entry_pointer.this_entry;
+                Ok(DumpEntry {
+                    list_name: names.get(&slot).map(|name| name.to_string()),
+                    slot,
+                    entry_pointer,
+                    bytes: self.io.read_bytes(start, entry_end.0 - start.0)?,
+                })
+            }))
+    }
+
+    /// The names of the lists as of this point in the transaction
+    fn list_names(&self) -> HashMap<ListSlot, &str> {
+        self.slots_by_name
+            .values()
+            .filter(|meta| !self.tx_removed_names.contains(&meta.name))
+            .chain(self.tx_slots_by_name.values())
+            .map(|meta| (meta.slot, meta.name.as_str()))
+            .collect()
+    }
+
+    /// Every entry reachable from a list along with where its bytes end (see [`dump`](Self::dump))
+    fn entry_extents(&self) -> Result<Vec<(ListSlot, EntryPointer, Pointer)>> {
+        let mut entries = vec![];
+        let mut boundaries = BTreeSet::new();
+        {
+            let inner = self.io.inner.borrow();
+            let free_space = inner.free_space.borrow();
+            for (start, _) in free_space.free_regions() {
+                boundaries.insert(start);
+            }
+            if let Some(overflow) = &inner.io.borrow().header_overflow {
+                boundaries.insert(overflow.location);
+            }
+            for (start, _) in inner.reservations.values() {
+                boundaries.insert(*start);
+            }
+
+            let mut walks = vec![];
+            for &slot in self.used_slots.iter().chain(&self.tx_used_slots) {
+                let mut it = self.io.iter(slot);
+                // stale pointers into reused space can lead back to where we've been or partway
+                // into an entry that doesn't decode
+                let mut visited = HashSet::new();
+                let mut walk = vec![];
+                while let Some(entry_pointer) = it.next_pointer() {
+                    let entry_pointer = match entry_pointer {
+                        Ok(entry_pointer) => entry_pointer,
+                        Err(_) if !visited.is_empty() => break,
+                        Err(e) => return Err(e),
+                    };
+                    if !visited.insert(entry_pointer.this_entry) {
+                        break;
+                    }
+                    walk.push(entry_pointer);
+                    if free_space.is_free(entry_pointer.next_entry_possibly_stale) {
+                        break;
+                    }
+                }
+                walks.push((slot, walk));
+            }
+
+            // A stale pointer into space another list has reused leads into that list's entries.
+            // Each entry is owned by the list that reaches it in the fewest steps from its head
+            // (a list's head is never stale) and a walk stops at an entry it doesn't own.
+            let mut owners = HashMap::<Pointer, (usize, ListSlot)>::new();
+            for (slot, walk) in &walks {
+                for (depth, entry_pointer) in walk.iter().enumerate() {
+                    let owner = owners
+                        .entry(entry_pointer.this_entry)
+                        .or_insert((depth, *slot));
+                    *owner = (*owner).min((depth, *slot));
+                }
+            }
+            for (slot, walk) in walks {
+                for entry_pointer in walk {
+                    if owners[&entry_pointer.this_entry].1 != slot {
+                        break;
+                    }
+                    boundaries.insert(entry_pointer.this_entry);
+                    entries.push((slot, entry_pointer));
+                }
+            }
+        }
+        let end = self.io.end_pointer()?;
+
+        Ok(entries
+            .into_iter()
+            .map(|(slot, entry_pointer)| {
+                let entry_end = boundaries
+                    .range(Pointer(entry_pointer.this_entry.0 + 1)..)
+                    .next()
+                    .copied()
+                    .unwrap_or(end)
+                    .min(end);
+                (slot, entry_pointer, entry_end)
+            })
+            .collect())
+    }
+
+    /// Limit how many entries and bytes the lists in `namespace` can hold between them from now on.
+    /// A list is in a namespace if its name starts with the namespace followed by a `/` so
+    /// `"tenant1/orders"` and `"tenant1/eu/orders"` are both in `"tenant1"`. Lists created in the
+    /// namespace later count towards it too.
+    ///
+    /// Pushes that would go over the quota fail with [`QuotaExceeded`] with its `namespace` set.
+    /// Unlike the quota of a list it is only kept in memory so it has to be set again each time the
+    /// database is loaded. The starting usage is found by walking the lists the way
+    /// [`dump`](Self::dump) does so every entry counts (including remaps) and lists with entries
+    /// unlinked from their middle are undercounted. Renaming a list in or out of the namespace
+    /// doesn't move its usage until the quota is set again.
+    pub fn set_namespace_quota(&mut self, namespace: &str, quota: ListQuota) -> Result<()> {
+        let slots = self
+            .list_names()
+            .into_iter()
+            .filter(|(_, name)| in_namespace(name, namespace))
+            .map(|(slot, _)| slot)
+            .collect::<BTreeSet<_>>();
+        let usage = self.usage_of(&slots)?;
+        self.io.inner.borrow_mut().quotas.namespaces.insert(
+            namespace.into(),
+            NamespaceQuota {
+                state: QuotaState { quota, usage },
+                slots,
+            },
+        );
+        Ok(())
+    }
+
+    /// How many entries and bytes the lists in `slots` use between them
+    fn usage_of(&self, slots: &BTreeSet<ListSlot>) -> Result<ListUsage> {
+        let mut usage = ListUsage::default();
+        for (slot, entry_pointer, entry_end) in self.entry_extents()? {
+            if slots.contains(&slot) {
+                usage.entries += 1;
+                usage.bytes += entry_end.0 - entry_pointer.this_entry.0;
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Set the quotas in `quotas` again counting their usage from scratch. Quotas of lists that no
+    /// longer exist or no longer have a quota in their [`Meta`] are dropped.
+    fn recount_quotas(&mut self, quotas: &Quotas) -> Result<()> {
+        let names = self.list_names();
+        let mut recounted = Quotas::default();
+        for &slot in quotas.lists.keys() {
+            let Some(quota) = names
+                .get(&slot)
+                .and_then(|name| self.lookup_meta(name))
+                .and_then(|meta| meta.quota)
+            else {
+                continue;
+            };
+            let usage = if quotas.mut_lists.contains(&slot) {
+                recounted.mut_lists.insert(slot);
+                self.mut_list_usage(slot)?
+            } else {
+                self.usage_of(&BTreeSet::from([slot]))?
+            };
+            recounted.lists.insert(slot, QuotaState { quota, usage });
+        }
+        for (namespace, quota) in &quotas.namespaces {
+            let slots = names
+                .iter()
+                .filter(|(_, name)| in_namespace(name, namespace))
+                .map(|(&slot, _)| slot)
+                .collect::<BTreeSet<_>>();
+            let usage = self.usage_of(&slots)?;
+            recounted.namespaces.insert(
+                namespace.clone(),
+                NamespaceQuota {
+                    state: QuotaState {
+                        quota: quota.state.quota,
+                        usage,
+                    },
+                    slots,
+                },
+            );
+        }
+        self.io.inner.borrow_mut().quotas = recounted;
+        Ok(())
+    }
+
     fn reserve_next_slot(&mut self) -> Option<ListSlot> {
         let inner = self.io.inner.borrow();
-        let n_list_slots = inner.io.borrow().n_list_slots;
+        let n_list_slots = inner.io.borrow().n_list_slots();
         for slot in 0..n_list_slots {
             if self.used_slots.contains(&slot) || !self.tx_used_slots.insert(slot) {
                 continue;
@@ -771,12 +4455,51 @@ impl<'tx, F> AsRef<TxIo<'tx, F>> for Transaction<'tx, F> {
 
 pub struct EntryIter<'tx, F> {
     io: Rc<RefCell<Io<F>>>,
+    slot: ListSlot,
     remap: HashMap<Pointer, Pointer>,
-    reverse_remap: HashMap<Pointer, Pointer>,
+    /// Every pointer remapped to each entry
+    reverse_remap: HashMap<Pointer, Vec<Pointer>>,
+    /// The pointer to the next entry as it was read. It's only mapped to current when we follow
+    /// it so that if the entry we just read was a remap it applies to its own pointer too.
     curr: Pointer,
+    /// The index iterating if any (see [`ReadTrace`])
+    index: Option<usize>,
+    strict: Option<StrictCheck>,
     lifetime: PhantomData<&'tx ()>,
 }
 
+/// What an [`EntryIter`] needs to check remaps (see [`LlsDb::set_strict_remaps`])
+struct StrictCheck {
+    free_space: Rc<RefCell<FreeSpace>>,
+    visited: HashSet<Pointer>,
+}
+
+impl StrictCheck {
+    /// Check moving on to `to` from the pointer `from` read from the previous entry
+    fn check(&mut self, slot: ListSlot, from: Pointer, to: Pointer) -> Result<()> {
+        if to == Pointer::NULL {
+            return Ok(());
+        }
+        if from != to && self.free_space.borrow().is_released(to) {
+            return Err(anyhow!(
+                "list {} is remapped from {:?} to {:?} which has been freed",
+                slot,
+                from,
+                to
+            ));
+        }
+        if !self.visited.insert(to) {
+            return Err(anyhow!(
+                "list {} goes back to the entry at {:?} (through {:?})",
+                slot,
+                to,
+                from
+            ));
+        }
+        Ok(())
+    }
+}
+
 impl<'tx, F: Backend> EntryIter<'tx, F> {
     pub fn into_pointer_iter(mut self) -> impl Iterator<Item = Result<EntryPointer>> + 'tx
     where
@@ -785,6 +4508,27 @@ impl<'tx, F: Backend> EntryIter<'tx, F> {
         core::iter::from_fn(move || self.next_pointer())
     }
 
+    /// Captures the current position of the iterator so it can be resumed later with
+    /// [`TxIo::iter_from_cursor`].
+    pub fn cursor(&self) -> ListCursor {
+        let mut it = Self {
+            io: self.io.clone(),
+            slot: self.slot,
+            remap: self.remap.clone(),
+            reverse_remap: self.reverse_remap.clone(),
+            curr: self.curr,
+            index: self.index,
+            strict: None,
+            lifetime: PhantomData,
+        };
+        it.follow();
+        ListCursor {
+            slot: it.slot,
+            curr: it.curr,
+            remap: it.remap,
+        }
+    }
+
     pub fn next<T: bincode::Encode + bincode::Decode>(&mut self) -> Option<Result<T>> {
         self.next_with_handle()
             .map(|res| res.map(|(_, value)| value))
@@ -797,49 +4541,92 @@ impl<'tx, F: Backend> EntryIter<'tx, F> {
             .unwrap_or(entry_pointer)
     }
 
+    /// Where the next entry will be read from with the remaps seen so far applied
+    pub(crate) fn next_entry(&mut self) -> Pointer {
+        self.follow();
+        self.curr
+    }
+
+    /// Map the pointer to the next entry to current.
+    ///
+    /// The entries remapped to where we end up were all between us and it so they are now behind
+    /// us. Their space may have been reused by entries further along the list so their remaps
+    /// mustn't apply to anything we read from now on.
+    fn follow(&mut self) {
+        self.curr = self.map_to_current(self.curr);
+        for passed in self.reverse_remap.remove(&self.curr).unwrap_or_default() {
+            self.remap.remove(&passed);
+        }
+    }
+
+    /// [`follow`](Self::follow) checking where it leads if remaps are being checked
+    fn advance(&mut self) -> Result<()> {
+        let from = self.curr;
+        self.follow();
+        match &mut self.strict {
+            Some(strict) => strict.check(self.slot, from, self.curr),
+            None => Ok(()),
+        }
+    }
+
     /// Pointer to the next value
     pub fn next_pointer(&mut self) -> Option<Result<EntryPointer>> {
+        if let Err(e) = self.advance() {
+            return Some(Err(e));
+        }
         (|| {
             let mut io = self.io.borrow_mut();
             if self.curr == Pointer::NULL {
                 return Ok(None);
             }
+            io.reading_for = self.index;
             let this_entry = self.curr;
             io.seek_to(this_entry)?;
-            let next_entry_possibly_stale: Pointer =
-                bincode::decode_from_std_read(io.reader(), BINCODE_CONFIG)?;
-            drop(io);
-            self.curr = self.map_to_current(next_entry_possibly_stale);
+            let links = io.link_encoding;
+            let next_entry_possibly_stale = links.decode(&mut io.reader())?;
+            self.curr = next_entry_possibly_stale;
             Ok(Some(EntryPointer {
                 this_entry,
                 next_entry_possibly_stale,
+                links,
             }))
         })()
         .transpose()
     }
 
-    pub(crate) fn next_with_handle<T: bincode::Encode + bincode::Decode>(
+    /// Like [`next`](Self::next) but also returns the [`EntryHandle`] of the entry so it can be
+    /// read again with [`TxIo::read_at`] without walking the list.
+    pub fn next_with_handle<T: bincode::Encode + bincode::Decode>(
         &mut self,
     ) -> Option<Result<(EntryHandle, T)>> {
+        if let Err(e) = self.advance() {
+            return Some(Err(e));
+        }
         (|| {
             let mut io = self.io.borrow_mut();
             if self.curr == Pointer::NULL {
                 return Ok(None);
             }
+            io.reading_for = self.index;
             let this_entry = self.curr;
             io.seek_to(self.curr)?;
-            let next_entry_possibly_stale: Pointer =
-                bincode::decode_from_std_read(io.reader(), BINCODE_CONFIG)?;
-            self.curr = self.map_to_current(next_entry_possibly_stale);
+            let links = io.link_encoding;
+            let next_entry_possibly_stale = links.decode(&mut io.reader())?;
+            self.curr = next_entry_possibly_stale;
             let value_start = io.current_position()?;
-            let value: T = bincode::decode_from_std_read(io.reader(), BINCODE_CONFIG)?;
+            let value: T = io.value_encoding.decode_from_std_read(&mut io.reader())?;
             let value_end = io.current_position()?;
             let len = value_end.0 - value_start.0;
+            if let Some(profile) = io.list_profile(self.slot) {
+                profile.reads += 1;
+                profile.value_sizes.record(len);
+            }
             Ok(Some((
                 EntryHandle {
                     entry_pointer: EntryPointer {
                         this_entry,
                         next_entry_possibly_stale,
+                        links,
                     },
                     value_len: len,
                 },
@@ -854,20 +4641,81 @@ impl<'tx, F: Backend> EntryIter<'tx, F> {
         let to = self.map_to_current(to);
 
         // anything pointing to `from` must now point to `to`
-        if let Some(prev_from) = self.reverse_remap.remove(&from) {
-            self.remap.insert(prev_from, to);
-            self.reverse_remap.insert(to, prev_from);
+        let mut froms = self.reverse_remap.remove(&from).unwrap_or_default();
+        for prev_from in &froms {
+            self.remap.insert(*prev_from, to);
         }
-
         self.remap.insert(from, to);
-        self.reverse_remap.insert(to, from);
+        froms.push(from);
+        self.reverse_remap.entry(to).or_default().extend(froms);
+    }
+}
+
+/// The position of an [`EntryIter`] within a list.
+///
+/// A `ListCursor` can be encoded and persisted so that iteration over a large list can be resumed
+/// in a later transaction (or after the database is reloaded).
+#[derive(Clone, Debug, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct ListCursor {
+    slot: ListSlot,
+    curr: Pointer,
+    remap: HashMap<Pointer, Pointer>,
+}
+
+impl ListCursor {
+    pub fn slot(&self) -> ListSlot {
+        self.slot
     }
+
+    /// Whether the iterator this cursor was taken from had reached the end of the list.
+    pub fn is_finished(&self) -> bool {
+        self.curr == Pointer::NULL
+    }
+}
+
+/// A raw entry returned from [`Transaction::dump`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpEntry {
+    /// The name of the list the entry belongs to (`None` for the meta list)
+    pub list_name: Option<String>,
+    pub slot: ListSlot,
+    pub entry_pointer: EntryPointer,
+    /// The bytes of the entry including the encoded pointer to the next entry
+    pub bytes: Vec<u8>,
 }
 
 #[derive(Clone, Debug, bincode::Encode, bincode::Decode)]
 pub struct Meta {
     pub name: String,
     pub slot: ListSlot,
+    /// Only encoded if the database has [`VersionedConfig::FEATURE_LIST_QUOTAS`]
+    pub quota: Option<ListQuota>,
+}
+
+/// How a [`Meta`] is encoded without [`VersionedConfig::FEATURE_LIST_QUOTAS`]
+#[derive(bincode::Encode, bincode::Decode)]
+struct LegacyMeta {
+    name: String,
+    slot: ListSlot,
+}
+
+impl From<Meta> for LegacyMeta {
+    fn from(meta: Meta) -> Self {
+        Self {
+            name: meta.name,
+            slot: meta.slot,
+        }
+    }
+}
+
+impl From<LegacyMeta> for Meta {
+    fn from(meta: LegacyMeta) -> Self {
+        Self {
+            name: meta.name,
+            slot: meta.slot,
+            quota: None,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -876,6 +4724,12 @@ pub struct IndexHandle<I> {
     index_ty: PhantomData<I>,
 }
 
+impl<I> IndexHandle<I> {
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+}
+
 impl<I> Clone for IndexHandle<I> {
     fn clone(&self) -> Self {
         Self {
@@ -886,3 +4740,10 @@ impl<I> Clone for IndexHandle<I> {
 }
 
 impl<I> Copy for IndexHandle<I> {}
+
+impl<I: IndexStore> IndexHandle<I> {
+    /// Throw away the in-memory state of the index and rebuild it from its lists.
+    pub fn rebuild<F: Backend>(&self, tx: &Transaction<'_, F>) -> Result<()> {
+        tx.rebuild_index(*self)
+    }
+}
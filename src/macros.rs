@@ -13,3 +13,212 @@ macro_rules! read_ints {
         }),*)
     }}
 }
+
+/// Declare all the lists a database uses along with their names and types in one place.
+///
+/// This generates a struct with a [`LinkedList`](crate::LinkedList) field for each list and a
+/// `take` constructor that takes them all in one transaction. Two lists with the same name is a
+/// compile error.
+///
+/// ```
+/// use llsdb::LlsDb;
+///
+/// llsdb::schema! {
+///     /// The lists of my app
+///     pub struct Schema {
+///         pub users: String = "users",
+///         pub events: (u64, String) = "events",
+///     }
+/// }
+///
+/// let mut db = LlsDb::init(std::io::Cursor::new(vec![])).unwrap();
+/// let schema = db.execute(|tx| Schema::take(tx)).unwrap();
+/// assert_eq!(Schema::LIST_NAMES, ["users", "events"]);
+/// ```
+///
+/// ```compile_fail
+/// llsdb::schema! {
+///     struct Clash {
+///         a: u32 = "list",
+///         b: u64 = "list",
+///     }
+/// }
+/// # fn main() { let mut db = llsdb::LlsDb::init(std::io::Cursor::new(vec![])).unwrap();
+/// # db.execute(|tx| Clash::take(tx)).unwrap(); }
+/// ```
+#[macro_export]
+macro_rules! schema {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident : $type:ty = $list_name:literal
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $crate::LinkedList<$type>,)*
+        }
+
+        impl $name {
+            /// The names of the lists in the order they were declared
+            pub const LIST_NAMES: &'static [&'static str] = &[$($list_name),*];
+
+            const _NAMES_ARE_DISTINCT: () = assert!(
+                $crate::macros::names_are_distinct(Self::LIST_NAMES),
+                concat!("two lists in ", stringify!($name), " have the same name")
+            );
+
+            /// Take all the lists in the schema
+            pub fn take<F: $crate::Backend>(
+                tx: &mut $crate::Transaction<'_, F>,
+            ) -> $crate::Result<Self> {
+                #[allow(clippy::let_unit_value)]
+                let _ = Self::_NAMES_ARE_DISTINCT;
+                Ok(Self {
+                    $($field: tx.take_list($list_name)?,)*
+                })
+            }
+        }
+    };
+}
+
+/// Declare the typed tables of a database (maps, vecs and cells) in one place.
+///
+/// Each table is an index from [`index`](crate::index) implementing [`Table`] kept in its own list.
+/// This generates a struct holding the [`IndexHandle`](crate::IndexHandle) of each table with a
+/// `take` constructor that takes their lists and stores their indexes in one transaction. Its
+/// `api` method takes all of them in a transaction returning the second struct which has a field
+/// with the API of each table. Like [`schema!`] two tables with the same name is a compile error.
+///
+/// The handles are only valid once the transaction that took them has committed.
+///
+/// [`Table`]: crate::index::Table
+///
+/// ```
+/// use llsdb::{index, LlsDb};
+///
+/// llsdb::tables! {
+///     /// The tables of my app
+///     pub struct Tables {
+///         pub users: index::BTreeMap<u64, String> = "users",
+///         pub events: index::Vec<String> = "events",
+///         pub n_logins: index::Cell<u64> = "n_logins",
+///     }
+///     /// The tables of my app in a transaction
+///     pub struct TablesApi;
+/// }
+///
+/// let mut db = LlsDb::init(std::io::Cursor::new(vec![])).unwrap();
+/// let tables = db.execute(|tx| Tables::take(tx)).unwrap();
+/// db.execute(|tx| {
+///     let mut t = tables.api(tx);
+///     t.users.insert(1, &"alice".to_string())?;
+///     t.events.push(&"alice logged in".to_string())?;
+///     t.n_logins.replace(&1)?;
+///     Ok(())
+/// })
+/// .unwrap();
+/// ```
+#[macro_export]
+macro_rules! tables {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident : $type:ty = $list_name:literal
+            ),* $(,)?
+        }
+        $(#[$api_meta:meta])*
+        $api_vis:vis struct $api:ident;
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy)]
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $crate::IndexHandle<$type>,)*
+        }
+
+        $(#[$api_meta])*
+        $api_vis struct $api<'i, F> {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: <$type as $crate::index::IndexStore>::Api<'i, F>,
+            )*
+        }
+
+        impl $name {
+            /// The names of the lists the tables are kept in in the order they were declared
+            pub const LIST_NAMES: &'static [&'static str] = &[$($list_name),*];
+
+            const _NAMES_ARE_DISTINCT: () = assert!(
+                $crate::macros::names_are_distinct(Self::LIST_NAMES),
+                concat!("two tables in ", stringify!($name), " have the same name")
+            );
+
+            /// Take the list of each table and store its index
+            pub fn take<F: $crate::Backend>(
+                tx: &mut $crate::Transaction<'_, F>,
+            ) -> $crate::Result<Self> {
+                #[allow(clippy::let_unit_value)]
+                let _ = Self::_NAMES_ARE_DISTINCT;
+                Ok(Self {
+                    $($field: {
+                        let list = tx.take_list($list_name)?;
+                        let index = <$type as $crate::index::Table>::open(list, tx)?;
+                        tx.store_index(index)
+                    },)*
+                })
+            }
+
+            /// Take every table in `tx`
+            ///
+            /// # Panics
+            ///
+            /// Like [`Transaction::take_index`]($crate::Transaction::take_index) if a table has
+            /// already been taken in `tx` or the transaction that took them failed.
+            pub fn api<'i, F: $crate::Backend>(
+                &self,
+                tx: &'i $crate::Transaction<'_, F>,
+            ) -> $api<'i, F> {
+                $api {
+                    $($field: tx.take_index(self.$field),)*
+                }
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+pub const fn names_are_distinct(names: &[&str]) -> bool {
+    let mut i = 0;
+    while i < names.len() {
+        let mut j = i + 1;
+        while j < names.len() {
+            if str_eq(names[i], names[j]) {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
@@ -0,0 +1,70 @@
+use crate::ReadCounts;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Hooks for collecting metrics about what the database is doing.
+///
+/// All methods have empty default implementations so you only need to implement the ones you care
+/// about. Install with [`LlsDb::set_metrics`](crate::LlsDb::set_metrics).
+pub trait Metrics: Send + Sync {
+    /// An entry of `entry_len` bytes was pushed onto a list
+    fn push(&self, _entry_len: u64) {}
+    /// An entry was popped off a list
+    fn pop(&self) {}
+    /// `len` bytes were freed
+    fn free(&self, _len: u64) {}
+    /// `n` bytes were read from the backend
+    fn bytes_read(&self, _n: u64) {}
+    /// `n` bytes were written to the backend
+    fn bytes_written(&self, _n: u64) {}
+    /// A transaction was committed
+    fn tx_commit(&self) {}
+    /// A transaction was rolled back
+    fn tx_rollback(&self) {}
+    /// A read was served from the read cache (see
+    /// [`LlsDb::set_read_cache`](crate::LlsDb::set_read_cache))
+    fn cache_hit(&self) {}
+    /// A read had to load a page from the backend into the read cache
+    fn cache_miss(&self) {}
+}
+
+/// Wraps a reader or writer and reports the number of bytes that go through it.
+pub(crate) struct Metered<'a, T> {
+    pub inner: &'a mut T,
+    pub metrics: Option<&'a dyn Metrics>,
+    /// Where to count reads if they're being traced
+    pub reads: Option<&'a mut ReadCounts>,
+}
+
+impl<T: Read> Read for Metered<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(metrics) = self.metrics {
+            metrics.bytes_read(n as u64);
+        }
+        if let Some(reads) = &mut self.reads {
+            reads.reads += 1;
+            reads.bytes += n as u64;
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Metered<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(metrics) = self.metrics {
+            metrics.bytes_written(n as u64);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Seek> Seek for Metered<'_, T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
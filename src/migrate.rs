@@ -0,0 +1,148 @@
+use crate::{Backend, LinkedList, ListCursor, LlsDb};
+use anyhow::{anyhow, Result};
+
+/// How far a [`LlsDb::migrate`] has got. It is kept in a list next to the one being migrated and
+/// updated in the same transaction as each chunk of work so an interrupted migration carries on
+/// from where it stopped.
+#[derive(Clone, Debug, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub enum MigrationProgress {
+    /// Converting the entries of the list into a scratch list. Since the list is read from newest
+    /// to oldest the scratch list ends up in reverse.
+    Converting(ListCursor),
+    /// Copying the scratch list into the new list which puts it back in order
+    Reversing(ListCursor),
+}
+
+/// The lists a migration works in
+struct MigrationLists<T> {
+    progress: LinkedList<MigrationProgress>,
+    scratch: LinkedList<T>,
+    new: LinkedList<T>,
+}
+
+impl<F: Backend> LlsDb<F> {
+    /// Convert every entry of `list` from `S` to `T` with `convert` and replace the list with the
+    /// converted one keeping its name and order. Returns the converted list.
+    ///
+    /// The work is split into transactions of at most `chunk_size` entries each so a huge list
+    /// doesn't have to be converted at once. Progress is saved with each one so if the process
+    /// dies (or `convert` returns an error) taking the list again and calling this with the same
+    /// arguments carries on where it left off. Nothing changes for readers of the list until the
+    /// last transaction which swaps the converted list in under its name. The list must not be
+    /// changed in the meantime.
+    ///
+    /// The migration keeps its state in lists named after the list with a `.migration` suffix.
+    /// They are left empty once it is done. The list has to be a plain list rather than one
+    /// holding [`Mut`](crate::Mut) entries.
+    pub fn migrate<S, T>(
+        &mut self,
+        list: LinkedList<S>,
+        chunk_size: usize,
+        convert: impl FnMut(S) -> Result<T>,
+    ) -> Result<LinkedList<T>>
+    where
+        S: bincode::Encode + bincode::Decode,
+        T: bincode::Encode + bincode::Decode,
+    {
+        let list_name = match self.list_name(list.slot()) {
+            Some(name) => name.to_string(),
+            None => return Err(anyhow!("list has not been committed yet")),
+        };
+        if chunk_size == 0 {
+            return Err(anyhow!("chunk size must be at least 1"));
+        }
+        let lists = self.execute(|tx| {
+            Ok(MigrationLists {
+                progress: tx.take_list(&format!("{}.migration", list_name))?,
+                scratch: tx.take_list(&format!("{}.migration.scratch", list_name))?,
+                new: tx.take_list(&format!("{}.migration.new", list_name))?,
+            })
+        })?;
+
+        let res = self.run_migration(&list, &list_name, &lists, chunk_size, convert);
+        self.release_list(lists.progress);
+        self.release_list(lists.scratch);
+        self.release_list(list);
+        match res {
+            Ok(()) => Ok(lists.new),
+            Err(e) => {
+                self.release_list(lists.new);
+                Err(e)
+            }
+        }
+    }
+
+    fn run_migration<S, T>(
+        &mut self,
+        source: &LinkedList<S>,
+        list_name: &str,
+        lists: &MigrationLists<T>,
+        chunk_size: usize,
+        mut convert: impl FnMut(S) -> Result<T>,
+    ) -> Result<()>
+    where
+        S: bincode::Encode + bincode::Decode,
+        T: bincode::Encode + bincode::Decode,
+    {
+        let mut done = false;
+        while !done {
+            done = self.execute(|tx| {
+                let next = {
+                    let progress_list = lists.progress.api(&tx);
+                    let (source, scratch) = (source.api(&tx), lists.scratch.api(&tx));
+                    let progress = match progress_list.pop()? {
+                        None => MigrationProgress::Converting(source.entry_iter().cursor()),
+                        Some(progress) => progress,
+                    };
+                    let next = match progress {
+                        MigrationProgress::Converting(cursor) => {
+                            let mut entries = source.entry_iter_from(&cursor)?;
+                            for _ in 0..chunk_size {
+                                match entries.next::<S>() {
+                                    Some(value) => scratch.push(&convert(value?)?)?,
+                                    None => break,
+                                };
+                            }
+                            Some(match entries.cursor().is_finished() {
+                                true => MigrationProgress::Reversing(scratch.entry_iter().cursor()),
+                                false => MigrationProgress::Converting(entries.cursor()),
+                            })
+                        }
+                        MigrationProgress::Reversing(cursor) => {
+                            let new = lists.new.api(&tx);
+                            let mut entries = scratch.entry_iter_from(&cursor)?;
+                            for _ in 0..chunk_size {
+                                match entries.next::<T>() {
+                                    Some(value) => new.push(&value?)?,
+                                    None => break,
+                                };
+                            }
+                            match entries.cursor().is_finished() {
+                                true => None,
+                                false => Some(MigrationProgress::Reversing(entries.cursor())),
+                            }
+                        }
+                    };
+                    if let Some(next) = &next {
+                        progress_list.push(next)?;
+                    } else {
+                        scratch.clear()?;
+                        source.clear()?;
+                    }
+                    next
+                };
+                if next.is_some() {
+                    return Ok(false);
+                }
+                // the emptied old list takes the place of the new one for next time
+                let old_name = format!("{}.migration.old", list_name);
+                let new_name = format!("{}.migration.new", list_name);
+                tx.rename_list(list_name, &old_name)?;
+                tx.rename_list(&new_name, list_name)?;
+                tx.rename_list(&old_name, &new_name)?;
+                Ok(true)
+            })?;
+        }
+        Ok(())
+    }
+}
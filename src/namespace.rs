@@ -0,0 +1,96 @@
+use crate::{Backend, LinkedList, LinkedListMut, ListQuota, ListUsage, Transaction, TxIo};
+use anyhow::Result;
+
+/// A view of a [`Transaction`] where every list name is in a namespace (see
+/// [`Transaction::namespace`]).
+///
+/// Lists taken through it can't collide with or reach lists outside the namespace which is handy
+/// when several tenants share a database.
+pub struct Namespace<'a, 'tx, F> {
+    tx: &'a mut Transaction<'tx, F>,
+    name: String,
+}
+
+impl<'tx, F: Backend> Transaction<'tx, F> {
+    /// A view of the transaction where list names are prefixed by `namespace` and a `/` so
+    /// `tx.namespace("tenant1").take_list("orders")` takes the list called `"tenant1/orders"`.
+    pub fn namespace(&mut self, namespace: &str) -> Namespace<'_, 'tx, F> {
+        Namespace {
+            tx: self,
+            name: namespace.into(),
+        }
+    }
+}
+
+impl<'a, 'tx, F: Backend> Namespace<'a, 'tx, F> {
+    /// The full name of the namespace
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The full name of the list called `list_name` in the namespace
+    pub fn list_name(&self, list_name: &str) -> String {
+        format!("{}/{}", self.name, list_name)
+    }
+
+    /// A namespace within this one so `tx.namespace("tenant1").namespace("eu")` is the same as
+    /// `tx.namespace("tenant1/eu")`.
+    pub fn namespace(&mut self, namespace: &str) -> Namespace<'_, 'tx, F> {
+        let name = self.list_name(namespace);
+        Namespace { tx: self.tx, name }
+    }
+
+    /// [`Transaction::take_list`] in the namespace
+    pub fn take_list<T>(&mut self, list_name: &str) -> Result<LinkedList<T>> {
+        let list_name = self.list_name(list_name);
+        self.tx.take_list(&list_name)
+    }
+
+    /// [`Transaction::take_list_with_quota`] in the namespace
+    pub fn take_list_with_quota<T: bincode::Encode + bincode::Decode>(
+        &mut self,
+        list_name: &str,
+        quota: ListQuota,
+    ) -> Result<LinkedList<T>> {
+        let list_name = self.list_name(list_name);
+        self.tx.take_list_with_quota(&list_name, quota)
+    }
+
+    /// [`Transaction::take_list_mut_with_quota`] in the namespace
+    pub fn take_list_mut_with_quota<T>(
+        &mut self,
+        list_name: &str,
+        quota: ListQuota,
+    ) -> Result<LinkedListMut<T>> {
+        let list_name = self.list_name(list_name);
+        self.tx.take_list_mut_with_quota(&list_name, quota)
+    }
+
+    /// [`Transaction::rename_list`] where both names are in the namespace
+    pub fn rename_list(&mut self, old: &str, new: &str) -> Result<()> {
+        let (old, new) = (self.list_name(old), self.list_name(new));
+        self.tx.rename_list(&old, &new)
+    }
+
+    /// [`Transaction::swap_lists`] where both names are in the namespace
+    pub fn swap_lists(&mut self, a: &str, b: &str) -> Result<()> {
+        let (a, b) = (self.list_name(a), self.list_name(b));
+        self.tx.swap_lists(&a, &b)
+    }
+
+    /// Limit the lists in the namespace with [`Transaction::set_namespace_quota`]
+    pub fn set_quota(&mut self, quota: ListQuota) -> Result<()> {
+        self.tx.set_namespace_quota(&self.name, quota)
+    }
+
+    /// The quota of the namespace and how much of it is used if it has one
+    pub fn quota(&self) -> Option<(ListQuota, ListUsage)> {
+        self.tx.io.namespace_quota(&self.name)
+    }
+}
+
+impl<'tx, F> AsRef<TxIo<'tx, F>> for Namespace<'_, 'tx, F> {
+    fn as_ref(&self) -> &TxIo<'tx, F> {
+        &self.tx.io
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{BackupHeader, BackupSink, BINCODE_CONFIG};
+use anyhow::{anyhow, Result};
+use std::io::{self, Read};
+
+/// Objects stored by key like in S3 or anything compatible with it. Implement this with whichever
+/// client you use to talk to the store to back up to it with [`ObjectStoreSink`].
+///
+/// llsdb doesn't ship a client of its own. Object store clients tend to be async and tied to a
+/// particular runtime and cloud SDK, so the two methods here are all a client has to provide.
+pub trait ObjectStore {
+    fn put(&mut self, key: &str, bytes: &[u8]) -> Result<()>;
+    /// The object at `key` or `None` if there isn't one
+    fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>>;
+}
+
+impl<S: ObjectStore + ?Sized> ObjectStore for &mut S {
+    fn put(&mut self, key: &str, bytes: &[u8]) -> Result<()> {
+        (**self).put(key, bytes)
+    }
+
+    fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        (**self).get(key)
+    }
+}
+
+/// What's put last so a backup without one is known to be incomplete
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+struct Manifest {
+    header: BackupHeader,
+    n_chunks: u64,
+}
+
+/// A [`BackupSink`] that puts each chunk of a backup in an [`ObjectStore`] as its own object.
+///
+/// The chunks of the backup under `prefix` go at `prefix/chunk-00000000`, `prefix/chunk-00000001`
+/// and so on. Once they're all there a manifest with the [`BackupHeader`] is put at
+/// `prefix/manifest` so a backup that was interrupted is never mistaken for a complete one. Read
+/// it back with [`ObjectStoreSource`]. Use a different prefix for each backup.
+#[derive(Debug)]
+pub struct ObjectStoreSink<S> {
+    store: S,
+    prefix: String,
+    chunk_size: usize,
+}
+
+impl<S: ObjectStore> ObjectStoreSink<S> {
+    /// Put backups under `prefix` in objects of at most `chunk_size` bytes
+    pub fn new(store: S, prefix: impl Into<String>, chunk_size: usize) -> Self {
+        Self {
+            store,
+            prefix: prefix.into(),
+            chunk_size: chunk_size.max(1),
+        }
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+}
+
+impl<S: ObjectStore> BackupSink for ObjectStoreSink<S> {
+    fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    fn put_chunk(&mut self, index: u64, chunk: &[u8]) -> Result<()> {
+        self.store.put(&chunk_key(&self.prefix, index), chunk)
+    }
+
+    fn finish(&mut self, header: &BackupHeader, n_chunks: u64) -> Result<()> {
+        let manifest = Manifest {
+            header: header.clone(),
+            n_chunks,
+        };
+        let bytes = bincode::encode_to_vec(&manifest, BINCODE_CONFIG)?;
+        self.store.put(&manifest_key(&self.prefix), &bytes)
+    }
+}
+
+/// Reads a backup put in an [`ObjectStore`] by [`ObjectStoreSink`] back a chunk at a time. Pass
+/// it to [`LlsDb::apply_backup`](crate::LlsDb::apply_backup) or
+/// [`LlsDb::restore_backup`](crate::LlsDb::restore_backup).
+#[derive(Debug)]
+pub struct ObjectStoreSource<S> {
+    store: S,
+    prefix: String,
+    manifest: Manifest,
+    next_chunk: u64,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl<S: ObjectStore> ObjectStoreSource<S> {
+    /// Errors if there isn't a complete backup under `prefix`
+    pub fn open(mut store: S, prefix: impl Into<String>) -> Result<Self> {
+        let prefix = prefix.into();
+        let bytes = store
+            .get(&manifest_key(&prefix))?
+            .ok_or(anyhow!("there is no complete backup at '{}'", prefix))?;
+        let (manifest, _) = bincode::decode_from_slice(&bytes, BINCODE_CONFIG)?;
+        Ok(Self {
+            store,
+            prefix,
+            manifest,
+            next_chunk: 0,
+            chunk: vec![],
+            position: 0,
+        })
+    }
+
+    /// The header of the backup
+    pub fn header(&self) -> &BackupHeader {
+        &self.manifest.header
+    }
+}
+
+impl<S: ObjectStore> Read for ObjectStoreSource<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            if self.next_chunk == self.manifest.n_chunks {
+                return Ok(0);
+            }
+            let key = chunk_key(&self.prefix, self.next_chunk);
+            self.chunk = self
+                .store
+                .get(&key)
+                .map_err(io::Error::other)?
+                .ok_or(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("backup chunk {key} is missing"),
+                ))?;
+            self.next_chunk += 1;
+            self.position = 0;
+        }
+        let n = buf.len().min(self.chunk.len() - self.position);
+        buf[..n].copy_from_slice(&self.chunk[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+fn chunk_key(prefix: &str, index: u64) -> String {
+    format!("{}/chunk-{:08}", prefix, index)
+}
+
+fn manifest_key(prefix: &str) -> String {
+    format!("{}/manifest", prefix)
+}
@@ -0,0 +1,48 @@
+use crate::{InitOptions, LlsDb};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    path::Path,
+};
+
+impl LlsDb<File> {
+    /// Open the database at `path` creating it if the file doesn't exist or is empty.
+    ///
+    /// The file is locked for as long as the database is open so opening it again (from this
+    /// process or another) fails rather than both writing to it. Use [`reader_pool`] to read from
+    /// it elsewhere.
+    ///
+    /// [`reader_pool`]: Self::reader_pool
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_file(path.as_ref(), None)
+    }
+
+    /// Like [`open`](Self::open) but a new database is created with `options`. They are ignored if
+    /// there is already a database at `path`.
+    pub fn open_with(path: impl AsRef<Path>, options: InitOptions) -> Result<Self> {
+        Self::open_file(path.as_ref(), Some(options))
+    }
+
+    fn open_file(path: &Path, options: Option<InitOptions>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(anyhow!("{} is already open", path.display()))
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("locking {}", path.display()))
+            }
+        }
+        match options {
+            Some(options) if file.metadata()?.len() == 0 => Self::init_with_options(file, options),
+            _ => Self::load_or_init(file),
+        }
+    }
+}
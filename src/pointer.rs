@@ -1,3 +1,7 @@
+use crate::{Backend, TxIo, BINCODE_CONFIG};
+use core::ops::Range;
+use std::io::{Read, Write};
+
 #[derive(
     Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Hash, bincode::Encode, bincode::Decode,
 )]
@@ -8,23 +12,91 @@ impl Pointer {
     pub const MAX: Self = Self(u64::MAX);
     pub const MIN: Self = Self(1u64);
 
-    pub fn encoded_len(&self) -> u64 {
-        if self.0 <= 250 {
+    /// The length of the pointer when it's encoded as the link at the start of an entry
+    pub fn encoded_len(&self, links: LinkEncoding) -> u64 {
+        if links == LinkEncoding::FixedU32 {
+            4
+        } else if self.0 <= 250 {
             1
         } else if self.0 <= u16::MAX as u64 {
             3
         } else if self.0 <= u32::MAX as u64 {
-            4
-        } else {
             5
+        } else {
+            9
         }
     }
 }
 
+/// How the pointer at the start of each entry to the next entry is encoded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum LinkEncoding {
+    /// A varint like the rest of the integers bincode encodes
+    #[default]
+    Varint,
+    /// A little endian `u32`. Used by databases with
+    /// [`compact_pointers`](crate::InitOptions::compact_pointers) so no link takes more than 4
+    /// bytes.
+    FixedU32,
+}
+
+impl LinkEncoding {
+    /// Write `pointer` to the end of `buf` returning how many bytes it took up
+    pub(crate) fn encode(self, pointer: Pointer, mut buf: impl Write) -> anyhow::Result<usize> {
+        match self {
+            LinkEncoding::Varint => Ok(bincode::encode_into_std_write(
+                pointer,
+                &mut buf,
+                BINCODE_CONFIG,
+            )?),
+            LinkEncoding::FixedU32 => {
+                let pointer = u32::try_from(pointer.0)
+                    .map_err(|_| anyhow::anyhow!("{} doesn't fit in 4 bytes", pointer.0))?;
+                buf.write_all(&pointer.to_le_bytes())?;
+                Ok(4)
+            }
+        }
+    }
+
+    pub(crate) fn decode(self, mut reader: impl Read) -> anyhow::Result<Pointer> {
+        match self {
+            LinkEncoding::Varint => Ok(bincode::decode_from_std_read(&mut reader, BINCODE_CONFIG)?),
+            LinkEncoding::FixedU32 => {
+                let mut bytes = [0u8; 4];
+                reader.read_exact(&mut bytes)?;
+                Ok(Pointer(u32::from_le_bytes(bytes).into()))
+            }
+        }
+    }
+}
+
+/// Reads a little endian unsigned integer that is `buf.len()` bytes wide.
+pub(crate) fn read_le_uint(buf: &[u8]) -> u64 {
+    let mut bytes = [0u8; core::mem::size_of::<u64>()];
+    bytes[..buf.len()].copy_from_slice(buf);
+    u64::from_le_bytes(bytes)
+}
+
+/// Writes `value` as a little endian unsigned integer that is `buf.len()` bytes wide. Errors
+/// rather than truncating if `value` doesn't fit.
+pub(crate) fn write_le_uint(buf: &mut [u8], value: u64) -> anyhow::Result<()> {
+    let bytes = value.to_le_bytes();
+    if bytes[buf.len()..].iter().any(|byte| *byte != 0) {
+        return Err(anyhow::anyhow!(
+            "{} doesn't fit in {} bytes",
+            value,
+            buf.len()
+        ));
+    }
+    buf.copy_from_slice(&bytes[..buf.len()]);
+    Ok(())
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub struct EntryPointer {
     pub this_entry: Pointer,
     pub next_entry_possibly_stale: Pointer,
+    pub(crate) links: LinkEncoding,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -34,22 +106,55 @@ pub struct EntryHandle {
 }
 
 impl EntryHandle {
+    /// The pointer to pass to [`TxIo::read_at`](crate::TxIo::read_at) to read the entry again
+    pub fn entry_pointer(&self) -> EntryPointer {
+        self.entry_pointer
+    }
+
     pub fn entry_len(&self) -> u64 {
-        self.entry_pointer.next_entry_possibly_stale.encoded_len() + self.value_len
+        self.entry_pointer.link_len() + self.value_len
     }
 
     pub fn value_pointer(&self) -> Pointer {
         self.entry_pointer.value_pointer()
     }
 
+    /// Pointer to the segment starting `offset` bytes into the value (see
+    /// [`TxIo::push_segments`](crate::TxIo::push_segments))
+    pub fn segment_pointer(&self, offset: u64) -> Pointer {
+        Pointer(self.value_pointer().0 + offset)
+    }
+
     pub fn pointer_to_end(&self) -> Pointer {
         Pointer(self.entry_pointer.this_entry.0 + self.entry_len())
     }
+
+    /// The bytes of the backend the entry takes up (not counting any padding) e.g. to find it in
+    /// a hexdump of the file. The value starts at [`value_pointer`](Self::value_pointer) which is
+    /// the same distance into the range as the length of the pointer to the next entry.
+    pub fn file_range<'tx, F: Backend>(&self, io: impl AsRef<TxIo<'tx, F>>) -> Range<u64> {
+        let start = self.entry_pointer.file_offset(io);
+        start..start + self.entry_len()
+    }
 }
 
 impl EntryPointer {
     pub fn value_pointer(&self) -> Pointer {
-        Pointer(self.this_entry.0 + self.next_entry_possibly_stale.encoded_len())
+        Pointer(self.this_entry.0 + self.link_len())
+    }
+
+    /// The length of the encoded pointer to the next entry the entry starts with
+    pub fn link_len(&self) -> u64 {
+        self.next_entry_possibly_stale.encoded_len(self.links)
+    }
+
+    /// Where the entry starts in the backend. Pointers count from the end of the first page rather
+    /// than the start of the file so they can't be used as offsets as is (see
+    /// [`LlsDb::pointer_to_offset`](crate::LlsDb::pointer_to_offset)).
+    pub fn file_offset<'tx, F: Backend>(&self, io: impl AsRef<TxIo<'tx, F>>) -> u64 {
+        io.as_ref()
+            .pointer_to_offset(self.this_entry)
+            .expect("entries are never at null")
     }
 }
 
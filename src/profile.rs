@@ -0,0 +1,164 @@
+use crate::ListSlot;
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+/// What was done to each list while profiling was on. Returned from
+/// [`LlsDb::profile_report`](crate::LlsDb::profile_report).
+///
+/// Activity in transactions that were rolled back is counted too.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProfileReport {
+    pub lists: BTreeMap<ListSlot, ListProfile>,
+    /// Values read straight from a pointer (e.g. lookups by an index) which can't be put down to
+    /// a list
+    pub reads_by_pointer: u64,
+    /// Values overwritten in place which can't be put down to a list either
+    pub overwrites: u64,
+}
+
+impl ProfileReport {
+    /// The lists sorted by the number of reads and writes they had, busiest first
+    pub fn hottest(&self) -> Vec<(ListSlot, &ListProfile)> {
+        let mut lists = self
+            .lists
+            .iter()
+            .map(|(&slot, profile)| (slot, profile))
+            .collect::<Vec<_>>();
+        lists.sort_by_key(|(_, profile)| core::cmp::Reverse(profile.n_ops()));
+        lists
+    }
+}
+
+/// See [`ProfileReport`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ListProfile {
+    /// The name of the list if it has one
+    pub name: Option<String>,
+    /// The number of entries read while iterating over the list
+    pub reads: u64,
+    /// The number of entries pushed onto the list
+    pub pushes: u64,
+    /// The number of entries popped off the list
+    pub pops: u64,
+    /// The encoded sizes of the values pushed and read
+    pub value_sizes: SizeHistogram,
+}
+
+impl ListProfile {
+    /// Reads plus writes
+    pub fn n_ops(&self) -> u64 {
+        self.reads + self.pushes + self.pops
+    }
+}
+
+/// A histogram of sizes in power of two buckets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SizeHistogram {
+    /// `buckets[0]` counts zeros and `buckets[i]` counts sizes in `2^(i-1)..2^i`
+    buckets: Vec<u64>,
+    total: u64,
+    min: u64,
+    max: u64,
+}
+
+impl SizeHistogram {
+    pub fn record(&mut self, size: u64) {
+        let bucket = (u64::BITS - size.leading_zeros()) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        if self.count() == 0 || size < self.min {
+            self.min = size;
+        }
+        self.max = self.max.max(size);
+        self.buckets[bucket] += 1;
+        self.total += size;
+    }
+
+    /// The number of sizes recorded
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// The sum of the sizes recorded
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        (self.count() > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        (self.count() > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        let count = self.count();
+        (count > 0).then(|| self.total as f64 / count as f64)
+    }
+
+    /// The range of sizes each non-empty bucket covers and how many sizes fell in it
+    pub fn buckets(&self) -> impl Iterator<Item = (RangeInclusive<u64>, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bucket, &count)| {
+                let range = match bucket {
+                    0 => 0..=0,
+                    _ => 1 << (bucket - 1)..=u64::MAX >> (u64::BITS as usize - bucket),
+                };
+                (range, count)
+            })
+    }
+}
+
+/// The reads each index did from the backend during a transaction while read tracing is on (see
+/// [`LlsDb::set_read_tracing`](crate::LlsDb::set_read_tracing)). Get it with
+/// [`Transaction::read_trace`](crate::Transaction::read_trace).
+///
+/// Reads are put down to the index whose API did them. Any index built on top of another (or any
+/// list it uses) counts as the index that was taken.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadTrace {
+    /// The reads done by each index by its id
+    pub by_index: BTreeMap<usize, ReadCounts>,
+    /// Reads not done by an index e.g. through lists used straight from the transaction
+    pub other: ReadCounts,
+}
+
+impl ReadTrace {
+    /// The reads done by the index behind `index`
+    pub fn index<I>(&self, index: &crate::IndexHandle<I>) -> ReadCounts {
+        self.by_index.get(&index.id()).copied().unwrap_or_default()
+    }
+
+    /// The indexes sorted by the number of seeks they did, most first
+    pub fn most_seeks(&self) -> Vec<(usize, ReadCounts)> {
+        let mut indexes = self
+            .by_index
+            .iter()
+            .map(|(&id, &counts)| (id, counts))
+            .collect::<Vec<_>>();
+        indexes.sort_by_key(|(_, counts)| core::cmp::Reverse(counts.seeks));
+        indexes
+    }
+
+    pub(crate) fn counts_mut(&mut self, index: Option<usize>) -> &mut ReadCounts {
+        match index {
+            Some(index) => self.by_index.entry(index).or_default(),
+            None => &mut self.other,
+        }
+    }
+}
+
+/// See [`ReadTrace`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadCounts {
+    /// The number of times the backend was seeked to somewhere to read or write
+    pub seeks: u64,
+    /// The number of calls to read on the backend
+    pub reads: u64,
+    /// The number of bytes read
+    pub bytes: u64,
+}
@@ -0,0 +1,183 @@
+use crate::ListSlot;
+use core::fmt;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Limits on how big a list can get (see [`Transaction::take_list_with_quota`]).
+///
+/// [`Transaction::take_list_with_quota`]: crate::Transaction::take_list_with_quota
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct ListQuota {
+    /// The maximum number of entries in the list
+    pub max_entries: Option<u64>,
+    /// The maximum number of bytes the entries in the list can take up
+    pub max_bytes: Option<u64>,
+}
+
+/// How much of the database a list is using
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ListUsage {
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+/// The error returned when pushing to a list would take it (or its namespace) over its
+/// [`ListQuota`]. Since it is returned through [`anyhow`] use `downcast_ref` to tell it apart from
+/// other errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    pub list_slot: ListSlot,
+    /// The namespace whose quota would be exceeded or `None` if it's the list's own quota (see
+    /// [`Transaction::set_namespace_quota`]).
+    ///
+    /// [`Transaction::set_namespace_quota`]: crate::Transaction::set_namespace_quota
+    pub namespace: Option<String>,
+    pub quota: ListQuota,
+    pub usage: ListUsage,
+    /// The size of the entry that was refused
+    pub entry_len: u64,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let whose = match &self.namespace {
+            Some(namespace) => format!("namespace '{}'", namespace),
+            None => "its".to_string(),
+        };
+        write!(
+            f,
+            "pushing {} bytes to list {} would exceed {} quota of {:?} \
+             (using {} entries and {} bytes)",
+            self.entry_len, self.list_slot, whose, self.quota, self.usage.entries, self.usage.bytes
+        )
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct QuotaState {
+    pub quota: ListQuota,
+    pub usage: ListUsage,
+}
+
+impl QuotaState {
+    pub fn check_push(&self, list_slot: ListSlot, entry_len: u64) -> Result<(), QuotaExceeded> {
+        let over_entries = self
+            .quota
+            .max_entries
+            .is_some_and(|max| self.usage.entries + 1 > max);
+        let over_bytes = self
+            .quota
+            .max_bytes
+            .is_some_and(|max| self.usage.bytes + entry_len > max);
+        if over_entries || over_bytes {
+            return Err(QuotaExceeded {
+                list_slot,
+                namespace: None,
+                quota: self.quota,
+                usage: self.usage,
+                entry_len,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn pushed(&mut self, entry_len: u64) {
+        self.usage.entries += 1;
+        self.usage.bytes += entry_len;
+    }
+
+    pub fn pushed_bytes(&mut self, entry_len: u64) {
+        self.usage.bytes += entry_len;
+    }
+
+    pub fn freed(&mut self, entry_len: u64) {
+        self.usage.entries = self.usage.entries.saturating_sub(1);
+        self.usage.bytes = self.usage.bytes.saturating_sub(entry_len);
+    }
+}
+
+/// Whether the list called `name` is in `namespace`. Namespaces are the parts of a list's name
+/// before a `/` so `"tenant1/orders"` is in `"tenant1"` and `"tenant1/eu/orders"` is in both
+/// `"tenant1"` and `"tenant1/eu"`.
+pub(crate) fn in_namespace(name: &str, namespace: &str) -> bool {
+    name.strip_prefix(namespace)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct NamespaceQuota {
+    pub state: QuotaState,
+    /// The lists in the namespace
+    pub slots: BTreeSet<ListSlot>,
+}
+
+/// The quotas of lists and namespaces
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Quotas {
+    pub lists: BTreeMap<ListSlot, QuotaState>,
+    /// The lists in `lists` that are [`LinkedListMut`](crate::LinkedListMut)s so only their
+    /// values count as entries
+    pub mut_lists: BTreeSet<ListSlot>,
+    pub namespaces: BTreeMap<String, NamespaceQuota>,
+}
+
+impl Quotas {
+    pub fn check_push(&self, list_slot: ListSlot, entry_len: u64) -> Result<(), QuotaExceeded> {
+        if let Some(quota) = self.lists.get(&list_slot) {
+            quota.check_push(list_slot, entry_len)?;
+        }
+        for (namespace, quota) in self.namespaces_of(list_slot) {
+            quota
+                .state
+                .check_push(list_slot, entry_len)
+                .map_err(|e| QuotaExceeded {
+                    namespace: Some(namespace.clone()),
+                    ..e
+                })?;
+        }
+        Ok(())
+    }
+
+    pub fn pushed(&mut self, list_slot: ListSlot, entry_len: u64) {
+        self.for_each_state(list_slot, |state| state.pushed(entry_len));
+    }
+
+    /// An entry that isn't a value (like a remap) was pushed so only its bytes count
+    pub fn pushed_bytes(&mut self, list_slot: ListSlot, entry_len: u64) {
+        self.for_each_state(list_slot, |state| state.pushed_bytes(entry_len));
+    }
+
+    pub fn freed(&mut self, list_slot: ListSlot, entry_len: u64) {
+        self.for_each_state(list_slot, |state| state.freed(entry_len));
+    }
+
+    /// Count a new list called `name` towards the quotas of the namespaces it's in
+    pub fn list_created(&mut self, list_slot: ListSlot, name: &str) {
+        for (namespace, quota) in &mut self.namespaces {
+            if in_namespace(name, namespace) {
+                quota.slots.insert(list_slot);
+            }
+        }
+    }
+
+    fn namespaces_of(
+        &self,
+        list_slot: ListSlot,
+    ) -> impl Iterator<Item = (&String, &NamespaceQuota)> + '_ {
+        self.namespaces
+            .iter()
+            .filter(move |(_, quota)| quota.slots.contains(&list_slot))
+    }
+
+    fn for_each_state(&mut self, list_slot: ListSlot, mut f: impl FnMut(&mut QuotaState)) {
+        if let Some(quota) = self.lists.get_mut(&list_slot) {
+            f(quota);
+        }
+        for quota in self.namespaces.values_mut() {
+            if quota.slots.contains(&list_slot) {
+                f(&mut quota.state);
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::Metrics;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+/// The pages of the backend read most recently (see
+/// [`LlsDb::set_read_cache`](crate::LlsDb::set_read_cache)).
+///
+/// Reads are served a page at a time so the many small reads that decoding an entry does only go to
+/// the backend once. Writes go straight to the backend and drop the pages they touch.
+pub(crate) struct ReadCache {
+    page_size: u64,
+    capacity: usize,
+    /// The pages by their index along with when they were last used
+    pages: HashMap<u64, (u64, Vec<u8>)>,
+    /// The index of each page by when it was last used
+    by_use: BTreeMap<u64, u64>,
+    tick: u64,
+    /// Where the next read starts. The backend's own position is only right after a seek since
+    /// reads served from the cache don't move it. `None` until the first seek.
+    pos: Option<u64>,
+}
+
+impl ReadCache {
+    pub fn new(page_size: u64, capacity: usize) -> Self {
+        Self {
+            page_size,
+            capacity,
+            pages: Default::default(),
+            by_use: Default::default(),
+            tick: 0,
+            pos: None,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The backend was moved to `pos`
+    pub fn seeked(&mut self, pos: u64) {
+        self.pos = Some(pos);
+    }
+
+    /// Where the next read from the cache would start if the backend hasn't been moved since
+    pub fn position(&self) -> Option<u64> {
+        self.pos
+    }
+
+    /// Forget the position and every page after the backend was changed other than by a write
+    pub fn clear(&mut self) {
+        self.pages.clear();
+        self.by_use.clear();
+        self.pos = None;
+    }
+
+    /// Drop the pages overlapping the `len` bytes written at `offset`. The backend has moved past
+    /// them so the position is forgotten too.
+    fn invalidate(&mut self, offset: u64, len: u64) {
+        self.pos = None;
+        if len == 0 {
+            return;
+        }
+        for page in offset / self.page_size..=(offset + len - 1) / self.page_size {
+            if let Some((used, _)) = self.pages.remove(&page) {
+                self.by_use.remove(&used);
+            }
+        }
+    }
+
+    fn touch(&mut self, page: u64) {
+        self.tick += 1;
+        if let Some((used, _)) = self.pages.get_mut(&page) {
+            self.by_use.remove(used);
+            *used = self.tick;
+            self.by_use.insert(self.tick, page);
+        }
+    }
+
+    fn insert(&mut self, page: u64, bytes: Vec<u8>) {
+        while self.pages.len() >= self.capacity {
+            let Some((_, oldest)) = self.by_use.pop_first() else {
+                break;
+            };
+            self.pages.remove(&oldest);
+        }
+        self.tick += 1;
+        self.pages.insert(page, (self.tick, bytes));
+        self.by_use.insert(self.tick, page);
+    }
+}
+
+/// Reads through a [`ReadCache`] if there is one and its position is known or straight from
+/// `inner` otherwise.
+pub(crate) struct CachedReader<'a, R> {
+    pub inner: R,
+    pub cache: Option<&'a mut ReadCache>,
+    pub metrics: Option<&'a dyn Metrics>,
+}
+
+impl<R: Read + Seek> Read for CachedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let cache = match &mut self.cache {
+            Some(cache) if cache.capacity > 0 => cache,
+            _ => return self.inner.read(buf),
+        };
+        let Some(pos) = cache.pos else {
+            return self.inner.read(buf);
+        };
+        let page = pos / cache.page_size;
+        if cache.pages.contains_key(&page) {
+            if let Some(metrics) = self.metrics {
+                metrics.cache_hit();
+            }
+            cache.touch(page);
+        } else {
+            if let Some(metrics) = self.metrics {
+                metrics.cache_miss();
+            }
+            let mut bytes = vec![0u8; cache.page_size as usize];
+            self.inner.seek(SeekFrom::Start(page * cache.page_size))?;
+            let mut len = 0;
+            while len < bytes.len() {
+                match self.inner.read(&mut bytes[len..])? {
+                    0 => break,
+                    n => len += n,
+                }
+            }
+            bytes.truncate(len);
+            // the backend is now at the end of the page rather than at `pos`
+            cache.insert(page, bytes);
+        }
+        let (_, bytes) = &cache.pages[&page];
+        let start = (pos - page * cache.page_size) as usize;
+        let n = buf.len().min(bytes.len().saturating_sub(start));
+        buf[..n].copy_from_slice(&bytes[start..start + n]);
+        cache.pos = Some(pos + n as u64);
+        Ok(n)
+    }
+}
+
+/// Drops the pages of a [`ReadCache`] that writes go over.
+pub(crate) struct Invalidating<'a, W> {
+    pub inner: W,
+    pub cache: Option<&'a mut ReadCache>,
+}
+
+impl<W: Write + Seek> Write for Invalidating<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let Some(cache) = &mut self.cache else {
+            return self.inner.write(buf);
+        };
+        let offset = self.inner.stream_position()?;
+        let n = self.inner.write(buf)?;
+        cache.invalidate(offset, n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for Invalidating<'_, W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
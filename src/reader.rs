@@ -0,0 +1,225 @@
+use crate::{EntryHandle, EntryPointer, LinkEncoding, Pointer, ValueEncoding};
+use anyhow::{anyhow, Result};
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// A pool of read-only handles to a database file (see [`LlsDb::reader_pool`]).
+///
+/// Each [`PooledReader`] has its own seek position so reads through them don't interfere with the
+/// database's own handle or each other. The pool is `Sync` so readers can be taken out on other
+/// threads while transactions run.
+///
+/// Readers go straight to the file and know nothing about transactions. They only see what has
+/// been committed and an entry they read is only valid until a later transaction removes it and
+/// its space is reused. They also don't follow the remaps written by
+/// [`LinkedListMut`](crate::LinkedListMut) so are best suited to append-only lists.
+///
+/// [`LlsDb::reader_pool`]: crate::LlsDb::reader_pool
+#[derive(Debug)]
+pub struct ReaderPool {
+    path: PathBuf,
+    page_size: u64,
+    value_encoding: ValueEncoding,
+    link_encoding: LinkEncoding,
+    max_idle: usize,
+    idle: Mutex<Vec<File>>,
+}
+
+impl ReaderPool {
+    pub(crate) fn new(
+        path: PathBuf,
+        page_size: u64,
+        value_encoding: ValueEncoding,
+        link_encoding: LinkEncoding,
+    ) -> Self {
+        Self {
+            path,
+            page_size,
+            value_encoding,
+            link_encoding,
+            max_idle: 4,
+            idle: Default::default(),
+        }
+    }
+
+    /// Set the maximum number of handles kept open once they have been returned to the pool.
+    /// Defaults to `4`.
+    pub fn with_max_idle(mut self, max_idle: usize) -> Self {
+        self.max_idle = max_idle;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Take a reader out of the pool opening a new handle if there are none idle. The handle goes
+    /// back into the pool when the reader is dropped.
+    pub fn reader(&self) -> Result<PooledReader<'_>> {
+        let idle = self
+            .idle
+            .lock()
+            .map_err(|_| anyhow!("reader pool poisoned"))?
+            .pop();
+        let file = match idle {
+            Some(file) => file,
+            None => File::open(&self.path)?,
+        };
+        Ok(PooledReader {
+            pool: self,
+            file: Some(file),
+        })
+    }
+
+    /// The number of handles sitting in the pool
+    pub fn n_idle(&self) -> usize {
+        self.idle.lock().map(|idle| idle.len()).unwrap_or(0)
+    }
+}
+
+/// A read-only handle taken from a [`ReaderPool`].
+#[derive(Debug)]
+pub struct PooledReader<'p> {
+    pool: &'p ReaderPool,
+    file: Option<File>,
+}
+
+impl<'p> PooledReader<'p> {
+    fn file(&mut self) -> &mut File {
+        self.file.as_mut().expect("only taken on drop")
+    }
+
+    fn seek_to(&mut self, pointer: Pointer) -> Result<()> {
+        if pointer == Pointer::NULL {
+            return Err(anyhow!("tried to seek to null pointer"));
+        }
+        let position = pointer.0 + self.pool.page_size - 1;
+        self.file().seek(SeekFrom::Start(position))?;
+        Ok(())
+    }
+
+    fn current_position(&mut self) -> Result<Pointer> {
+        let position = self.file().stream_position()?;
+        Ok(Pointer(position + 1 - self.pool.page_size))
+    }
+
+    /// Read the value of the entry at `pointer`
+    pub fn read_at<T: bincode::Decode>(
+        &mut self,
+        pointer: EntryPointer,
+    ) -> Result<(EntryHandle, T)> {
+        let value_pointer = pointer.value_pointer();
+        self.seek_to(value_pointer)?;
+        let encoding = self.pool.value_encoding;
+        let value = encoding.decode_from_std_read(self.file())?;
+        let end = self.current_position()?;
+        Ok((
+            EntryHandle {
+                entry_pointer: pointer,
+                value_len: end.0 - value_pointer.0,
+            },
+            value,
+        ))
+    }
+
+    /// Read the link at the start of the entry at `this_entry`
+    fn read_link(&mut self, this_entry: Pointer) -> Result<EntryPointer> {
+        self.seek_to(this_entry)?;
+        let links = self.pool.link_encoding;
+        let next_entry_possibly_stale = links.decode(self.file())?;
+        Ok(EntryPointer {
+            this_entry,
+            next_entry_possibly_stale,
+            links,
+        })
+    }
+
+    /// Read the entry at `this_entry` returning it along with a pointer to the next one
+    fn read_entry<T: bincode::Decode>(&mut self, this_entry: Pointer) -> Result<(EntryHandle, T)> {
+        let pointer = self.read_link(this_entry)?;
+        self.read_at(pointer)
+    }
+
+    /// Iterate over the pointers to the entries of the list starting at `head` without reading
+    /// their values
+    pub fn iter_pointers(&mut self, head: Pointer) -> ReaderPointerIter<'_, 'p> {
+        ReaderPointerIter {
+            reader: self,
+            curr: head,
+        }
+    }
+
+    /// Iterate over the list starting at `head` (see [`TxIo::curr_head`] and [`LlsDb::head`]).
+    ///
+    /// [`TxIo::curr_head`]: crate::TxIo::curr_head
+    /// [`LlsDb::head`]: crate::LlsDb::head
+    pub fn iter<T: bincode::Decode>(&mut self, head: Pointer) -> ReaderIter<'_, 'p, T> {
+        ReaderIter {
+            reader: self,
+            curr: head,
+            ty: PhantomData,
+        }
+    }
+}
+
+/// Iterator over a list returned from [`PooledReader::iter`]
+#[derive(Debug)]
+pub struct ReaderIter<'r, 'p, T> {
+    reader: &'r mut PooledReader<'p>,
+    curr: Pointer,
+    ty: PhantomData<T>,
+}
+
+impl<T: bincode::Decode> Iterator for ReaderIter<'_, '_, T> {
+    type Item = Result<(EntryHandle, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.curr == Pointer::NULL {
+            return None;
+        }
+        let res = self.reader.read_entry::<T>(self.curr);
+        self.curr = match &res {
+            Ok((handle, _)) => handle.entry_pointer.next_entry_possibly_stale,
+            Err(_) => Pointer::NULL,
+        };
+        Some(res)
+    }
+}
+
+/// Iterator over the entry pointers of a list returned from [`PooledReader::iter_pointers`]
+#[derive(Debug)]
+pub struct ReaderPointerIter<'r, 'p> {
+    reader: &'r mut PooledReader<'p>,
+    curr: Pointer,
+}
+
+impl Iterator for ReaderPointerIter<'_, '_> {
+    type Item = Result<EntryPointer>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.curr == Pointer::NULL {
+            return None;
+        }
+        let res = self.reader.read_link(self.curr);
+        self.curr = match &res {
+            Ok(pointer) => pointer.next_entry_possibly_stale,
+            Err(_) => Pointer::NULL,
+        };
+        Some(res)
+    }
+}
+
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        if let (Some(file), Ok(mut idle)) = (self.file.take(), self.pool.idle.lock()) {
+            if idle.len() < self.pool.max_idle {
+                idle.push(file);
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{Seek, Write};
+
+/// The physical changes a committed transaction made to the backend.
+///
+/// Appended entries, overwritten values, freed ranges and head updates all end up as bytes
+/// written somewhere in the backend so a changeset is just those writes plus the length the
+/// backend was left at. Turn on [`LlsDb::set_replication_log`] on a primary and feed what
+/// [`LlsDb::take_changesets`] returns to [`LlsDb::apply_changeset`] on a follower.
+///
+/// [`LlsDb::set_replication_log`]: crate::LlsDb::set_replication_log
+/// [`LlsDb::take_changesets`]: crate::LlsDb::take_changesets
+/// [`LlsDb::apply_changeset`]: crate::LlsDb::apply_changeset
+#[derive(Debug, Clone, Default, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct Changeset {
+    /// Writes in the order they were made. Later writes win where they overlap.
+    pub writes: Vec<ChangesetWrite>,
+    /// The length of the backend after the transaction
+    pub file_len: u64,
+}
+
+/// Bytes written at a position in the backend. See [`Changeset`].
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct ChangesetWrite {
+    pub offset: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// The start of a backup written by [`LlsDb::backup_since`]. It is followed by `n_writes`
+/// [`ChangesetWrite`]s of whole pages.
+///
+/// [`LlsDb::backup_since`]: crate::LlsDb::backup_since
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct BackupHeader {
+    /// The id of the database that was backed up
+    pub id: Option<[u8; 16]>,
+    /// The generation the backup has the changes since. `None` if it is a full backup.
+    pub since: Option<u64>,
+    /// The generation of the database when it was backed up
+    pub generation: u64,
+    /// The length of the backend when it was backed up
+    pub file_len: u64,
+    pub n_writes: u64,
+}
+
+impl Changeset {
+    /// The number of bytes written
+    pub fn n_bytes(&self) -> u64 {
+        self.writes
+            .iter()
+            .map(|write| write.bytes.len() as u64)
+            .sum()
+    }
+}
+
+/// Wraps a writer and records what was written where if there is somewhere to record it.
+pub(crate) struct Captured<'a, W> {
+    pub inner: W,
+    pub writes: Option<&'a mut Vec<ChangesetWrite>>,
+    /// Where to record just the `(offset, len)` of each write
+    pub ranges: Option<&'a mut Vec<(u64, u64)>>,
+}
+
+impl<W: Write + Seek> Write for Captured<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.writes.is_none() && self.ranges.is_none() {
+            return self.inner.write(buf);
+        }
+        let offset = self.inner.stream_position()?;
+        let n = self.inner.write(buf)?;
+        if let Some(ranges) = &mut self.ranges {
+            match ranges.last_mut() {
+                Some((last, len)) if *last + *len == offset => *len += n as u64,
+                _ => ranges.push((offset, n as u64)),
+            }
+        }
+        let writes = match &mut self.writes {
+            Some(writes) => writes,
+            None => return Ok(n),
+        };
+        match writes.last_mut() {
+            Some(last) if last.offset + last.bytes.len() as u64 == offset => {
+                last.bytes.extend_from_slice(&buf[..n])
+            }
+            _ => writes.push(ChangesetWrite {
+                offset,
+                bytes: buf[..n].to_vec(),
+            }),
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
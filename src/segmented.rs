@@ -0,0 +1,291 @@
+use crate::Backend;
+use anyhow::{anyhow, Context, Result};
+use std::{
+    cell::Cell,
+    fs::{File, OpenOptions, TryLockError},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// A [`Backend`] that spreads the database over files of `segment_size` bytes each so it can grow
+/// past the biggest file the filesystem allows.
+///
+/// The segments of the database at `path` are called `path.0`, `path.1` and so on. The first one
+/// holds the first page and is locked like [`LlsDb::open`](crate::LlsDb::open) locks its file.
+/// Segments that are entirely free space are deleted by [`Backend::discard`] (see
+/// [`LlsDb::discard_free_space`](crate::LlsDb::discard_free_space)) and read back as zeros until
+/// something is written to them again. Truncating deletes the segments past the new end.
+///
+/// The same `segment_size` has to be used every time the database is opened.
+#[derive(Debug)]
+pub struct SegmentedFile {
+    path: PathBuf,
+    segment_size: u64,
+    /// The segments by their index. `None` if the file doesn't exist.
+    segments: Vec<Option<File>>,
+    len: u64,
+    position: u64,
+    /// Whether segments have been created or deleted since the directory was last synced
+    dir_dirty: Cell<bool>,
+}
+
+impl SegmentedFile {
+    /// Open the segments of the database at `path` creating the first one if there aren't any
+    pub fn open(path: impl AsRef<Path>, segment_size: u64) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if segment_size == 0 {
+            return Err(anyhow!("segment size must not be zero"));
+        }
+        let mut segments = vec![];
+        for (index, segment_path) in Self::existing_segments(&path)? {
+            if segments.len() <= index {
+                segments.resize_with(index + 1, || None);
+            }
+            segments[index] = Some(open_segment(&segment_path)?);
+        }
+        if segments.is_empty() {
+            segments.push(None);
+        }
+        let mut segmented = Self {
+            path,
+            segment_size,
+            segments,
+            len: 0,
+            position: 0,
+            dir_dirty: Cell::new(false),
+        };
+        let first = segmented.segment(0)?;
+        match first.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(anyhow!("{} is already open", segmented.path.display()))
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("locking {}", segmented.path.display()))
+            }
+        }
+        for (index, segment) in segmented.segments.iter().enumerate() {
+            if let Some(file) = segment {
+                let segment_len = file.metadata()?.len();
+                if segment_len > segment_size {
+                    return Err(anyhow!(
+                        "segment {} is bigger than the segment size of {}",
+                        index,
+                        segment_size
+                    ));
+                }
+                if segment_len > 0 {
+                    segmented.len = segmented.len.max(index as u64 * segment_size + segment_len);
+                }
+            }
+        }
+        Ok(segmented)
+    }
+
+    /// The path of the segment at `index`
+    pub fn segment_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn existing_segments(path: &Path) -> Result<Vec<(usize, PathBuf)>> {
+        let dir = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        let name = path
+            .file_name()
+            .ok_or(anyhow!("{} isn't a file path", path.display()))?
+            .to_string_lossy()
+            .into_owned();
+        let mut segments = vec![];
+        if !dir.exists() {
+            return Ok(segments);
+        }
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let index = file_name
+                .strip_prefix(name.as_str())
+                .and_then(|rest| rest.strip_prefix('.'))
+                .and_then(|index| index.parse::<usize>().ok());
+            if let Some(index) = index {
+                segments.push((index, entry.path()));
+            }
+        }
+        Ok(segments)
+    }
+
+    /// The segment at `index` creating it if it doesn't exist
+    fn segment(&mut self, index: usize) -> io::Result<&mut File> {
+        if self.segments.len() <= index {
+            self.segments.resize_with(index + 1, || None);
+        }
+        if self.segments[index].is_none() {
+            let file = open_segment(&self.segment_path(index))?;
+            self.dir_dirty.set(true);
+            self.segments[index] = Some(file);
+        }
+        Ok(self.segments[index].as_mut().expect("just created"))
+    }
+
+    fn remove_segment(&mut self, index: usize) -> io::Result<()> {
+        // the first segment holds the lock and the first page
+        if index == 0 {
+            return Ok(());
+        }
+        if let Some(file) = self.segments.get_mut(index).and_then(Option::take) {
+            drop(file);
+            std::fs::remove_file(self.segment_path(index))?;
+            self.dir_dirty.set(true);
+        }
+        Ok(())
+    }
+
+    /// Where `position` is as the index of its segment and the offset into it
+    fn locate(&self, position: u64) -> (usize, u64) {
+        (
+            (position / self.segment_size) as usize,
+            position % self.segment_size,
+        )
+    }
+
+    fn sync_dir(&self) -> Result<()> {
+        if self.dir_dirty.replace(false) {
+            let dir = match self.path.parent() {
+                Some(parent) if parent != Path::new("") => parent,
+                _ => Path::new("."),
+            };
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+fn open_segment(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+impl Read for SegmentedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let (index, offset) = self.locate(self.position);
+        let n = (buf.len() as u64)
+            .min(self.segment_size - offset)
+            .min(self.len - self.position) as usize;
+        let read = match self.segments.get_mut(index).and_then(Option::as_mut) {
+            Some(file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                file.read(&mut buf[..n])?
+            }
+            None => 0,
+        };
+        // deleted segments and the space past the end of a short one read as zeros
+        let read = if read == 0 {
+            buf[..n].fill(0);
+            n
+        } else {
+            read
+        };
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for SegmentedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (index, offset) = self.locate(self.position);
+        let n = (buf.len() as u64).min(self.segment_size - offset) as usize;
+        let file = self.segment(index)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&buf[..n])?;
+        self.position += n as u64;
+        self.len = self.len.max(self.position);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SegmentedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = position.ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        ))?;
+        Ok(self.position)
+    }
+}
+
+impl Backend for SegmentedFile {
+    fn truncate(&mut self, size: u64) -> Result<()> {
+        let (last, offset) = self.locate(size);
+        for index in (last + 1..self.segments.len()).rev() {
+            self.remove_segment(index)?;
+        }
+        if offset == 0 {
+            self.remove_segment(last)?;
+        }
+        if let Some(file) = self.segments.get_mut(last).and_then(Option::as_mut) {
+            if file.metadata()?.len() > offset {
+                file.set_len(offset)?;
+            }
+        }
+        self.segments.truncate(last + 1);
+        self.len = size;
+        Ok(())
+    }
+
+    fn init_max_size(&self) -> u64 {
+        u64::MAX
+    }
+
+    fn init_page_size(&self) -> u16 {
+        4096
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        for file in self.segments.iter().flatten() {
+            file.sync_data()?;
+        }
+        self.sync_dir()
+    }
+
+    /// Deletes the segments that lie entirely within the region
+    fn discard(&mut self, position: u64, len: u64) -> Result<()> {
+        let end = position.saturating_add(len);
+        let first = position.div_ceil(self.segment_size) as usize;
+        let last = (end / self.segment_size) as usize;
+        for index in first..last.min(self.segments.len()) {
+            self.remove_segment(index)?;
+        }
+        Ok(())
+    }
+
+    /// Also releases the lock on the first segment
+    fn close(&mut self) -> Result<()> {
+        self.sync_data()?;
+        if let Some(Some(first)) = self.segments.first() {
+            first.unlock()?;
+        }
+        Ok(())
+    }
+}
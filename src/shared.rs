@@ -0,0 +1,132 @@
+use crate::{Backend, LlsDb, Pointer, Transaction};
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+};
+
+/// A [`LlsDb`] that can be shared between threads by cloning it.
+///
+/// [`execute`](Self::execute) takes `&self` and runs one transaction at a time so handlers that
+/// share the database don't each need a lock around it. Readers that only need what has been
+/// committed can take a [`DbSnapshot`] without waiting for a transaction to finish and read the
+/// lists in it through a [`ReaderPool`](crate::ReaderPool) concurrently with the writer.
+pub struct SharedLlsDb<F: Backend> {
+    inner: Arc<Shared<F>>,
+}
+
+struct Shared<F: Backend> {
+    db: Mutex<LlsDb<F>>,
+    snapshot: RwLock<Arc<DbSnapshot>>,
+}
+
+/// The heads of the named lists as of a commit. Taken with [`SharedLlsDb::snapshot`].
+///
+/// Like the readers of a [`ReaderPool`](crate::ReaderPool) it reads with, an entry in a snapshot
+/// is only valid until a later transaction removes it and its space is reused so snapshots are
+/// best suited to append-only lists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DbSnapshot {
+    generation: Option<u64>,
+    heads: HashMap<String, Pointer>,
+}
+
+impl DbSnapshot {
+    fn take<F: Backend>(db: &mut LlsDb<F>) -> Self {
+        let heads = db
+            .named_heads()
+            .map(|(name, head)| (name.to_string(), head))
+            .collect();
+        Self {
+            generation: db.generation(),
+            heads,
+        }
+    }
+
+    /// The head of the list called `list` if it existed at the time
+    pub fn head(&self, list: &str) -> Option<Pointer> {
+        self.heads.get(list).copied()
+    }
+
+    /// The [generation](LlsDb::generation) of the database at the time
+    pub fn generation(&self) -> Option<u64> {
+        self.generation
+    }
+}
+
+impl<F: Backend> SharedLlsDb<F> {
+    pub fn new(mut db: LlsDb<F>) -> Self {
+        let snapshot = DbSnapshot::take(&mut db);
+        Self {
+            inner: Arc::new(Shared {
+                db: Mutex::new(db),
+                snapshot: RwLock::new(Arc::new(snapshot)),
+            }),
+        }
+    }
+
+    /// Run `query` in a transaction like [`LlsDb::execute`] waiting for any transaction running on
+    /// another thread to finish first
+    pub fn execute<Func, R>(&self, query: Func) -> Result<R>
+    where
+        Func: for<'a, 'tx> FnOnce(&'a mut Transaction<'tx, F>) -> Result<R>,
+    {
+        self.with_db(|db| db.execute(query))?
+    }
+
+    /// Do something else with the database (e.g. [`LlsDb::set_metrics`]) while no transaction is
+    /// running
+    pub fn with_db<R>(&self, f: impl FnOnce(&mut LlsDb<F>) -> R) -> Result<R> {
+        let mut db = self.lock()?;
+        let generation = db.generation();
+        let output = f(&mut db);
+        // databases from before the generation was kept always get a new snapshot
+        if generation.is_none() || db.generation() != generation {
+            let snapshot = Arc::new(DbSnapshot::take(&mut db));
+            *self
+                .inner
+                .snapshot
+                .write()
+                .map_err(|_| anyhow!("database snapshot lock poisoned"))? = snapshot;
+        }
+        Ok(output)
+    }
+
+    /// The heads of the lists as of the last commit. This never waits for a running transaction.
+    pub fn snapshot(&self) -> Arc<DbSnapshot> {
+        match self.inner.snapshot.read() {
+            Ok(snapshot) => snapshot.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Get the database back if this is the last handle to it
+    pub fn try_unwrap(self) -> core::result::Result<LlsDb<F>, Self> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(shared) => Ok(shared.db.into_inner().unwrap_or_else(|e| e.into_inner())),
+            Err(inner) => Err(Self { inner }),
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, LlsDb<F>>> {
+        // a panic in a transaction leaves the database without its backend
+        self.inner
+            .db
+            .lock()
+            .map_err(|_| anyhow!("a transaction panicked while holding the database lock"))
+    }
+}
+
+impl<F: Backend> Clone for SharedLlsDb<F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<F: Backend> core::fmt::Debug for SharedLlsDb<F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SharedLlsDb").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,191 @@
+//! Tools for checking that a database survives being interrupted at any point.
+//!
+//! Wrap the database in a [`RecordingBackend`], [`mark`](RecordingBackend::mark) it just before
+//! the transaction you care about and then use [`check_crash_consistency`] to load every state the
+//! backend could have been left in if the process had died part way through. Each of them should
+//! load and hold either what was there before the transaction or what was there after.
+//!
+//! Crashes are modeled as losing every write after some point. The writes before that point are
+//! assumed to have made it to the media in the order they were made.
+//!
+//! With the `testing` feature [`model`] has a harness for model based property tests of whole
+//! databases.
+use crate::{Backend, LlsDb};
+use anyhow::{anyhow, Result};
+use std::{
+    cell::{Ref, RefCell},
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+};
+
+#[cfg(feature = "testing")]
+pub mod model;
+
+/// An operation recorded by a [`RecordingBackend`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Write { offset: u64, bytes: Vec<u8> },
+    Truncate(u64),
+    Sync,
+}
+
+/// An in-memory backend that records every write so the states it passed through can be replayed.
+#[derive(Debug, Clone)]
+pub struct RecordingBackend {
+    base: Vec<u8>,
+    data: Cursor<Vec<u8>>,
+    ops: RefCell<Vec<Op>>,
+    page_size: u16,
+}
+
+impl Default for RecordingBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self::from_image(vec![])
+    }
+
+    /// Start from an existing database image
+    pub fn from_image(image: Vec<u8>) -> Self {
+        Self {
+            base: image.clone(),
+            data: Cursor::new(image),
+            ops: Default::default(),
+            page_size: 128,
+        }
+    }
+
+    /// Set the page size a database initialized on the backend will use. Defaults to `128` like
+    /// the `Cursor` backend.
+    pub fn with_page_size(mut self, page_size: u16) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Forget the operations recorded so far. Crash images will all start from the current state.
+    pub fn mark(&mut self) {
+        self.base = self.data.get_ref().clone();
+        self.ops.get_mut().clear();
+    }
+
+    /// The operations since the backend was created or last marked
+    pub fn ops(&self) -> Ref<'_, [Op]> {
+        Ref::map(self.ops.borrow(), |ops| ops.as_slice())
+    }
+
+    /// The current contents of the backend
+    pub fn image(&self) -> &[u8] {
+        self.data.get_ref()
+    }
+
+    /// What the backend would hold if only the first `n_ops` operations since the last mark had
+    /// happened.
+    pub fn crash_image(&self, n_ops: usize) -> Vec<u8> {
+        let mut image = Cursor::new(self.base.clone());
+        for op in &self.ops()[..n_ops] {
+            match op {
+                Op::Write { offset, bytes } => {
+                    image.set_position(*offset);
+                    image.write_all(bytes).expect("writing to a vec can't fail");
+                }
+                Op::Truncate(len) => image.get_mut().truncate(*len as usize),
+                Op::Sync => {}
+            }
+        }
+        image.into_inner()
+    }
+
+    /// Every state the backend could have been left in by a crash since the last mark along with
+    /// the number of operations that made it in each one.
+    pub fn crash_images(&self) -> impl Iterator<Item = (usize, Vec<u8>)> + '_ {
+        (0..=self.ops().len()).map(|n_ops| (n_ops, self.crash_image(n_ops)))
+    }
+}
+
+impl Read for RecordingBackend {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl Write for RecordingBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let offset = self.data.position();
+        let n = self.data.write(buf)?;
+        self.ops.get_mut().push(Op::Write {
+            offset,
+            bytes: buf[..n].to_vec(),
+        });
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for RecordingBackend {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.data.seek(pos)
+    }
+}
+
+impl Backend for RecordingBackend {
+    fn truncate(&mut self, size: u64) -> Result<()> {
+        self.data.get_mut().truncate(size as usize);
+        self.ops.get_mut().push(Op::Truncate(size));
+        Ok(())
+    }
+
+    fn init_max_size(&self) -> u64 {
+        u64::MAX
+    }
+
+    fn init_page_size(&self) -> u16 {
+        self.page_size
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.ops.borrow_mut().push(Op::Sync);
+        Ok(())
+    }
+}
+
+/// Load every crash image of `backend` and check `read_state` finds either `before` or `after` in
+/// it. `read_state` is given a freshly loaded database so it can set up whatever indexes it needs.
+///
+/// Errors with the first crash point where the database doesn't load or holds something else.
+pub fn check_crash_consistency<S, R>(
+    backend: &RecordingBackend,
+    before: &S,
+    after: &S,
+    mut read_state: R,
+) -> Result<()>
+where
+    S: PartialEq + core::fmt::Debug,
+    R: FnMut(&mut LlsDb<Cursor<Vec<u8>>>) -> Result<S>,
+{
+    for (n_ops, image) in backend.crash_images() {
+        let mut db = LlsDb::load(Cursor::new(image)).map_err(|e| {
+            anyhow!(
+                "database didn't load after a crash at {}/{} ops: {}",
+                n_ops,
+                backend.ops().len(),
+                e
+            )
+        })?;
+        let state = read_state(&mut db)?;
+        if &state != before && &state != after {
+            return Err(anyhow!(
+                "database held {:?} after a crash at {}/{} ops",
+                state,
+                n_ops,
+                backend.ops().len()
+            ));
+        }
+    }
+    Ok(())
+}
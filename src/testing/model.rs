@@ -0,0 +1,271 @@
+//! Model based property tests of a whole database.
+//!
+//! A [`Model`] is a plain in-memory version of some lists or indexes. [`run`] applies the same
+//! random operations to it and to a real database, committing some transactions, rolling others
+//! back and reloading the database in between, and checks the two still agree after every step.
+//! Implement [`Model`] for an [`IndexStore`](crate::index::IndexStore) of your own and use
+//! [`steps`] to generate the steps with proptest. [`ListsModel`] models plain lists.
+use crate::{LinkedList, LinkedListMut, LlsDb, Mut, Pointer, Transaction};
+use anyhow::{anyhow, Result};
+use core::fmt;
+use proptest::prelude::*;
+use std::io::Cursor;
+
+/// The backend [`run`] keeps the database in
+pub type ModelBackend = Cursor<Vec<u8>>;
+
+/// Something that can be checked against a database by [`run`]
+pub trait Model: Clone + fmt::Debug {
+    type Op: Clone + fmt::Debug;
+    /// Whatever is needed to get at the database's side of the model e.g. lists or index handles
+    type Handles: Clone;
+    /// The operations to generate
+    fn ops() -> BoxedStrategy<Self::Op>;
+    /// Take the lists and indexes the model lives in. Called when the database is first created
+    /// and again each time it's reloaded.
+    fn take(&self, tx: &mut Transaction<'_, ModelBackend>) -> Result<Self::Handles>;
+    /// Apply `op` to the model and to the database erroring if they behave differently. If the
+    /// transaction is rolled back both the model and the handles are put back the way they were.
+    fn apply(
+        &mut self,
+        handles: &mut Self::Handles,
+        tx: &mut Transaction<'_, ModelBackend>,
+        op: &Self::Op,
+    ) -> Result<()>;
+    /// Error if the database doesn't hold what the model does
+    fn check(&self, handles: &Self::Handles, tx: &mut Transaction<'_, ModelBackend>) -> Result<()>;
+}
+
+/// A step [`run`] takes
+#[derive(Debug, Clone)]
+pub enum Step<Op> {
+    /// Apply the operations in one transaction and commit it
+    Commit(Vec<Op>),
+    /// Apply the operations in one transaction and then roll it back
+    Rollback(Vec<Op>),
+    /// Drop the database and load it again from the backend
+    Reload,
+}
+
+/// Up to `max_steps` random steps for `M`
+pub fn steps<M: Model>(max_steps: usize) -> impl Strategy<Value = Vec<Step<M::Op>>> {
+    let ops = || proptest::collection::vec(M::ops(), 0..8);
+    let step = prop_oneof![
+        6 => ops().prop_map(Step::Commit),
+        2 => ops().prop_map(Step::Rollback),
+        1 => Just(Step::Reload),
+    ];
+    proptest::collection::vec(step, 0..max_steps)
+}
+
+/// Take `steps` on both `model` and a fresh database checking they agree after each one. The
+/// database checks its allocations against live data (see [`LlsDb::check_allocations`]) so it
+/// panics if it ever overwrites an entry.
+pub fn run<M: Model>(mut model: M, steps: &[Step<M::Op>]) -> Result<()> {
+    let mut db = LlsDb::init(Cursor::new(vec![]))?;
+    db.check_allocations()?;
+    let mut handles = db.execute(|tx| model.take(tx))?;
+    for (i, step) in steps.iter().enumerate() {
+        let failed = |e: anyhow::Error| anyhow!("step {} ({:?}) failed: {}", i, step, e);
+        let res = match step {
+            Step::Commit(ops) | Step::Rollback(ops) => {
+                let commit = matches!(step, Step::Commit(_));
+                let (mut tx_model, mut tx_handles) = (model.clone(), handles.clone());
+                let res = db.execute(|tx| {
+                    for op in ops {
+                        tx_model.apply(&mut tx_handles, tx, op)?;
+                    }
+                    tx_model.check(&tx_handles, tx)?;
+                    if commit {
+                        Ok(())
+                    } else {
+                        tx.abort("rolling back")
+                    }
+                });
+                match res {
+                    Ok(()) => {
+                        model = tx_model;
+                        handles = tx_handles;
+                        Ok(())
+                    }
+                    Err(e) if e.is::<crate::Aborted>() => Ok(()),
+                    Err(e) => Err(e),
+                }
+            }
+            Step::Reload => {
+                db = LlsDb::load(db.into_backend()).map_err(failed)?;
+                db.check_allocations().and_then(|()| {
+                    handles = db.execute(|tx| model.take(tx))?;
+                    Ok(())
+                })
+            }
+        };
+        res.and_then(|()| db.execute(|tx| model.check(&handles, tx)))
+            .map_err(failed)?;
+    }
+    Ok(())
+}
+
+/// An operation on a [`ListsModel`]. Lists are picked by their position modulo the number of
+/// lists and entries by their position from the newest modulo the length of the list.
+#[derive(Debug, Clone)]
+pub enum ListOp {
+    Create,
+    Push { list: usize, value: u32 },
+    Pop { list: usize },
+    Unlink { list: usize, entry: usize },
+    Relocate { list: usize, entry: usize },
+}
+
+/// Models a few lists of `u32`s as vecs (newest first) that are created, pushed and popped and
+/// have entries unlinked or relocated from anywhere in them.
+///
+/// An entry is only unlinked if the entry it points to on disk is still the one after it in the
+/// list. Unlinking it after what's behind it has been unlinked writes a remap to a stale pointer
+/// which can be resolved to the wrong entry once that space is reused, so those unlinks are
+/// skipped.
+#[derive(Debug, Clone, Default)]
+pub struct ListsModel {
+    lists: Vec<Vec<u32>>,
+}
+
+impl ListsModel {
+    const MAX_LISTS: usize = 4;
+
+    fn list_name(i: usize) -> String {
+        format!("model-{}", i)
+    }
+
+    /// Where every entry still in `list` is (including its remap entries) in order
+    fn entries_in(
+        tx: &Transaction<'_, ModelBackend>,
+        list: &LinkedList<Mut<u32>>,
+    ) -> Result<Vec<Pointer>> {
+        let mut entries = vec![];
+        let mut it = tx.io.iter(list.slot());
+        while let Some((handle, value)) = it.next_with_handle::<Mut<u32>>().transpose()? {
+            entries.push(handle.entry_pointer.this_entry);
+            if let Mut::Remap(remap) = value {
+                it.remap(remap);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+impl Model for ListsModel {
+    type Op = ListOp;
+    type Handles = Vec<LinkedList<Mut<u32>>>;
+
+    fn ops() -> BoxedStrategy<ListOp> {
+        prop_oneof![
+            1 => Just(ListOp::Create),
+            6 => (any::<usize>(), any::<u32>())
+                .prop_map(|(list, value)| ListOp::Push { list, value }),
+            2 => any::<usize>().prop_map(|list| ListOp::Pop { list }),
+            2 => (any::<usize>(), any::<usize>())
+                .prop_map(|(list, entry)| ListOp::Unlink { list, entry }),
+            1 => (any::<usize>(), any::<usize>())
+                .prop_map(|(list, entry)| ListOp::Relocate { list, entry }),
+        ]
+        .boxed()
+    }
+
+    fn take(&self, tx: &mut Transaction<'_, ModelBackend>) -> Result<Self::Handles> {
+        (0..self.lists.len())
+            .map(|i| tx.take_list(&Self::list_name(i)))
+            .collect()
+    }
+
+    fn apply(
+        &mut self,
+        handles: &mut Self::Handles,
+        tx: &mut Transaction<'_, ModelBackend>,
+        op: &ListOp,
+    ) -> Result<()> {
+        if let ListOp::Create = op {
+            if self.lists.len() < Self::MAX_LISTS {
+                handles.push(tx.take_list(&Self::list_name(self.lists.len()))?);
+                self.lists.push(vec![]);
+            }
+            return Ok(());
+        }
+        let (ListOp::Push { list, .. }
+        | ListOp::Pop { list }
+        | ListOp::Unlink { list, .. }
+        | ListOp::Relocate { list, .. }) = op
+        else {
+            unreachable!("handled above");
+        };
+        if self.lists.is_empty() {
+            return Ok(());
+        }
+        let i = list % self.lists.len();
+        let (model, list) = (&mut self.lists[i], LinkedListMut(handles[i].clone()));
+        let api = list.api(&*tx);
+        match *op {
+            ListOp::Push { value, .. } => {
+                api.push(value)?;
+                model.insert(0, value);
+            }
+            ListOp::Pop { .. } => {
+                let popped = api.pop()?;
+                let expected = (!model.is_empty()).then(|| model.remove(0));
+                if popped != expected {
+                    return Err(anyhow!("popped {:?} expected {:?}", popped, expected));
+                }
+            }
+            ListOp::Unlink { entry, .. } if !model.is_empty() => {
+                let entry = entry % model.len();
+                let (handle, value) = api
+                    .iter_handles()
+                    .nth(entry)
+                    .ok_or(anyhow!("list {} is too short", i))??;
+                if value != model[entry] {
+                    return Err(anyhow!(
+                        "entry {} is {} expected {}",
+                        entry,
+                        value,
+                        model[entry]
+                    ));
+                }
+                let entries = Self::entries_in(tx, &handles[i])?;
+                let after = entries
+                    .iter()
+                    .position(|pointer| *pointer == handle.entry_pointer.this_entry)
+                    .and_then(|position| entries.get(position + 1))
+                    .copied()
+                    .unwrap_or(Pointer::NULL);
+                if after == handle.entry_pointer.next_entry_possibly_stale {
+                    api.unlink(handle)?;
+                    model.remove(entry);
+                }
+            }
+            ListOp::Relocate { entry, .. } if !model.is_empty() => {
+                let (handle, _) = api
+                    .iter_handles()
+                    .nth(entry % model.len())
+                    .ok_or(anyhow!("list {} is too short", i))??;
+                api.relocate(handle)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn check(&self, handles: &Self::Handles, tx: &mut Transaction<'_, ModelBackend>) -> Result<()> {
+        for (i, (model, list)) in self.lists.iter().zip(handles).enumerate() {
+            let list = LinkedListMut(list.clone());
+            let values = list.api(&*tx).iter().collect::<Result<Vec<_>>>()?;
+            if &values != model {
+                return Err(anyhow!(
+                    "list {} holds {:?} expected {:?}",
+                    i,
+                    values,
+                    model
+                ));
+            }
+        }
+        Ok(())
+    }
+}
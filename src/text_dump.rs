@@ -0,0 +1,245 @@
+use crate::{Backend, Endian, InitOptions, IntEncoding, LlsDb, ValueEncoding};
+use anyhow::{anyhow, Context, Result};
+use bincode::enc::{write::Writer, Encoder};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read, Write},
+};
+
+const DUMP_MAGIC: &str = "llsdb-dump 1";
+
+impl<F: Backend> LlsDb<F> {
+    /// Write the contents of every list as text that is the same for any two databases holding
+    /// the same values in the same lists however they're laid out in the file. Check it in as a
+    /// golden file or diff the dumps of two databases to see how they differ.
+    ///
+    /// The dump looks like this:
+    ///
+    /// ```text
+    /// llsdb-dump 1
+    /// encoding varint little
+    /// list "ints" 2
+    ///   01
+    ///   02
+    /// list "strings" 1
+    ///   0568656c6c6f
+    /// end
+    /// ```
+    ///
+    /// Lists are in order of their names and the values in each (in hex) from the oldest to the
+    /// newest. Nothing about where things are in the file, the free space or the generation is
+    /// included. The bytes of each value are found like
+    /// [`Transaction::dump`](crate::Transaction::dump) finds them so they include the padding of
+    /// databases whose entries are padded and the dump of a list with entries unlinked from its
+    /// middle stops early.
+    pub fn dump_to(&mut self, mut writer: impl Write) -> Result<()> {
+        let mut lists = self
+            .lists()
+            .map(|name| (name.to_string(), vec![]))
+            .collect::<BTreeMap<String, Vec<Vec<u8>>>>();
+        let value_encoding = self.execute_read_only(|tx| {
+            for entry in tx.dump()? {
+                let entry = entry?;
+                let Some(values) = entry
+                    .list_name
+                    .as_ref()
+                    .and_then(|name| lists.get_mut(name))
+                else {
+                    continue;
+                };
+                let prev_len = entry.entry_pointer.link_len() as usize;
+                values.push(entry.bytes[prev_len..].to_vec());
+            }
+            Ok(tx.io.value_encoding())
+        })?;
+
+        writeln!(writer, "{}", DUMP_MAGIC)?;
+        writeln!(
+            writer,
+            "encoding {} {}",
+            match value_encoding.int_encoding {
+                IntEncoding::Varint => "varint",
+                IntEncoding::Fixint => "fixint",
+            },
+            match value_encoding.endian {
+                Endian::Little => "little",
+                Endian::Big => "big",
+            }
+        )?;
+        for (name, values) in lists {
+            writeln!(writer, "list {:?} {}", name, values.len())?;
+            // the dump walks each list from its head
+            for value in values.iter().rev() {
+                write!(writer, "  ")?;
+                for byte in value {
+                    write!(writer, "{:02x}", byte)?;
+                }
+                writeln!(writer)?;
+            }
+        }
+        writeln!(writer, "end")?;
+        Ok(())
+    }
+
+    /// Create a database in an empty `file` with the lists and values of a dump written by
+    /// [`dump_to`](Self::dump_to).
+    ///
+    /// Values are copied byte for byte so a list whose values point at other entries (like the
+    /// nodes of a [`BigBTreeMap`](crate::index::BigBTreeMap)) won't make sense in the new database.
+    pub fn restore_from(file: F, reader: impl Read) -> Result<Self> {
+        let mut lines = BufReader::new(reader)
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line));
+        let mut next_line = || -> Result<(usize, String)> {
+            match lines.next() {
+                Some((n, line)) => Ok((n, line?)),
+                None => Err(anyhow!("dump ends before its last line")),
+            }
+        };
+
+        let (_, magic) = next_line()?;
+        if magic != DUMP_MAGIC {
+            return Err(anyhow!("not a dump (starts with '{}')", magic));
+        }
+        let (n, encoding) = next_line()?;
+        let value_encoding = parse_encoding(&encoding).with_context(|| format!("line {}", n))?;
+
+        let mut lists = vec![];
+        loop {
+            let (n, line) = next_line()?;
+            if line == "end" {
+                break;
+            }
+            let (name, n_values) = parse_list(&line).with_context(|| format!("line {}", n))?;
+            let mut values = Vec::with_capacity(n_values.min(1024));
+            for _ in 0..n_values {
+                let (n, line) = next_line()?;
+                let value = line
+                    .strip_prefix("  ")
+                    .ok_or(anyhow!("expected a value"))
+                    .and_then(parse_hex)
+                    .with_context(|| format!("line {}", n))?;
+                values.push(value);
+            }
+            lists.push((name, values));
+        }
+
+        let mut db = Self::init_with_options(
+            file,
+            InitOptions {
+                value_encoding,
+                ..Default::default()
+            },
+        )?;
+        let taken = db.execute(|tx| {
+            let mut taken = vec![];
+            for (name, values) in &lists {
+                let list = tx.take_list::<()>(name)?;
+                for value in values {
+                    tx.io.push(list.slot(), &RawValue(value))?;
+                }
+                taken.push(list);
+            }
+            Ok(taken)
+        })?;
+        for list in taken {
+            db.release_list(list);
+        }
+        Ok(db)
+    }
+}
+
+/// Bytes that are already encoded written as they are
+struct RawValue<'a>(&'a [u8]);
+
+impl bincode::Encode for RawValue<'_> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), bincode::error::EncodeError> {
+        encoder.writer().write(self.0)
+    }
+}
+
+fn parse_encoding(line: &str) -> Result<ValueEncoding> {
+    let mut words = line.split(' ');
+    if words.next() != Some("encoding") {
+        return Err(anyhow!("expected the encoding"));
+    }
+    let int_encoding = match words.next() {
+        Some("varint") => IntEncoding::Varint,
+        Some("fixint") => IntEncoding::Fixint,
+        other => return Err(anyhow!("unknown int encoding {:?}", other)),
+    };
+    let endian = match words.next() {
+        Some("little") => Endian::Little,
+        Some("big") => Endian::Big,
+        other => return Err(anyhow!("unknown endianness {:?}", other)),
+    };
+    Ok(ValueEncoding {
+        int_encoding,
+        endian,
+    })
+}
+
+/// Parse `list "<name>" <n_values>` where the name is quoted like `{:?}` quotes it
+fn parse_list(line: &str) -> Result<(String, usize)> {
+    let quoted = line
+        .strip_prefix("list ")
+        .ok_or(anyhow!("expected a list or the end"))?;
+    let (n_values, name) = match quoted.rsplit_once(' ') {
+        Some((quoted, n_values)) => (n_values.parse::<usize>()?, unquote(quoted)?),
+        None => return Err(anyhow!("expected the number of values in the list")),
+    };
+    Ok((name, n_values))
+}
+
+fn unquote(quoted: &str) -> Result<String> {
+    let inner = quoted
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .ok_or(anyhow!("list name isn't quoted"))?;
+    let mut name = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            name.push(c);
+            continue;
+        }
+        let unescaped = match chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '"' | '\'')) => c,
+            Some('u') => {
+                let rest = chars.as_str();
+                let (hex, after) = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .ok_or(anyhow!("bad unicode escape in list name"))?;
+                chars = after.chars();
+                u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(anyhow!("bad unicode escape in list name"))?
+            }
+            other => return Err(anyhow!("unknown escape {:?} in list name", other)),
+        };
+        name.push(unescaped);
+    }
+    Ok(name)
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(anyhow!("odd number of hex digits"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .filter(|byte| byte.bytes().all(|digit| digit.is_ascii_hexdigit()))
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(anyhow!("invalid hex '{}'", hex))
+        })
+        .collect()
+}
@@ -0,0 +1,167 @@
+use crate::{Backend, Clock, SystemClock};
+use anyhow::Result;
+use std::{
+    cell::Cell,
+    io::{self, Read, Seek, SeekFrom, Write},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// How much IO a [`ThrottledBackend`] lets through. `None` means no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Bytes read and written per second
+    pub bytes_per_sec: Option<u64>,
+    /// Reads, writes, truncates and syncs per second
+    pub ops_per_sec: Option<u64>,
+}
+
+/// How far ahead of the rate IO can get before it's made to wait
+const BURST: Duration = Duration::from_secs(1);
+
+/// A [`Backend`] that limits how fast IO goes to the backend it wraps by sleeping once it gets
+/// ahead of its [`RateLimit`]. Up to a second's worth of IO can go through at once after it has
+/// been idle. Use it for a database that's loaded in bulk or compacted in the background so it
+/// doesn't starve everything else of IO on a small device.
+///
+/// The limit can be changed while the database is using the backend through
+/// [`LlsDb::backend`](crate::LlsDb::backend) (e.g. lifted while nothing else is going on).
+pub struct ThrottledBackend<B> {
+    inner: B,
+    limit: Cell<RateLimit>,
+    clock: Arc<dyn Clock>,
+    /// When the IO done so far would have finished at exactly the rate of each limit
+    bytes_until: Cell<Option<SystemTime>>,
+    ops_until: Cell<Option<SystemTime>>,
+}
+
+impl<B> ThrottledBackend<B> {
+    pub fn new(inner: B, limit: RateLimit) -> Self {
+        Self::with_clock(inner, limit, Arc::new(SystemClock))
+    }
+
+    /// Like [`new`](Self::new) but the time is taken from `clock` and it does the sleeping
+    pub fn with_clock(inner: B, limit: RateLimit, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner,
+            limit: Cell::new(limit),
+            clock,
+            bytes_until: Cell::new(None),
+            ops_until: Cell::new(None),
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit.get()
+    }
+
+    pub fn set_limit(&self, limit: RateLimit) {
+        self.limit.set(limit);
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Account for an operation of `bytes` bytes and sleep if that puts IO too far ahead
+    fn throttle(&self, bytes: u64) {
+        let RateLimit {
+            bytes_per_sec,
+            ops_per_sec,
+        } = self.limit.get();
+        let now = self.clock.now();
+        let wait = [
+            (&self.bytes_until, bytes_per_sec, bytes),
+            (&self.ops_until, ops_per_sec, 1),
+        ]
+        .into_iter()
+        .map(|(until, rate, cost)| match rate.filter(|rate| *rate > 0) {
+            Some(rate) => {
+                let start = until.get().filter(|until| *until > now).unwrap_or(now);
+                until.set(Some(
+                    start + Duration::from_secs_f64(cost as f64 / rate as f64),
+                ));
+                start
+                    .duration_since(now)
+                    .unwrap_or_default()
+                    .saturating_sub(BURST)
+            }
+            None => {
+                until.set(None);
+                Duration::ZERO
+            }
+        })
+        .max()
+        .unwrap_or_default();
+        if !wait.is_zero() {
+            self.clock.sleep(wait);
+        }
+    }
+}
+
+impl<B: core::fmt::Debug> core::fmt::Debug for ThrottledBackend<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ThrottledBackend")
+            .field("inner", &self.inner)
+            .field("limit", &self.limit.get())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: Read> Read for ThrottledBackend<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.throttle(n as u64);
+        Ok(n)
+    }
+}
+
+impl<B: Write> Write for ThrottledBackend<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.throttle(n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<B: Seek> Seek for ThrottledBackend<B> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<B: Backend> Backend for ThrottledBackend<B> {
+    fn truncate(&mut self, size: u64) -> Result<()> {
+        self.throttle(0);
+        self.inner.truncate(size)
+    }
+
+    fn init_max_size(&self) -> u64 {
+        self.inner.init_max_size()
+    }
+
+    fn init_page_size(&self) -> u16 {
+        self.inner.init_page_size()
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.throttle(0);
+        self.inner.sync_data()
+    }
+
+    fn discard(&mut self, position: u64, len: u64) -> Result<()> {
+        self.inner.discard(position, len)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+}
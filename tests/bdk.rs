@@ -0,0 +1,45 @@
+#![cfg(feature = "bdk")]
+use bdk_wallet::{bitcoin::Network, KeychainKind, Wallet};
+use llsdb::{LlsDb, WalletStore};
+use std::io::Cursor;
+
+const DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/0/*)";
+const CHANGE_DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/1/*)";
+
+#[test]
+fn bdk_wallet_persists() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let mut store = WalletStore::new(&mut db, "wallet").unwrap();
+    let mut wallet = Wallet::create(DESCRIPTOR, CHANGE_DESCRIPTOR)
+        .network(Network::Testnet)
+        .create_wallet(&mut store)
+        .unwrap();
+    let revealed = wallet
+        .reveal_addresses_to(KeychainKind::External, 5)
+        .count();
+    assert_eq!(revealed, 6);
+    assert!(wallet.persist(&mut store).unwrap());
+    let next = wallet.reveal_next_address(KeychainKind::External);
+    assert!(wallet.persist(&mut store).unwrap());
+    assert!(!wallet.persist(&mut store).unwrap());
+    store.compact().unwrap();
+    drop(wallet);
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let mut store = WalletStore::new(&mut db, "wallet").unwrap();
+    let wallet = Wallet::load()
+        .descriptor(KeychainKind::External, Some(DESCRIPTOR))
+        .descriptor(KeychainKind::Internal, Some(CHANGE_DESCRIPTOR))
+        .extract_keys()
+        .check_network(Network::Testnet)
+        .load_wallet(&mut store)
+        .unwrap()
+        .expect("wallet was persisted");
+    assert_eq!(wallet.derivation_index(KeychainKind::External), Some(6));
+    assert_eq!(
+        wallet.peek_address(KeychainKind::External, 6).address,
+        next.address
+    );
+}
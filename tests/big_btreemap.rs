@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Result};
+use llsdb::{
+    index::{BigBTreeMap, BigBTreeMapApi},
+    LlsDb,
+};
+use std::collections::BTreeMap as StdBTreeMap;
+use std::io::Cursor;
+
+#[test]
+fn big_btreemap_matches_std() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let mut model = StdBTreeMap::new();
+
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list("big")?;
+            let handle = tx.store_index(BigBTreeMap::<u32, String>::new(list, 4, &tx)?);
+            let mut map = tx.take_index(handle);
+            for i in (0..200u32).map(|i| (i * 37) % 200) {
+                assert_eq!(map.insert(i, i.to_string())?, None);
+                model.insert(i, i.to_string());
+            }
+            assert_eq!(map.insert(5, "five".into())?, Some("5".into()));
+            model.insert(5, "five".into());
+            Ok(handle)
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let mut map = tx.take_index(handle);
+        for i in (0..200u32).filter(|i| i % 3 == 0) {
+            assert_eq!(map.remove(&i)?, model.remove(&i));
+        }
+        assert_eq!(map.remove(&3)?, None);
+        Ok(())
+    })
+    .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut map = tx.take_index(handle);
+        for i in 0..200u32 {
+            map.remove(&i)?;
+        }
+        map.insert(1_000, "gone".into())?;
+        assert_eq!(map.len(), 1);
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| check(&tx.take_index(handle), &model))
+        .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("big")?;
+        assert!(BigBTreeMap::<u32, String>::new(list.clone(), 8, &tx).is_err());
+        let (_, map) = tx.store_and_take_index(BigBTreeMap::new(list, 4, &tx)?);
+        check(&map, &model)
+    })
+    .unwrap();
+}
+
+fn check<F: llsdb::Backend>(
+    map: &BigBTreeMapApi<'_, F, u32, String>,
+    model: &StdBTreeMap<u32, String>,
+) -> Result<()> {
+    assert_eq!(map.len(), model.len() as u64);
+    assert_eq!(map.get(&5)?, Some("five".into()));
+    assert_eq!(map.get(&6)?, None);
+    assert_eq!(
+        map.iter().collect::<Result<Vec<_>>>()?,
+        model.clone().into_iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        map.range(50..=100).collect::<Result<Vec<_>>>()?,
+        model
+            .range(50..=100)
+            .map(|(k, v)| (*k, v.clone()))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        map.range(190..)
+            .map(|res| res.map(|(k, _)| k))
+            .collect::<Result<Vec<_>>>()?,
+        model.range(190..).map(|(k, _)| *k).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn big_btreemap_reuses_freed_nodes() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list("big")?;
+            let handle = tx.store_index(BigBTreeMap::<u64, u64>::new(list, 8, &tx)?);
+            let mut map = tx.take_index(handle);
+            for i in 0..100 {
+                map.insert(i, i)?;
+            }
+            Ok(handle)
+        })
+        .unwrap();
+
+    let len_after_first = db.backend().get_ref().len();
+    for round in 0..20 {
+        db.execute(|tx| {
+            let mut map = tx.take_index(handle);
+            map.insert(round % 100, round)?;
+            Ok(())
+        })
+        .unwrap();
+    }
+    // each change frees the path it replaced so the file shouldn't keep growing
+    assert!(db.backend().get_ref().len() <= len_after_first + 1024);
+}
@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use llsdb::{
+    index::{BigHashMap, BigHashMapApi},
+    LlsDb,
+};
+use std::collections::BTreeMap as StdBTreeMap;
+use std::io::Cursor;
+
+#[test]
+fn big_hashmap_matches_std() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let mut model = StdBTreeMap::new();
+
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list("big")?;
+            let handle = tx.store_index(BigHashMap::<u32, String>::new(list, 2, &tx)?);
+            let mut map = tx.take_index(handle);
+            for i in 0..1_000u32 {
+                assert_eq!(map.insert(i, i.to_string())?, None);
+                model.insert(i, i.to_string());
+            }
+            // enough buckets to need more than one segment
+            assert_eq!(map.n_buckets(), 1 + (1_000 - 1) / 2);
+            assert_eq!(map.insert(5, "five".into())?, Some("5".into()));
+            model.insert(5, "five".into());
+            Ok(handle)
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let mut map = tx.take_index(handle);
+        for i in (0..1_000u32).filter(|i| i % 3 == 0) {
+            assert_eq!(map.remove(&i)?, model.remove(&i));
+        }
+        assert_eq!(map.remove(&3)?, None);
+        Ok(())
+    })
+    .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut map = tx.take_index(handle);
+        for i in 0..100u32 {
+            map.remove(&i)?;
+        }
+        map.insert(10_000, "gone".into())?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| check(&tx.take_index(handle), &model))
+        .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("big")?;
+        assert!(BigHashMap::<u32, String>::new(list.clone(), 3, &tx).is_err());
+        let (_, map) = tx.store_and_take_index(BigHashMap::new(list, 2, &tx)?);
+        check(&map, &model)
+    })
+    .unwrap();
+}
+
+fn check<F: llsdb::Backend>(
+    map: &BigHashMapApi<'_, F, u32, String>,
+    model: &StdBTreeMap<u32, String>,
+) -> Result<()> {
+    assert_eq!(map.len(), model.len() as u64);
+    assert_eq!(map.get(&5)?, Some("five".into()));
+    assert_eq!(map.get(&6)?, None);
+    assert!(!map.contains_key(&10_000)?);
+    let mut entries = map.iter().collect::<Result<Vec<_>>>()?;
+    entries.sort();
+    assert_eq!(entries, model.clone().into_iter().collect::<Vec<_>>());
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use anyhow::anyhow;
+use llsdb::{index::BitSet, LlsDb};
+use std::io::Cursor;
+
+#[test]
+fn bitset_sets_and_clears_across_chunks() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let bitset = db
+        .execute(|tx| {
+            let list = tx.take_list("flags")?;
+            let handle = tx.store_index(BitSet::new(list, &tx)?);
+            let mut bitset = tx.take_index(handle);
+            assert!(!bitset.set(3)?);
+            assert!(bitset.set(3)?);
+            // a long run, a sparse chunk and a dense chunk
+            bitset.set_all(100_000..150_000)?;
+            bitset.set_all((1 << 20..(1 << 20) + 60_000).step_by(3))?;
+            bitset.set(5_000_000_000)?;
+            assert!(bitset.clear(100_001)?);
+            assert!(!bitset.clear(100_001)?);
+            assert!(bitset.contains(5_000_000_000));
+            assert!(!bitset.contains(100_001));
+            assert_eq!(bitset.count_ones(), 1 + 49_999 + 20_000 + 1);
+            Ok(handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut bitset = tx.take_index(bitset);
+        bitset.clear_all(0..200_000)?;
+        bitset.set(7)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let bitset = tx.take_index(bitset);
+        assert!(bitset.contains(3));
+        assert!(!bitset.contains(7));
+        assert_eq!(
+            bitset.iter_ones().take(3).collect::<Vec<_>>(),
+            vec![3, 100_000, 100_002]
+        );
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("flags")?;
+        let bitset = BitSet::new(list, &tx)?;
+        let handle = tx.store_index(bitset);
+        let bitset = tx.take_index(handle);
+        assert_eq!(bitset.count_ones(), 1 + 49_999 + 20_000 + 1);
+        assert_eq!(bitset.iter_ones().last(), Some(5_000_000_000));
+        assert!(bitset.contains((1 << 20) + 3));
+        assert!(!bitset.contains((1 << 20) + 4));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn bitset_bitwise_ops() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    db.execute(|tx| {
+        let (a, b) = (tx.take_list("a")?, tx.take_list("b")?);
+        let (a, b) = (
+            tx.store_index(BitSet::new(a, &tx)?),
+            tx.store_index(BitSet::new(b, &tx)?),
+        );
+        let (mut a, mut b) = (tx.take_index(a), tx.take_index(b));
+        a.set_all([1, 2, 3, 70_000])?;
+        b.set_all([2, 3, 4, 200_000])?;
+
+        a.intersect_with(&b)?;
+        assert_eq!(a.iter_ones().collect::<Vec<_>>(), vec![2, 3]);
+        a.union_with(&b)?;
+        assert_eq!(a.iter_ones().collect::<Vec<_>>(), vec![2, 3, 4, 200_000]);
+        a.symmetric_difference_with(&b)?;
+        assert!(a.is_empty());
+        b.set(9)?;
+        a.set_all([3, 9, 10])?;
+        a.difference_with(&b)?;
+        assert_eq!(a.iter_ones().collect::<Vec<_>>(), vec![10]);
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,63 @@
+use anyhow::anyhow;
+use llsdb::{
+    index::{BlobHash, BlobStore},
+    LlsDb,
+};
+use std::io::Cursor;
+
+#[test]
+fn blob_store_dedups_and_counts_refs() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let attachment = vec![7u8; 1_000];
+
+    let (handle, hash) = db
+        .execute(|tx| {
+            let blobs = tx.take_list("blobs")?;
+            let refs = tx.take_list("blob_refs")?;
+            let handle = tx.store_index(BlobStore::new(blobs, refs, &tx)?);
+            let mut store = tx.take_index(handle);
+            let hash = store.put(&attachment)?;
+            assert_eq!(hash, BlobHash::of(&attachment));
+            assert_eq!(store.put(&attachment)?, hash);
+            store.put(b"other")?;
+            assert_eq!(store.len(), 2);
+            assert_eq!(store.n_refs(&hash)?, 2);
+            Ok((handle, hash))
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut store = tx.take_index(handle);
+        assert_eq!(store.release(&hash)?, 1);
+        assert_eq!(store.release(&hash)?, 0);
+        assert!(!store.contains(&hash));
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let mut store = tx.take_index(handle);
+        assert_eq!(store.get(&hash)?, Some(attachment.clone()));
+        assert_eq!(store.release(&hash)?, 1);
+        assert!(store.release(&BlobHash::of(b"missing")).is_err());
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let blobs = tx.take_list("blobs")?;
+        let refs = tx.take_list("blob_refs")?;
+        let (_, mut store) = tx.store_and_take_index(BlobStore::new(blobs, refs, &tx)?);
+        assert_eq!(store.n_refs(&hash)?, 1);
+        assert_eq!(store.release(&hash)?, 0);
+        assert_eq!(store.get(&hash)?, None);
+        assert_eq!(
+            store.hashes().collect::<Vec<_>>(),
+            [&BlobHash::of(b"other")]
+        );
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,93 @@
+use anyhow::anyhow;
+use llsdb::{
+    index::{BloomFilter, BloomRecord},
+    LlsDb,
+};
+use std::io::Cursor;
+
+#[test]
+fn bloom_filter() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let bloom = db
+        .execute(|tx| {
+            let list = tx.take_list("bloom")?;
+            let handle = tx.store_index(BloomFilter::new(list, 1024, 3, tx)?);
+            let mut bloom = tx.take_index(handle);
+            for i in 0..50u32 {
+                assert!(!bloom.maybe_contains(&i.to_le_bytes()));
+                bloom.insert(&i.to_le_bytes())?;
+            }
+            assert!(bloom.insert(&0u32.to_le_bytes())?);
+            Ok(handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut bloom = tx.take_index(bloom);
+        bloom.insert(b"rolled back")?;
+        assert!(bloom.maybe_contains(b"rolled back"));
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let mut bloom = tx.take_index(bloom);
+        assert!(!bloom.maybe_contains(b"rolled back"));
+        bloom.insert(b"hello")?;
+        bloom.compact()?;
+        bloom.insert(b"world")?;
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("bloom")?;
+        assert!(BloomFilter::new(list.clone(), 2048, 3, tx).is_err());
+        let handle = tx.store_index(BloomFilter::new(list, 1024, 3, tx)?);
+        let bloom = tx.take_index(handle);
+        for i in 0..50u32 {
+            assert!(bloom.maybe_contains(&i.to_le_bytes()));
+        }
+        assert!(bloom.maybe_contains(b"hello"));
+        assert!(bloom.maybe_contains(b"world"));
+        let false_positives = (50..1050u32)
+            .filter(|i| bloom.maybe_contains(&i.to_le_bytes()))
+            .count();
+        assert!(false_positives < 50, "{}", false_positives);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn corrupt_bloom_records_are_errors() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("bloom")?;
+        BloomFilter::new(list.clone(), 64, 3, tx)?;
+        list.api(&tx).push(&BloomRecord::Set(vec![64]))?;
+        let empty = tx.take_list("empty")?;
+        empty.api(&tx).push(&BloomRecord::Snapshot {
+            n_hashes: 3,
+            bits: vec![],
+        })?;
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("bloom")?;
+        let error = BloomFilter::new(list, 64, 3, tx).unwrap_err();
+        assert!(error.to_string().contains("bit 64"), "{}", error);
+        let empty = tx.take_list("empty")?;
+        assert!(BloomFilter::new(empty, 64, 3, tx).is_err());
+        Ok(())
+    })
+    .unwrap();
+}
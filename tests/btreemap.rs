@@ -1,6 +1,13 @@
 use anyhow::{anyhow, Result};
-use llsdb::{index::BTreeMap, LlsDb};
+use llsdb::{
+    index::{
+        bytes_prefix_end, str_prefix_end, BTreeMap, BTreeMapRemove, DuplicateKey, IntegrityError,
+        OnRemove, Relation, Unique,
+    },
+    LlsDb, Mut,
+};
 use std::io::Cursor;
+use std::ops::Bound;
 
 #[test]
 fn btreemap_basic() {
@@ -111,6 +118,7 @@ fn btreemap_overwriting_values() {
         Ok(())
     })
     .unwrap();
+    drop(db);
 
     let mut db = LlsDb::load_or_init(Cursor::new(&mut backend)).unwrap();
     db.execute(|tx| {
@@ -158,3 +166,426 @@ fn btreemap_repeated_identical_insert_doesnt_grow() {
 
     assert_eq!(db.backend().get_ref().len(), size_before_redundant_insert);
 }
+
+#[test]
+fn btreemap_key_only_ops() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    db.execute(|tx| {
+        let list = tx.take_list::<(u32, String)>("btree")?;
+        let map_handle = tx.store_index(BTreeMap::new(list, &tx)?);
+        let mut map = tx.take_index(map_handle);
+        assert_eq!(map.first_key(), None);
+        assert_eq!(map.last_key(), None);
+        for i in [3, 1, 2] {
+            map.insert(i, &i.to_string())?;
+        }
+        assert!(map.contains_key(&2));
+        assert!(!map.contains_key(&4));
+        assert_eq!(map.first_key(), Some(&1));
+        assert_eq!(map.last_key(), Some(&3));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn btreemap_remove() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let map_handle = db
+        .execute(|tx| {
+            let list = tx.take_list::<Mut<(u32, String)>>("btree")?;
+            let map_handle = tx.store_index(BTreeMapRemove::new(list, &tx)?);
+            let mut map = tx.take_index(map_handle);
+            for i in 0..5 {
+                map.insert(i, i.to_string())?;
+            }
+            assert_eq!(map.insert(2, "two".into())?, Some("2".to_string()));
+            Ok(map_handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut map = tx.take_index(map_handle);
+        assert_eq!(map.pop_first()?, Some((0, "0".to_string())));
+        assert_eq!(map.remove(&2)?, Some("two".to_string()));
+        Err::<(), _>(anyhow!("fail the tx"))
+    });
+
+    db.execute(|tx| {
+        let mut map = tx.take_index(map_handle);
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.pop_first()?, Some((0, "0".to_string())));
+        assert_eq!(map.pop_last()?, Some((4, "4".to_string())));
+        assert_eq!(map.remove(&2)?, Some("two".to_string()));
+        assert_eq!(map.remove(&2)?, None);
+        assert!(!map.contains_key(&2));
+        map.debug_validate()?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<(u32, String)>>("btree")?;
+        let map_handle = tx.store_index(BTreeMapRemove::new(list, &tx)?);
+        let mut map = tx.take_index(map_handle);
+        assert_eq!(
+            map.iter().collect::<Result<Vec<_>>>()?,
+            vec![(1, "1".to_string()), (3, "3".to_string())]
+        );
+        assert_eq!(map.pop_last()?, Some((3, "3".to_string())));
+        assert_eq!(map.pop_last()?, Some((1, "1".to_string())));
+        assert_eq!(map.pop_last()?, None);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn btreemap_iter_prefix() {
+    assert_eq!(bytes_prefix_end(&[1, 0xff, 0xff]), Some(vec![2]));
+    assert_eq!(bytes_prefix_end(&[0xff]), None);
+    assert_eq!(str_prefix_end("ab"), Some("ac".to_string()));
+    assert_eq!(str_prefix_end("a\u{d7ff}"), Some("a\u{e000}".to_string()));
+    assert_eq!(str_prefix_end("a\u{10ffff}"), Some("b".to_string()));
+    assert_eq!(str_prefix_end(""), None);
+
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    db.execute(|tx| {
+        let list = tx.take_list::<(String, u32)>("words")?;
+        let handle = tx.store_index(BTreeMap::new(list, &tx)?);
+        let mut map = tx.take_index(handle);
+        for (i, word) in ["car", "card", "care", "cart", "cat", "ca\u{10ffff}", "cb"]
+            .into_iter()
+            .enumerate()
+        {
+            map.insert(word.to_string(), &(i as u32))?;
+        }
+        let keys = |prefix: &str| -> Result<Vec<String>> {
+            map.iter_prefix(&prefix.to_string())
+                .map(|res| res.map(|(key, _)| key))
+                .collect()
+        };
+        assert_eq!(keys("car")?, vec!["car", "card", "care", "cart"]);
+        assert_eq!(keys("ca\u{10ffff}")?, vec!["ca\u{10ffff}"]);
+        assert_eq!(keys("cat")?, vec!["cat"]);
+        assert_eq!(keys("d")?, Vec::<String>::new());
+        assert_eq!(keys("")?.len(), 7);
+        Ok(())
+    })
+    .unwrap();
+
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<(Vec<u8>, u32)>>("bytes")?;
+        let handle = tx.store_index(BTreeMapRemove::new(list, &tx)?);
+        let mut map = tx.take_index(handle);
+        for (i, key) in [vec![1], vec![1, 0xff], vec![1, 0xff, 0], vec![2]]
+            .into_iter()
+            .enumerate()
+        {
+            map.insert(key, i as u32)?;
+        }
+        assert_eq!(
+            map.iter_prefix(&vec![1, 0xff])
+                .collect::<Result<Vec<_>>>()?,
+            vec![(vec![1, 0xff], 1), (vec![1, 0xff, 0], 2)]
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn btreemap_borrowed_lookup() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<(String, u32)>>("map")?;
+        let handle = tx.store_index(BTreeMapRemove::new(list, &tx)?);
+        let mut map = tx.take_index(handle);
+        for (i, key) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            map.insert(key.to_string(), i as u32)?;
+        }
+        assert_eq!(map.get("b")?, Some(1));
+        assert!(map.contains_key("c"));
+        assert!(!map.contains_key("e"));
+        let keys = map
+            .range::<str, _>((Bound::Included("b"), Bound::Excluded("d")))
+            .map(|res| res.map(|(key, _)| key))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(keys, ["b", "c"]);
+        assert_eq!(map.remove("a")?, Some(0));
+        assert_eq!(map.get("a")?, None);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn btreemap_get_many() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<(u32, String)>("map")?;
+        let (_, mut map) = tx.store_and_take_index(BTreeMap::new(list, &tx)?);
+        // insert in reverse so the order in the file is different from key order
+        for i in (0..10u32).rev() {
+            map.insert(i, &i.to_string())?;
+        }
+        map.insert(3, &"three".to_string())?;
+        assert_eq!(
+            map.get_many(&[7, 3, 42, 0])?,
+            [
+                Some("7".to_string()),
+                Some("three".to_string()),
+                None,
+                Some("0".to_string())
+            ]
+        );
+        assert_eq!(map.get_many::<u32>(&[])?, []);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn btreemap_modify() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<(String, u32)>("map")?;
+        let (_, mut map) = tx.store_and_take_index(BTreeMap::new(list, &tx)?);
+        map.insert("a".to_string(), &1)?;
+        assert!(map.modify("a", |value| *value += 1)?);
+        assert!(!map.modify("b", |value| *value += 1)?);
+        assert_eq!(map.get("a")?, Some(2));
+        Ok(())
+    })
+    .unwrap();
+
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<(String, u32)>>("map-remove")?;
+        let (_, mut map) = tx.store_and_take_index(BTreeMapRemove::new(list, &tx)?);
+        map.insert("a".to_string(), 1)?;
+        assert!(map.modify("a", |value| *value *= 10)?);
+        assert_eq!(map.get("a")?, Some(10));
+        Ok(())
+    })
+    .unwrap();
+
+    // an unchanged value isn't written again
+    let len = db.backend().get_ref().len();
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<(String, u32)>>("map-remove-2")?;
+        let (_, mut map) = tx.store_and_take_index(BTreeMapRemove::new(list, &tx)?);
+        map.insert("a".to_string(), 1)?;
+        Ok(())
+    })
+    .unwrap();
+    let len_after_insert = db.backend().get_ref().len();
+    assert!(len_after_insert > len);
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<(String, u32)>>("map-remove-2")?;
+        let (_, mut map) = tx.store_and_take_index(BTreeMapRemove::new(list, &tx)?);
+        assert!(map.modify("a", |_| {})?);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(db.backend().get_ref().len(), len_after_insert);
+}
+
+#[test]
+fn unique_rejects_duplicates() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list::<Mut<(String, u32)>>("users")?;
+            let handle = tx.store_index(Unique::new(list, &tx)?);
+            let mut users = tx.take_index(handle);
+            users.insert("alice@example.com".into(), 1)?;
+            users.insert("bob@example.com".into(), 2)?;
+            let error = users.insert("alice@example.com".into(), 3).unwrap_err();
+            assert_eq!(
+                error.downcast_ref::<DuplicateKey<String>>(),
+                Some(&DuplicateKey {
+                    key: "alice@example.com".into()
+                })
+            );
+            assert_eq!(users.get("alice@example.com")?, Some(1));
+            Ok(handle)
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let mut users = tx.take_index(handle);
+        assert_eq!(users.remove("bob@example.com")?, Some(2));
+        users.insert("bob@example.com".into(), 4)?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<(String, u32)>>("users")?;
+        let (_, mut users) = tx.store_and_take_index(Unique::new(list, &tx)?);
+        assert_eq!(users.len(), 2);
+        assert_eq!(users.get("bob@example.com")?, Some(4));
+        assert!(users.insert("bob@example.com".into(), 5).is_err());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn relation_keeps_children_attached() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let handle = db
+        .execute(|tx| {
+            let users = tx.take_list::<Mut<(String, u32)>>("users")?;
+            let orders = tx.take_list::<Mut<(u64, (String, u32))>>("orders")?;
+            let handle = tx.store_index(Relation::new(users, orders, OnRemove::Restrict, &tx)?);
+            let mut relation = tx.take_index(handle);
+            relation.insert("alice".into(), 30)?;
+            relation.insert("bob".into(), 40)?;
+            relation.insert_child(1, "alice".into(), 100)?;
+            relation.insert_child(2, "alice".into(), 200)?;
+            relation.insert_child(3, "bob".into(), 300)?;
+            let error = relation.insert_child(4, "carol".into(), 1).unwrap_err();
+            assert_eq!(
+                error.downcast_ref::<IntegrityError<String>>(),
+                Some(&IntegrityError::Missing {
+                    key: "carol".into()
+                })
+            );
+            Ok(handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut relation = tx.take_index(handle);
+        // move order 3 over to alice
+        relation.insert_child(3, "alice".into(), 300)?;
+        assert_eq!(relation.remove(&"bob".to_string())?, Some(40));
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let mut relation = tx.take_index(handle);
+        assert_eq!(relation.n_children(&"bob".to_string()), 1);
+        let error = relation.remove(&"alice".to_string()).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<IntegrityError<String>>(),
+            Some(&IntegrityError::Referenced {
+                key: "alice".into(),
+                n_children: 2
+            })
+        );
+        assert_eq!(relation.remove_child(&1)?, Some(("alice".into(), 100)));
+        assert_eq!(
+            relation
+                .children_of(&"alice".to_string())
+                .collect::<Result<Vec<_>, _>>()?,
+            [(2, 200)]
+        );
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let users = tx.take_list::<Mut<(String, u32)>>("users")?;
+        let orders = tx.take_list::<Mut<(u64, (String, u32))>>("orders")?;
+        let (_, mut relation) =
+            tx.store_and_take_index(Relation::new(users, orders, OnRemove::Cascade, &tx)?);
+        assert_eq!(
+            relation
+                .child_keys(&"alice".to_string())
+                .collect::<Vec<_>>(),
+            [&2]
+        );
+        assert_eq!(relation.remove(&"bob".to_string())?, Some(40));
+        assert_eq!(relation.get_child(&3)?, None);
+        assert_eq!(relation.len(), 1);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn btreemap_snapshot_used_until_next_commit() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<(u32, String)>("btree")?;
+        let snapshots = tx.take_list("btree-snapshots")?;
+        let map = BTreeMap::with_snapshots(list, snapshots, &tx)?;
+        assert!(!map.loaded_from_snapshot());
+        let (_, mut map) = tx.store_and_take_index(map);
+        for i in 0..100 {
+            map.insert(i, &i.to_string())?;
+        }
+        map.insert(7, &"seven".into())?;
+        map.write_snapshot()?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let load = |backend: &mut Vec<u8>, change: bool| {
+        let mut db = LlsDb::load(Cursor::new(backend)).unwrap();
+        db.execute(|tx| {
+            let list = tx.take_list::<(u32, String)>("btree")?;
+            let snapshots = tx.take_list("btree-snapshots")?;
+            let map = BTreeMap::with_snapshots(list, snapshots, &tx)?;
+            let from_snapshot = map.loaded_from_snapshot();
+            let (_, mut map) = tx.store_and_take_index(map);
+            assert_eq!(map.len(), 100);
+            assert_eq!(map.get(&7)?, Some("seven".to_string()));
+            assert_eq!(map.get(&99)?, Some("99".to_string()));
+            map.debug_validate()?;
+            if change {
+                map.insert(50, &"fifty".into())?;
+            }
+            Ok(from_snapshot)
+        })
+        .unwrap()
+    };
+    assert!(load(&mut backend, false));
+    // the snapshot is stale after any commit so the list is scanned
+    assert!(!load(&mut backend, true));
+    assert!(!load(&mut backend, false));
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let result = db.execute(|tx| {
+        let list = tx.take_list::<(u32, String)>("btree")?;
+        let snapshots = tx.take_list("btree-snapshots")?;
+        let map = BTreeMap::with_snapshots(list, snapshots, &tx)?;
+        let (_, mut map) = tx.store_and_take_index(map);
+        map.write_snapshot()?;
+        map.insert(200, &"200".into())?;
+        Ok(())
+    });
+    assert!(result.is_err());
+    let result = db.execute(|tx| {
+        let list = tx.take_list::<(u32, String)>("btree")?;
+        let map = BTreeMap::new(list, &tx)?;
+        let (_, mut map) = tx.store_and_take_index(map);
+        map.write_snapshot()
+    });
+    assert!(result.is_err());
+}
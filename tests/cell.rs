@@ -1,4 +1,8 @@
-use llsdb::{index::Cell, LlsDb};
+use anyhow::anyhow;
+use llsdb::{
+    index::{Cell, CellHistory},
+    LlsDb,
+};
 use std::io::Cursor;
 
 #[test]
@@ -43,3 +47,53 @@ fn cell_get_replace() {
     })
     .unwrap();
 }
+
+#[test]
+fn cell_history() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let cell = db
+        .execute(|tx| {
+            let lists = [tx.take_list("history-0")?, tx.take_list("history-1")?];
+            let cell = CellHistory::new(lists, 3, 0u32, tx)?;
+            Ok(tx.store_index(cell))
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let mut cell = tx.take_index(cell);
+        assert_eq!(cell.get()?, 0);
+        assert_eq!(cell.history().count(), 0);
+        for i in 1..=5 {
+            assert_eq!(cell.replace(i)?, i - 1);
+        }
+        Ok(())
+    })
+    .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut cell = tx.take_index(cell);
+        cell.replace(100)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let cell = tx.take_index(cell);
+        assert_eq!(cell.get()?, 5);
+        assert_eq!(cell.history_len(), 3);
+        assert_eq!(cell.history().collect::<Result<Vec<_>, _>>()?, [4, 3, 2]);
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let lists = [tx.take_list("history-0")?, tx.take_list("history-1")?];
+        let (_, cell) = tx.store_and_take_index(CellHistory::new(lists, 3, 0u32, tx)?);
+        assert_eq!(cell.get()?, 5);
+        assert_eq!(cell.history().collect::<Result<Vec<_>, _>>()?, [4, 3, 2]);
+        Ok(())
+    })
+    .unwrap();
+}
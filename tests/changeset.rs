@@ -0,0 +1,72 @@
+use llsdb::{
+    index::{ChangeSetLog, Merge},
+    LlsDb,
+};
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+#[derive(Debug, Default, Clone, PartialEq, bincode::Encode, bincode::Decode)]
+struct ChangeSet {
+    tip: Option<u32>,
+    txs: BTreeMap<u32, String>,
+}
+
+impl Merge for ChangeSet {
+    fn merge(&mut self, other: Self) {
+        if other.tip.is_some() {
+            self.tip = other.tip;
+        }
+        self.txs.extend(other.txs);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tip.is_none() && self.txs.is_empty()
+    }
+}
+
+#[test]
+fn changeset_log_aggregates() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("changesets")?;
+        let (_, log) = tx.store_and_take_index(ChangeSetLog::new(list));
+        assert_eq!(log.aggregate()?, ChangeSet::default());
+        assert!(log.append(&ChangeSet {
+            tip: Some(1),
+            txs: [(1, "a".to_string())].into(),
+        })?);
+        assert!(!log.append(&ChangeSet::default())?);
+        assert!(log.append(&ChangeSet {
+            tip: Some(2),
+            txs: [(2, "b".to_string()), (1, "a'".to_string())].into(),
+        })?);
+        assert!(log.append(&ChangeSet {
+            tip: None,
+            txs: [(3, "c".to_string())].into(),
+        })?);
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let expected = ChangeSet {
+        tip: Some(2),
+        txs: [
+            (1, "a'".to_string()),
+            (2, "b".to_string()),
+            (3, "c".to_string()),
+        ]
+        .into(),
+    };
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("changesets")?;
+        let (_, log) = tx.store_and_take_index(ChangeSetLog::<ChangeSet>::new(list));
+        assert_eq!(log.aggregate()?, expected);
+        assert_eq!(log.compact()?, expected);
+        assert_eq!(log.aggregate()?, expected);
+        Ok(())
+    })
+    .unwrap();
+}
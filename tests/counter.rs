@@ -0,0 +1,86 @@
+use anyhow::anyhow;
+use llsdb::{
+    index::{Counter, Overflow},
+    LlsDb,
+};
+use std::io::Cursor;
+
+#[test]
+fn counter_add() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("counter")?;
+        let (_, api) = tx.store_and_take_index(Counter::<u64>::new(list, Overflow::Checked, tx)?);
+        assert_eq!(api.get()?, 0);
+        assert_eq!(api.add(5)?, 5);
+        assert_eq!(api.add(-2)?, 3);
+        assert!(api.add(-4).is_err());
+        assert_eq!(api.get()?, 3);
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let size = backend.len();
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let counter = db
+        .execute(|tx| {
+            let list = tx.take_list("counter")?;
+            Ok(tx.store_index(Counter::<u64>::new(list, Overflow::Checked, tx)?))
+        })
+        .unwrap();
+    for _ in 0..10 {
+        db.execute(|tx| {
+            tx.take_index(counter).add(1)?;
+            Ok(())
+        })
+        .unwrap();
+    }
+    drop(db);
+    // the value is overwritten in place so the file doesn't grow
+    assert_eq!(backend.len(), size);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let counter = db
+        .execute(|tx| {
+            let list = tx.take_list("counter")?;
+            Ok(tx.store_index(Counter::<u64>::new(list, Overflow::Checked, tx)?))
+        })
+        .unwrap();
+    let _it_should_fail = db.execute(|tx| {
+        tx.take_index(counter).add(100)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+    db.execute(|tx| {
+        assert_eq!(tx.take_index(counter).get()?, 13);
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("counter")?;
+        let (_, api) = tx.store_and_take_index(Counter::<u64>::new(list, Overflow::Checked, tx)?);
+        assert_eq!(api.get()?, 13);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn counter_saturating() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("counter")?;
+        let (_, api) =
+            tx.store_and_take_index(Counter::<i64>::new(list, Overflow::Saturating, tx)?);
+        assert_eq!(api.add(-5)?, -5);
+        assert_eq!(api.add(i64::MIN)?, i64::MIN);
+        api.set(i64::MAX - 1)?;
+        assert_eq!(api.add(10)?, i64::MAX);
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,41 @@
+use llsdb::{CountingBackend, Durability, IoStats, LlsDb};
+use std::io::Cursor;
+
+#[test]
+fn counting_backend_counts_io() {
+    let mut db = LlsDb::init(CountingBackend::new(Cursor::new(vec![]))).unwrap();
+    let list = db
+        .execute(|tx| {
+            let list = tx.take_list::<String>("list")?;
+            list.api(&tx).push(&"hello".to_string())?;
+            Ok(list)
+        })
+        .unwrap();
+    let stats = db.backend().reset_stats();
+    assert!(stats.writes > 0);
+    assert!(stats.seeks > 0);
+    assert!(stats.syncs >= 2);
+    // the first page is written more than once
+    assert!(stats.bytes_written > db.backend().inner().get_ref().len() as u64);
+    assert_eq!(db.backend().stats(), IoStats::default());
+
+    db.set_durability(Durability::NoSync);
+    db.execute(|tx| {
+        for _ in 0..3 {
+            list.api(&tx).iter().for_each(drop);
+        }
+        Ok(())
+    })
+    .unwrap();
+    let stats = db.backend().stats();
+    assert_eq!(stats.syncs, 0);
+    assert!(stats.reads >= 3);
+    assert!(stats.bytes_read > 0);
+
+    db.execute(|tx| {
+        list.api(&tx).pop()?;
+        Ok(())
+    })
+    .unwrap();
+    assert!(db.backend().stats().truncates > 0);
+}
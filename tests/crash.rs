@@ -0,0 +1,120 @@
+use llsdb::{
+    index::BTreeMapRemove,
+    testing::{check_crash_consistency, Op, RecordingBackend},
+    Backend, Durability, LlsDb, Mut, Result,
+};
+
+type State = (Vec<u32>, Vec<(String, u32)>);
+
+fn read_state<F: Backend>(db: &mut LlsDb<F>) -> Result<State> {
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        let values = list.api(&tx).iter().collect::<Result<Vec<_>>>()?;
+        let map_list = tx.take_list::<Mut<(String, u32)>>("map")?;
+        let (_, map) = tx.store_and_take_index(BTreeMapRemove::new(map_list, &tx)?);
+        let entries = map.iter().collect::<Result<Vec<_>>>()?;
+        Ok((values, entries))
+    })
+}
+
+#[test]
+fn crash_during_tx_leaves_old_or_new_state() {
+    let mut backend = RecordingBackend::new();
+    let mut db = LlsDb::init(&mut backend).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        let api = list.api(&tx);
+        for i in 0..3 {
+            api.push(&i)?;
+        }
+        let map_list = tx.take_list::<Mut<(String, u32)>>("map")?;
+        let (_, mut map) = tx.store_and_take_index(BTreeMapRemove::new(map_list, &tx)?);
+        map.insert("a".into(), 1)?;
+        map.insert("b".into(), 2)?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(&mut backend).unwrap();
+    let before = read_state(&mut db).unwrap();
+    drop(db);
+
+    backend.mark();
+    let mut db = LlsDb::load(&mut backend).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        let api = list.api(&tx);
+        api.pop()?;
+        api.push(&10)?;
+        api.push(&11)?;
+        let map_list = tx.take_list::<Mut<(String, u32)>>("map")?;
+        let (_, mut map) = tx.store_and_take_index(BTreeMapRemove::new(map_list, &tx)?);
+        map.remove("a")?;
+        map.insert("b".into(), 20)?;
+        map.insert("c".into(), 3)?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(&mut backend).unwrap();
+    let after = read_state(&mut db).unwrap();
+    drop(db);
+    assert_ne!(before, after);
+    assert!(!backend.ops().is_empty());
+
+    check_crash_consistency(&backend, &before, &after, read_state).unwrap();
+}
+
+#[test]
+fn commit_syncs_data_before_first_page_and_truncates_after() {
+    let mut backend = RecordingBackend::new();
+    let mut db = LlsDb::init(&mut backend).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<Vec<u8>>("list")?;
+        list.api(&tx).push(&vec![1; 500])?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    backend.mark();
+    let mut db = LlsDb::load(&mut backend).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<Vec<u8>>("list")?;
+        let api = list.api(&tx);
+        api.pop()?;
+        api.push(&vec![2; 10])?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let ops = backend.ops().to_vec();
+    let first_page = ops
+        .iter()
+        .position(|op| matches!(op, Op::Write { offset: 0, .. }))
+        .unwrap();
+    assert!(first_page > 0);
+    // the entries are written and synced before the first page pointing to them
+    assert!(ops[..first_page - 1]
+        .iter()
+        .any(|op| matches!(op, Op::Write { .. })));
+    assert_eq!(ops[first_page - 1], Op::Sync);
+    assert_eq!(ops[first_page + 1], Op::Sync);
+    // the last sync is from dropping the database
+    assert!(matches!(ops[first_page + 2..], [Op::Truncate(_), Op::Sync]));
+
+    backend.mark();
+    let mut db = LlsDb::load(&mut backend).unwrap();
+    db.set_durability(Durability::NoSync);
+    db.execute(|tx| {
+        let list = tx.take_list::<Vec<u8>>("list")?;
+        list.api(&tx).push(&vec![3; 10])?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    assert!(!backend.ops().contains(&Op::Sync));
+}
@@ -1,8 +1,8 @@
 use std::cell::RefMut;
 
 use llsdb::{
-    index::{IndexStore, Vec},
-    Backend, Result, Transaction, TxIo,
+    index::{Cell, CellApi, IndexStore, Vec},
+    Backend, LlsDb, Result, Transaction, TxIo,
 };
 
 #[derive(Debug)]
@@ -57,6 +57,11 @@ impl IndexStore for Custom {
         self.foos.tx_success();
         self.bars.tx_success();
     }
+
+    fn rebuild<F: Backend>(&mut self, io: &TxIo<'_, F>) -> Result<()> {
+        self.foos.rebuild(io)?;
+        self.bars.rebuild(io)
+    }
 }
 
 impl<'i, F: Backend> CustomApi<'i, F> {
@@ -66,3 +71,242 @@ impl<'i, F: Backend> CustomApi<'i, F> {
         Ok(())
     }
 }
+
+/// Amounts that must never add up to more than a limit once a transaction is done
+#[derive(Debug)]
+pub struct Budget {
+    amounts: Vec<u64>,
+    limit: u64,
+    total: u64,
+    committed_total: u64,
+}
+
+impl Budget {
+    pub fn new(tx: &mut Transaction<'_, impl Backend>, limit: u64) -> Result<Self> {
+        let list = tx.take_list::<u64>("amounts")?;
+        let total = list.api(&tx).iter().sum::<Result<u64>>()?;
+        let amounts = Vec::new(list, tx)?;
+        Ok(Self {
+            amounts,
+            limit,
+            total,
+            committed_total: total,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BudgetApi<'i, F> {
+    amounts: <Vec<u64> as IndexStore>::Api<'i, F>,
+    total: RefMut<'i, u64>,
+}
+
+impl IndexStore for Budget {
+    type Api<'i, F> = BudgetApi<'i, F>;
+
+    fn owned_lists(&self) -> std::vec::Vec<llsdb::ListSlot> {
+        self.amounts.owned_lists()
+    }
+
+    fn create_api<'s, F>(store: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (amounts, total) =
+            RefMut::map_split(store, |budget| (&mut budget.amounts, &mut budget.total));
+        BudgetApi {
+            amounts: Vec::create_api(amounts, io),
+            total,
+        }
+    }
+
+    fn tx_fail_rollback(&mut self) {
+        self.amounts.tx_fail_rollback();
+        self.total = self.committed_total;
+    }
+
+    fn tx_success(&mut self) {
+        self.amounts.tx_success();
+        self.committed_total = self.total;
+    }
+
+    fn tx_pre_commit(&mut self) -> Result<()> {
+        if self.total > self.limit {
+            return Err(anyhow::anyhow!(
+                "total of {} is over the limit of {}",
+                self.total,
+                self.limit
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'i, F: Backend> BudgetApi<'i, F> {
+    pub fn add(&mut self, amount: u64) -> Result<()> {
+        self.amounts.push(&amount)?;
+        *self.total += amount;
+        Ok(())
+    }
+
+    pub fn remove_last(&mut self) -> Result<()> {
+        if let Some(amount) = self.amounts.pop()? {
+            *self.total -= amount;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn pre_commit_vetoes_transaction() {
+    let mut db = LlsDb::init(std::io::Cursor::new(vec![])).unwrap();
+    let handle = db
+        .execute(|tx| {
+            let budget = Budget::new(tx, 10)?;
+            Ok(tx.store_index(budget))
+        })
+        .unwrap();
+
+    // over the limit part way through is fine
+    db.execute(|tx| {
+        let mut budget = tx.take_index(handle);
+        budget.add(6)?;
+        budget.add(6)?;
+        budget.remove_last()?;
+        Ok(())
+    })
+    .unwrap();
+
+    let error = db
+        .execute(|tx| {
+            tx.take_index(handle).add(5)?;
+            Ok(())
+        })
+        .unwrap_err();
+    assert!(error.to_string().contains("over the limit"));
+
+    db.execute(|tx| {
+        let mut budget = tx.take_index(handle);
+        assert_eq!(*budget.total, 6);
+        assert_eq!(budget.amounts.len(), 1);
+        budget.add(4)?;
+        Ok(())
+    })
+    .unwrap();
+}
+
+/// Strings along with how many have ever been pushed which it keeps in a list it takes itself
+#[derive(Debug)]
+pub struct Counted {
+    strings: Vec<String>,
+    pushed: Option<Cell<u64>>,
+}
+
+#[derive(Debug)]
+pub struct CountedApi<'i, F> {
+    strings: <Vec<String> as IndexStore>::Api<'i, F>,
+    pushed: CellApi<'i, F, u64>,
+}
+
+impl IndexStore for Counted {
+    type Api<'i, F> = CountedApi<'i, F>;
+
+    fn on_attach<F: Backend>(&mut self, tx: &mut Transaction<'_, F>) -> Result<()> {
+        let list = tx.take_list("counted-pushed")?;
+        self.pushed = Some(Cell::new_with_initial_value(list, &0, tx)?);
+        Ok(())
+    }
+
+    fn owned_lists(&self) -> std::vec::Vec<llsdb::ListSlot> {
+        let mut lists = self.strings.owned_lists();
+        lists.extend(self.pushed.iter().flat_map(Cell::owned_lists));
+        lists
+    }
+
+    fn create_api<'s, F>(store: RefMut<'s, Self>, io: TxIo<'s, F>) -> Self::Api<'s, F>
+    where
+        Self: Sized,
+    {
+        let (strings, pushed) =
+            RefMut::map_split(store, |counted| (&mut counted.strings, &mut counted.pushed));
+        let pushed = RefMut::map(pushed, |pushed| pushed.as_mut().expect("attached"));
+        CountedApi {
+            strings: Vec::create_api(strings, io.clone()),
+            pushed: Cell::create_api(pushed, io),
+        }
+    }
+
+    fn tx_fail_rollback(&mut self) {
+        self.strings.tx_fail_rollback();
+        self.pushed.iter_mut().for_each(Cell::tx_fail_rollback);
+    }
+
+    fn tx_success(&mut self) {
+        self.strings.tx_success();
+        self.pushed.iter_mut().for_each(Cell::tx_success);
+    }
+}
+
+impl<'i, F: Backend> CountedApi<'i, F> {
+    pub fn push(&mut self, string: &String) -> Result<()> {
+        self.strings.push(string)?;
+        let pushed = self.pushed.get()?;
+        self.pushed.replace(&(pushed + 1))?;
+        Ok(())
+    }
+}
+
+#[test]
+fn index_takes_its_own_lists_on_attach() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(std::io::Cursor::new(&mut backend)).unwrap();
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list("counted")?;
+            let counted = Counted {
+                strings: Vec::new(list, tx)?,
+                pushed: None,
+            };
+            let (handle, mut api) = tx.store_and_take_index(counted);
+            api.push(&"one".to_string())?;
+            api.push(&"two".to_string())?;
+            Ok(handle)
+        })
+        .unwrap();
+    db.execute(|tx| {
+        let mut api = tx.take_index(handle);
+        api.strings.pop()?;
+        api.push(&"three".to_string())?;
+        assert_eq!(api.pushed.get()?, 3);
+        Ok(())
+    })
+    .unwrap();
+    assert!(db.lists().any(|list| list == "counted-pushed"));
+
+    // the list is taken by the stored index so attaching another one fails
+    let result = db.execute(|tx| {
+        let list = tx.take_list::<String>("other")?;
+        let counted = Counted {
+            strings: Vec::new(list, tx)?,
+            pushed: None,
+        };
+        tx.try_store_index(counted)
+    });
+    assert!(result.is_err());
+    drop(db);
+
+    let mut db = LlsDb::load(std::io::Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("counted")?;
+        let counted = Counted {
+            strings: Vec::new(list, tx)?,
+            pushed: None,
+        };
+        let mut api = tx.store_and_take_index(counted).1;
+        assert_eq!(api.pushed.get()?, 3);
+        api.push(&"four".to_string())?;
+        assert_eq!(api.strings.len(), 3);
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,107 @@
+use anyhow::anyhow;
+use llsdb::{index::Deque, LlsDb};
+use std::{collections::VecDeque, io::Cursor};
+
+#[test]
+fn deque_pushes_and_pops_at_both_ends() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let deque = db
+        .execute(|tx| {
+            let (front, back) = (tx.take_list("front")?, tx.take_list("back")?);
+            let handle = tx.store_index(Deque::new(front, back, tx)?);
+            let mut deque = tx.take_index(handle);
+            for i in 0..5u32 {
+                deque.push_back(&i)?;
+            }
+            deque.push_front(&100)?;
+            assert_eq!(deque.front()?, Some(100));
+            assert_eq!(deque.back()?, Some(4));
+            assert_eq!(deque.pop_front()?, Some(100));
+            // the front list is empty so this takes from the back list
+            assert_eq!(deque.pop_front()?, Some(0));
+            assert_eq!(deque.pop_back()?, Some(4));
+            assert_eq!(deque.len(), 3);
+            Ok(handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut deque = tx.take_index(deque);
+        while deque.pop_back()?.is_some() {}
+        deque.push_front(&7)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let deque = tx.take_index(deque);
+        assert_eq!(deque.len(), 3);
+        assert_eq!(deque.iter()?.collect::<Result<Vec<_>, _>>()?, vec![1, 2, 3]);
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let (front, back) = (tx.take_list("front")?, tx.take_list("back")?);
+        let handle = tx.store_index(Deque::<u32>::new(front, back, tx)?);
+        let mut deque = tx.take_index(handle);
+        assert_eq!(deque.len(), 3);
+        assert_eq!(deque.pop_back()?, Some(3));
+        assert_eq!(deque.pop_back()?, Some(2));
+        assert_eq!(deque.pop_back()?, Some(1));
+        assert_eq!(deque.pop_back()?, None);
+        assert_eq!(deque.pop_front()?, None);
+        assert!(deque.is_empty());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn deque_matches_vec_deque_as_a_sliding_window() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let deque = db
+        .execute(|tx| {
+            let (front, back) = (tx.take_list("front")?, tx.take_list("back")?);
+            Ok(tx.store_index(Deque::new(front, back, tx)?))
+        })
+        .unwrap();
+    let mut expected = VecDeque::new();
+
+    for i in 0..200u32 {
+        db.execute(|tx| {
+            let mut deque = tx.take_index(deque);
+            if i % 3 == 0 {
+                deque.push_front(&i)?;
+                expected.push_front(i);
+            } else {
+                deque.push_back(&i)?;
+                expected.push_back(i);
+            }
+            if expected.len() > 10 {
+                if i % 2 == 0 {
+                    assert_eq!(deque.pop_front()?, expected.pop_front());
+                } else {
+                    assert_eq!(deque.pop_back()?, expected.pop_back());
+                }
+            }
+            assert_eq!(deque.front()?, expected.front().copied());
+            assert_eq!(deque.back()?, expected.back().copied());
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    db.execute(|tx| {
+        let deque = tx.take_index(deque);
+        assert_eq!(
+            deque.iter()?.collect::<Result<Vec<_>, _>>()?,
+            expected.iter().copied().collect::<Vec<_>>()
+        );
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,68 @@
+use llsdb::{DynEntry, DynList, DynValue, LlsDb};
+use std::io::Cursor;
+
+#[derive(Debug, PartialEq, bincode::Encode, bincode::Decode)]
+struct Login {
+    user: String,
+}
+
+impl DynValue for Login {
+    const TAG: u32 = 1;
+}
+
+#[derive(Debug, PartialEq, bincode::Encode, bincode::Decode)]
+struct Purchase {
+    user: String,
+    amount: u64,
+}
+
+impl DynValue for Purchase {
+    const TAG: u32 = 2;
+}
+
+#[test]
+fn dyn_list_mixes_types_and_skips_unknown_tags() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let events = db
+        .execute(|tx| {
+            let events = DynList::new(tx.take_list("events")?);
+            let api = events.api(&tx);
+            api.push(&Login {
+                user: "alice".into(),
+            })?;
+            // written by a newer version of the program
+            api.push_entry(&DynEntry {
+                tag: 3,
+                bytes: vec![1, 2, 3],
+            })?;
+            api.push(&Purchase {
+                user: "alice".into(),
+                amount: 5,
+            })?;
+            Ok(events)
+        })
+        .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let api = events.api(&tx);
+        let tags = api
+            .iter()
+            .map(|entry| Ok(entry?.tag))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(tags, [2, 3, 1]);
+        assert_eq!(
+            api.iter_of::<Login>().collect::<anyhow::Result<Vec<_>>>()?,
+            [Login {
+                user: "alice".into()
+            }]
+        );
+        let purchase = api.pop()?.unwrap();
+        assert_eq!(purchase.decode::<Login>()?, None);
+        assert_eq!(purchase.decode::<Purchase>()?.unwrap().amount, 5);
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+use llsdb::{
+    index::{Cipher, EncryptedList, Keyring},
+    LlsDb, Mut,
+};
+use std::io::Cursor;
+use std::sync::Arc;
+
+/// Not a real cipher. XORs with the key and tags the ciphertext with the key so decrypting with the
+/// wrong one fails.
+struct Xor {
+    key: u8,
+    padding: usize,
+}
+
+impl Cipher for Xor {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut ciphertext = vec![self.key; 1 + self.padding];
+        ciphertext.extend(plaintext.iter().map(|byte| byte ^ self.key));
+        Ok(ciphertext)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.first() != Some(&self.key) {
+            return Err(anyhow!("wrong key"));
+        }
+        Ok(ciphertext[1 + self.padding..]
+            .iter()
+            .map(|byte| byte ^ self.key)
+            .collect())
+    }
+}
+
+fn xor(key: u8, padding: usize) -> Arc<dyn Cipher> {
+    Arc::new(Xor { key, padding })
+}
+
+#[test]
+fn encrypted_list_key_rotation() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list::<Mut<_>>("secrets")?;
+            let keyring = Keyring::new(1, xor(1, 0)).with_key(2, xor(2, 0));
+            let handle = tx.store_index(EncryptedList::<String>::new(list, keyring));
+            let api = tx.take_index(handle);
+            for secret in ["a", "b", "c", "d"] {
+                api.push(&secret.to_string())?;
+            }
+            Ok(handle)
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let mut api = tx.take_index(handle);
+        api.set_current_key(2)?;
+        api.push(&"e".to_string())?;
+        assert_eq!(api.count_with_key(1)?, 4);
+        // rotated entries move to the front keeping their order
+        assert_eq!(api.rotate_key(1, 2, 3)?, 3);
+        assert_eq!(
+            api.iter().collect::<Result<Vec<_>>>()?,
+            ["d", "c", "b", "e", "a"]
+        );
+        assert_eq!(api.rotate_key(1, 2, 3)?, 1);
+        assert_eq!(api.rotate_key(1, 2, 3)?, 0);
+        assert_eq!(api.count_with_key(2)?, 5);
+        assert_eq!(
+            api.iter().collect::<Result<Vec<_>>>()?,
+            ["a", "d", "c", "b", "e"]
+        );
+
+        // the ciphertext can change length
+        api.add_key(3, xor(3, 2));
+        assert_eq!(api.rotate_key(2, 3, 2)?, 2);
+        assert_eq!(
+            api.iter().collect::<Result<Vec<_>>>()?,
+            ["a", "d", "c", "b", "e"]
+        );
+        assert!(api.rotate_key(2, 4, 1).is_err());
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<_>>("secrets")?;
+        let keyring = Keyring::new(2, xor(2, 0)).with_key(3, xor(3, 2));
+        let (_, api) = tx.store_and_take_index(EncryptedList::<String>::new(list, keyring));
+        let mut secrets = api.iter().collect::<Result<Vec<_>>>()?;
+        secrets.sort();
+        assert_eq!(secrets, ["a", "b", "c", "d", "e"]);
+        assert_eq!(api.count_with_key(3)?, 2);
+        assert!(api.pop()?.is_some());
+        Ok(())
+    })
+    .unwrap();
+}
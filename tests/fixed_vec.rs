@@ -0,0 +1,124 @@
+use anyhow::anyhow;
+use llsdb::{index::FixedVec, LlsDb};
+use std::io::Cursor;
+
+#[test]
+fn fixed_vec() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list("records")?;
+            let handle = tx.store_index(FixedVec::<u32>::new(list, 5, tx)?);
+            let mut vec = tx.take_index(handle);
+            for i in 0..200u32 {
+                assert_eq!(vec.push(&(i * 1_000))?, u64::from(i));
+            }
+            assert!(vec.push(&u32::MAX).is_ok());
+            assert_eq!(vec.pop()?, Some(u32::MAX));
+            Ok(handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut vec = tx.take_index(handle);
+        while vec.pop()?.is_some() {}
+        vec.push(&7)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let mut vec = tx.take_index(handle);
+        assert_eq!(vec.len(), 200);
+        assert_eq!(vec.get(0)?, Some(0));
+        assert_eq!(vec.get(64)?, Some(64_000));
+        assert_eq!(vec.get(199)?, Some(199_000));
+        assert_eq!(vec.get(200)?, None);
+        // pop back into the first extent
+        for i in (64..200).rev() {
+            assert_eq!(vec.pop()?, Some(i * 1_000));
+        }
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("records")?;
+        assert!(FixedVec::<u32>::new(list, 4, tx).is_err());
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("records")?;
+        let handle = tx.store_index(FixedVec::<u32>::new(list, 5, tx)?);
+        let mut vec = tx.take_index(handle);
+        assert_eq!(vec.len(), 64);
+        let values = vec.iter().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, (0..64).map(|i| i * 1_000).collect::<Vec<_>>());
+        vec.push(&1)?;
+        assert_eq!(vec.get(64)?, Some(1));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn fixed_vec_record_too_big() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("records")?;
+        let (_, mut vec) = tx.store_and_take_index(FixedVec::<u32>::new(list, 2, tx)?);
+        vec.push(&100)?;
+        assert!(vec.push(&100_000).is_err());
+        assert_eq!(vec.len(), 1);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn fixed_vec_pop_push_rollback() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list("records")?;
+            let (handle, mut vec) = tx.store_and_take_index(FixedVec::<u32>::new(list, 5, tx)?);
+            for i in 0..10u32 {
+                vec.push(&i)?;
+            }
+            Ok(handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut vec = tx.take_index(handle);
+        assert_eq!(vec.pop()?, Some(9));
+        vec.push(&999)?;
+        assert_eq!(vec.get(9)?, Some(999));
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let vec = tx.take_index(handle);
+        assert_eq!(vec.get(9)?, Some(9));
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("records")?;
+        let (_, vec) = tx.store_and_take_index(FixedVec::<u32>::new(list, 5, tx)?);
+        let values = vec.iter().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, (0..10).collect::<Vec<_>>());
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,215 @@
+#![cfg(feature = "embedded-storage")]
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+use llsdb::{AllocationPolicy, FlashBackend, LinkedListMut, LlsDb};
+
+const ERASE_SIZE: usize = 1024;
+const PAGE_SIZE: u16 = 128;
+
+/// Flash that panics if a word is written to without being erased first
+#[derive(Clone)]
+struct MockFlash {
+    data: Vec<u8>,
+    erases: Vec<usize>,
+    /// The number of erases and writes left before the power goes out
+    power: Option<usize>,
+}
+
+impl MockFlash {
+    fn new(n_blocks: usize) -> Self {
+        Self {
+            data: vec![0xff; n_blocks * ERASE_SIZE],
+            erases: vec![0; n_blocks],
+            power: None,
+        }
+    }
+
+    fn use_power(&mut self) -> Result<(), NorFlashErrorKind> {
+        match &mut self.power {
+            Some(0) => Err(NorFlashErrorKind::Other),
+            Some(power) => {
+                *power -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl ErrorType for MockFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for MockFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl NorFlash for MockFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.use_power()?;
+        let (from, to) = (from as usize, to as usize);
+        assert_eq!(from % ERASE_SIZE, 0);
+        assert_eq!(to % ERASE_SIZE, 0);
+        self.data[from..to].fill(0xff);
+        for block in from / ERASE_SIZE..to / ERASE_SIZE {
+            self.erases[block] += 1;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.use_power()?;
+        let offset = offset as usize;
+        assert_eq!(offset % Self::WRITE_SIZE, 0);
+        assert_eq!(bytes.len() % Self::WRITE_SIZE, 0);
+        let target = &mut self.data[offset..offset + bytes.len()];
+        assert!(
+            target.iter().all(|byte| *byte == 0xff),
+            "writing to flash at {} that wasn't erased",
+            offset
+        );
+        target.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[test]
+fn flash_backend_persists_lists() {
+    let backend = FlashBackend::new(MockFlash::new(32), PAGE_SIZE, 2).unwrap();
+    let mut db = LlsDb::load_or_init(backend).unwrap();
+    db.set_allocation_policy(AllocationPolicy::NextFit);
+    let list = db
+        .execute(|tx| {
+            let list = LinkedListMut::<u32>(tx.take_list("list")?);
+            let api = list.api(&tx);
+            for i in 0..100 {
+                api.push(i)?;
+            }
+            Ok(list)
+        })
+        .unwrap();
+
+    for _ in 0..10 {
+        db.execute(|tx| {
+            let api = list.api(&tx);
+            for _ in 0..10 {
+                let value = api.pop()?.unwrap();
+                api.push(value + 1)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    let flash = db.into_backend().into_inner();
+    let backend = FlashBackend::new(flash, PAGE_SIZE, 2).unwrap();
+    let mut db = LlsDb::load_or_init(backend).unwrap();
+    let list = LinkedListMut::<u32>(db.get_list("list").unwrap());
+    db.execute(|tx| {
+        let values = list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?;
+        let mut expected = vec![199];
+        expected.extend((0..99).rev());
+        assert_eq!(values, expected);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn flash_backend_spreads_header_writes() {
+    let n_header_blocks = 4;
+    let backend = FlashBackend::new(MockFlash::new(16), PAGE_SIZE, n_header_blocks).unwrap();
+    let mut db = LlsDb::init(backend).unwrap();
+    let list = db.execute(|tx| tx.take_list::<u32>("list")).unwrap();
+    for i in 0..200 {
+        db.execute(|tx| list.api(&tx).push(&i)).unwrap();
+    }
+
+    let flash = db.into_backend().into_inner();
+    let header_erases = &flash.erases[..n_header_blocks];
+    let records_per_block = ERASE_SIZE / (PAGE_SIZE as usize + 16);
+    let max_erases = 202 / (records_per_block * n_header_blocks) + 1;
+    assert!(
+        header_erases
+            .iter()
+            .all(|erases| *erases > 0 && *erases <= max_erases),
+        "{:?}",
+        header_erases
+    );
+
+    let backend = FlashBackend::new(flash, PAGE_SIZE, n_header_blocks).unwrap();
+    let mut db = LlsDb::load(backend).unwrap();
+    let list = db.get_list::<u32>("list").unwrap();
+    assert_eq!(db.execute(|tx| list.api(&tx).head()).unwrap(), Some(199));
+}
+
+#[test]
+fn flash_backend_survives_power_loss_while_rewriting_a_block() {
+    let backend = FlashBackend::new(MockFlash::new(16), PAGE_SIZE, 2).unwrap();
+    let mut db = LlsDb::init(backend).unwrap();
+    let list = db.execute(|tx| tx.take_list::<u32>("list")).unwrap();
+    db.execute(|tx| {
+        let api = list.api(&tx);
+        for i in 0..100 {
+            api.push(&i)?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    // free space in the middle of blocks holding committed entries
+    db.execute(|tx| {
+        let api = list.api(&tx);
+        for _ in 0..10 {
+            api.pop()?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    let image = db.into_backend().into_inner();
+
+    let mut rewrote_a_block = false;
+    for power in 0.. {
+        let mut flash = image.clone();
+        flash.power = Some(power);
+        let backend = FlashBackend::new(flash, PAGE_SIZE, 2).unwrap();
+        let mut db = LlsDb::load(backend).unwrap();
+        let res = db.execute(|tx| {
+            let api = list.api(&tx);
+            for i in 0..10 {
+                api.push(&(1000 + i))?;
+            }
+            Ok(())
+        });
+        let mut flash = db.into_backend().into_inner();
+        let erases = flash.erases.iter().sum::<usize>() - image.erases.iter().sum::<usize>();
+        rewrote_a_block |= erases > 1;
+
+        flash.power = None;
+        let backend = FlashBackend::new(flash, PAGE_SIZE, 2).unwrap();
+        let mut db = LlsDb::load(backend).unwrap();
+        let values = db
+            .execute(|tx| list.api(&tx).iter().collect::<Result<Vec<_>, _>>())
+            .unwrap();
+        let mut expected = (0..90).rev().collect::<Vec<_>>();
+        if res.is_ok() {
+            expected.splice(0..0, (1000..1010).rev());
+        }
+        assert_eq!(values, expected, "power lost after {} operations", power);
+        if res.is_ok() {
+            break;
+        }
+    }
+    assert!(rewrote_a_block);
+}
@@ -0,0 +1,69 @@
+use anyhow::anyhow;
+use llsdb::{index::HashChain, Endian, InitOptions, IntEncoding, LlsDb, ValueEncoding};
+use std::io::Cursor;
+
+fn hex(bytes: [u8; 32]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[test]
+fn hash_chain_digest() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let chain = db
+        .execute(|tx| {
+            let list = tx.take_list("chain")?;
+            let handle = tx.store_index(HashChain::<u32>::new(list));
+            let api = tx.take_index(handle);
+            assert_eq!(api.digest()?, [0u8; 32]);
+            assert_eq!(
+                hex(api.push(1)?),
+                "1fd4247443c9440cb3c48c28851937196bc156032d70a96c98e127ecb347e45f"
+            );
+            api.push(300)?;
+            Ok(handle)
+        })
+        .unwrap();
+    let expected = "babe3c9c073007fee8067813ed7af81c345bfd3aa43e237bcbfb268c1c77ed2d";
+
+    let _it_should_fail = db.execute(|tx| {
+        let api = tx.take_index(chain);
+        api.push(7)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let api = tx.take_index(chain);
+        assert_eq!(hex(api.digest()?), expected);
+        api.verify()?;
+        api.push(7)?;
+        assert_eq!(api.pop()?, Some(7));
+        assert_eq!(hex(api.digest()?), expected);
+        Ok(())
+    })
+    .unwrap();
+
+    // a replica with a different value encoding has the same digest
+    let mut replica = LlsDb::init_with_options(
+        Cursor::new(vec![]),
+        InitOptions {
+            page_size: 128,
+            value_encoding: ValueEncoding {
+                int_encoding: IntEncoding::Fixint,
+                endian: Endian::Big,
+            },
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    replica
+        .execute(|tx| {
+            let list = tx.take_list("chain")?;
+            let (_, api) = tx.store_and_take_index(HashChain::<u32>::new(list));
+            api.push(1)?;
+            api.push(300)?;
+            assert_eq!(hex(api.digest()?), expected);
+            Ok(())
+        })
+        .unwrap();
+}
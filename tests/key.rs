@@ -0,0 +1,67 @@
+use llsdb::{index::BTreeMap, BigEndian, Lexicographic, LlsDb};
+use proptest::prelude::*;
+use std::io::Cursor;
+
+fn encode<T: bincode::Encode>(value: &T) -> Vec<u8> {
+    bincode::encode_to_vec(value, bincode::config::standard()).unwrap()
+}
+
+fn decode<T: bincode::Decode>(bytes: &[u8]) -> T {
+    let (value, len) = bincode::decode_from_slice(bytes, bincode::config::standard()).unwrap();
+    assert_eq!(len, bytes.len());
+    value
+}
+
+proptest! {
+    #[test]
+    fn big_endian_preserves_order(a: i64, b: i64, c: u32, d: u32) {
+        let (a, b) = (BigEndian(a), BigEndian(b));
+        prop_assert_eq!(a.cmp(&b), encode(&a).cmp(&encode(&b)));
+        prop_assert_eq!(decode::<BigEndian<i64>>(&encode(&a)), a);
+        let (c, d) = (BigEndian(c), BigEndian(d));
+        prop_assert_eq!(c.cmp(&d), encode(&c).cmp(&encode(&d)));
+    }
+
+    #[test]
+    fn composite_keys_preserve_order(
+        a in (any::<Vec<u8>>(), any::<u16>(), ".*"),
+        b in (any::<Vec<u8>>(), any::<u16>(), ".*"),
+    ) {
+        let key = |(bytes, n, string): (Vec<u8>, u16, String)| {
+            (Lexicographic(bytes), BigEndian(n), Lexicographic(string))
+        };
+        let (a, b) = (key(a), key(b));
+        prop_assert_eq!(a.cmp(&b), encode(&a).cmp(&encode(&b)));
+        type Key = (Lexicographic<Vec<u8>>, BigEndian<u16>, Lexicographic<String>);
+        prop_assert_eq!(&decode::<Key>(&encode(&a)), &a);
+    }
+}
+
+#[test]
+fn ordered_keys_in_btreemap() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("by_name")?;
+        let (_, mut map) = tx.store_and_take_index(BTreeMap::new(list, &tx)?);
+        for (name, height) in [("b", 2u64), ("a\0", 1), ("a", 0)] {
+            map.insert((Lexicographic::from(name), BigEndian(height)), &height)?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("by_name")?;
+        let (_, map) = tx
+            .store_and_take_index(
+                BTreeMap::<(Lexicographic<String>, BigEndian<u64>), u64>::new(list, &tx)?,
+            );
+        let values = map.values().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, [0, 1, 2]);
+        Ok(())
+    })
+    .unwrap();
+}
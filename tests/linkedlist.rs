@@ -48,6 +48,7 @@ fn linked_list_head() {
             (Some(51), Some(61))
         );
 
+        drop(db);
         {
             let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
             let ll1: LinkedList<u32> = db.get_list("ll1").unwrap();
@@ -255,6 +256,7 @@ fn ll_push_after_pop_reclaims_space() {
     assert_eq!(db.backend().get_ref().len(), len_at_start);
 
     db.execute(|tx| ll.api(tx).push(&2)).unwrap();
+    drop(db);
 
     assert_eq!(len_at_start, backend.len() - 1 * 2);
 }
@@ -280,8 +282,10 @@ fn ll_push_after_pop_reclaims_space_after_load() {
     .unwrap();
 
     let len_before_push = db.backend().get_ref().len();
+    drop(db);
     let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
     db.execute(|tx| ll1.api(tx).push(&2)).unwrap();
+    drop(db);
 
     assert_eq!(
         len_before_push,
@@ -348,3 +352,202 @@ fn push_result_of_pop() {
     })
     .unwrap();
 }
+
+#[test]
+fn resume_iteration_from_cursor() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let ll = db
+        .execute(|tx| {
+            let ll = tx.take_list::<u32>("ll")?;
+            let api = ll.api(&tx);
+            for i in 0..10 {
+                api.push(&i)?;
+            }
+            Ok(ll)
+        })
+        .unwrap();
+
+    let cursor = db
+        .execute(|tx| {
+            let mut it = ll.api(&tx).entry_iter();
+            for expected in (6..10).rev() {
+                assert_eq!(it.next::<u32>().transpose()?, Some(expected));
+            }
+            Ok(it.cursor())
+        })
+        .unwrap();
+    assert!(!cursor.is_finished());
+
+    let encoded_cursor = bincode::encode_to_vec(&cursor, bincode::config::standard()).unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let ll: LinkedList<u32> = db.get_list("ll").unwrap();
+    db.execute(|tx| {
+        let (cursor, _): (llsdb::ListCursor, _) =
+            bincode::decode_from_slice(&encoded_cursor, bincode::config::standard())?;
+        let api = ll.api(&tx);
+        api.push(&10)?;
+        let other = tx.take_list::<u32>("other")?;
+        assert!(other.api(&tx).entry_iter_from(&cursor).is_err());
+        let mut it = api.entry_iter_from(&cursor)?;
+        let mut rest = vec![];
+        while let Some(value) = it.next::<u32>() {
+            rest.push(value?);
+        }
+        assert_eq!(rest, vec![5, 4, 3, 2, 1, 0]);
+        assert!(it.cursor().is_finished());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn reserved_space_keeps_list_together() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let (a, b) = db
+        .execute(|tx| {
+            let a = tx.take_list::<u32>("a")?;
+            let b = tx.take_list::<u32>("b")?;
+            a.api(&tx).reserve(64)?;
+            Ok((a, b))
+        })
+        .unwrap();
+
+    let mut a_handles = vec![];
+    let mut b_handles = vec![];
+    for _ in 0..2 {
+        let (a_handle, b_handle) = db
+            .execute(|tx| Ok((a.api(&tx).push(&1)?, b.api(&tx).push(&2)?)))
+            .unwrap();
+        a_handles.push(a_handle);
+        b_handles.push(b_handle);
+    }
+    // b's entries go after the reservation
+    assert!(a_handles[1].value_pointer() > a_handles[0].value_pointer());
+    assert!(b_handles[0].value_pointer() > a_handles[1].value_pointer());
+
+    let _it_should_fail = db.execute(|tx| {
+        a.api(&tx).push(&3)?;
+        Err::<(), _>(anyhow::anyhow!("rollback"))
+    });
+    let (reserved, handle) = db
+        .execute(|tx| {
+            let handle = a.api(&tx).push(&3)?;
+            Ok((tx.io.reserved(a.slot()), handle))
+        })
+        .unwrap();
+    assert!(reserved < 64 && reserved > 0);
+    assert!(handle.value_pointer() < b_handles[0].value_pointer());
+
+    // the unused part of the reservation isn't lost when the database is closed
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let b = tx.take_list::<u32>("b")?;
+        assert_eq!(tx.io.reserved(b.slot()), 0);
+        let handle = b.api(&tx).push(&4)?;
+        assert!(handle.value_pointer() < b_handles[0].value_pointer());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn iter_with_handles_reads_back() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let ll = tx.take_list::<String>("ll")?;
+        let api = ll.api(&tx);
+        for word in ["one", "two", "three"] {
+            api.push(&word.to_string())?;
+        }
+        let entries = api.iter_with_handles().collect::<Result<Vec<_>, _>>()?;
+        let words = entries
+            .iter()
+            .map(|(_, word)| word.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(words, ["three", "two", "one"]);
+        for (handle, word) in &entries {
+            let (read_handle, read) = tx.io.read_at::<String>(handle.entry_pointer())?;
+            assert_eq!(&read, word);
+            assert_eq!(read_handle, *handle);
+        }
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn push_segments_reads_each_segment() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let ll = tx.take_list::<(u32, String, Vec<u8>)>("ll")?;
+        let api = ll.api(&tx);
+        let (handle, offsets) =
+            api.push_segments(&[&7u32, &"meta".to_string(), &vec![1u8, 2, 3]])?;
+        assert_eq!(offsets.len(), 3);
+        assert_eq!(offsets[0], 0);
+        let meta: String = tx.io.raw_read_at(handle.segment_pointer(offsets[1]))?;
+        assert_eq!(meta, "meta");
+        let blob: Vec<u8> = tx.io.raw_read_at(handle.segment_pointer(offsets[2]))?;
+        assert_eq!(blob, [1, 2, 3]);
+        assert_eq!(api.head()?, Some((7, "meta".to_string(), vec![1, 2, 3])));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn pop_while_stops_at_first_value_not_matching() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let ll = db
+        .execute(|tx| {
+            let ll = tx.take_list::<u32>("ll")?;
+            let api = ll.api(&tx);
+            for i in [1, 5, 2, 7, 8] {
+                api.push(&i)?;
+            }
+            Ok(ll)
+        })
+        .unwrap();
+    let len_before = db.backend().get_ref().len();
+
+    let popped = db.execute(|tx| ll.api(tx).pop_while(|i| *i > 4)).unwrap();
+    assert_eq!(popped, vec![8, 7]);
+    assert_eq!(db.backend().get_ref().len(), len_before - 2 * 2);
+
+    db.execute(|tx| {
+        let api = ll.api(tx);
+        assert_eq!(api.pop_while(|i| *i > 4)?, Vec::<u32>::new());
+        assert_eq!(api.iter().collect::<Result<Vec<_>, _>>()?, vec![2, 5, 1]);
+        assert_eq!(api.pop_while(|_| true)?, vec![2, 5, 1]);
+        assert!(api.is_empty());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn find_and_position_stop_at_first_match() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    db.execute(|tx| {
+        let ll = tx.take_list::<u32>("ll")?;
+        let api = ll.api(&tx);
+        for i in [1, 5, 2, 7, 8] {
+            api.push(&i)?;
+        }
+        assert_eq!(api.find(|i| *i < 5)?, Some(2));
+        assert_eq!(api.position(|i| *i < 5)?, Some(2));
+        assert_eq!(api.find(|i| *i > 10)?, None);
+        assert_eq!(api.position(|i| *i > 10)?, None);
+        assert!(api.any(|i| *i == 1)?);
+        assert!(!api.any(|i| *i == 3)?);
+        Ok(())
+    })
+    .unwrap();
+}
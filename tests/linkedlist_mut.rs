@@ -1,4 +1,4 @@
-use llsdb::{LinkedListMut, LlsDb};
+use llsdb::{LinkedListMut, LlsDb, Mut, Remap, VersionConflict};
 use std::io::Cursor;
 
 #[test]
@@ -65,6 +65,7 @@ fn linked_list_mut_remove_middle() {
         Ok(())
     })
     .unwrap();
+    drop(db);
     assert_eq!(
         backend.len(),
         len_before_push,
@@ -103,3 +104,289 @@ fn linked_list_mut_remove_start() {
         .unwrap();
     }
 }
+
+#[test]
+fn linked_list_mut_overwrite() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let (ll1, handle) = db
+        .execute(|tx| {
+            let ll1 = LinkedListMut::<u32>(tx.take_list("ll1").unwrap());
+            let api = ll1.api(tx);
+            let handle = api.push(1)?;
+            api.push(2)?;
+            Ok((ll1, handle))
+        })
+        .unwrap();
+
+    let len_before = db.backend().get_ref().len();
+    db.execute(|tx| ll1.api(tx).overwrite(handle, &3)).unwrap();
+    assert_eq!(db.backend().get_ref().len(), len_before);
+
+    // integers over 250 take more than one byte as a varint
+    assert!(db
+        .execute(|tx| ll1.api(tx).overwrite(handle, &1_000))
+        .is_err());
+
+    let res = db.execute(|tx| {
+        ll1.api(tx).overwrite(handle, &4)?;
+        Err::<(), _>(anyhow::anyhow!("rollback"))
+    });
+    assert!(res.is_err());
+
+    db.execute(|tx| {
+        assert_eq!(
+            ll1.api(tx).iter().collect::<Result<Vec<_>, _>>()?,
+            vec![2, 3]
+        );
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let ll1 = LinkedListMut::<u32>(db.get_list("ll1").unwrap());
+    db.execute(|tx| {
+        assert_eq!(
+            ll1.api(tx).iter().collect::<Result<Vec<_>, _>>()?,
+            vec![2, 3]
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn compare_and_update_detects_changes() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let (ll, handle, version) = db
+        .execute(|tx| {
+            let ll = LinkedListMut(tx.take_list("ll").unwrap());
+            let api = ll.api(&tx);
+            api.push(1u32)?;
+            let handle = api.push(2)?;
+            api.push(3)?;
+            let version = api.version(handle)?.unwrap();
+            Ok((ll, handle, version))
+        })
+        .unwrap();
+
+    // someone else updates the entry first
+    let new_handle = db
+        .execute(|tx| ll.api(&tx).compare_and_update(handle, version, 20))
+        .unwrap();
+
+    db.execute(|tx| {
+        let api = ll.api(&tx);
+        assert_eq!(api.version(handle)?, None);
+        let err = api.compare_and_update(handle, version, 200).unwrap_err();
+        let conflict = err.downcast_ref::<VersionConflict>().unwrap();
+        assert_eq!(conflict.found, None);
+
+        // overwriting in place changes the version too
+        let version = api.version(new_handle)?.unwrap();
+        api.overwrite(new_handle, &21)?;
+        assert!(api.compare_and_update(new_handle, version, 22).is_err());
+        let version = api.version(new_handle)?.unwrap();
+        let new_handle = api.compare_and_update(new_handle, version, 22)?;
+        // unlinking in the same transaction is noticed
+        let version = api.version(new_handle)?.unwrap();
+        api.unlink(new_handle)?;
+        assert!(api.compare_and_update(new_handle, version, 23).is_err());
+        assert_eq!(api.iter().collect::<Result<Vec<_>, _>>()?, vec![3, 1]);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn relocate_moves_entry_into_lowest_free_space() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+
+    let (ll, other, handle) = db
+        .execute(|tx| {
+            let other = LinkedListMut::<u32>(tx.take_list("other").unwrap());
+            other.api(&tx).push(100)?;
+            let ll = LinkedListMut(tx.take_list("ll").unwrap());
+            let api = ll.api(&tx);
+            api.push(1u32)?;
+            let handle = api.push(2)?;
+            api.push(3)?;
+            Ok((ll, other, handle))
+        })
+        .unwrap();
+
+    let hole = db
+        .execute(|tx| {
+            let api = other.api(&tx);
+            let (hole, _) = api.iter_handles().next().unwrap()?;
+            api.pop()?;
+            Ok(hole.entry_pointer().this_entry)
+        })
+        .unwrap();
+
+    let moved = db
+        .execute(|tx| {
+            let api = ll.api(&tx);
+            let moved = api.relocate(handle)?;
+            assert!(api.relocate(handle).is_err());
+            assert_eq!(api.iter().collect::<Result<Vec<_>, _>>()?, vec![3, 2, 1]);
+            Ok(moved)
+        })
+        .unwrap();
+    assert_eq!(moved.entry_pointer().this_entry, hole);
+
+    let mut db = LlsDb::load(db.into_backend()).unwrap();
+    db.execute(|tx| {
+        let ll = LinkedListMut::<u32>(tx.take_list("ll")?);
+        let api = ll.api(&tx);
+        assert_eq!(api.iter().collect::<Result<Vec<_>, _>>()?, vec![3, 2, 1]);
+        let (handle, _) = api.iter_handles().nth(1).unwrap()?;
+        assert_eq!(handle, moved);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn find_handle_skips_unlinked_entries() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    db.execute(|tx| {
+        let ll = LinkedListMut(tx.take_list("ll")?);
+        let api = ll.api(&tx);
+        for i in [1u32, 5, 2, 7, 8] {
+            api.push(i)?;
+        }
+        let (handle, value) = api.find_handle(|i| *i < 5)?.unwrap();
+        assert_eq!(value, 2);
+        api.unlink(handle)?;
+        assert_eq!(
+            api.find_handle(|i| *i < 5)?.map(|(_, value)| value),
+            Some(1)
+        );
+        assert_eq!(api.position(|i| *i < 5)?, Some(3));
+        assert!(!api.any(|i| *i == 2)?);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn strict_remaps_catch_remap_to_freed_entry() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let (ll, junk, handles) = db
+        .execute(|tx| {
+            let ll = LinkedListMut::<u32>(tx.take_list("ll")?);
+            let junk = tx.take_list::<Vec<u8>>("junk")?;
+            let mut handles = vec![ll.api(&tx).push(1)?, ll.api(&tx).push(2)?];
+            junk.api(&tx).push(&vec![0xff; 100])?;
+            handles.push(ll.api(&tx).push(3)?);
+            handles.push(ll.api(&tx).push(4)?);
+            ll.api(&tx).unlink(handles[2])?;
+            Ok((ll, junk, handles))
+        })
+        .unwrap();
+    db.set_strict_remaps(true);
+    db.execute(|tx| {
+        assert_eq!(
+            ll.api(&tx).iter().collect::<Result<Vec<_>, _>>()?,
+            vec![4, 2, 1]
+        );
+        Ok(())
+    })
+    .unwrap();
+
+    db.set_strict_remaps(false);
+    let freed = db
+        .execute(|tx| {
+            let junk_handle = junk.api(&tx).iter_with_handles().next().unwrap()?.0;
+            junk.api(&tx).pop()?;
+            let freed = junk_handle.segment_pointer(50);
+            ll.0.api(&tx).push(&Mut::Remap(Remap {
+                from: handles[1].entry_pointer().this_entry,
+                to: freed,
+            }))?;
+            Ok(freed)
+        })
+        .unwrap();
+    db.set_strict_remaps(true);
+    let err = db
+        .execute(|tx| ll.api(&tx).iter().collect::<Result<Vec<_>, _>>())
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains(&format!("{:?}", freed)), "{}", err);
+}
+
+#[test]
+fn strict_remaps_catch_cycles() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    // a remap to the front of the list that's only read after it so without strict remaps this
+    // list would be iterated forever
+    let (ll, front) = db
+        .execute(|tx| {
+            let ll = LinkedListMut::<u32>(tx.take_list("ll")?);
+            let from = ll.api(&tx).push(1)?.entry_pointer().this_entry;
+            ll.api(&tx).push(2)?;
+            let remap = ll.0.api(&tx).push(&Mut::Remap(Remap { from, to: from }))?;
+            let front = ll.api(&tx).push(3)?.entry_pointer().this_entry;
+            tx.io
+                .overwrite(remap, &Mut::<u32>::Remap(Remap { from, to: front }))?;
+            Ok((ll, front))
+        })
+        .unwrap();
+    db.set_strict_remaps(true);
+    let err = db
+        .execute(|tx| ll.api(&tx).iter().collect::<Result<Vec<_>, _>>())
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains(&format!("{:?}", front)), "{}", err);
+}
+
+#[test]
+fn unlinking_neighbours_in_one_tx_leaves_one_remap() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let ll = db
+        .execute(|tx| {
+            let ll = LinkedListMut::<u32>(tx.take_list("ll")?);
+            for i in 1..=7 {
+                ll.api(&tx).push(i)?;
+            }
+            Ok(ll)
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let api = ll.api(&tx);
+        // from both ends of the run so the chain is squashed from either side
+        for i in [4, 3, 5, 2] {
+            let (handle, _) = api.find_handle(|value| *value == i)?.unwrap();
+            api.unlink(handle)?;
+        }
+        assert_eq!(api.iter().collect::<Result<Vec<_>, _>>()?, vec![7, 6, 1]);
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.set_strict_remaps(true);
+    db.execute(|tx| {
+        let ll = LinkedListMut::<u32>(tx.take_list("ll")?);
+        assert_eq!(
+            ll.api(&tx).iter().collect::<Result<Vec<_>, _>>()?,
+            vec![7, 6, 1]
+        );
+        let mut it = tx.io.iter(ll.0.slot());
+        let mut n_remaps = 0;
+        while let Some(Mut::Remap(_)) = it.next::<Mut<u32>>().transpose()? {
+            n_remaps += 1;
+        }
+        assert_eq!(n_remaps, 1);
+        Ok(())
+    })
+    .unwrap();
+}
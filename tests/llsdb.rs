@@ -0,0 +1,1790 @@
+use anyhow::anyhow;
+use llsdb::{
+    index::{BTreeMap, Cell},
+    Aborted, Backend, Endian, InitOptions, IntEncoding, LinkedListMut, ListQuota, LlsDb,
+    ManualClock, Metrics, Mut, NewerFormat, Pointer, PrepareToken, QuotaExceeded, ReadCounts,
+    SystemClock, TrimPolicy, TxMemoryExceeded, ValueEncoding,
+};
+use std::io::Cursor;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn init_with_n_free_slots() {
+    let mut backend = vec![];
+    let options = InitOptions {
+        page_size: 128,
+        n_free_slots: Some(1),
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(Cursor::new(&mut backend), options).unwrap();
+    assert_eq!(db.free_space_stats().n_free_slots, 1);
+    assert!(!db.free_space_stats().is_spilling());
+
+    let (list, handles) = db
+        .execute(|tx| {
+            let list = LinkedListMut::<u32>(tx.take_list("list")?);
+            let api = list.api(&tx);
+            let handles = (0..5).map(|i| api.push(i)).collect::<Result<Vec<_>, _>>()?;
+            Ok((list, handles))
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let api = list.api(&tx);
+        api.unlink(handles[1])?;
+        api.unlink(handles[3])?;
+        Ok(())
+    })
+    .unwrap();
+
+    let stats = db.free_space_stats();
+    assert_eq!(stats.n_used_free_slots, 1);
+    assert!(stats.is_spilling());
+    drop(db);
+
+    let db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    assert_eq!(db.free_space_stats().n_free_slots, 1);
+}
+
+#[test]
+fn init_with_too_many_free_slots() {
+    let options = InitOptions {
+        page_size: 128,
+        n_free_slots: Some(8),
+        ..Default::default()
+    };
+    assert!(LlsDb::init_with_options(Cursor::new(vec![]), options).is_err());
+}
+
+#[test]
+fn padded_entries() {
+    let mut backend = vec![];
+    let options = InitOptions {
+        page_size: 256,
+        pad_entries_to: 8,
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(Cursor::new(&mut backend), options).unwrap();
+    let (list, handles) = db
+        .execute(|tx| {
+            let list = tx.take_list::<String>("list")?;
+            let api = list.api(&tx);
+            let handles = ["a", "bb", "ccc"]
+                .into_iter()
+                .map(|value| api.push(&value.to_string()))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((list, handles))
+        })
+        .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        // the padding leaves room for a longer value
+        tx.io.overwrite(handles[0], &"abcd".to_string())?;
+        let err = tx.io.overwrite(handles[0], &"a".repeat(8)).unwrap_err();
+        // the error gives the padded lengths that were compared
+        assert_eq!(
+            err.to_string(),
+            "can't overwrite an entry taking up 8 bytes with one that would take up 16"
+        );
+        let api = list.api(&tx);
+        api.push(&"d".to_string())?;
+        assert_eq!(
+            api.iter().collect::<Result<Vec<_>, _>>()?,
+            ["d", "ccc", "bb", "abcd"]
+        );
+        while api.pop()?.is_some() {}
+        Ok(())
+    })
+    .unwrap();
+    // the padding is freed along with the entries so it all joins back up with the rest
+    // the free space left by the pops was carried over
+    assert!(db.free_space_stats().n_used_free_slots > 0);
+
+    let options = InitOptions {
+        pad_entries_to: 0,
+        ..Default::default()
+    };
+    assert!(LlsDb::init_with_options(Cursor::new(vec![]), options).is_err());
+}
+
+#[test]
+fn checked_allocations() {
+    let mut backend = vec![];
+    let options = InitOptions {
+        page_size: 256,
+        n_extra_header_pages: 1,
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(Cursor::new(&mut backend), options).unwrap();
+    db.check_allocations().unwrap();
+    let list = db
+        .execute(|tx| {
+            let list = LinkedListMut::<u32>(tx.take_list("list")?);
+            let api = list.api(&tx);
+            for i in 0..20 {
+                api.push(i)?;
+            }
+            Ok(list)
+        })
+        .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.check_allocations().unwrap();
+    for _ in 0..2 {
+        let _it_should_fail = db.execute(|tx| {
+            let api = list.api(&tx);
+            let handles = api.iter_handles().collect::<Result<Vec<_>, _>>()?;
+            for (handle, _) in handles.into_iter().step_by(3) {
+                api.unlink(handle)?;
+            }
+            for i in 0..10 {
+                api.push(i)?;
+            }
+            Err::<(), _>(anyhow!("rollback"))
+        });
+    }
+    db.execute(|tx| {
+        let api = list.api(&tx);
+        while api.pop()?.is_some() {}
+        for i in 0..30 {
+            api.push(i)?;
+        }
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn free_regions_include_unplaced() {
+    let options = InitOptions {
+        page_size: 128,
+        n_free_slots: Some(1),
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(Cursor::new(vec![]), options).unwrap();
+    // a fresh database is one free space from the end of the header to the maximum size
+    assert_eq!(db.free_regions().count(), 1);
+    let (list, handles) = db
+        .execute(|tx| {
+            let list = LinkedListMut::<u32>(tx.take_list("list")?);
+            let api = list.api(&tx);
+            let handles = (0..5).map(|i| api.push(i)).collect::<Result<Vec<_>, _>>()?;
+            Ok((list, handles))
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let api = list.api(&tx);
+        api.unlink(handles[1])?;
+        api.unlink(handles[3])?;
+        Ok(())
+    })
+    .unwrap();
+
+    assert!(db.free_space_stats().is_spilling());
+    let regions = db.free_regions().collect::<Vec<_>>();
+    assert_eq!(regions.len(), 3);
+    assert!(LlsDb::load(db.into_backend())
+        .unwrap()
+        .free_regions()
+        .all(|(_, len)| len > 0));
+    assert_eq!(
+        regions[..2],
+        vec![
+            (
+                handles[1].entry_pointer().this_entry,
+                handles[1].entry_len()
+            ),
+            (
+                handles[3].entry_pointer().this_entry,
+                handles[3].entry_len()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn extra_header_pages_hold_more_lists() {
+    let mut backend = vec![];
+    let options = InitOptions {
+        page_size: 128,
+        n_extra_header_pages: 2,
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(Cursor::new(&mut backend), options).unwrap();
+
+    db.execute(|tx| {
+        for i in 0..30u32 {
+            let list = tx.take_list::<u32>(&format!("list-{}", i))?;
+            list.api(&tx).push(&i)?;
+        }
+        Ok(())
+    })
+    .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let list = tx.take_list::<u32>("list-29")?;
+        list.api(&tx).push(&100)?;
+        Err::<(), _>(anyhow::anyhow!("fail the tx"))
+    });
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        for i in 0..30u32 {
+            let list = tx.take_list::<u32>(&format!("list-{}", i))?;
+            let api = list.api(&tx);
+            assert_eq!(api.head()?, Some(i));
+            api.push(&(i + 1))?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        for i in 0..30u32 {
+            let list = tx.take_list::<u32>(&format!("list-{}", i))?;
+            assert_eq!(
+                list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?,
+                vec![i + 1, i]
+            );
+        }
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[derive(Default)]
+struct CountingMetrics {
+    pushes: AtomicU64,
+    pops: AtomicU64,
+    bytes_written: AtomicU64,
+    bytes_read: AtomicU64,
+    commits: AtomicU64,
+    rollbacks: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+impl Metrics for CountingMetrics {
+    fn push(&self, _entry_len: u64) {
+        self.pushes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn pop(&self) {
+        self.pops.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn bytes_read(&self, n: u64) {
+        self.bytes_read.fetch_add(n, Ordering::SeqCst);
+    }
+
+    fn bytes_written(&self, n: u64) {
+        self.bytes_written.fetch_add(n, Ordering::SeqCst);
+    }
+
+    fn tx_commit(&self) {
+        self.commits.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn tx_rollback(&self) {
+        self.rollbacks.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn cache_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn metrics_are_reported() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let metrics = Arc::new(CountingMetrics::default());
+    db.set_metrics(metrics.clone());
+
+    let list = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("list")?;
+            let api = list.api(&tx);
+            api.push(&1)?;
+            api.push(&2)?;
+            assert_eq!(api.pop()?, Some(2));
+            Ok(list)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        list.api(&tx).push(&3)?;
+        Err::<(), _>(anyhow::anyhow!("fail the tx"))
+    });
+
+    // the meta entry for the list plus the three values
+    assert_eq!(metrics.pushes.load(Ordering::SeqCst), 4);
+    assert_eq!(metrics.pops.load(Ordering::SeqCst), 1);
+    assert_eq!(metrics.commits.load(Ordering::SeqCst), 1);
+    assert_eq!(metrics.rollbacks.load(Ordering::SeqCst), 1);
+    assert!(metrics.bytes_read.load(Ordering::SeqCst) > 0);
+    // the entries plus the first page
+    assert!(metrics.bytes_written.load(Ordering::SeqCst) > 128);
+    // there's no read cache by default
+    assert_eq!(metrics.cache_hits.load(Ordering::SeqCst), 0);
+    assert_eq!(metrics.cache_misses.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn read_cache_reports_hits_and_misses() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let metrics = Arc::new(CountingMetrics::default());
+    db.set_metrics(metrics.clone());
+    db.set_read_cache(2);
+
+    let list = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("list")?;
+            let api = list.api(&tx);
+            for i in 0..20 {
+                api.push(&i)?;
+            }
+            Ok(list)
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let api = list.api(&tx);
+        for _ in 0..2 {
+            let values = api.iter().collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(values, (0..20).rev().collect::<Vec<_>>());
+        }
+        // writing over what's cached
+        assert_eq!(api.pop()?, Some(19));
+        api.push(&100)?;
+        assert_eq!(api.head()?, Some(100));
+        Ok(())
+    })
+    .unwrap();
+    let (hits, misses) = (
+        metrics.cache_hits.load(Ordering::SeqCst),
+        metrics.cache_misses.load(Ordering::SeqCst),
+    );
+    assert!(misses > 0);
+    assert!(hits > misses, "{} hits {} misses", hits, misses);
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        let values = list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values[0], 100);
+        assert_eq!(values[1..], (0..19).rev().collect::<Vec<_>>());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[derive(Debug, PartialEq, bincode::Encode, bincode::Decode)]
+struct Header {
+    id: u32,
+    body_len: u64,
+}
+
+#[test]
+fn read_prefix_skips_the_body() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let metrics = Arc::new(CountingMetrics::default());
+    db.set_metrics(metrics.clone());
+
+    db.execute(|tx| {
+        let list = tx.take_list::<(Header, Vec<u8>)>("list")?;
+        let api = list.api(&tx);
+        for id in 0..3 {
+            let header = Header {
+                id,
+                body_len: 10_000,
+            };
+            api.push(&(header, vec![id as u8; 10_000]))?;
+        }
+        let pointers = api.iter_pointers().collect::<Result<Vec<_>, _>>()?;
+        let before = metrics.bytes_read.load(Ordering::SeqCst);
+        let headers = pointers
+            .iter()
+            .map(|pointer| tx.io.read_prefix_at::<Header>(*pointer))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            headers.iter().map(|header| header.id).collect::<Vec<_>>(),
+            [2, 1, 0]
+        );
+        assert!(metrics.bytes_read.load(Ordering::SeqCst) - before < 100);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn tx_memory_limit() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let list = db.execute(|tx| tx.take_list::<u32>("list")).unwrap();
+    db.set_tx_memory_limit(Some(1_000));
+
+    let err = db
+        .execute(|tx| {
+            let api = list.api(&tx);
+            for i in 0..100 {
+                api.push(&i)?;
+            }
+            Ok(())
+        })
+        .unwrap_err();
+    let exceeded = err.downcast_ref::<TxMemoryExceeded>().unwrap();
+    assert_eq!(exceeded.limit, 1_000);
+    assert!(exceeded.used > 1_000);
+
+    // the same work split up over several transactions fits
+    for chunk in 0..10 {
+        db.execute(|tx| {
+            let api = list.api(&tx);
+            for i in 0..10 {
+                api.push(&(chunk * 10 + i))?;
+            }
+            assert!(tx.io.memory_used() <= 1_000);
+            Ok(())
+        })
+        .unwrap();
+    }
+    db.set_tx_memory_limit(None);
+    db.execute(|tx| {
+        let values = list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, (0..100).rev().collect::<Vec<_>>());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn dump_all_lists() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let ints = tx.take_list::<u32>("ints")?;
+        let strings = tx.take_list::<String>("strings")?;
+        ints.api(&tx).push(&1)?;
+        strings.api(&tx).push(&"hello".to_string())?;
+        ints.api(&tx).push(&2)?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let new = tx.take_list::<u32>("new")?;
+        new.api(&tx).push(&3)?;
+        let dump = tx.dump()?.collect::<Result<Vec<_>, _>>()?;
+        let names = dump
+            .iter()
+            .map(|entry| entry.list_name.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                None,
+                None,
+                None,
+                Some("ints"),
+                Some("ints"),
+                Some("strings"),
+                Some("new")
+            ]
+        );
+        let ints = dump
+            .iter()
+            .filter(|entry| entry.list_name.as_deref() == Some("ints"))
+            .map(|entry| {
+                let prev_len = entry.entry_pointer.link_len() as usize;
+                bincode::decode_from_slice::<u32, _>(
+                    &entry.bytes[prev_len..],
+                    bincode::config::standard(),
+                )
+                .map(|(value, len)| {
+                    assert_eq!(len + prev_len, entry.bytes.len());
+                    value
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(ints, vec![2, 1]);
+        let strings = dump
+            .iter()
+            .find(|entry| entry.list_name.as_deref() == Some("strings"))
+            .unwrap();
+        assert!(strings.bytes.ends_with(b"hello"));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn entries_map_to_file_offsets() {
+    for n_extra_header_pages in [0, 2] {
+        let mut db = LlsDb::init_with_options(
+            Cursor::new(vec![]),
+            InitOptions {
+                n_extra_header_pages,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (pointers, ranges) = db
+            .execute(|tx| {
+                let list = tx.take_list::<String>("list")?;
+                let mut pointers = vec![];
+                let mut ranges = vec![];
+                for value in ["hello", "world"] {
+                    let handle = list.api(&tx).push(&value.to_string())?;
+                    pointers.push(handle.entry_pointer());
+                    ranges.push(handle.file_range(&tx));
+                }
+                let iterated = tx
+                    .io
+                    .iter(list.slot())
+                    .into_pointer_iter()
+                    .map(|pointer| pointer.map(|pointer| pointer.file_offset(&tx)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                assert_eq!(
+                    iterated,
+                    ranges
+                        .iter()
+                        .rev()
+                        .map(|range| range.start)
+                        .collect::<Vec<_>>()
+                );
+                Ok((pointers, ranges))
+            })
+            .unwrap();
+
+        let file = db.backend().get_ref();
+        for (value, range) in ["hello", "world"].iter().zip(&ranges) {
+            assert!(range.start >= 4096);
+            assert!(file[range.start as usize..range.end as usize].ends_with(value.as_bytes()));
+        }
+        for (pointer, range) in pointers.iter().zip(&ranges) {
+            assert_eq!(db.pointer_to_offset(pointer.this_entry), Some(range.start));
+        }
+        assert_eq!(db.pointer_to_offset(Pointer::NULL), None);
+    }
+}
+
+#[test]
+fn type_erased_backend() {
+    let backend: Box<dyn Backend> = Box::new(Cursor::new(vec![]));
+    let mut db = LlsDb::init(backend).unwrap();
+    let list = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("list")?;
+            list.api(&tx).push(&42)?;
+            Ok(list)
+        })
+        .unwrap();
+
+    let mut backend = db.into_backend();
+    let mut db = LlsDb::load(&mut backend).unwrap();
+    assert_eq!(db.execute(|tx| list.api(&tx).head()).unwrap(), Some(42));
+}
+
+fn lists_that_fit(options: InitOptions) -> usize {
+    let mut db = LlsDb::init_with_options(Cursor::new(vec![]), options).unwrap();
+    let mut n = 0;
+    while db
+        .execute(|tx| tx.take_list::<u32>(&format!("list-{}", n)))
+        .is_ok()
+    {
+        n += 1;
+    }
+    n
+}
+
+#[test]
+fn compact_pointers_hold_more_lists() {
+    let standard = lists_that_fit(InitOptions {
+        page_size: 128,
+        n_free_slots: Some(2),
+        ..Default::default()
+    });
+    let compact = lists_that_fit(InitOptions {
+        page_size: 128,
+        n_free_slots: Some(2),
+        compact_pointers: true,
+        ..Default::default()
+    });
+    assert!(compact > standard, "{} > {}", compact, standard);
+}
+
+#[test]
+fn compact_pointers_use_fixed_links() {
+    for compact_pointers in [false, true] {
+        let mut backend = vec![];
+        let options = InitOptions {
+            compact_pointers,
+            ..Default::default()
+        };
+        let mut db = LlsDb::init_with_options(Cursor::new(&mut backend), options).unwrap();
+        let (list, last) = db
+            .execute(|tx| {
+                let list = tx.take_list::<u8>("list")?;
+                let api = list.api(&tx);
+                // enough entries that the pointers to the last ones are past 64KiB
+                let mut last = None;
+                for i in 0..20_000u32 {
+                    last = Some(api.push(&(i as u8))?);
+                }
+                Ok((list, last.unwrap()))
+            })
+            .unwrap();
+        // a varint link to an entry past 64KiB takes 5 bytes
+        let link_len = if compact_pointers { 4 } else { 5 };
+        assert_eq!(last.entry_len(), link_len + 1);
+
+        drop(db);
+        let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+        db.execute(|tx| {
+            let api = list.api(&tx);
+            let (handle, value) = api.iter_with_handles().next().unwrap()?;
+            assert_eq!((handle, value), (last, (19_999u32 as u8)));
+            assert_eq!(api.iter().count(), 20_000);
+            assert_eq!(api.pop()?, Some(19_999u32 as u8));
+            Ok(())
+        })
+        .unwrap();
+    }
+}
+
+#[test]
+fn grow_past_64kib() {
+    for compact_pointers in [false, true] {
+        let mut backend = vec![];
+        let options = InitOptions {
+            compact_pointers,
+            ..Default::default()
+        };
+        let mut db = LlsDb::init_with_options(Cursor::new(&mut backend), options).unwrap();
+        let list = db
+            .execute(|tx| {
+                let list = LinkedListMut::<Vec<u8>>(tx.take_list("list")?);
+                let api = list.api(&tx);
+                for i in 0..100u8 {
+                    api.push(vec![i; 1_000])?;
+                }
+                Ok(list)
+            })
+            .unwrap();
+
+        db.execute(|tx| {
+            let api = list.api(&tx);
+            let handles = api
+                .iter_handles()
+                .map(|res| res.map(|(handle, _)| handle))
+                .collect::<Result<Vec<_>, _>>()?;
+            for handle in handles.into_iter().skip(1).step_by(2) {
+                api.unlink(handle)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        drop(db);
+
+        let len_before = backend.len();
+        assert!(len_before > 1 << 16);
+        let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+        let list = LinkedListMut::<Vec<u8>>(db.get_list("list").unwrap());
+        db.execute(|tx| {
+            let api = list.api(&tx);
+            for i in 0..10u8 {
+                api.push(vec![i; 1_000])?;
+            }
+            assert_eq!(api.iter().count(), 60);
+            Ok(())
+        })
+        .unwrap();
+        drop(db);
+        assert_eq!(backend.len(), len_before, "pushes should reuse freed space");
+    }
+}
+
+#[test]
+fn try_take_index_errors() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let mut stale_handle = None;
+    let _it_should_fail = db.execute(|tx| {
+        let list = tx.take_list::<u32>("stale")?;
+        stale_handle = Some(tx.store_index(llsdb::index::Vec::new(list, tx)?));
+        Err::<(), _>(anyhow::anyhow!("fail the tx"))
+    });
+    let stale_handle = stale_handle.unwrap();
+
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("vec")?;
+            Ok(tx.store_index(llsdb::index::Vec::new(list, tx)?))
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let _vec = tx.try_take_index(handle)?;
+        assert!(tx.try_take_index(handle).is_err());
+        Ok(())
+    })
+    .unwrap();
+
+    db.execute(|tx| {
+        assert!(tx.try_take_index(stale_handle).is_err());
+        assert!(tx.try_take_index(handle).is_ok());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn fixint_big_endian_values() {
+    let mut backend = vec![];
+    let options = InitOptions {
+        value_encoding: ValueEncoding {
+            int_encoding: IntEncoding::Fixint,
+            endian: Endian::Big,
+        },
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(Cursor::new(&mut backend), options).unwrap();
+    let list = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("list")?;
+            let api = list.api(&tx);
+            api.push(&0x0102_0304)?;
+            api.push(&1)?;
+            Ok(list)
+        })
+        .unwrap();
+    drop(db);
+    assert!(backend.windows(4).any(|bytes| bytes == [1, 2, 3, 4]));
+    assert!(backend.windows(4).any(|bytes| bytes == [0, 0, 0, 1]));
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let values = list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, vec![1, 0x0102_0304]);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn list_quota() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let quota = ListQuota {
+        max_entries: Some(3),
+        max_bytes: None,
+    };
+    let list = db
+        .execute(|tx| {
+            let list = tx.take_list_with_quota::<u32>("list", quota)?;
+            let api = list.api(&tx);
+            for i in 0..3 {
+                api.push(&i)?;
+            }
+            let error = api.push(&3).unwrap_err();
+            let exceeded = error.downcast_ref::<QuotaExceeded>().unwrap();
+            assert_eq!(exceeded.list_slot, list.slot());
+            assert_eq!(exceeded.usage.entries, 3);
+            api.pop()?;
+            api.push(&3)?;
+            Ok(list)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let api = list.api(&tx);
+        api.pop()?;
+        api.pop()?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    let usage = db
+        .execute(|tx| {
+            assert!(list.api(&tx).push(&4).is_err());
+            Ok(tx.io.quota(list.slot()).unwrap().1)
+        })
+        .unwrap();
+    assert_eq!(usage.entries, 3);
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        // the quota is kept with the list
+        assert!(tx.take_list::<u32>("list").is_err());
+        let list = tx.take_list_with_quota::<u32>("list", quota)?;
+        assert_eq!(tx.io.quota(list.slot()).unwrap(), (quota, usage));
+        assert!(list.api(&tx).push(&4).is_err());
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        // a quota without limits removes it
+        tx.take_list_with_quota::<u32>("list", ListQuota::default())?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        // without a quota it can grow as much as it likes
+        let list = tx.take_list::<u32>("list")?;
+        list.api(&tx).push(&4)?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let quota = ListQuota {
+            max_entries: None,
+            max_bytes: Some(12),
+        };
+        let list = tx.take_list_with_quota::<u32>("list", quota)?;
+        let (_, usage) = tx.io.quota(list.slot()).unwrap();
+        assert_eq!(usage.entries, 4);
+        assert!(usage.bytes < 12);
+        let api = list.api(&tx);
+        let error = loop {
+            if let Err(error) = api.push(&5) {
+                break error;
+            }
+        };
+        assert!(error.downcast_ref::<QuotaExceeded>().is_some());
+        let (_, usage) = tx.io.quota(list.slot()).unwrap();
+        assert!(usage.bytes <= 12);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn mut_list_quota_counts_values() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let quota = ListQuota {
+        max_entries: Some(3),
+        max_bytes: None,
+    };
+    db.execute(|tx| {
+        let list = tx.take_list_mut_with_quota::<u32>("list", quota)?;
+        let api = list.api(&tx);
+        for i in 0..3 {
+            api.push(i)?;
+        }
+        let (middle, _) = api.iter_handles().nth(1).unwrap()?;
+        api.unlink(middle)?;
+        // the remap written by unlinking doesn't count as a value
+        assert_eq!(tx.io.quota(list.0.slot()).unwrap().1.entries, 2);
+        api.push(3)?;
+        assert!(api.push(4).is_err());
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list_mut_with_quota::<u32>("list", quota)?;
+        assert_eq!(tx.io.quota(list.0.slot()).unwrap().1.entries, 3);
+        let api = list.api(&tx);
+        assert!(api.push(4).is_err());
+        let (last, _) = api.iter_handles().last().unwrap()?;
+        api.unlink(last)?;
+        api.push(4)?;
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn namespace_quota() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let (a, other) = db
+        .execute(|tx| {
+            let (a, other) = (
+                tx.take_list::<u32>("tenant1/a")?,
+                tx.take_list::<u32>("tenant2/a")?,
+            );
+            a.api(&tx).push(&0)?;
+            Ok((a, other))
+        })
+        .unwrap();
+
+    let usage = db
+        .execute(|tx| {
+            let quota = ListQuota {
+                max_entries: Some(3),
+                max_bytes: None,
+            };
+            tx.set_namespace_quota("tenant1", quota)?;
+            assert_eq!(tx.io.namespace_quota("tenant1").unwrap().1.entries, 1);
+            // lists created after the quota was set count towards it
+            let b = tx.take_list::<u32>("tenant1/b")?;
+            let (a, b, other) = (a.api(&tx), b.api(&tx), other.api(&tx));
+            a.push(&1)?;
+            b.push(&2)?;
+            let error = b.push(&3).unwrap_err();
+            let exceeded = error.downcast_ref::<QuotaExceeded>().unwrap();
+            assert_eq!(exceeded.namespace.as_deref(), Some("tenant1"));
+            assert_eq!(exceeded.usage.entries, 3);
+            for i in 0..10 {
+                other.push(&i)?;
+            }
+            a.pop()?;
+            b.push(&3)?;
+            Ok(tx.io.namespace_quota("tenant1").unwrap().1)
+        })
+        .unwrap();
+    assert_eq!(usage.entries, 3);
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        assert!(tx.io.namespace_quota("tenant1").is_none());
+        tx.set_namespace_quota("tenant1", ListQuota::default())?;
+        assert_eq!(tx.io.namespace_quota("tenant1").unwrap().1, usage);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn namespaced_lists() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let orders = db
+        .execute(|tx| {
+            let mut orders = vec![];
+            for tenant in ["tenant1", "tenant2"] {
+                let mut ns = tx.namespace(tenant);
+                let list = ns.take_list::<String>("orders")?;
+                list.api(&ns).push(&tenant.to_string())?;
+                orders.push(list);
+            }
+            let mut ns = tx.namespace("tenant1");
+            assert!(ns.take_list::<String>("orders").is_err());
+            let mut eu = ns.namespace("eu");
+            assert_eq!(eu.name(), "tenant1/eu");
+            eu.take_list::<String>("orders")?;
+            ns.set_quota(ListQuota::default())?;
+            assert_eq!(ns.quota().unwrap().1.entries, 1);
+            Ok(orders)
+        })
+        .unwrap();
+
+    let mut list_names = db.lists().collect::<Vec<_>>();
+    list_names.sort();
+    assert_eq!(
+        list_names,
+        ["tenant1/eu/orders", "tenant1/orders", "tenant2/orders"]
+    );
+    db.execute(|tx| {
+        assert_eq!(orders[1].api(&tx).head()?, Some("tenant2".to_string()));
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn header_page_checksum_and_id() {
+    let mut backend = vec![];
+    let db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let id = db.id().unwrap();
+    assert_eq!(id.to_string().len(), 36);
+    assert_ne!(LlsDb::init(Cursor::new(vec![])).unwrap().id(), Some(id));
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    assert_eq!(db.id(), Some(id));
+    db.execute(|tx| {
+        tx.take_list::<u32>("list")?.api(&tx).push(&1)?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    assert_eq!(
+        LlsDb::load(Cursor::new(&mut backend)).unwrap().id(),
+        Some(id)
+    );
+
+    // flip a bit in the first page
+    backend[100] ^= 1;
+    assert!(LlsDb::load(Cursor::new(&mut backend)).is_err());
+}
+
+#[test]
+fn corrupt_free_slots_are_refused() {
+    let mut backend = vec![];
+    drop(LlsDb::init(Cursor::new(&mut backend)).unwrap());
+    // a 128 byte page has a 51 byte header, 5 list slots and then 2 free slots
+    let free_slots_start = 51 + 5 * 8;
+    // shrinking the first free space leaves it valid and not overlapping anything
+    backend[free_slots_start] ^= 1;
+    fix_first_page_checksum(&mut backend);
+    let err = LlsDb::load(Cursor::new(&mut backend)).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "checksum of llsdb free slots doesn't match"
+    );
+
+    backend[free_slots_start] ^= 1;
+    fix_first_page_checksum(&mut backend);
+    LlsDb::load(Cursor::new(&mut backend)).unwrap();
+}
+
+#[test]
+fn clean_shutdown_is_recorded() {
+    let mut backend = vec![];
+    let db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    assert_eq!(db.closed_cleanly(), None);
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    assert_eq!(db.closed_cleanly(), Some(false));
+    db.execute(|tx| {
+        tx.take_list::<u32>("list")?.api(&tx).push(&1)?;
+        Ok(())
+    })
+    .unwrap();
+    db.close().unwrap();
+
+    let db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    assert_eq!(db.closed_cleanly(), Some(true));
+    // closing again without a commit in between leaves the flag set
+    db.close().unwrap();
+    let db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    assert_eq!(db.closed_cleanly(), Some(true));
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        tx.take_list::<u32>("list")?.api(&tx).push(&2)?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    let db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    assert_eq!(db.closed_cleanly(), Some(false));
+}
+
+#[test]
+fn newer_formats_are_refused() {
+    let mut backend = vec![];
+    drop(LlsDb::init(Cursor::new(&mut backend)).unwrap());
+    // the version comes right after the magic bytes and the feature flags after the other settings
+    let (version_offset, features) = (5, 14..18);
+
+    let mut newer = backend.clone();
+    newer[version_offset] = 9;
+    let err = LlsDb::load(Cursor::new(&mut newer)).err().unwrap();
+    assert_eq!(
+        err.downcast_ref::<NewerFormat>(),
+        Some(&NewerFormat {
+            version: 9,
+            unknown_features: 0
+        })
+    );
+    assert!(err.to_string().contains("newer llsdb"));
+
+    let mut newer = backend.clone();
+    newer[features.start] |= 0x80;
+    let err = LlsDb::load(Cursor::new(&mut newer)).err().unwrap();
+    assert_eq!(
+        err.downcast_ref::<NewerFormat>(),
+        Some(&NewerFormat {
+            version: 4,
+            unknown_features: 0x80
+        })
+    );
+
+    // an optional feature is ignored and then cleared by the next commit
+    backend[features.end - 1] |= 0x01;
+    fix_first_page_checksum(&mut backend);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        tx.take_list::<u32>("list")?.api(&tx).push(&1)?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    assert_eq!(backend[features.end - 1] & 0x01, 0);
+    LlsDb::load(Cursor::new(&mut backend)).unwrap();
+}
+
+#[test]
+fn upgrade_header_from_version_three() {
+    let mut backend = vec![];
+    let options = InitOptions {
+        n_free_slots: Some(8),
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(Cursor::new(&mut backend), options).unwrap();
+    db.execute(|tx| {
+        let (list, other) = (tx.take_list::<u32>("list")?, tx.take_list::<u32>("other")?);
+        for i in 0..10 {
+            other.api(&tx).push(&i)?;
+            list.api(&tx).push(&i)?;
+        }
+        // leave some free space behind between the entries
+        for _ in 0..3 {
+            list.api(&tx).pop()?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    downgrade_to_version_three(&mut backend, 8);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    assert_eq!(db.generation(), None);
+    assert!(db.upgrade_header(3).is_err());
+    assert!(db.upgrade_header(5).is_err());
+    db.upgrade_header(4).unwrap();
+    assert_eq!(db.generation(), Some(0));
+    assert!(db.id().is_some());
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        list.api(&tx).push(&100)?;
+        assert_eq!(
+            list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?,
+            vec![100, 6, 5, 4, 3, 2, 1, 0]
+        );
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    assert_eq!(db.generation(), Some(1));
+    db.check_allocations().unwrap();
+    // the free space left by the pops was carried over
+    assert!(db.free_space_stats().n_used_free_slots > 0);
+    db.execute(|tx| {
+        let (list, other) = (tx.take_list::<u32>("list")?, tx.take_list::<u32>("other")?);
+        assert_eq!(list.api(&tx).iter().count(), 8);
+        assert_eq!(other.api(&tx).iter().count(), 10);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn upgrade_format_copies_into_a_new_layout() {
+    let mut backend = vec![];
+    let options = InitOptions {
+        n_free_slots: Some(8),
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(Cursor::new(&mut backend), options).unwrap();
+    let mut_list = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("list")?;
+            for i in 0..10 {
+                list.api(&tx).push(&i)?;
+            }
+            for _ in 0..3 {
+                list.api(&tx).pop()?;
+            }
+            // enough lists that some end up in the extra header page of the copy
+            for i in 0..60u32 {
+                tx.take_list::<u32>(&format!("list-{}", i))?
+                    .api(&tx)
+                    .push(&i)?;
+            }
+            let mut_list = LinkedListMut::<u32>(tx.take_list("mut")?);
+            for i in 0..5 {
+                mut_list.api(&tx).push(i)?;
+            }
+            Ok(mut_list)
+        })
+        .unwrap();
+    // unlinking from the middle leaves remaps pointing at entries behind
+    db.execute(|tx| {
+        let api = mut_list.api(&tx);
+        let (handle, _) = api.iter_handles().nth(2).unwrap()?;
+        api.unlink(handle)?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    downgrade_to_version_three(&mut backend, 8);
+
+    let (mut rejected, mut fresh, mut again) = (vec![], vec![], vec![]);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let fixint = InitOptions {
+        value_encoding: ValueEncoding {
+            int_encoding: IntEncoding::Fixint,
+            endian: Endian::Little,
+        },
+        ..Default::default()
+    };
+    assert!(db
+        .upgrade_format(Cursor::new(&mut rejected), fixint)
+        .is_err());
+    let options = InitOptions {
+        page_size: 512,
+        n_extra_header_pages: 1,
+        ..Default::default()
+    };
+    let old = db.upgrade_format(Cursor::new(&mut fresh), options).unwrap();
+    // the old backend is handed back as it was
+    assert_eq!(old.get_ref()[5], 3);
+    assert_eq!(db.generation(), Some(0));
+    // version 3 didn't have an id
+    assert!(db.id().is_some());
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        list.api(&tx).push(&100)?;
+        assert_eq!(
+            list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?,
+            vec![100, 6, 5, 4, 3, 2, 1, 0]
+        );
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    assert_eq!(u16::from_le_bytes([fresh[6], fresh[7]]), 512);
+
+    let mut db = LlsDb::load(Cursor::new(&mut fresh)).unwrap();
+    assert_eq!(db.generation(), Some(1));
+    db.check_allocations().unwrap();
+    assert!(db.free_space_stats().n_used_free_slots > 0);
+    db.execute(|tx| {
+        for i in 0..60u32 {
+            let list = tx.take_list::<u32>(&format!("list-{}", i))?;
+            assert_eq!(list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?, [i]);
+        }
+        let mut_list = LinkedListMut::<u32>(tx.take_list("mut")?);
+        assert_eq!(
+            mut_list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?,
+            [4, 3, 1, 0]
+        );
+        Ok(())
+    })
+    .unwrap();
+
+    // copying a version 4 database keeps its id and generation
+    let (id, generation) = (db.id(), db.generation());
+    db.upgrade_format(Cursor::new(&mut again), InitOptions::default())
+        .unwrap();
+    assert_eq!((db.id(), db.generation()), (id, generation));
+    db.check_allocations().unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("list-59")?;
+        assert_eq!(list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?, [59]);
+        Ok(())
+    })
+    .unwrap();
+}
+
+/// Lay out the first page of a version 4 database created with `n_free_slots` free slots the way
+/// version 3 did. Its header is shorter so it has more list slots.
+fn downgrade_to_version_three(backend: &mut [u8], n_free_slots: usize) {
+    let page_size = u16::from_le_bytes([backend[6], backend[7]]) as usize;
+    // the preamble is the same up to the feature flags apart from the version and version 4 also
+    // has the free slot checksum, the clean shutdown flag and the commit generation
+    let (header_len, old_header_len) = (51, 14);
+    let list_slots_len = (page_size - header_len - n_free_slots * 16) / 8 * 8;
+    let old_list_slots_len = (page_size - old_header_len - n_free_slots * 16) / 8 * 8;
+    let list_slots = backend[header_len..header_len + list_slots_len].to_vec();
+    let free_slots_start = header_len + list_slots_len;
+    let free_slots = backend[free_slots_start..free_slots_start + n_free_slots * 16].to_vec();
+
+    let page = &mut backend[..page_size];
+    page[5] = 3;
+    page[old_header_len..].fill(0);
+    page[old_header_len..old_header_len + list_slots_len].copy_from_slice(&list_slots);
+    let old_free_slots_start = old_header_len + old_list_slots_len;
+    page[old_free_slots_start..old_free_slots_start + free_slots.len()]
+        .copy_from_slice(&free_slots);
+}
+
+fn fix_first_page_checksum(backend: &mut [u8]) {
+    let page_size = u16::from_le_bytes([backend[6], backend[7]]) as usize;
+    let checksum = 34..38;
+    backend[checksum.clone()].fill(0);
+    let crc = !backend[..page_size].iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    });
+    backend[checksum].copy_from_slice(&crc.to_le_bytes());
+}
+
+#[test]
+fn rename_list() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        tx.take_list::<u32>("list")?.api(&tx).push(&1)?;
+        tx.take_list::<u32>("list_v2")?.api(&tx).push(&2)?;
+        assert!(tx.rename_list("list_v2", "list").is_err());
+        assert!(tx.rename_list("nope", "list_v3").is_err());
+        Ok(())
+    })
+    .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        tx.rename_list("list", "list_old")?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+    let mut names = db.lists().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["list", "list_v2"]);
+
+    db.execute(|tx| {
+        tx.rename_list("list", "list_old")?;
+        tx.rename_list("list_v2", "list")?;
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let mut names = db.lists().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["list", "list_old"]);
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        let old = tx.take_list::<u32>("list_old")?;
+        assert_eq!(list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?, [2]);
+        assert_eq!(old.api(&tx).iter().collect::<Result<Vec<_>, _>>()?, [1]);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn swap_lists() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let (list, rebuilt) = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("list")?;
+            let rebuilt = tx.take_list::<u32>("list.rebuilt")?;
+            list.api(&tx).push(&1)?;
+            rebuilt.api(&tx).push(&2)?;
+            assert!(tx.swap_lists("list", "nope").is_err());
+            Ok((list, rebuilt))
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        tx.swap_lists("list", "list.rebuilt")?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        tx.swap_lists("list", "list.rebuilt")?;
+        // handles stay with their entries
+        assert_eq!(list.api(&tx).head()?, Some(1));
+        assert_eq!(rebuilt.api(&tx).head()?, Some(2));
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let mut names = db.lists().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["list", "list.rebuilt"]);
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        let old = tx.take_list::<u32>("list.rebuilt")?;
+        assert_eq!(list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?, [2]);
+        assert_eq!(old.api(&tx).iter().collect::<Result<Vec<_>, _>>()?, [1]);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn pinned_entries_are_not_reused() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let (list, handle) = db
+        .execute(|tx| {
+            let list = tx.take_list::<String>("list")?;
+            let handle = list.api(&tx).push(&"a".repeat(100))?;
+            tx.io.pin(handle);
+            Ok((list, handle))
+        })
+        .unwrap();
+
+    // a pin in a failed transaction is undone
+    let _it_should_fail = db.execute(|tx| {
+        tx.io.pin(handle);
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        assert_eq!(list.api(&tx).pop()?, Some("a".repeat(100)));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(db.free_space_stats().n_held, 1);
+
+    db.execute(|tx| {
+        for _ in 0..3 {
+            list.api(&tx).push(&"b".repeat(100))?;
+        }
+        let (_, value) = tx.io.read_at::<String>(handle.entry_pointer())?;
+        assert_eq!(value, "a".repeat(100));
+        Ok(())
+    })
+    .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        tx.io.unpin(handle)?;
+        assert!(tx.io.unpin(handle).is_err());
+        Err::<(), _>(anyhow!("rollback"))
+    });
+    assert_eq!(db.free_space_stats().n_held, 1);
+    db.execute(|tx| tx.io.unpin(handle)).unwrap();
+    assert_eq!(db.free_space_stats().n_held, 0);
+}
+
+#[test]
+fn installed_clock_is_used() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    let clock = Arc::new(ManualClock::new(start));
+    db.set_clock(clock.clone());
+    assert_eq!(db.now(), start);
+
+    clock.advance(Duration::from_secs(5));
+    db.execute(|tx| {
+        assert_eq!(tx.io.now(), start + Duration::from_secs(5));
+        Ok(())
+    })
+    .unwrap();
+
+    db.set_clock(Arc::new(SystemClock));
+    assert!(db.now() > start + Duration::from_secs(5));
+}
+
+#[test]
+fn two_phase_commit() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let list = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("list")?;
+            list.api(&tx).push(&1)?;
+            Ok(list)
+        })
+        .unwrap();
+
+    let prepared = db
+        .prepare(|tx| {
+            list.api(&tx).push(&2)?;
+            Ok("prepared")
+        })
+        .unwrap();
+    assert_eq!(*prepared.output(), "prepared");
+    assert_eq!(prepared.commit().unwrap(), "prepared");
+
+    let prepared = db
+        .prepare(|tx| {
+            list.api(&tx).push(&3)?;
+            Ok(())
+        })
+        .unwrap();
+    prepared.abort();
+
+    assert!(db
+        .prepare(|tx| {
+            list.api(&tx).push(&4)?;
+            Err::<(), _>(anyhow!("fail"))
+        })
+        .is_err());
+
+    db.execute(|tx| {
+        let api = list.api(&tx);
+        assert_eq!(api.iter().collect::<Result<Vec<_>, _>>()?, [2, 1]);
+        api.push(&5)?;
+        Ok(())
+    })
+    .unwrap();
+
+    // the process dying while a transaction is prepared
+    let prepared = db
+        .prepare(|tx| {
+            list.api(&tx).push(&6)?;
+            Ok(())
+        })
+        .unwrap();
+    let token = prepared.token();
+    std::mem::forget(prepared);
+    drop(db);
+    let encoded = bincode::encode_to_vec(token, bincode::config::standard()).unwrap();
+    let (token, _): (PrepareToken, _) =
+        bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+
+    let read_list = |db: &mut LlsDb<_>| {
+        db.execute(|tx| {
+            let list = tx.take_list::<u32>("list")?;
+            let values = list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?;
+            Ok(values)
+        })
+        .unwrap()
+    };
+    let image = backend.clone();
+    // it isn't visible until it's committed
+    let mut db = LlsDb::load(Cursor::new(image.clone())).unwrap();
+    db.execute_read_only(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        assert_eq!(list.api(&tx).head()?, Some(5));
+        Ok(())
+    })
+    .unwrap();
+    db.commit_prepared(&token).unwrap();
+    assert!(db.commit_prepared(&token).is_err());
+    assert_eq!(read_list(&mut db), [6, 5, 2, 1]);
+
+    // a transaction that was rolled back may have written over what the prepared one wrote
+    let mut db = LlsDb::load(Cursor::new(image.clone())).unwrap();
+    let _it_should_fail = db.execute(|tx| {
+        let list = tx.take_list::<u32>("list")?;
+        list.api(&tx).push(&8)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+    assert!(db.commit_prepared(&token).is_err());
+
+    let mut db = LlsDb::load(Cursor::new(image.clone())).unwrap();
+    db.abort_prepared(&token).unwrap();
+    assert!(db.commit_prepared(&token).is_err());
+    assert_eq!(read_list(&mut db), [5, 2, 1]);
+
+    // committing anything else first means it can't be committed anymore
+    let mut db = LlsDb::load(Cursor::new(image)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("other")?;
+        list.api(&tx).push(&7)?;
+        Ok(())
+    })
+    .unwrap();
+    assert!(db.commit_prepared(&token).is_err());
+    db.abort_prepared(&token).unwrap();
+    assert_eq!(read_list(&mut db), [5, 2, 1]);
+}
+
+#[test]
+fn profile_lists() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    assert!(db.profile_report().lists.is_empty());
+    db.set_profiling(true);
+    let (small, big) = db
+        .execute(|tx| {
+            let small = tx.take_list::<u8>("small")?;
+            let big = tx.take_list::<Vec<u8>>("big")?;
+            for i in 0..10 {
+                small.api(&tx).push(&i)?;
+            }
+            big.api(&tx).push(&vec![0u8; 300])?;
+            Ok((small, big))
+        })
+        .unwrap();
+    db.execute(|tx| {
+        for _ in 0..3 {
+            assert_eq!(small.api(&tx).iter().count(), 10);
+        }
+        small.api(&tx).pop()?;
+        Ok(())
+    })
+    .unwrap();
+
+    let report = db.profile_report();
+    let hottest = report.hottest();
+    assert_eq!(hottest[0].0, small.slot());
+    let small = &report.lists[&small.slot()];
+    assert_eq!(small.name.as_deref(), Some("small"));
+    assert_eq!((small.pushes, small.reads, small.pops), (10, 31, 1));
+    assert_eq!(small.value_sizes.count(), 41);
+    assert_eq!(small.value_sizes.max(), Some(1));
+    let big = &report.lists[&big.slot()];
+    assert_eq!(big.value_sizes.min(), Some(303));
+    assert_eq!(
+        big.value_sizes.buckets().collect::<Vec<_>>(),
+        [(256..=511, 1)]
+    );
+
+    db.set_profiling(false);
+    assert_eq!(db.profile_report(), Default::default());
+}
+
+#[test]
+fn read_tracing_by_index() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    db.set_read_tracing(true);
+    let (map, cell, list) = db
+        .execute(|tx| {
+            let map = BTreeMap::new(tx.take_list("map")?, &tx)?;
+            let cell = Cell::new_with_initial_value(tx.take_list("cell")?, &7u32, tx)?;
+            let list = tx.take_list::<u32>("list")?;
+            let (map, cell) = (tx.store_index(map), tx.store_index(cell));
+            let mut map_api = tx.take_index(map);
+            for i in 0..10u32 {
+                map_api.insert(i, &i)?;
+            }
+            list.api(&tx).push(&1)?;
+            Ok((map, cell, list))
+        })
+        .unwrap();
+
+    db.execute(|tx| {
+        let map_api = tx.take_index(map);
+        for i in 0..10u32 {
+            assert_eq!(map_api.get(&i)?, Some(i));
+        }
+        assert_eq!(tx.take_index(cell).get()?, 7);
+        assert_eq!(list.api(&tx).iter().count(), 1);
+
+        let trace = tx.read_trace().unwrap();
+        assert_eq!(trace.index(&map).seeks, 10);
+        assert!(trace.index(&map).bytes > 0);
+        assert!(trace.index(&cell).seeks > 0);
+        assert!(trace.index(&cell).seeks < 10);
+        assert!(trace.other.reads > 0);
+        assert_eq!(trace.most_seeks()[0].1, trace.index(&map));
+        Ok(())
+    })
+    .unwrap();
+
+    db.execute(|tx| {
+        // it starts again with each transaction
+        assert_eq!(tx.read_trace().unwrap().index(&map), ReadCounts::default());
+        Ok(())
+    })
+    .unwrap();
+
+    db.set_read_tracing(false);
+    db.execute(|tx| {
+        assert_eq!(tx.read_trace(), None);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn abort_rolls_back() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let list = db.execute(|tx| tx.take_list::<u32>("list")).unwrap();
+    let error = db
+        .execute(|tx| {
+            list.api(&tx).push(&1)?;
+            // ignoring the error doesn't stop the rollback
+            let _ = tx.abort::<()>("first");
+            let _ = tx.abort::<()>("second");
+            Ok(())
+        })
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<Aborted>(),
+        Some(&Aborted {
+            reason: "first".into()
+        })
+    );
+    let error = db
+        .execute(|_tx| Err::<(), _>(anyhow!("failed")))
+        .unwrap_err();
+    assert!(error.downcast_ref::<Aborted>().is_none());
+    db.execute(|tx| {
+        assert_eq!(list.api(&tx).iter().count(), 0);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn trim_threshold_leaves_small_free_space_at_end() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    db.set_trim_policy(TrimPolicy::Threshold(1_000));
+    let list = db.execute(|tx| tx.take_list::<Vec<u8>>("list")).unwrap();
+    let len_at_start = db.backend().get_ref().len();
+
+    db.execute(|tx| list.api(&tx).push(&vec![1; 500])).unwrap();
+    let len_after_push = db.backend().get_ref().len();
+    db.execute(|tx| list.api(&tx).pop()).unwrap();
+    assert_eq!(db.backend().get_ref().len(), len_after_push);
+    // growing again reuses the space without extending the backend
+    db.execute(|tx| list.api(&tx).push(&vec![2; 500])).unwrap();
+    assert_eq!(db.backend().get_ref().len(), len_after_push);
+
+    db.execute(|tx| list.api(&tx).push(&vec![3; 1_000]))
+        .unwrap();
+    db.execute(|tx| list.api(&tx).clear()).unwrap();
+    assert_eq!(db.backend().get_ref().len(), len_at_start);
+
+    db.execute(|tx| list.api(&tx).push(&vec![4; 500])).unwrap();
+    db.execute(|tx| list.api(&tx).pop()).unwrap();
+    assert_eq!(db.backend().get_ref().len(), len_after_push);
+    db.shrink_to_fit().unwrap();
+    assert_eq!(db.backend().get_ref().len(), len_at_start);
+}
+
+#[test]
+fn dump_doesnt_follow_stale_pointers_into_other_lists() {
+    let mut db = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let (mutable, unlinked) = db
+        .execute(|tx| {
+            let mutable = LinkedListMut(tx.take_list::<Mut<u32>>("mutable")?);
+            let api = mutable.api(tx);
+            api.push(50)?;
+            let unlinked = api.push(60)?;
+            api.push(70)?;
+            Ok((mutable, unlinked))
+        })
+        .unwrap();
+    db.execute(|tx| mutable.api(tx).unlink(unlinked)).unwrap();
+
+    db.execute(|tx| {
+        let other = tx.take_list::<u32>("other")?;
+        let handle = other.api(&tx).push(&61)?;
+        // the entry went where the unlinked one was so "mutable" has a stale pointer to it
+        assert_eq!(
+            handle.entry_pointer().this_entry,
+            unlinked.entry_pointer().this_entry
+        );
+        let dump = tx.dump()?.collect::<Result<Vec<_>, _>>()?;
+        let owners = dump
+            .iter()
+            .filter(|entry| entry.entry_pointer.this_entry == handle.entry_pointer().this_entry)
+            .map(|entry| entry.list_name.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(owners, vec![Some("other")]);
+        Ok(())
+    })
+    .unwrap();
+}
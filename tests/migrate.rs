@@ -0,0 +1,83 @@
+use anyhow::anyhow;
+use llsdb::{LinkedList, LlsDb};
+use std::io::Cursor;
+
+fn read_list<T: bincode::Encode + bincode::Decode>(
+    db: &mut LlsDb<Cursor<&mut Vec<u8>>>,
+    list: &LinkedList<T>,
+) -> Vec<T> {
+    db.execute(|tx| {
+        let values = list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?;
+        Ok(values)
+    })
+    .unwrap()
+}
+
+#[test]
+fn migrate_resumes_and_keeps_order() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let numbers = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("numbers")?;
+            let api = list.api(&tx);
+            for i in 0..250 {
+                api.push(&i)?;
+            }
+            drop(api);
+            Ok(list)
+        })
+        .unwrap();
+    let before = read_list(&mut db, &numbers);
+
+    // fail part way through as if the process had died
+    let mut n_converted = 0;
+    let res = db.migrate(numbers, 40, |n: u32| {
+        n_converted += 1;
+        if n_converted > 100 {
+            return Err(anyhow!("interrupted"));
+        }
+        Ok(n.to_string())
+    });
+    assert!(res.is_err());
+    let numbers = db.get_list::<u32>("numbers").unwrap();
+    assert_eq!(read_list(&mut db, &numbers), before);
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let numbers = db.get_list::<u32>("numbers").unwrap();
+    let mut n_converted = 0;
+    let numbers = db
+        .migrate(numbers, 40, |n: u32| {
+            n_converted += 1;
+            Ok(n.to_string())
+        })
+        .unwrap();
+    // only the chunks that hadn't been committed are converted again
+    assert_eq!(n_converted, 250 - 80);
+    assert_eq!(
+        read_list(&mut db, &numbers),
+        before.iter().map(|n| n.to_string()).collect::<Vec<_>>()
+    );
+
+    // the same list can be migrated again
+    let numbers = db
+        .migrate(numbers, 1_000, |s: String| Ok(s.len() as u8))
+        .unwrap();
+    assert_eq!(
+        read_list(&mut db, &numbers),
+        before
+            .iter()
+            .map(|n| n.to_string().len() as u8)
+            .collect::<Vec<_>>()
+    );
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let numbers = db.get_list::<u8>("numbers").unwrap();
+    assert_eq!(read_list(&mut db, &numbers).len(), 250);
+    for helper in ["numbers.migration.new", "numbers.migration.scratch"] {
+        let helper = db.get_list::<u8>(helper).unwrap();
+        assert!(read_list(&mut db, &helper).is_empty());
+    }
+}
@@ -0,0 +1,11 @@
+#![cfg(feature = "testing")]
+use llsdb::testing::model::{run, steps, ListsModel};
+use proptest::prelude::*;
+
+proptest! {
+    #![proptest_config(ProptestConfig { cases: 64, ..Default::default() })]
+    #[test]
+    fn lists_match_model(steps in steps::<ListsModel>(40)) {
+        run(ListsModel::default(), &steps).unwrap();
+    }
+}
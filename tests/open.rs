@@ -0,0 +1,77 @@
+use llsdb::{InitOptions, LlsDb};
+
+#[test]
+fn open_creates_then_loads_and_locks() {
+    let path = std::env::temp_dir().join(format!("llsdb-open-{}", std::process::id()));
+    std::fs::remove_file(&path).ok();
+
+    let options = InitOptions {
+        page_size: 512,
+        ..Default::default()
+    };
+    let mut db = LlsDb::open_with(&path, options).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<String>("list")?;
+        list.api(&tx).push(&"hello".to_string())?;
+        Ok(())
+    })
+    .unwrap();
+    assert!(LlsDb::open(&path).is_err());
+    drop(db);
+
+    let mut db = LlsDb::open(&path).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<String>("list")?;
+        assert_eq!(list.api(&tx).head()?, Some("hello".to_string()));
+        Ok(())
+    })
+    .unwrap();
+
+    // closing releases the lock even while the file is still around
+    let file = db.close().unwrap();
+    let db = LlsDb::open(&path).unwrap();
+    drop((db, file));
+    std::fs::remove_file(&path).ok();
+}
+
+#[cfg(all(feature = "hole-punching", target_os = "linux"))]
+#[test]
+fn discarding_punches_holes_in_the_file() {
+    use std::os::unix::fs::MetadataExt;
+    let path = std::env::temp_dir().join(format!("llsdb-holes-{}", std::process::id()));
+    std::fs::remove_file(&path).ok();
+
+    let mut db = LlsDb::open(&path).unwrap();
+    let (scratch, keep) = db
+        .execute(|tx| {
+            let scratch = tx.take_list::<Vec<u8>>("scratch")?;
+            let keep = tx.take_list::<u32>("keep")?;
+            for _ in 0..256 {
+                scratch.api(&tx).push(&vec![1u8; 4096])?;
+            }
+            keep.api(&tx).push(&42)?;
+            Ok((scratch, keep))
+        })
+        .unwrap();
+    db.execute(|tx| {
+        while scratch.api(&tx).pop()?.is_some() {}
+        Ok(())
+    })
+    .unwrap();
+    let len_before = std::fs::metadata(&path).unwrap().len();
+    let blocks_before = std::fs::metadata(&path).unwrap().blocks();
+    db.discard_free_space(64 * 1024).unwrap();
+    let metadata = std::fs::metadata(&path).unwrap();
+    assert_eq!(metadata.len(), len_before);
+    assert!(metadata.blocks() < blocks_before / 2);
+
+    db.execute(|tx| {
+        assert_eq!(keep.api(&tx).head()?, Some(42));
+        scratch.api(&tx).push(&vec![2u8; 4096])?;
+        assert_eq!(scratch.api(&tx).head()?, Some(vec![2u8; 4096]));
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    std::fs::remove_file(&path).ok();
+}
@@ -0,0 +1,89 @@
+use llsdb::{
+    index::{BTreeMap, Vec as LlsVec},
+    LlsDb,
+};
+use std::fs::OpenOptions;
+
+#[test]
+fn load_indexes_in_parallel() {
+    let path = std::env::temp_dir().join(format!("llsdb-parallel-load-{}", std::process::id()));
+    let open = || {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .unwrap()
+    };
+    std::fs::remove_file(&path).ok();
+    let mut db = LlsDb::init(open()).unwrap();
+    db.execute(|tx| {
+        let (numbers, sparse, map) = (
+            tx.take_list::<u64>("numbers")?,
+            tx.take_list::<u64>("sparse")?,
+            tx.take_list::<(u32, String)>("map")?,
+        );
+        let (numbers, sparse, map) = (numbers.api(&tx), sparse.api(&tx), map.api(&tx));
+        for i in 0..1_000 {
+            numbers.push(&i)?;
+            sparse.push(&(i * 2))?;
+            map.push(&((i % 100) as u32, i.to_string()))?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(open()).unwrap();
+    let (numbers, sparse, map, empty) = db
+        .execute(|tx| {
+            Ok((
+                tx.take_list::<u64>("numbers")?,
+                tx.take_list::<u64>("sparse")?,
+                tx.take_list::<(u32, String)>("map")?,
+                tx.take_list::<u64>("empty")?,
+            ))
+        })
+        .unwrap();
+    let pool = db.reader_pool(&path);
+    let (numbers, sparse, map, empty) = db
+        .load_indexes(
+            &pool,
+            (
+                LlsVec::loader(numbers),
+                LlsVec::sparse_loader(sparse, 16),
+                BTreeMap::loader(map),
+                LlsVec::loader(empty),
+            ),
+        )
+        .unwrap();
+
+    db.execute(|tx| {
+        let (numbers, sparse) = (tx.take_index(numbers), tx.take_index(sparse));
+        assert_eq!(numbers.len(), 1_000);
+        assert_eq!(sparse.len(), 1_000);
+        for i in [0, 15, 16, 500, 999] {
+            assert_eq!(numbers.get(i as usize)?, Some(i));
+            assert_eq!(sparse.get(i as usize)?, Some(i * 2));
+        }
+        let map = tx.take_index(map);
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.get(&7)?, Some("907".to_string()));
+        assert!(tx.take_index(empty).is_empty());
+        Ok(())
+    })
+    .unwrap();
+
+    // the loaded indexes carry on as if they had been loaded in a transaction
+    db.execute(|tx| {
+        let mut sparse = tx.take_index(sparse);
+        sparse.push(&2_000)?;
+        assert_eq!(sparse.get(1_000)?, Some(2_000));
+        assert_eq!(sparse.pop()?, Some(2_000));
+        assert_eq!(sparse.pop()?, Some(1_998));
+        Ok(())
+    })
+    .unwrap();
+    std::fs::remove_file(&path).ok();
+}
@@ -0,0 +1,95 @@
+use anyhow::anyhow;
+use llsdb::{
+    index::{BTreeMap, EventIndex, Projection},
+    Backend, LlsDb, Result,
+};
+use std::io::Cursor;
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+struct Deposit {
+    account: String,
+    amount: i64,
+}
+
+impl EventIndex<Deposit> for BTreeMap<String, i64> {
+    fn apply<'i, F: Backend + 'i>(api: &mut Self::Api<'i, F>, event: Deposit) -> Result<()> {
+        let balance = api.get(&event.account)?.unwrap_or(0);
+        api.insert(event.account, &(balance + event.amount))?;
+        Ok(())
+    }
+}
+
+fn deposit(account: &str, amount: i64) -> Deposit {
+    Deposit {
+        account: account.into(),
+        amount,
+    }
+}
+
+#[test]
+fn projection_replays_events_after_cursor() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let projection = db
+        .execute(|tx| {
+            let events = tx.take_list("events")?;
+            let cursor = tx.take_list("cursor")?;
+            let balances = BTreeMap::new(tx.take_list("balances")?, &tx)?;
+            let projection = Projection::new(events, cursor, balances, tx)?;
+            let handle = tx.store_index(projection);
+            let mut api = tx.take_index(handle);
+            api.push(deposit("alice", 10))?;
+            api.push(deposit("bob", 5))?;
+            api.push(deposit("alice", -3))?;
+            assert_eq!(api.index().get("alice")?, Some(7));
+            Ok(handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut api = tx.take_index(projection);
+        api.push(deposit("alice", 100))?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let mut api = tx.take_index(projection);
+        assert_eq!(api.catch_up()?, 0);
+        assert_eq!(api.index().get("alice")?, Some(7));
+        assert_eq!(api.events().count(), 3);
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    // events pushed straight onto the list are applied when the projection is next created
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let events = tx.take_list::<Deposit>("events")?;
+        events.api(&tx).push(&deposit("bob", 1))?;
+        events.api(&tx).push(&deposit("carol", 2))?;
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let events = tx.take_list("events")?;
+        let cursor = tx.take_list("cursor")?;
+        let balances = BTreeMap::new(tx.take_list("balances")?, &tx)?;
+        let (_, mut api) = tx.store_and_take_index(Projection::new(events, cursor, balances, tx)?);
+        assert_eq!(api.catch_up()?, 0);
+        let balances = api.index().iter().collect::<Result<std::vec::Vec<_>>>()?;
+        assert_eq!(
+            balances,
+            [
+                ("alice".to_string(), 7),
+                ("bob".to_string(), 6),
+                ("carol".to_string(), 2)
+            ]
+        );
+        Ok(())
+    })
+    .unwrap();
+}
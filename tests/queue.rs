@@ -0,0 +1,70 @@
+use anyhow::anyhow;
+use llsdb::{index::JobQueue, LlsDb};
+use std::io::Cursor;
+
+#[test]
+fn job_queue() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let (queue, in_flight) = db
+        .execute(|tx| {
+            let list = tx.take_list("jobs")?;
+            let handle = tx.store_index(JobQueue::new(list, tx)?);
+            let mut queue = tx.take_index(handle);
+            for job in 0..4u32 {
+                queue.enqueue(job)?;
+            }
+            let (in_flight, job) = queue.dequeue()?.unwrap();
+            assert_eq!(job, 0);
+            let (acked, job) = queue.dequeue()?.unwrap();
+            assert_eq!(job, 1);
+            queue.ack(acked)?;
+            assert!(queue.ack(acked).is_err());
+            assert_eq!(queue.len_pending(), 2);
+            assert_eq!(queue.len_in_flight(), 1);
+            Ok((handle, in_flight))
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut queue = tx.take_index(queue);
+        queue.ack(in_flight)?;
+        let (job, _) = queue.dequeue()?.unwrap();
+        queue.requeue(job)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let mut queue = tx.take_index(queue);
+        assert_eq!(queue.len_in_flight(), 1);
+        queue.nack(in_flight)?;
+        assert_eq!(queue.peek()?, Some(0));
+        let (job, value) = queue.dequeue()?.unwrap();
+        assert_eq!(value, 0);
+        queue.requeue(job)?;
+        let (_, value) = queue.dequeue()?.unwrap();
+        assert_eq!(value, 2);
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("jobs")?;
+        let handle = tx.store_index(JobQueue::<u32>::new(list, tx)?);
+        let mut queue = tx.take_index(handle);
+        // the in-flight job is pending again after reloading
+        assert_eq!(queue.len_in_flight(), 0);
+        let mut jobs = vec![];
+        while let Some((job, value)) = queue.dequeue()? {
+            queue.ack(job)?;
+            jobs.push(value);
+        }
+        assert_eq!(jobs, vec![0, 2, 3]);
+        assert!(queue.is_empty());
+        Ok(())
+    })
+    .unwrap();
+}
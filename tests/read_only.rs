@@ -0,0 +1,67 @@
+use llsdb::{index::BTreeMap, LlsDb, ReadOnlySlice};
+use std::{io::Cursor, sync::Arc};
+
+fn image() -> Vec<u8> {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let words = tx.take_list::<String>("words")?;
+        let map = tx.take_list::<(u32, String)>("map")?;
+        for (i, word) in ["zero", "one", "two"].into_iter().enumerate() {
+            words.api(&tx).push(&word.to_string())?;
+            map.api(&tx).push(&(i as u32, word.to_string()))?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+    backend
+}
+
+#[test]
+fn read_only_slice_is_queried_without_writing() {
+    let image = image();
+    let mut db = LlsDb::load(ReadOnlySlice::new(&image[..])).unwrap();
+    let (words, map) = db
+        .execute_read_only(|tx| {
+            let words = tx.take_list::<String>("words")?;
+            assert_eq!(
+                words.api(&tx).iter().collect::<Result<Vec<_>, _>>()?,
+                ["two", "one", "zero"]
+            );
+            let map = tx.take_list::<(u32, String)>("map")?;
+            Ok((words, tx.store_index(BTreeMap::new(map, &tx)?)))
+        })
+        .unwrap();
+
+    // the index stored above is still there
+    db.execute_read_only(|tx| {
+        assert_eq!(tx.take_index(map).get(&1)?, Some("one".to_string()));
+        Ok(())
+    })
+    .unwrap();
+
+    assert!(db
+        .execute_read_only(|tx| words.api(&tx).push(&"three".to_string()))
+        .is_err());
+    assert!(db
+        .execute_read_only(|tx| tx.take_list::<u32>("new").map(|_| ()))
+        .is_err());
+    assert!(db.execute(|_| Ok(())).is_err());
+    db.execute_read_only(|tx| {
+        assert_eq!(words.api(&tx).head()?, Some("two".to_string()));
+        assert_eq!(tx.take_index(map).len(), 3);
+        Ok(())
+    })
+    .unwrap();
+
+    let shared: Arc<[u8]> = image.clone().into();
+    let mut db = LlsDb::load(ReadOnlySlice::new(shared.clone())).unwrap();
+    db.execute_read_only(|tx| {
+        let words = tx.take_list::<String>("words")?;
+        assert_eq!(words.api(&tx).head()?, Some("two".to_string()));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(&db.into_backend().into_inner()[..], &image[..]);
+}
@@ -0,0 +1,113 @@
+use llsdb::{InitOptions, LlsDb};
+use std::fs::OpenOptions;
+
+#[test]
+fn reader_pool_reads_committed_entries() {
+    let path = std::env::temp_dir().join(format!("llsdb-reader-pool-{}", std::process::id()));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    let mut db = LlsDb::init(file).unwrap();
+    let list = db
+        .execute(|tx| {
+            let list = tx.take_list::<String>("list")?;
+            let api = list.api(&tx);
+            for word in ["one", "two", "three"] {
+                api.push(&word.to_string())?;
+            }
+            Ok(list)
+        })
+        .unwrap();
+
+    let pool = db.reader_pool(&path);
+    let head = db.head(&list);
+    let results = std::thread::scope(|s| {
+        let readers = (0..2)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut reader = pool.reader().unwrap();
+                    reader
+                        .iter::<String>(head)
+                        .collect::<Result<Vec<_>, _>>()
+                        .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        readers
+            .into_iter()
+            .map(|reader| reader.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    for entries in &results {
+        let values = entries
+            .iter()
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["three", "two", "one"]);
+    }
+    assert!(pool.n_idle() > 0);
+
+    // a reader started before a transaction still sees what was there when it started
+    let mut reader = pool.reader().unwrap();
+    let mut old = reader.iter::<String>(head);
+    let (_, first) = old.next().unwrap().unwrap();
+    assert_eq!(first, "three");
+    db.execute(|tx| {
+        list.api(&tx).push(&"four".to_string())?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(old.count(), 2);
+    drop(reader);
+
+    let head = db.head(&list);
+    let mut reader = pool.reader().unwrap();
+    assert_eq!(reader.iter::<String>(head).count(), 4);
+    drop(reader);
+    drop(pool);
+    drop(db);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn reader_pool_reads_compact_pointers() {
+    let path = std::env::temp_dir().join(format!("llsdb-reader-compact-{}", std::process::id()));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    let options = InitOptions {
+        compact_pointers: true,
+        ..Default::default()
+    };
+    let mut db = LlsDb::init_with_options(file, options).unwrap();
+    let list = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("list")?;
+            for i in 0..3 {
+                list.api(&tx).push(&i)?;
+            }
+            Ok(list)
+        })
+        .unwrap();
+
+    let pool = db.reader_pool(&path);
+    let mut reader = pool.reader().unwrap();
+    let values = reader
+        .iter::<u32>(db.head(&list))
+        .map(|res| res.map(|(_, value)| value))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(values, [2, 1, 0]);
+    drop(reader);
+    drop(pool);
+    drop(db);
+    std::fs::remove_file(&path).unwrap();
+}
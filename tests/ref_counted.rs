@@ -0,0 +1,60 @@
+use anyhow::anyhow;
+use llsdb::{
+    index::{BTreeMapRemove, RcId, RefCounted},
+    LlsDb,
+};
+use std::io::Cursor;
+
+#[test]
+fn shared_values_are_freed_with_the_last_ref() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let (values, owners, id) = db
+        .execute(|tx| {
+            let values = tx.take_list("values")?;
+            let refs = tx.take_list("refs")?;
+            let values = tx.store_index(RefCounted::new(values, refs, &tx)?);
+            let owners = tx.take_list("owners")?;
+            let owners = tx.store_index(BTreeMapRemove::<String, RcId>::new(owners, &tx)?);
+            let (mut rc, mut by_owner) = (tx.take_index(values), tx.take_index(owners));
+            let id = rc.insert("shared".to_string())?;
+            by_owner.insert("alice".into(), id)?;
+            assert_eq!(rc.retain_ref(id)?, 2);
+            by_owner.insert("bob".into(), id)?;
+            Ok((values, owners, id))
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut rc = tx.take_index(values);
+        assert_eq!(rc.release_ref(id)?, 1);
+        assert_eq!(rc.release_ref(id)?, 0);
+        assert!(!rc.contains(id));
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let (mut rc, mut by_owner) = (tx.take_index(values), tx.take_index(owners));
+        let id = by_owner.remove("alice")?.unwrap();
+        assert_eq!(rc.release_ref(id)?, 1);
+        assert_eq!(rc.get(id)?, Some("shared".to_string()));
+        assert!(rc.retain_ref(RcId(42)).is_err());
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let values = tx.take_list("values")?;
+        let refs = tx.take_list("refs")?;
+        let (_, mut rc) = tx.store_and_take_index(RefCounted::<String>::new(values, refs, &tx)?);
+        assert_eq!(rc.ref_count(id)?, 1);
+        assert_eq!(rc.release_ref(id)?, 0);
+        assert!(rc.is_empty());
+        assert!(rc.release_ref(id).is_err());
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,282 @@
+use anyhow::anyhow;
+use llsdb::{
+    Backend, Changeset, ChangesetWrite, LinkedList, ListQuota, LlsDb, Poisoned, QuotaExceeded,
+    ReadOnlySlice, TxMemoryExceeded,
+};
+use std::io::Cursor;
+
+#[test]
+fn follower_applies_changesets() {
+    let mut primary = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let follower_image = primary.backend().get_ref().clone();
+    let mut follower = LlsDb::load(Cursor::new(follower_image)).unwrap();
+    primary.set_replication_log(true);
+
+    let list = primary
+        .execute(|tx| {
+            let list = tx.take_list::<String>("words")?;
+            let api = list.api(&tx);
+            for word in ["one", "two", "three"] {
+                api.push(&word.to_string())?;
+            }
+            Ok(list)
+        })
+        .unwrap();
+    let _it_should_fail = primary.execute(|tx| {
+        list.api(&tx).push(&"nope".to_string())?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+    primary
+        .execute(|tx| {
+            let api = list.api(&tx);
+            api.pop()?;
+            api.push(&"four".to_string())?;
+            Ok(())
+        })
+        .unwrap();
+
+    let changesets = primary.take_changesets();
+    // the rolled back transaction doesn't count
+    assert_eq!(changesets.len(), 2);
+    assert!(primary.take_changesets().is_empty());
+
+    for changeset in &changesets {
+        let encoded = bincode::encode_to_vec(changeset, bincode::config::standard()).unwrap();
+        let (decoded, _): (Changeset, _) =
+            bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+        follower.apply_changeset(&decoded).unwrap();
+    }
+    assert_eq!(primary.backend().get_ref(), follower.backend().get_ref());
+
+    let words = follower
+        .execute(|tx| {
+            let list = tx.take_list::<String>("words")?;
+            let words = list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?;
+            Ok(words)
+        })
+        .unwrap();
+    assert_eq!(words, ["four", "two", "one"]);
+}
+
+#[test]
+fn applying_changesets_keeps_settings() {
+    let mut primary = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let mut follower = LlsDb::load(Cursor::new(primary.backend().get_ref().clone())).unwrap();
+    primary.set_replication_log(true);
+    follower.set_tx_memory_limit(Some(1_000));
+    follower
+        .execute(|tx| {
+            let quota = ListQuota {
+                max_entries: Some(4),
+                max_bytes: None,
+            };
+            tx.set_namespace_quota("tenant", quota)
+        })
+        .unwrap();
+
+    let list = primary
+        .execute(|tx| tx.take_list::<String>("tenant/words"))
+        .unwrap();
+    push_words(&mut primary, &list, 0..3);
+    for changeset in primary.take_changesets() {
+        follower.apply_changeset(&changeset).unwrap();
+    }
+
+    // the list the changesets made counts towards the namespace
+    let list = follower
+        .execute(|tx| tx.take_list::<String>("tenant/words"))
+        .unwrap();
+    push_words(&mut follower, &list, 3..4);
+    let error = follower
+        .execute(|tx| list.api(&tx).push(&"5".to_string()))
+        .unwrap_err();
+    assert!(error.downcast_ref::<QuotaExceeded>().is_some());
+
+    let error = follower
+        .execute(|tx| {
+            let other = tx.take_list::<u32>("other")?;
+            let api = other.api(&tx);
+            for i in 0..100 {
+                api.push(&i)?;
+            }
+            Ok(())
+        })
+        .unwrap_err();
+    assert!(error.downcast_ref::<TxMemoryExceeded>().is_some());
+}
+
+#[test]
+fn failing_to_apply_a_changeset_poisons() {
+    let garbage = Changeset {
+        writes: vec![ChangesetWrite {
+            offset: 0,
+            bytes: vec![0xff; 16],
+        }],
+        file_len: 1024,
+    };
+    let image = LlsDb::init(Cursor::new(vec![]))
+        .unwrap()
+        .into_backend()
+        .into_inner();
+
+    // the backend can't be written to so the old one is still there to hand back
+    let mut read_only = LlsDb::load(ReadOnlySlice::new(image.clone())).unwrap();
+    assert!(read_only.apply_changeset(&garbage).is_err());
+    let error = read_only.execute_read_only(|_| Ok(())).unwrap_err();
+    assert!(error.downcast_ref::<Poisoned>().is_some());
+    assert_eq!(read_only.into_backend().into_inner(), image);
+
+    // the rewritten backend doesn't load
+    let mut db = LlsDb::load(Cursor::new(image)).unwrap();
+    assert!(db.apply_changeset(&garbage).is_err());
+    let error = db.execute(|_| Ok(())).unwrap_err();
+    assert!(error.downcast_ref::<Poisoned>().is_some());
+    let error = db.apply_changeset(&garbage).unwrap_err();
+    assert!(error.downcast_ref::<Poisoned>().is_some());
+    assert!(db.close().unwrap_err().downcast_ref::<Poisoned>().is_some());
+}
+
+fn push_words<F: Backend>(
+    db: &mut LlsDb<F>,
+    list: &LinkedList<String>,
+    words: std::ops::Range<u32>,
+) {
+    db.execute(|tx| {
+        let api = list.api(&tx);
+        for word in words {
+            api.push(&word.to_string())?;
+        }
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn differential_backup() {
+    let mut primary = LlsDb::init(Cursor::new(vec![])).unwrap();
+    assert_eq!(primary.generation(), Some(0));
+    primary.set_backup_tracking(true);
+    let list = primary
+        .execute(|tx| tx.take_list::<String>("words"))
+        .unwrap();
+    push_words(&mut primary, &list, 0..1000);
+    assert_eq!(primary.generation(), Some(2));
+
+    let mut full = vec![];
+    let full_header = primary.backup(&mut full).unwrap();
+    assert_eq!(full_header.since, None);
+    assert_eq!(full_header.generation, 2);
+    let mut replica = LlsDb::restore_backup(Cursor::new(vec![]), &full[..]).unwrap();
+    assert_eq!(replica.backend().get_ref(), primary.backend().get_ref());
+
+    push_words(&mut primary, &list, 1000..1010);
+    let mut diff = vec![];
+    let diff_header = primary
+        .backup_since(full_header.generation, &mut diff)
+        .unwrap();
+    assert_eq!(diff_header.since, Some(2));
+    assert_eq!(diff_header.generation, 3);
+    let n_pages = primary.backend().get_ref().len() as u64 / 128;
+    assert!(diff_header.n_writes < n_pages / 2);
+    assert!(diff.len() < full.len() / 2);
+
+    replica.apply_backup(&diff[..]).unwrap();
+    assert_eq!(replica.backend().get_ref(), primary.backend().get_ref());
+    // applying it again does nothing
+    replica.apply_backup(&diff[..]).unwrap();
+    assert_eq!(replica.generation(), Some(3));
+
+    push_words(&mut primary, &list, 1010..1020);
+    let mut next_diff = vec![];
+    primary
+        .backup_since(diff_header.generation, &mut next_diff)
+        .unwrap();
+    let mut stale = LlsDb::restore_backup(Cursor::new(vec![]), &full[..]).unwrap();
+    assert!(stale.apply_backup(&next_diff[..]).is_err());
+    replica.apply_backup(&next_diff[..]).unwrap();
+    let words = replica
+        .execute(|tx| {
+            let list = tx.take_list::<String>("words")?;
+            let words = list.api(&tx).iter().collect::<Result<Vec<_>, _>>()?;
+            Ok(words)
+        })
+        .unwrap();
+    assert_eq!(words.len(), 1020);
+    assert_eq!(words[0], "1019");
+
+    assert!(primary.backup_since(10, &mut vec![]).is_err());
+    // changes from before the database was opened aren't known about
+    let mut primary = LlsDb::load(Cursor::new(primary.backend().get_ref().clone())).unwrap();
+    primary.set_backup_tracking(true);
+    let header = primary.backup_since(3, &mut vec![]).unwrap();
+    assert_eq!(header.since, None);
+}
+
+/// An object store in memory
+#[derive(Default)]
+struct MemoryStore(std::collections::BTreeMap<String, Vec<u8>>);
+
+impl llsdb::ObjectStore for MemoryStore {
+    fn put(&mut self, key: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        self.0.insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn get(&mut self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.0.get(key).cloned())
+    }
+}
+
+#[test]
+fn backups_go_to_an_object_store_in_chunks() {
+    use llsdb::{ObjectStoreSink, ObjectStoreSource};
+
+    let mut store = MemoryStore::default();
+    let mut primary = LlsDb::init(Cursor::new(vec![])).unwrap();
+    primary.set_backup_tracking(true);
+    let list = primary
+        .execute(|tx| tx.take_list::<String>("words"))
+        .unwrap();
+    push_words(&mut primary, &list, 0..1000);
+
+    let full_header = primary
+        .backup_to(None, ObjectStoreSink::new(&mut store, "full", 1000))
+        .unwrap();
+    let mut full = vec![];
+    primary.backup(&mut full).unwrap();
+    let chunks = store
+        .0
+        .iter()
+        .filter(|(key, _)| key.starts_with("full/chunk-"))
+        .map(|(_, chunk)| chunk.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(chunks.len(), full.len().div_ceil(1000));
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 1000));
+    assert_eq!(chunks.concat(), full);
+
+    let source = ObjectStoreSource::open(&mut store, "full").unwrap();
+    assert_eq!(source.header(), &full_header);
+    let mut replica = LlsDb::restore_backup(Cursor::new(vec![]), source).unwrap();
+    assert_eq!(replica.backend().get_ref(), primary.backend().get_ref());
+
+    push_words(&mut primary, &list, 1000..1010);
+    primary
+        .backup_to(
+            Some(full_header.generation),
+            ObjectStoreSink::new(&mut store, "diff", 1000),
+        )
+        .unwrap();
+    // a backup that never finished has no manifest
+    let manifest = store.0.remove("diff/manifest").unwrap();
+    assert!(ObjectStoreSource::open(&mut store, "diff").is_err());
+    store.0.insert("diff/manifest".into(), manifest);
+    replica
+        .apply_backup(ObjectStoreSource::open(&mut store, "diff").unwrap())
+        .unwrap();
+    assert_eq!(replica.backend().get_ref(), primary.backend().get_ref());
+
+    store.0.remove("diff/chunk-00000000");
+    assert!(replica
+        .apply_backup(ObjectStoreSource::open(&mut store, "diff").unwrap())
+        .is_err());
+}
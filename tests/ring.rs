@@ -0,0 +1,87 @@
+use anyhow::anyhow;
+use llsdb::{
+    index::{RingBuffer, RingCapacity},
+    LlsDb,
+};
+use std::io::Cursor;
+
+#[test]
+fn ring_buffer_evicts_oldest() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let ring = db
+        .execute(|tx| {
+            let lists = [tx.take_list("log-0")?, tx.take_list("log-1")?];
+            Ok(tx.store_index(RingBuffer::new(lists, RingCapacity::Entries(5), tx)?))
+        })
+        .unwrap();
+
+    let mut max_len = 0;
+    for i in 0..200u32 {
+        db.execute(|tx| {
+            let mut ring = tx.take_index(ring);
+            let n_evicted = ring.push(i)?;
+            assert_eq!(n_evicted, usize::from(i >= 5));
+            Ok(())
+        })
+        .unwrap();
+        max_len = max_len.max(db.backend().get_ref().len());
+    }
+    // evicted entries are freed so the file doesn't keep growing
+    assert!(max_len < 4096 + 100, "{}", max_len);
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut ring = tx.take_index(ring);
+        ring.push(1_000)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let ring = tx.take_index(ring);
+        assert_eq!(
+            ring.iter().collect::<Result<Vec<_>, _>>()?,
+            vec![195, 196, 197, 198, 199]
+        );
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let lists = [tx.take_list("log-0")?, tx.take_list("log-1")?];
+        let handle = tx.store_index(RingBuffer::<u32>::new(lists, RingCapacity::Entries(3), tx)?);
+        let mut ring = tx.take_index(handle);
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.push(200)?, 1);
+        assert_eq!(
+            ring.iter().collect::<Result<Vec<_>, _>>()?,
+            vec![198, 199, 200]
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn ring_buffer_byte_budget() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    db.execute(|tx| {
+        let lists = [tx.take_list("log-0")?, tx.take_list("log-1")?];
+        let handle = tx.store_index(RingBuffer::new(lists, RingCapacity::Bytes(100), tx)?);
+        let mut ring = tx.take_index(handle);
+        for i in 0..50u8 {
+            ring.push(vec![i; 20])?;
+            assert!(ring.byte_len() <= 100);
+        }
+        let values = ring.iter().collect::<Result<Vec<_>, _>>()?;
+        // each entry is 23 bytes with the generation and the pointer to the previous entry
+        assert_eq!(values.len(), 4);
+        assert_eq!(values.last(), Some(&vec![49; 20]));
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,48 @@
+use llsdb::LlsDb;
+use std::io::Cursor;
+
+llsdb::schema! {
+    struct Schema {
+        users: String = "users",
+        scores: (String, u64) = "scores",
+    }
+}
+
+#[test]
+fn schema_takes_all_lists() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let schema = Schema::take(tx)?;
+        schema.users.api(&tx).push(&"alice".to_string())?;
+        schema.scores.api(&tx).push(&("alice".to_string(), 3))?;
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let schema = Schema::take(tx)?;
+        assert_eq!(
+            schema
+                .users
+                .api(&tx)
+                .iter()
+                .collect::<Result<Vec<_>, _>>()?,
+            ["alice"]
+        );
+        assert_eq!(
+            schema
+                .scores
+                .api(&tx)
+                .iter()
+                .collect::<Result<Vec<_>, _>>()?,
+            [("alice".to_string(), 3)]
+        );
+        // the lists can't be taken again
+        assert!(Schema::take(tx).is_err());
+        Ok(())
+    })
+    .unwrap();
+}
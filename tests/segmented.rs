@@ -0,0 +1,68 @@
+use llsdb::{LlsDb, SegmentedFile};
+
+const SEGMENT_SIZE: u64 = 16 * 1024;
+
+#[test]
+fn segments_hold_the_database_and_free_ones_are_deleted() {
+    let dir = std::env::temp_dir().join(format!("llsdb-segmented-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("db");
+    let blob = vec![7u8; 1000];
+
+    let mut db = LlsDb::load_or_init(SegmentedFile::open(&path, SEGMENT_SIZE).unwrap()).unwrap();
+    let scratch = db
+        .execute(|tx| {
+            let keep = tx.take_list::<Vec<u8>>("keep")?;
+            let scratch = tx.take_list::<Vec<u8>>("scratch")?;
+            for _ in 0..100 {
+                scratch.api(&tx).push(&blob)?;
+            }
+            keep.api(&tx).push(&vec![1, 2, 3])?;
+            Ok(scratch)
+        })
+        .unwrap();
+    let before = std::fs::read_dir(&dir).unwrap().count();
+    assert!(before > 5);
+
+    // a second instance can't be opened while the first one is
+    assert!(SegmentedFile::open(&path, SEGMENT_SIZE).is_err());
+
+    db.execute(|tx| {
+        while scratch.api(&tx).pop()?.is_some() {}
+        Ok(())
+    })
+    .unwrap();
+    // the entry being kept is at the end so nothing can be truncated
+    db.discard_free_space(SEGMENT_SIZE).unwrap();
+    let after = std::fs::read_dir(&dir).unwrap().count();
+    assert!(after < before);
+    assert!(after >= 2);
+
+    // writing to a deleted segment brings it back
+    db.execute(|tx| {
+        for _ in 0..20 {
+            scratch.api(&tx).push(&vec![9u8; 1000])?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    db.close().unwrap();
+
+    let mut db = LlsDb::load(SegmentedFile::open(&path, SEGMENT_SIZE).unwrap()).unwrap();
+    db.execute(|tx| {
+        let keep = tx.take_list::<Vec<u8>>("keep")?;
+        let scratch = tx.take_list::<Vec<u8>>("scratch")?;
+        assert_eq!(
+            keep.api(&tx).iter().collect::<Result<Vec<_>, _>>()?,
+            vec![vec![1, 2, 3]]
+        );
+        let values = scratch.api(&tx).iter().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values.len(), 20);
+        assert!(values.iter().all(|value| *value == vec![9u8; 1000]));
+        Ok(())
+    })
+    .unwrap();
+    db.check_allocations().unwrap();
+    drop(db);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
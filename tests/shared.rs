@@ -0,0 +1,63 @@
+use llsdb::{LlsDb, SharedLlsDb};
+use std::fs::OpenOptions;
+
+#[test]
+fn shared_db_serializes_writers_and_snapshots_heads() {
+    let path = std::env::temp_dir().join(format!("llsdb-shared-{}", std::process::id()));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    let mut db = LlsDb::init(file).unwrap();
+    let list = db.execute(|tx| tx.take_list::<u32>("list")).unwrap();
+    let pool = db.reader_pool(&path);
+    let db = SharedLlsDb::new(db);
+    let list = &list;
+    assert_eq!(db.snapshot().head("list"), Some(llsdb::Pointer::NULL));
+
+    std::thread::scope(|s| {
+        for thread in 0..4u32 {
+            let db = db.clone();
+            s.spawn(move || {
+                for i in 0..10u32 {
+                    db.execute(|tx| list.api(&tx).push(&(thread * 100 + i)))
+                        .unwrap();
+                }
+            });
+        }
+        s.spawn(|| {
+            for _ in 0..20 {
+                let snapshot = db.snapshot();
+                let head = snapshot.head("list").unwrap();
+                let mut reader = pool.reader().unwrap();
+                assert!(reader.iter::<u32>(head).all(|entry| entry.is_ok()));
+            }
+        });
+    });
+
+    let snapshot = db.snapshot();
+    let head = snapshot.head("list").unwrap();
+    let mut values = pool
+        .reader()
+        .unwrap()
+        .iter::<u32>(head)
+        .map(|entry| entry.unwrap().1)
+        .collect::<Vec<_>>();
+    assert_eq!(values.len(), 40);
+    values.sort();
+    values.dedup();
+    assert_eq!(values.len(), 40);
+
+    let generation = db.with_db(|db| db.generation()).unwrap();
+    assert_eq!(snapshot.generation(), generation);
+
+    let other = db.clone();
+    let db = db.try_unwrap().err().unwrap();
+    drop(other);
+    let db = db.try_unwrap().ok().unwrap();
+    assert_eq!(db.lists().collect::<Vec<_>>(), ["list"]);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,72 @@
+use anyhow::anyhow;
+use llsdb::{index::SortedVec, LlsDb};
+use std::io::Cursor;
+
+#[test]
+fn sorted_vec_keeps_values_in_order() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let sorted = db
+        .execute(|tx| {
+            let list = tx.take_list("sorted")?;
+            let handle = tx.store_index(SortedVec::new(list, &tx)?);
+            let mut sorted = tx.take_index(handle);
+            for value in [5u32, 1, 9, 3, 7] {
+                assert!(sorted.insert(value)?);
+            }
+            assert!(!sorted.insert(3)?);
+            assert!(sorted.contains(&9)?);
+            assert!(!sorted.contains(&4)?);
+            assert!(sorted.remove(&5)?);
+            assert!(!sorted.remove(&5)?);
+            assert_eq!(sorted.first()?, Some(1));
+            assert_eq!(sorted.last()?, Some(9));
+            assert_eq!(
+                sorted.range(2..=7)?.collect::<Result<Vec<_>, _>>()?,
+                vec![3, 7]
+            );
+            // a range that starts after it ends is empty
+            let (start, end) = (8, 2);
+            assert_eq!(
+                sorted.range(start..end)?.collect::<Result<Vec<_>, _>>()?,
+                Vec::<u32>::new()
+            );
+            Ok(handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut sorted = tx.take_index(sorted);
+        sorted.insert(4)?;
+        sorted.remove(&1)?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let sorted = tx.take_index(sorted);
+        assert_eq!(
+            sorted.iter().collect::<Result<Vec<_>, _>>()?,
+            vec![1, 3, 7, 9]
+        );
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("sorted")?;
+        let handle = tx.store_index(SortedVec::<u32>::new(list, &tx)?);
+        let mut sorted = tx.take_index(handle);
+        assert_eq!(sorted.len(), 4);
+        assert_eq!(
+            sorted.range(3..)?.rev().collect::<Result<Vec<_>, _>>()?,
+            vec![9, 7, 3]
+        );
+        assert!(sorted.remove(&1)?);
+        assert_eq!(sorted.get(0)?, Some(3));
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,48 @@
+use llsdb::{index, LlsDb};
+use std::io::Cursor;
+
+llsdb::tables! {
+    struct Tables {
+        users: index::BTreeMap<u64, String> = "users",
+        events: index::Vec<String> = "events",
+        n_logins: index::Cell<u64> = "n_logins",
+        last_login: index::CellOption<u64> = "last_login",
+    }
+    struct TablesApi;
+}
+
+#[test]
+fn tables_take_and_reload() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let tables = db.execute(Tables::take).unwrap();
+    for (id, name) in [(1, "alice"), (2, "bob")] {
+        db.execute(|tx| {
+            let mut t = tables.api(tx);
+            t.users.insert(id, &name.to_string())?;
+            t.events.push(&format!("{} logged in", name))?;
+            let n_logins = t.n_logins.get()?;
+            t.n_logins.replace(&(n_logins + 1))?;
+            t.last_login.replace(Some(&id))?;
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    let tables = db.execute(Tables::take).unwrap();
+    db.execute(|tx| {
+        let t = tables.api(tx);
+        assert_eq!(t.users.get(&2)?, Some("bob".to_string()));
+        assert_eq!(t.events.len(), 2);
+        assert_eq!(t.events.get(0)?, Some("alice logged in".to_string()));
+        assert_eq!(t.n_logins.get()?, 2);
+        assert_eq!(t.last_login.get()?, Some(2));
+        drop(t);
+        // the lists can't be taken again
+        assert!(Tables::take(tx).is_err());
+        Ok(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,74 @@
+use anyhow::anyhow;
+use llsdb::{
+    index::{simple_tokens, TextIndex},
+    LlsDb,
+};
+use std::io::Cursor;
+
+fn titles(
+    results: impl Iterator<Item = anyhow::Result<(llsdb::EntryPointer, String)>>,
+) -> Vec<String> {
+    let mut titles = results
+        .map(|res| res.map(|(_, title)| title))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    titles.sort();
+    titles
+}
+
+#[test]
+fn text_index_search() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let (index, gone) = db
+        .execute(|tx| {
+            let list = tx.take_list("docs")?;
+            let handle =
+                tx.store_index(TextIndex::new(list, |doc: &String| simple_tokens(doc), tx)?);
+            let mut index = tx.take_index(handle);
+            index.add("The quick brown fox".to_string())?;
+            index.add("A quick brown dog".to_string())?;
+            let gone = index.add("The lazy dog".to_string())?;
+            Ok((handle, gone))
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut index = tx.take_index(index);
+        index.remove(gone)?;
+        index.add("Another fox".to_string())?;
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let mut index = tx.take_index(index);
+        assert_eq!(titles(index.search("fox")), vec!["The quick brown fox"]);
+        assert_eq!(
+            titles(index.search_all(&["quick", "brown"])),
+            vec!["A quick brown dog", "The quick brown fox"]
+        );
+        assert_eq!(
+            titles(index.search_all(&["dog", "lazy"])),
+            vec!["The lazy dog"]
+        );
+        assert_eq!(index.remove(gone)?, Some("The lazy dog".to_string()));
+        assert_eq!(index.remove(gone)?, None);
+        assert!(titles(index.search("lazy")).is_empty());
+        assert!(titles(index.search_all(&["dog", "missing"])).is_empty());
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list("docs")?;
+        let handle = tx.store_index(TextIndex::new(list, |doc: &String| simple_tokens(doc), tx)?);
+        let index = tx.take_index(handle);
+        assert_eq!(index.len(), 2);
+        assert_eq!(titles(index.search("dog")), vec!["A quick brown dog"]);
+        Ok(())
+    })
+    .unwrap();
+}
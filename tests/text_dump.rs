@@ -0,0 +1,85 @@
+use llsdb::LlsDb;
+use std::io::Cursor;
+
+#[test]
+fn dump_is_the_same_for_the_same_contents() {
+    let mut a = LlsDb::init(Cursor::new(vec![])).unwrap();
+    a.execute(|tx| {
+        let strings = tx.take_list::<String>("strings")?;
+        let ints = tx.take_list::<u32>("ints")?;
+        ints.api(&tx).push(&1)?;
+        strings.api(&tx).push(&"hello".to_string())?;
+        ints.api(&tx).push(&2)?;
+        tx.take_list::<u32>("empty \"list\"\n")?;
+        Ok(())
+    })
+    .unwrap();
+
+    // same contents reached a different way so they end up elsewhere in the file
+    let mut b = LlsDb::init(Cursor::new(vec![])).unwrap();
+    let junk = b
+        .execute(|tx| {
+            tx.take_list::<u32>("empty \"list\"\n")?;
+            let junk = tx.take_list::<Vec<u8>>("junk")?;
+            junk.api(&tx).push(&vec![0; 100])?;
+            let ints = tx.take_list::<u32>("ints")?;
+            ints.api(&tx).push(&1)?;
+            ints.api(&tx).push(&2)?;
+            Ok(junk)
+        })
+        .unwrap();
+    b.execute(|tx| {
+        junk.api(&tx).pop()?;
+        tx.take_list::<String>("strings")?
+            .api(&tx)
+            .push(&"hello".to_string())?;
+        Ok(())
+    })
+    .unwrap();
+
+    let mut dump_a = vec![];
+    a.dump_to(&mut dump_a).unwrap();
+    let mut dump_b = vec![];
+    b.dump_to(&mut dump_b).unwrap();
+    assert_ne!(dump_a, dump_b);
+    let dump_a = String::from_utf8(dump_a).unwrap();
+    assert_eq!(
+        dump_a,
+        "llsdb-dump 1\n\
+         encoding varint little\n\
+         list \"empty \\\"list\\\"\\n\" 0\n\
+         list \"ints\" 2\n  01\n  02\n\
+         list \"strings\" 1\n  0568656c6c6f\n\
+         end\n"
+    );
+    // the only difference is the empty list b was left with
+    let dump_b = String::from_utf8(dump_b).unwrap();
+    assert_eq!(
+        dump_b.replace("list \"junk\" 0\n", ""),
+        dump_a,
+        "{}",
+        dump_b
+    );
+
+    let mut restored = LlsDb::restore_from(Cursor::new(vec![]), dump_a.as_bytes()).unwrap();
+    let mut dump_restored = vec![];
+    restored.dump_to(&mut dump_restored).unwrap();
+    assert_eq!(String::from_utf8(dump_restored).unwrap(), dump_a);
+    restored
+        .execute(|tx| {
+            let ints = tx.take_list::<u32>("ints")?;
+            let strings = tx.take_list::<String>("strings")?;
+            assert_eq!(
+                ints.api(&tx).iter().collect::<Result<Vec<_>, _>>()?,
+                vec![2, 1]
+            );
+            assert_eq!(strings.api(&tx).head()?, Some("hello".to_string()));
+            Ok(())
+        })
+        .unwrap();
+
+    let truncated = dump_a.replace("end\n", "");
+    assert!(LlsDb::restore_from(Cursor::new(vec![]), truncated.as_bytes()).is_err());
+    let bad_hex = dump_a.replace("  01", "  0g");
+    assert!(LlsDb::restore_from(Cursor::new(vec![]), bad_hex.as_bytes()).is_err());
+}
@@ -0,0 +1,67 @@
+use llsdb::{Clock, LlsDb, ManualClock, RateLimit, ThrottledBackend};
+use std::{io::Cursor, sync::Arc, time::Duration};
+
+#[test]
+fn throttled_backend_waits_once_past_the_burst() {
+    let clock = Arc::new(ManualClock::default());
+    let start = clock.now();
+    let backend = ThrottledBackend::with_clock(
+        Cursor::new(vec![]),
+        RateLimit {
+            bytes_per_sec: Some(1_000),
+            ops_per_sec: None,
+        },
+        clock.clone(),
+    );
+    let mut db = LlsDb::init(backend).unwrap();
+    let list = db.execute(|tx| tx.take_list::<Vec<u8>>("list")).unwrap();
+    db.execute(|tx| {
+        for _ in 0..10 {
+            list.api(&tx).push(&vec![0xab; 1_000])?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    // everything has to have taken at least as long as the rate allows less the burst
+    let written = db.backend().inner().get_ref().len() as u64;
+    let waited = clock.now().duration_since(start).unwrap();
+    assert!(written > 10_000);
+    assert!(waited >= Duration::from_millis(written - 1_000));
+
+    // nothing waits once the limit is lifted
+    db.backend().set_limit(RateLimit::default());
+    let before = clock.now();
+    db.execute(|tx| {
+        for _ in 0..10 {
+            list.api(&tx).push(&vec![0xcd; 1_000])?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(clock.now(), before);
+    assert_eq!(
+        db.execute(|tx| Ok(list.api(&tx).iter().count())).unwrap(),
+        20
+    );
+}
+
+#[test]
+fn throttled_backend_limits_ops() {
+    let clock = Arc::new(ManualClock::default());
+    let start = clock.now();
+    let backend = ThrottledBackend::with_clock(
+        Cursor::new(vec![]),
+        RateLimit {
+            bytes_per_sec: None,
+            ops_per_sec: Some(10),
+        },
+        clock.clone(),
+    );
+    let mut db = LlsDb::init(backend).unwrap();
+    let list = db.execute(|tx| tx.take_list::<u64>("list")).unwrap();
+    for i in 0..20u64 {
+        db.execute(|tx| list.api(&tx).push(&i)).unwrap();
+    }
+    // each commit writes and syncs more than once so this is well over two seconds of ops
+    assert!(clock.now().duration_since(start).unwrap() >= Duration::from_secs(2));
+}
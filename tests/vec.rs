@@ -1,5 +1,8 @@
 use anyhow::anyhow;
-use llsdb::{index::Vec, LlsDb};
+use llsdb::{
+    index::{IndexDiff, Vec},
+    LinkedList, LlsDb, Pointer,
+};
 use std::io::Cursor;
 
 #[test]
@@ -213,3 +216,179 @@ fn vec_load_index() {
         .unwrap();
     }
 }
+
+#[test]
+fn vec_range_reads() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("vec")?;
+        let (_, mut vec) = tx.store_and_take_index(Vec::new(list, tx)?);
+        assert_eq!(vec.first()?, None);
+        assert_eq!(vec.last()?, None);
+        for i in 0..10 {
+            vec.push(&i)?;
+        }
+        assert_eq!(vec.first()?, Some(0));
+        assert_eq!(vec.last()?, Some(9));
+        assert_eq!(
+            vec.iter_range(2..5)
+                .collect::<Result<std::vec::Vec<_>, _>>()?,
+            vec![2, 3, 4]
+        );
+        assert_eq!(
+            vec.iter_range(7..)
+                .rev()
+                .collect::<Result<std::vec::Vec<_>, _>>()?,
+            vec![9, 8, 7]
+        );
+        assert_eq!(vec.iter_range(8..20).len(), 2);
+        assert_eq!(vec.iter_range(15..20).len(), 0);
+        assert_eq!(
+            vec.get_many(&[9, 0, 42, 3])?,
+            vec![Some(9), Some(0), None, Some(3)]
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn vec_debug_validate() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("vec")?;
+        // a second handle to the same list to mess with it behind the index's back
+        let sneaky = LinkedList::<u32>::new(list.slot());
+        let vec_handle = tx.store_index(Vec::new(list, tx)?);
+        let mut vec = tx.take_index(vec_handle);
+        vec.push(&1)?;
+        vec.push(&2)?;
+        vec.debug_validate()?;
+
+        sneaky.api(&tx).push(&3)?;
+        let error = vec.debug_validate().unwrap_err();
+        let diff = error.downcast_ref::<IndexDiff<Pointer>>().unwrap();
+        assert_eq!(diff.mismatches.len(), 1);
+        assert_eq!(diff.mismatches[0].position, 2);
+        assert_eq!(diff.mismatches[0].in_memory, None);
+        assert!(diff.mismatches[0].on_disk.is_some());
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn vec_rebuild() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+
+    let (vec_handle, sneaky) = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("vec")?;
+            let sneaky = LinkedList::<u32>::new(list.slot());
+            let vec_handle = tx.store_index(Vec::new(list, tx)?);
+            let mut vec = tx.take_index(vec_handle);
+            vec.push(&1)?;
+            sneaky.api(&tx).push(&2)?;
+            Ok((vec_handle, sneaky))
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        vec_handle.rebuild(tx)?;
+        assert_eq!(tx.take_index(vec_handle).len(), 2);
+        Err::<(), _>(anyhow!("fail the tx"))
+    });
+
+    db.execute(|tx| {
+        assert_eq!(tx.take_index(vec_handle).len(), 1);
+        sneaky.api(&tx).push(&3)?;
+        vec_handle.rebuild(tx)?;
+        let vec = tx.take_index(vec_handle);
+        vec.debug_validate()?;
+        assert_eq!(
+            vec.iter().collect::<Result<std::vec::Vec<_>, _>>()?,
+            vec![1, 2, 3]
+        );
+        Ok(())
+    })
+    .unwrap();
+
+    db.execute(|tx| {
+        let vec = tx.take_index(vec_handle);
+        assert!(vec_handle.rebuild(tx).is_err(), "can't rebuild while taken");
+        assert_eq!(vec.len(), 3);
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn vec_sparse() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let mut model = (0..23u32).collect::<std::vec::Vec<_>>();
+
+    let handle = db
+        .execute(|tx| {
+            let list = tx.take_list::<u32>("vec")?;
+            assert!(Vec::<u32>::new_sparse(list, 0, tx).is_err());
+            let list = tx.take_list::<u32>("sparse")?;
+            let handle = tx.store_index(Vec::new_sparse(list, 5, tx)?);
+            let mut vec = tx.take_index(handle);
+            for i in &model {
+                vec.push(i)?;
+            }
+            Ok(handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut vec = tx.take_index(handle);
+        for _ in 0..12 {
+            vec.pop()?;
+        }
+        vec.push(&100)?;
+        assert_eq!(vec.len(), 12);
+        assert_eq!(vec.get(10)?, Some(10));
+        assert_eq!(vec.last()?, Some(100));
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let mut vec = tx.take_index(handle);
+        vec.debug_validate()?;
+        assert_eq!(vec.iter().collect::<Result<std::vec::Vec<_>, _>>()?, model);
+        assert_eq!(vec.pop()?, model.pop());
+        assert_eq!(vec.pop()?, model.pop());
+        assert_eq!(vec.pop()?, model.pop());
+        assert_eq!(vec.pop()?, model.pop());
+        vec.debug_validate()?;
+        assert_eq!(
+            vec.iter_range(3..12)
+                .collect::<Result<std::vec::Vec<_>, _>>()?,
+            model[3..12]
+        );
+        assert_eq!(vec.get(model.len())?, None);
+        Ok(())
+    })
+    .unwrap();
+    drop(db);
+
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<u32>("sparse")?;
+        let (_, vec) = tx.store_and_take_index(Vec::new_sparse(list, 5, tx)?);
+        vec.debug_validate()?;
+        assert_eq!(
+            vec.iter().rev().collect::<Result<std::vec::Vec<_>, _>>()?,
+            model.iter().rev().copied().collect::<std::vec::Vec<_>>()
+        );
+        Ok(())
+    })
+    .unwrap();
+}
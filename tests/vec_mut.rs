@@ -146,6 +146,7 @@ fn vec_mut_remove_last_elem() {
         Ok(())
     })
     .unwrap();
+    drop(db);
 
     assert_eq!(backend.len(), len_before_remove - 3);
 }
@@ -181,6 +182,7 @@ fn vec_mut_retain_should_shrink_backend_if_you_remove_end_elements() {
         Ok(())
     })
     .unwrap();
+    drop(db);
 
     assert_eq!(backend.len(), len_before_retain - 2 * 3);
 }
@@ -248,3 +250,62 @@ fn vec_mut_retain() {
     })
     .unwrap();
 }
+
+#[test]
+fn vec_mut_swap_remove() {
+    let mut backend = vec![];
+    let mut db = LlsDb::init(Cursor::new(&mut backend)).unwrap();
+    let my_vec = db
+        .execute(|tx| {
+            let list = tx.take_list::<Mut<u32>>("vec_mut")?;
+            let index_handle = tx.store_index(VecRemove::new(list, tx)?);
+            let mut vec = tx.take_index(index_handle);
+            for i in 0..5 {
+                vec.push(i)?;
+            }
+            Ok(index_handle)
+        })
+        .unwrap();
+
+    let _it_should_fail = db.execute(|tx| {
+        let mut vec = tx.take_index(my_vec);
+        assert_eq!(vec.swap_remove(1)?, 1);
+        assert_eq!(vec.swap_remove(3)?, 3);
+        assert_eq!(vec.iter().collect::<Result<Vec<_>, _>>()?, [0, 4, 2]);
+        Err::<(), _>(anyhow!("rollback"))
+    });
+
+    db.execute(|tx| {
+        let mut vec = tx.take_index(my_vec);
+        assert_eq!(vec.iter().collect::<Result<Vec<_>, _>>()?, [0, 1, 2, 3, 4]);
+        assert_eq!(vec.swap_remove(1)?, 1);
+        assert_eq!(vec.iter().collect::<Result<Vec<_>, _>>()?, [0, 4, 2, 3]);
+        assert_eq!(vec.get(1)?, Some(4));
+        vec.debug_validate()?;
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<u32>>("vec_mut")?;
+        let (_, mut vec) = tx.store_and_take_index(VecRemove::new(list, tx)?);
+        assert_eq!(vec.iter().collect::<Result<Vec<_>, _>>()?, [0, 4, 2, 3]);
+        // the removed element is right before the one moved into its place
+        assert_eq!(vec.swap_remove(2)?, 2);
+        vec.debug_validate()?;
+        Ok(())
+    })
+    .unwrap();
+
+    drop(db);
+    let mut db = LlsDb::load(Cursor::new(&mut backend)).unwrap();
+    db.execute(|tx| {
+        let list = tx.take_list::<Mut<u32>>("vec_mut")?;
+        let (_, vec) = tx.store_and_take_index(VecRemove::new(list, tx)?);
+        assert_eq!(vec.iter().collect::<Result<Vec<_>, _>>()?, [0, 4, 3]);
+        Ok(())
+    })
+    .unwrap();
+}